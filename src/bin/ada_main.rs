@@ -14,35 +14,82 @@
 //!
 //! # Optional
 //! export BOT_DEPTH=5              # Engine search depth
+//! export BOT_MAX_DEPTH=20         # Hard ceiling on search depth
+//! export BOT_ANTI_SHUFFLE_CP=50   # Penalty for moving back to an earlier position when ahead
+//! export BOT_ANTI_SHUFFLE_MIN_CP=200 # Eval from which that penalty applies
 //! export BOT_MAX_GAMES=4          # Max concurrent games
+//! export BOT_GAME_TIMEOUT_SECS=3600 # Abandon a game task after this long
+//! export BOT_CHALLENGE_CONFIG=challenge.yaml # Challenge filter file, overridden by BOT_VARIANTS etc.
+//! export BOT_RATED_HOURS="mon-fri 18-23, sat-sun 10-22" # Accept rated challenges only then (local time)
+//! export BOT_REMATCH=rules       # Rematches: always accept, never accept, or apply the usual rules
+//! export BOT_CHALLENGE_SUMMARY_SECS=600 # Log challenge accept/decline tally (0 = off)
+//! export BOT_METRICS_ADDR=127.0.0.1:9100 # Serve challenge metrics at /metrics
 //! export BOT_WHATIF=false          # Enable what-if branching
-//! export BOT_USERNAME=AdaChessBot # Bot username (auto-detected if omitted)
+//! export BOT_WHATIF_POOL_SIZE=2   # Threads dedicated to what-if analysis
+//! export BOT_WHATIF_MAX_CONCURRENT=2 # What-if trees generated at once
+//! export BOT_WHATIF_SESSION_BUDGET=0 # What-if nodes per game (0 = unlimited)
+//! export BOT_WHATIF_MAX_MEMORY=0  # Estimated bytes per what-if tree (0 = unlimited)
+//! export BOT_MIN_HARVEST_MOVES=0  # Skip harvesting games shorter than this (half-moves)
+//! export BOT_HARVEST_QUEUE=64     # Harvest records waiting for the sink at most
+//! export BOT_HARVEST_OVERFLOW=block # When the queue is full: block or drop
+//! export BOT_MOVE_RETRIES=3       # Retries for a move Lichess failed to accept
+//! export BOT_OPENING_VARIETY=false # Randomize the first moves among the best candidates
+//! export BOT_OPENING_VARIETY_PLIES=8 # Half-moves randomized per game
+//! export BOT_OPENING_SEED=        # Fixed seed for reproducible openings (default: clock)
+//! export BOT_STATE_DIR=./harvest/games # Saved in-progress games, resumed after a restart
+//! export BOT_CHAT=false          # Greet opponents and say goodbye in the game chat
+//! export BOT_ACCEPT_DRAWS=false   # Consider the opponent's draw offers
+//! export BOT_DRAW_MAX_EVAL_CP=0   # Accept only when our eval is at most this
+//! export BOT_DRAW_MIN_PLIES=40    # Accept only after this many half-moves
 //! export HARVEST_DIR=./harvest    # Output directory for harvested data
-//! export HARVEST_FORMAT=both      # cypher, json, or both
+//! export HARVEST_FORMAT=both      # cypher, cypher_batch, json, debug, redis, kafka, or both
+//! export HARVEST_REDIS_URL=redis://127.0.0.1:6379 # Redis server for HARVEST_FORMAT=redis
+//! export HARVEST_REDIS_STREAM_GAME=stonksfish:games # Stream for game records
+//! export HARVEST_REDIS_STREAM_BRANCH=stonksfish:branches # Stream for what-if trees
+//! export HARVEST_KAFKA_BROKERS=127.0.0.1:9092 # Kafka brokers (comma-separated) for HARVEST_FORMAT=kafka (needs --features kafka)
+//! export HARVEST_KAFKA_TOPIC_GAME=stonksfish.games # Topic for game records
+//! export HARVEST_KAFKA_TOPIC_BRANCH=stonksfish.branches # Topic for what-if trees
+//! export HARVEST_KAFKA_SECURITY_PROTOCOL=sasl_ssl # plaintext, ssl, sasl_plaintext or sasl_ssl
+//! export HARVEST_KAFKA_SASL_MECHANISM=SCRAM-SHA-512 # SASL mechanism, e.g. PLAIN
+//! export HARVEST_KAFKA_SASL_USERNAME=bot # SASL credentials
+//! export HARVEST_KAFKA_SASL_PASSWORD=...
+//! export HARVEST_KAFKA_SSL_CA_LOCATION=/etc/ssl/kafka-ca.pem # CA to verify brokers with
+//! export HARVEST_KAFKA_MAX_QUEUED=100000 # Records waiting for delivery at most
+//! export HARVEST_BATCH_SIZE=5000  # Statements per file for cypher_batch
+//! export HARVEST_PERSPECTIVE=side_to_move # Eval perspective: side_to_move or white
+//! export HARVEST_EVAL_CLAMP=0     # Clamp evals to ±N cp, mates as mate_in (0 = off)
 //!
 //! cargo run --bin stonksfish-ada --release
 //! ```
 
 use dotenv::dotenv;
-use log::info;
+use tracing::{info, warn};
 use std::path::PathBuf;
 
 use stonksfish::harvest::collector::{JsonHarvester, MultiHarvester};
-use stonksfish::harvest::cypher::CypherHarvester;
-use stonksfish::harvest::{HarvestSink, NullHarvester};
-use stonksfish::lichess::{BotConfig, LichessBot};
+use stonksfish::harvest::cypher::{CypherBatchHarvester, CypherHarvester, DEFAULT_BATCH_SIZE};
+use stonksfish::harvest::debug::DebugHarvester;
+#[cfg(feature = "kafka")]
+use stonksfish::harvest::kafka::{KafkaConfig, KafkaHarvester, RdKafkaProducer, DEFAULT_BRANCH_TOPIC, DEFAULT_GAME_TOPIC};
+use stonksfish::harvest::redis::{RedisHarvester, RespClient, DEFAULT_BRANCH_STREAM, DEFAULT_GAME_STREAM};
+use stonksfish::harvest::{EvalClamp, EvalPerspective, HarvestSink, NullHarvester};
+use stonksfish::error::BotError;
+use stonksfish::lichess::{fetch_account_username, BotConfig, LichessBot};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    env_logger::init();
+    stonksfish::util::logging::init();
 
     println!("=== stonksfish-ada ===");
     println!("Unified Lichess bot with game harvesting");
     println!();
 
     // Load configuration
-    let mut config = BotConfig::from_env();
+    let mut config = BotConfig::from_env().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
 
     if config.token.is_empty() {
         eprintln!("Error: RUST_BOT_TOKEN environment variable is required.");
@@ -50,8 +97,14 @@ async fn main() {
         std::process::exit(1);
     }
 
-    // Set bot username from env or default
-    config.bot_username = std::env::var("BOT_USERNAME").unwrap_or_else(|_| "AdaChessBot".to_string());
+    // The bot's color in each game is decided by its account name
+    config.bot_username = match fetch_account_username(&config.token).await {
+        Ok(username) => username,
+        Err(e) => {
+            eprintln!("Error: could not determine the bot's Lichess account: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     info!(
         "Config: depth={}, max_games={}, whatif={}, username={}",
@@ -60,28 +113,135 @@ async fn main() {
 
     // Build harvester based on HARVEST_FORMAT
     let harvest_dir = std::env::var("HARVEST_DIR").unwrap_or_else(|_| "./harvest".to_string());
+    config
+        .state_dir
+        .get_or_insert_with(|| PathBuf::from(&harvest_dir).join("games"));
     let harvest_format = std::env::var("HARVEST_FORMAT").unwrap_or_else(|_| "both".to_string());
+    let perspective = match std::env::var("HARVEST_PERSPECTIVE") {
+        Ok(value) => value.parse::<EvalPerspective>().unwrap_or_else(|e| {
+            eprintln!("{}. Use: side_to_move or white", e);
+            std::process::exit(1);
+        }),
+        Err(_) => EvalPerspective::default(),
+    };
+    let clamp = match std::env::var("HARVEST_EVAL_CLAMP") {
+        Ok(value) => EvalClamp::new(value.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid HARVEST_EVAL_CLAMP '{}'", value);
+            std::process::exit(1);
+        })),
+        Err(_) => EvalClamp::new(0),
+    };
 
     let harvester: Box<dyn HarvestSink + Send> = match harvest_format.as_str() {
         "cypher" => {
             info!("Harvest format: Cypher (aiwar-neo4j-harvest compatible)");
-            Box::new(CypherHarvester::new(PathBuf::from(&harvest_dir)))
+            Box::new(
+                writable(CypherHarvester::new(PathBuf::from(&harvest_dir)))
+                    .with_perspective(perspective)
+                    .with_eval_clamp(clamp),
+            )
+        }
+        "cypher_batch" => {
+            let batch_size = match std::env::var("HARVEST_BATCH_SIZE") {
+                Ok(value) => value.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid HARVEST_BATCH_SIZE '{}'", value);
+                    std::process::exit(1);
+                }),
+                Err(_) => DEFAULT_BATCH_SIZE,
+            };
+            info!("Harvest format: Cypher transactions (batch size {})", batch_size);
+            Box::new(
+                writable(CypherBatchHarvester::new(PathBuf::from(&harvest_dir)))
+                    .with_batch_size(batch_size)
+                    .with_perspective(perspective)
+                    .with_eval_clamp(clamp),
+            )
         }
         "json" => {
             info!("Harvest format: JSON (crewai-rust agent compatible)");
-            Box::new(JsonHarvester::new(PathBuf::from(&harvest_dir)))
+            Box::new(
+                writable(JsonHarvester::from_existing_file(
+                    &PathBuf::from(&harvest_dir).join("live_games.jsonl"),
+                ))
+                    .with_perspective(perspective)
+                    .with_eval_clamp(clamp),
+            )
+        }
+        "debug" => {
+            info!("Harvest format: plain-text game recaps");
+            Box::new(writable(DebugHarvester::new(PathBuf::from(&harvest_dir))))
+        }
+        "redis" => {
+            let url = std::env::var("HARVEST_REDIS_URL")
+                .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+            let game_stream = std::env::var("HARVEST_REDIS_STREAM_GAME")
+                .unwrap_or_else(|_| DEFAULT_GAME_STREAM.to_string());
+            let branch_stream = std::env::var("HARVEST_REDIS_STREAM_BRANCH")
+                .unwrap_or_else(|_| DEFAULT_BRANCH_STREAM.to_string());
+            info!("Harvest format: Redis streams {} / {}", game_stream, branch_stream);
+            let client = RespClient::connect(&url).await.unwrap_or_else(|e| {
+                eprintln!("Failed to connect to HARVEST_REDIS_URL {}: {}", url, e);
+                std::process::exit(1);
+            });
+            Box::new(
+                RedisHarvester::new(client)
+                    .with_streams(game_stream, branch_stream)
+                    .with_perspective(perspective)
+                    .with_eval_clamp(clamp),
+            )
+        }
+        #[cfg(feature = "kafka")]
+        "kafka" => {
+            let mut config = KafkaConfig::new(
+                std::env::var("HARVEST_KAFKA_BROKERS").unwrap_or_else(|_| "127.0.0.1:9092".to_string()),
+            );
+            config.security_protocol = std::env::var("HARVEST_KAFKA_SECURITY_PROTOCOL").ok();
+            config.sasl_mechanism = std::env::var("HARVEST_KAFKA_SASL_MECHANISM").ok();
+            config.sasl_username = std::env::var("HARVEST_KAFKA_SASL_USERNAME").ok();
+            config.sasl_password = std::env::var("HARVEST_KAFKA_SASL_PASSWORD").ok();
+            config.ssl_ca_location = std::env::var("HARVEST_KAFKA_SSL_CA_LOCATION").ok();
+            if let Ok(max_queued) = std::env::var("HARVEST_KAFKA_MAX_QUEUED") {
+                config.max_queued = max_queued.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid HARVEST_KAFKA_MAX_QUEUED '{}'", max_queued);
+                    std::process::exit(1);
+                });
+            }
+            let game_topic = std::env::var("HARVEST_KAFKA_TOPIC_GAME")
+                .unwrap_or_else(|_| DEFAULT_GAME_TOPIC.to_string());
+            let branch_topic = std::env::var("HARVEST_KAFKA_TOPIC_BRANCH")
+                .unwrap_or_else(|_| DEFAULT_BRANCH_TOPIC.to_string());
+            info!("Harvest format: Kafka topics {} / {}", game_topic, branch_topic);
+            let producer = RdKafkaProducer::connect(&config).await.unwrap_or_else(|e| {
+                eprintln!("Failed to connect to HARVEST_KAFKA_BROKERS {}: {}", config.brokers, e);
+                std::process::exit(1);
+            });
+            Box::new(
+                KafkaHarvester::new(producer)
+                    .with_topics(game_topic, branch_topic)
+                    .with_perspective(perspective)
+                    .with_eval_clamp(clamp),
+            )
+        }
+        #[cfg(not(feature = "kafka"))]
+        "kafka" => {
+            eprintln!("HARVEST_FORMAT=kafka needs a build with --features kafka");
+            std::process::exit(1);
         }
         "both" => {
             info!("Harvest format: Cypher + JSON (dual output)");
             Box::new(MultiHarvester::new(vec![
-                Box::new(CypherHarvester::new(PathBuf::from(format!(
-                    "{}/cypher",
-                    harvest_dir
-                )))),
-                Box::new(JsonHarvester::new(PathBuf::from(format!(
-                    "{}/json",
-                    harvest_dir
-                )))),
+                Box::new(
+                    writable(CypherHarvester::new(PathBuf::from(format!("{}/cypher", harvest_dir))))
+                        .with_perspective(perspective)
+                        .with_eval_clamp(clamp),
+                ),
+                Box::new(
+                    writable(JsonHarvester::from_existing_file(
+                        &PathBuf::from(format!("{}/json/live_games.jsonl", harvest_dir)),
+                    ))
+                        .with_perspective(perspective)
+                        .with_eval_clamp(clamp),
+                ),
             ]))
         }
         "none" => {
@@ -90,7 +250,7 @@ async fn main() {
         }
         _ => {
             eprintln!(
-                "Unknown HARVEST_FORMAT '{}'. Use: cypher, json, both, or none",
+                "Unknown HARVEST_FORMAT '{}'. Use: cypher, cypher_batch, json, debug, redis, kafka, both, or none",
                 harvest_format
             );
             std::process::exit(1);
@@ -100,6 +260,15 @@ async fn main() {
     // Create and run the bot
     let bot = LichessBot::new(config, harvester);
 
+    // Ctrl+C / SIGTERM stop the bot gracefully: running games get a chance
+    // to finish and the harvester is flushed before exiting.
+    let stop = bot.stop_handle();
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        info!("Shutdown signal received");
+        stop.stop();
+    });
+
     info!("Connecting to Lichess...");
     match bot.run().await {
         Ok(()) => info!("Bot shut down cleanly."),
@@ -109,3 +278,37 @@ async fn main() {
         }
     }
 }
+
+/// Unwrap a file-based harvester, refusing to start if `HARVEST_DIR` can't
+/// be written rather than losing data at the first flush.
+fn writable<T>(harvester: Result<T, BotError>) -> T {
+    harvester.unwrap_or_else(|e| {
+        eprintln!("HARVEST_DIR not writable: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Resolve once the process is asked to terminate.
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Failed to install SIGTERM handler: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// Resolve once the process is asked to terminate.
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
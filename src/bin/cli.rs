@@ -0,0 +1,152 @@
+//! Stonksfish command-line analysis tool.
+//!
+//! Runs the engine's analysis directly, without a GUI or a Lichess bot.
+//!
+//! ```sh
+//! stonksfish-cli analyze <fen> [--depth N] [--multipv N]    # JSON analysis
+//! stonksfish-cli whatif <fen> [--config quick|deep] [--dot] [--pgn]
+//! stonksfish-cli perft <fen> <depth>                         # per-move counts
+//! stonksfish-cli bench [--depth N]                           # nodes per second
+//! ```
+//!
+//! `startpos` can be given instead of a FEN. The what-if tree is printed
+//! as JSON unless `--dot` (Graphviz) or `--pgn` is given. Run with
+//! `--help` for every option.
+
+use chess::Board;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use stonksfish::uci::{analyze_position_deep, bench, format_move, perft_divide};
+use stonksfish::whatif::{generate_branch_tree, tree_to_dot, tree_to_json, tree_to_pgn, BranchConfig};
+
+/// Search depth when `--depth` isn't given.
+const DEFAULT_DEPTH: u8 = 4;
+
+/// Stonksfish analysis without a GUI or a Lichess bot.
+#[derive(Debug, Parser)]
+#[command(name = "stonksfish-cli", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the analysis of a position as JSON.
+    Analyze {
+        /// Position to analyze, as a FEN or `startpos`.
+        #[arg(value_parser = parse_board)]
+        fen: Board,
+        /// Search depth in plies.
+        #[arg(long, default_value_t = DEFAULT_DEPTH)]
+        depth: u8,
+        /// Number of moves to list.
+        #[arg(long)]
+        multipv: Option<usize>,
+    },
+    /// Print the what-if tree of a position.
+    Whatif {
+        /// Position to explore, as a FEN or `startpos`.
+        #[arg(value_parser = parse_board)]
+        fen: Board,
+        /// Size of the tree.
+        #[arg(long, value_enum, default_value_t = TreeSize::Quick)]
+        config: TreeSize,
+        /// Print Graphviz instead of JSON.
+        #[arg(long, conflicts_with = "pgn")]
+        dot: bool,
+        /// Print PGN instead of JSON.
+        #[arg(long)]
+        pgn: bool,
+    },
+    /// Count the leaf nodes below each legal move.
+    Perft {
+        /// Position to count from, as a FEN or `startpos`.
+        #[arg(value_parser = parse_board)]
+        fen: Board,
+        /// Depth in plies.
+        depth: u8,
+    },
+    /// Measure search speed in nodes per second.
+    Bench {
+        /// Search depth in plies.
+        #[arg(long, default_value_t = DEFAULT_DEPTH)]
+        depth: u8,
+    },
+}
+
+/// What-if tree presets, see [`BranchConfig`].
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TreeSize {
+    Quick,
+    Deep,
+}
+
+fn main() -> ExitCode {
+    // clap prints the usage and exits with status 2 on bad arguments
+    match run(Cli::parse()) {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run `cli`'s command, returning what to print.
+fn run(cli: Cli) -> Result<String, String> {
+    match cli.command {
+        Command::Analyze { fen, depth, multipv } => {
+            let mut analysis = analyze_position_deep(&fen, depth);
+            if let Some(multipv) = multipv {
+                analysis.legal_moves.truncate(multipv.max(1));
+            }
+            Ok(format!("{:#}\n", analysis.to_json_value()))
+        }
+        Command::Whatif { fen, config, dot, pgn } => {
+            let config = match config {
+                TreeSize::Quick => BranchConfig::quick(),
+                TreeSize::Deep => BranchConfig::deep(),
+            };
+            let tree = generate_branch_tree(&fen.to_string(), &config)
+                .ok_or("No moves to explore from this position")?;
+            Ok(match (dot, pgn) {
+                (true, _) => tree_to_dot(&tree),
+                (_, true) => tree_to_pgn(&tree),
+                _ => format!("{:#}\n", tree_to_json(&tree)),
+            })
+        }
+        Command::Perft { fen, depth } => {
+            let divide = perft_divide(&fen, depth);
+            let mut out = String::new();
+            for (chess_move, nodes) in &divide {
+                out.push_str(&format!("{}: {}\n", format_move(*chess_move), nodes));
+            }
+            let total: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+            out.push_str(&format!("\nNodes searched: {}\n", total));
+            Ok(out)
+        }
+        Command::Bench { depth } => {
+            let result = bench(depth);
+            Ok(format!(
+                "Nodes: {}\nTime: {} ms\nNPS: {}\n",
+                result.nodes,
+                result.elapsed.as_millis(),
+                result.nps()
+            ))
+        }
+    }
+}
+
+/// A FEN, or `startpos` for the initial position.
+fn parse_board(fen: &str) -> Result<Board, String> {
+    match fen {
+        "startpos" => Ok(Board::default()),
+        fen => Board::from_str(fen).map_err(|e| format!("Invalid FEN '{}': {}", fen, e)),
+    }
+}
@@ -1,7 +1,259 @@
+pub use draw::{draw_score, is_insufficient_material};
+pub use simple::threat_bonus;
+
 /// A collection of simple chess board evaluaiton techniques.
 ///
 pub mod simple {
-    use chess::{BitBoard, Board, Color, Piece};
+    use chess::{
+        get_bishop_moves, get_file, get_knight_moves, get_pawn_attacks, get_rank, get_rook_moves,
+        BitBoard, Board, BoardBuilder, Color, Piece, Rank, Square, ALL_SQUARES, EMPTY,
+    };
+
+    /// Evaluation parameters shared by the whole engine, so tuning them
+    /// propagates to evaluation, endgame scoring and what-if pruning alike.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EvalParams {
+        /// Pawn, knight, bishop, rook and queen values in centipawns.
+        pub piece_values: [i32; 5],
+        /// Bonus for having the move, in centipawns. Keeps quiet symmetric
+        /// positions from scoring dead equal.
+        pub tempo: i32,
+        /// Bonus for a rook on a file without pawns, in the middlegame and
+        /// the endgame (see [`game_phase`]).
+        pub rook_open_file: [i32; 2],
+        /// Bonus for a rook on a file with only enemy pawns, in the
+        /// middlegame and the endgame.
+        pub rook_semi_open_file: [i32; 2],
+        /// Bonus for a rook on the opponent's second rank, in the
+        /// middlegame and the endgame.
+        pub rook_seventh_rank: [i32; 2],
+        /// Bonus for attacking an enemy pawn, knight, bishop, rook or
+        /// queen with a piece of lower value.
+        pub threats: [i32; 5],
+        /// King piece-square table for the endgame, peaking in the centre,
+        /// indexed by square from a1 to h8 (mirrored for Black). Phased in
+        /// as the pieces come off, like the rest of [`king_activity`].
+        ///
+        /// [`king_activity`]: EvalParams::king_activity
+        pub king_endgame_squares: [i32; 64],
+        /// Endgame bonus per square the king is closer to a passed pawn of
+        /// either side than the far side of the board.
+        pub king_passer_proximity: i32,
+    }
+
+    impl EvalParams {
+        /// The parameters [`evaluate_board`] uses.
+        pub const DEFAULT: EvalParams = EvalParams {
+            piece_values: [100, 320, 330, 500, 900],
+            tempo: 8,
+            rook_open_file: [25, 10],
+            rook_semi_open_file: [10, 5],
+            rook_seventh_rank: [10, 20],
+            threats: [0, 15, 15, 20, 30],
+            king_endgame_squares: KING_ENDGAME_SQUARES,
+            king_passer_proximity: 5,
+        };
+
+        /// Value of `piece` in centipawns. The king has no material value.
+        pub const fn piece_value(&self, piece: Piece) -> i32 {
+            match piece {
+                Piece::Pawn => self.piece_values[0],
+                Piece::Knight => self.piece_values[1],
+                Piece::Bishop => self.piece_values[2],
+                Piece::Rook => self.piece_values[3],
+                Piece::Queen => self.piece_values[4],
+                Piece::King => 0,
+            }
+        }
+
+        /// Total material of `color` on `board`.
+        pub fn material(&self, board: &Board, color: Color) -> i32 {
+            WeightSet::PIECES
+                .iter()
+                .map(|&piece| {
+                    let pieces = board.pieces(piece) & board.color_combined(color);
+                    pieces.popcnt() as i32 * self.piece_value(piece)
+                })
+                .sum()
+        }
+
+        /// Rook placement bonuses of `color` on `board` at `phase`: open
+        /// and semi-open files, classified by the pawns on them, and the
+        /// opponent's second rank.
+        pub fn rook_placement(&self, board: &Board, color: Color, phase: i32) -> i32 {
+            let pawns = board.pieces(Piece::Pawn);
+            let own_pawns = pawns & board.color_combined(color);
+            let seventh = get_rank(match color {
+                Color::White => Rank::Seventh,
+                Color::Black => Rank::Second,
+            });
+            let mut bonus = 0;
+            for square in board.pieces(Piece::Rook) & board.color_combined(color) {
+                let file = get_file(square.get_file());
+                if file & pawns == EMPTY {
+                    bonus += taper(self.rook_open_file, phase);
+                } else if file & own_pawns == EMPTY {
+                    bonus += taper(self.rook_semi_open_file, phase);
+                }
+                if seventh & BitBoard::from_square(square) != EMPTY {
+                    bonus += taper(self.rook_seventh_rank, phase);
+                }
+            }
+            bonus
+        }
+
+        /// Threat bonuses of `color` on `board`: each enemy piece attacked
+        /// by a pawn, minor piece or rook of ours worth less than it counts
+        /// once. Knights and bishops don't threaten each other.
+        pub fn threats(&self, board: &Board, color: Color) -> i32 {
+            let ours = board.color_combined(color);
+            let enemy = board.color_combined(!color);
+            let blockers = *board.combined();
+            let attacks = |piece: Piece| {
+                (board.pieces(piece) & ours).fold(EMPTY, |attacked, square| {
+                    attacked
+                        | match piece {
+                            Piece::Pawn => get_pawn_attacks(square, color, !EMPTY),
+                            Piece::Knight => get_knight_moves(square),
+                            Piece::Bishop => get_bishop_moves(square, blockers),
+                            _ => get_rook_moves(square, blockers),
+                        }
+                })
+            };
+
+            // From the cheapest attackers up, so each victim is scored once
+            let rounds: [(&[Piece], &[Piece]); 3] = [
+                (&[Piece::Pawn], &[Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]),
+                (&[Piece::Knight, Piece::Bishop], &[Piece::Rook, Piece::Queen]),
+                (&[Piece::Rook], &[Piece::Queen]),
+            ];
+            let mut attacked = EMPTY;
+            let mut threatened = EMPTY;
+            let mut bonus = 0;
+            for (attackers, victims) in rounds {
+                for &piece in attackers {
+                    attacked |= attacks(piece);
+                }
+                for &victim in victims {
+                    let hit = attacked & board.pieces(victim) & enemy & !threatened;
+                    bonus += hit.popcnt() as i32 * self.threats[victim.to_index()];
+                    threatened |= hit;
+                }
+            }
+            bonus
+        }
+
+        /// King activity bonus of `color` on `board` at `phase`: its square
+        /// in the endgame table plus its proximity to passed pawns, to
+        /// escort its own and stop the opponent's. Zero with all pieces on
+        /// the board, in full with only kings and pawns.
+        pub fn king_activity(&self, board: &Board, color: Color, phase: i32) -> i32 {
+            let king = board.king_square(color);
+            let flip = match color {
+                Color::White => 0,
+                Color::Black => 56,
+            };
+            let centralization = self.king_endgame_squares[king.to_index() ^ flip];
+            let proximity: i32 = passed_pawns(board, Color::White)
+                .chain(passed_pawns(board, Color::Black))
+                .map(|pawn| 7 - square_distance(king, pawn))
+                .sum();
+            taper([0, centralization + proximity * self.king_passer_proximity], phase)
+        }
+
+        /// Material of one side in the starting position.
+        pub const fn starting_material(&self) -> i32 {
+            8 * self.piece_value(Piece::Pawn)
+                + 2 * self.piece_value(Piece::Knight)
+                + 2 * self.piece_value(Piece::Bishop)
+                + 2 * self.piece_value(Piece::Rook)
+                + self.piece_value(Piece::Queen)
+        }
+    }
+
+    impl Default for EvalParams {
+        fn default() -> Self {
+            EvalParams::DEFAULT
+        }
+    }
+
+    /// [`game_phase`] of the starting position.
+    pub const MAX_GAME_PHASE: i32 = 24;
+
+    /// How far from the endgame `board` is, from [`MAX_GAME_PHASE`] with
+    /// all pieces on the board down to 0 with only kings and pawns:
+    /// knights and bishops count 1, rooks 2 and queens 4.
+    pub fn game_phase(board: &Board) -> i32 {
+        let count = |piece| board.pieces(piece).popcnt() as i32;
+        let phase = count(Piece::Knight)
+            + count(Piece::Bishop)
+            + 2 * count(Piece::Rook)
+            + 4 * count(Piece::Queen);
+        phase.min(MAX_GAME_PHASE)
+    }
+
+    /// Pawns of `color` with no enemy pawn ahead of them on their own or
+    /// an adjacent file.
+    fn passed_pawns(board: &Board, color: Color) -> impl Iterator<Item = Square> + '_ {
+        let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+        let ahead = move |pawn: Square, enemy: Square| match color {
+            Color::White => enemy.get_rank() > pawn.get_rank(),
+            Color::Black => enemy.get_rank() < pawn.get_rank(),
+        };
+        (board.pieces(Piece::Pawn) & board.color_combined(color)).filter(move |&pawn| {
+            !enemy_pawns.into_iter().any(|enemy| {
+                let files_apart = enemy.get_file().to_index().abs_diff(pawn.get_file().to_index());
+                files_apart <= 1 && ahead(pawn, enemy)
+            })
+        })
+    }
+
+    /// King moves between two squares on an empty board.
+    fn square_distance(a: Square, b: Square) -> i32 {
+        let ranks = (a.get_rank().to_index() as i32 - b.get_rank().to_index() as i32).abs();
+        let files = (a.get_file().to_index() as i32 - b.get_file().to_index() as i32).abs();
+        ranks.max(files)
+    }
+
+    /// Interpolate a (middlegame, endgame) bonus at `phase`.
+    fn taper([middlegame, endgame]: [i32; 2], phase: i32) -> i32 {
+        (middlegame * phase + endgame * (MAX_GAME_PHASE - phase)) / MAX_GAME_PHASE
+    }
+
+    /// White's material minus Black's under [`EvalParams::DEFAULT`], in
+    /// centipawns.
+    pub fn material_balance(board: &Board) -> i32 {
+        EvalParams::DEFAULT.material(board, Color::White)
+            - EvalParams::DEFAULT.material(board, Color::Black)
+    }
+
+    /// Bonus of `color` for attacking enemy pieces with cheaper ones,
+    /// under [`EvalParams::DEFAULT`], in centipawns.
+    pub fn threat_bonus(board: &Board, color: Color) -> i32 {
+        EvalParams::DEFAULT.threats(board, color)
+    }
+
+    /// Centipawn value of a pawn under [`EvalParams::DEFAULT`].
+    pub const PAWN_VALUE: i32 = EvalParams::DEFAULT.piece_value(Piece::Pawn);
+
+    /// Nominal value of the king, above all other material combined, for
+    /// exchange calculations where losing it must always score worst.
+    pub const KING_VALUE: i32 = 20_000;
+
+    /// Centipawn value of every piece under [`EvalParams::DEFAULT`],
+    /// indexed by `Piece` discriminant (pawn first), with the king at
+    /// [`KING_VALUE`].
+    pub const fn piece_values() -> [i32; 6] {
+        let [pawn, knight, bishop, rook, queen] = EvalParams::DEFAULT.piece_values;
+        [pawn, knight, bishop, rook, queen, KING_VALUE]
+    }
+
+    /// Value of `piece` from [`piece_values`]. Unlike
+    /// [`EvalParams::piece_value`], the king counts as [`KING_VALUE`].
+    pub const fn piece_value(piece: Piece) -> i32 {
+        piece_values()[piece as usize]
+    }
 
     /// Evaluate the board as seen from the perspective of the player who's side
     /// it is to move.
@@ -9,100 +261,792 @@ pub mod simple {
     /// See https://www.chessprogramming.org/Simplified_Evaluation_Function#Piece_Values
     ///
     pub fn evaluate_board(board: &Board) -> i32 {
+        evaluate_board_with_weights(board, &DEFAULT_WEIGHTS)
+    }
+
+    /// Like [`evaluate_board`], but with every weight taken from
+    /// `weights`.
+    ///
+    pub fn evaluate_board_with_weights(board: &Board, weights: &WeightSet) -> i32 {
+        if super::is_insufficient_material(board) {
+            return 0;
+        }
+        if board.combined().popcnt() < 8 {
+            if let Some(score) = super::endgame_tables::evaluate_endgame(board) {
+                return score;
+            }
+        }
+
         let side: i32 = match board.side_to_move() {
             Color::White => 1,
             Color::Black => -1,
         };
-        let black_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::Black);
-        let white_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::White);
-        let black_knights = board.pieces(Piece::Knight) & board.color_combined(Color::Black);
-        let white_knights = board.pieces(Piece::Knight) & board.color_combined(Color::White);
-        let black_bishops = board.pieces(Piece::Bishop) & board.color_combined(Color::Black);
-        let white_bishops = board.pieces(Piece::Bishop) & board.color_combined(Color::White);
-        let black_rooks = board.pieces(Piece::Rook) & board.color_combined(Color::Black);
-        let white_rooks = board.pieces(Piece::Rook) & board.color_combined(Color::White);
-        let black_queens = board.pieces(Piece::Queen) & board.color_combined(Color::Black);
-        let white_queens = board.pieces(Piece::Queen) & board.color_combined(Color::White);
-
-        let mut positional_value = 0;
-        positional_value -= positional_evaluation(black_pawns, BLACK_PAWN_SQUARES);
-        positional_value += positional_evaluation(white_pawns, WHITE_PAWN_SQUARES);
-        positional_value -= positional_evaluation(black_knights, BLACK_KNIGHT_SQUARES);
-        positional_value += positional_evaluation(white_knights, WHITE_KNIGHT_SQUARES);
-        positional_value -= positional_evaluation(black_bishops, BLACK_BISHOP_SQUARES);
-        positional_value += positional_evaluation(white_bishops, WHITE_BISHOP_SQUARES);
-        positional_value -= positional_evaluation(black_rooks, BLACK_ROOK_SQUARES);
-        positional_value += positional_evaluation(white_rooks, WHITE_ROOK_SQUARES);
-
-        return ((white_pawns.popcnt() as i32 - black_pawns.popcnt() as i32) * 100
-            + (white_knights.popcnt() as i32 - black_knights.popcnt() as i32) * 320
-            + (white_bishops.popcnt() as i32 - black_bishops.popcnt() as i32) * 330
-            + (white_rooks.popcnt() as i32 - black_rooks.popcnt() as i32) * 500
-            + (white_queens.popcnt() as i32 - black_queens.popcnt() as i32) * 900
-            + positional_value)
-            * side;
+
+        let mut value = 0;
+        for (index, piece) in WeightSet::PIECES.iter().enumerate() {
+            let black = board.pieces(*piece) & board.color_combined(Color::Black);
+            let white = board.pieces(*piece) & board.color_combined(Color::White);
+            value += (white.popcnt() as i32 - black.popcnt() as i32) * weights.material[index];
+            if let Some(table) = weights.squares.get(index) {
+                value += positional_evaluation(white, table, 0);
+                value -= positional_evaluation(black, table, 56);
+            }
+        }
+        let params = weights.eval_params();
+        let phase = game_phase(board);
+        value += params.rook_placement(board, Color::White, phase);
+        value -= params.rook_placement(board, Color::Black, phase);
+        value += params.threats(board, Color::White);
+        value -= params.threats(board, Color::Black);
+        value += params.king_activity(board, Color::White, phase);
+        value -= params.king_activity(board, Color::Black, phase);
+        value += weights.tempo * side;
+        value * side
+    }
+
+    /// The position with the colours swapped: ranks mirrored, every piece
+    /// changing sides, castling rights and en passant carried over, and
+    /// the other side to move.
+    ///
+    /// Since [`evaluate_board`] scores from the side to move's
+    /// perspective, a correct evaluation gives both boards the same score;
+    /// a term applied to only one side shows up as a difference.
+    ///
+    pub fn mirror_board(board: &Board) -> Board {
+        let original = BoardBuilder::from(board);
+        let mut mirrored = BoardBuilder::new();
+        mirrored
+            .side_to_move(!original.get_side_to_move())
+            .castle_rights(Color::White, original.get_castle_rights(Color::Black))
+            .castle_rights(Color::Black, original.get_castle_rights(Color::White))
+            .en_passant(original.get_en_passant().map(|square| square.get_file()));
+        for square in ALL_SQUARES {
+            let flipped = Square::make_square(
+                Rank::from_index(7 - square.get_rank().to_index()),
+                square.get_file(),
+            );
+            mirrored[flipped] = original[square].map(|(piece, color)| (piece, !color));
+        }
+        Board::try_from(mirrored).expect("mirroring a legal position keeps it legal")
+    }
+
+    /// Tunable evaluation parameters: material values, White's
+    /// piece-square tables (Black uses the same tables mirrored), and the
+    /// other [`EvalParams`] terms.
+    ///
+    /// Flattened to and from `f64` parameter vectors for SPSA or Texel
+    /// tuning against a database of positions.
+    ///
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct WeightSet {
+        /// Pawn, knight, bishop, rook and queen values in centipawns,
+        /// initially [`EvalParams::DEFAULT`]'s.
+        pub material: [i32; 5],
+        /// Pawn, knight, bishop and rook piece-square tables, indexed by
+        /// square from a1 to h8.
+        pub squares: [[i32; 64]; 4],
+        /// Bonus for the side to move, initially [`EvalParams::DEFAULT`]'s.
+        pub tempo: i32,
+        /// Rook on an open file, initially [`EvalParams::DEFAULT`]'s.
+        pub rook_open_file: [i32; 2],
+        /// Rook on a semi-open file, initially [`EvalParams::DEFAULT`]'s.
+        pub rook_semi_open_file: [i32; 2],
+        /// Rook on the seventh rank, initially [`EvalParams::DEFAULT`]'s.
+        pub rook_seventh_rank: [i32; 2],
+        /// Threat bonus per victim, initially [`EvalParams::DEFAULT`]'s.
+        pub threats: [i32; 5],
+        /// King endgame piece-square table, indexed like `squares`.
+        pub king_endgame_squares: [i32; 64],
+        /// King proximity to passed pawns, initially
+        /// [`EvalParams::DEFAULT`]'s.
+        pub king_passer_proximity: i32,
+    }
+
+    impl WeightSet {
+        /// Pieces in the order of `material` and `squares`.
+        pub const PIECES: [Piece; 5] = [
+            Piece::Pawn,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+        ];
+
+        /// Length of the SPSA parameter vector.
+        pub const NUM_PARAMS: usize = 5 + 4 * 64 + 1 + 3 * 2 + 5 + 64 + 1;
+
+        /// Build a weight set from an SPSA parameter vector, rounding each
+        /// parameter to the nearest centipawn.
+        ///
+        /// # Panics
+        ///
+        /// If `params` doesn't hold exactly [`WeightSet::NUM_PARAMS`] values.
+        ///
+        pub fn from_spsa_params(params: &[f64]) -> WeightSet {
+            assert_eq!(
+                params.len(),
+                Self::NUM_PARAMS,
+                "expected {} SPSA parameters",
+                Self::NUM_PARAMS
+            );
+            let mut weights = DEFAULT_WEIGHTS;
+            for (weight, param) in weights.weights_mut().zip(params) {
+                *weight = param.round() as i32;
+            }
+            weights
+        }
+
+        /// Flatten the weights into an SPSA parameter vector: material
+        /// values first, then each piece-square table in turn, the tempo
+        /// bonus, and the remaining terms in field order.
+        ///
+        pub fn to_spsa_params(&self) -> Vec<f64> {
+            let mut weights = self.clone();
+            weights.weights_mut().map(|&mut weight| weight as f64).collect()
+        }
+
+        /// The evaluation terms these weights set.
+        pub fn eval_params(&self) -> EvalParams {
+            EvalParams {
+                piece_values: self.material,
+                tempo: self.tempo,
+                rook_open_file: self.rook_open_file,
+                rook_semi_open_file: self.rook_semi_open_file,
+                rook_seventh_rank: self.rook_seventh_rank,
+                threats: self.threats,
+                king_endgame_squares: self.king_endgame_squares,
+                king_passer_proximity: self.king_passer_proximity,
+            }
+        }
+
+        /// Every weight, in SPSA parameter order.
+        fn weights_mut(&mut self) -> impl Iterator<Item = &mut i32> {
+            self.material
+                .iter_mut()
+                .chain(self.squares.iter_mut().flatten())
+                .chain(std::iter::once(&mut self.tempo))
+                .chain(&mut self.rook_open_file)
+                .chain(&mut self.rook_semi_open_file)
+                .chain(&mut self.rook_seventh_rank)
+                .chain(&mut self.threats)
+                .chain(&mut self.king_endgame_squares)
+                .chain(std::iter::once(&mut self.king_passer_proximity))
+        }
+    }
+
+    impl Default for WeightSet {
+        fn default() -> Self {
+            DEFAULT_WEIGHTS
+        }
     }
 
     /// Evaluate piece positions as spesified in a Piece-Square table.
+    /// `flip` is XORed into each square index; 56 mirrors the board
+    /// vertically for Black.
     ///
     /// See https://www.chessprogramming.org/Simplified_Evaluation_Function#Piece-Square_Tables
     ///
     #[inline]
-    fn positional_evaluation(pieces: BitBoard, piece_square_table: [i32; 64]) -> i32 {
+    fn positional_evaluation(pieces: BitBoard, piece_square_table: &[i32; 64], flip: usize) -> i32 {
         let mut sum = 0;
         for square in pieces {
-            unsafe {
-                sum += piece_square_table.get_unchecked(square.to_index());
-            }
+            sum += piece_square_table[square.to_index() ^ flip];
         }
-        return sum;
+        sum
     }
 
-    const BLACK_PAWN_SQUARES: [i32; 64] = [
-        0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5,
-        5, 10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10,
-        -20, -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
-    ];
+    /// The weights [`evaluate_board`] uses.
+    ///
+    pub const DEFAULT_WEIGHTS: WeightSet = WeightSet {
+        material: EvalParams::DEFAULT.piece_values,
+        squares: [
+            PAWN_SQUARES,
+            KNIGHT_SQUARES,
+            BISHOP_SQUARES,
+            ROOK_SQUARES,
+        ],
+        tempo: EvalParams::DEFAULT.tempo,
+        rook_open_file: EvalParams::DEFAULT.rook_open_file,
+        rook_semi_open_file: EvalParams::DEFAULT.rook_semi_open_file,
+        rook_seventh_rank: EvalParams::DEFAULT.rook_seventh_rank,
+        threats: EvalParams::DEFAULT.threats,
+        king_endgame_squares: EvalParams::DEFAULT.king_endgame_squares,
+        king_passer_proximity: EvalParams::DEFAULT.king_passer_proximity,
+    };
 
-    const WHITE_PAWN_SQUARES: [i32; 64] = [
+    const PAWN_SQUARES: [i32; 64] = [
         0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, -20, -20, 10, 10, 5, 5, -5, -10, 0, 0, -10, -5, 5, 0, 0,
         0, 20, 20, 0, 0, 0, 5, 5, 10, 25, 25, 10, 5, 5, 10, 10, 20, 30, 30, 20, 10, 10, 50, 50, 50,
         50, 50, 50, 50, 50, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
 
-    const BLACK_KNIGHT_SQUARES: [i32; 64] = [
-        -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15,
-        10, 0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15,
-        15, 10, 5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
-    ];
-
-    const WHITE_KNIGHT_SQUARES: [i32; 64] = [
+    const KNIGHT_SQUARES: [i32; 64] = [
         -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 5, 5, 0, -20, -40, -30, 5, 10, 15, 15,
         10, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 10, 15,
         15, 10, 0, -30, -40, -20, 0, 0, 0, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
     ];
 
-    const BLACK_BISHOP_SQUARES: [i32; 64] = [
-        -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5,
-        0, -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 10, 10, 10, 10,
-        10, 10, -10, -10, 5, 0, 0, 0, 0, 5, -10, -20, -10, -10, -10, -10, -10, -10, -20,
-    ];
-
-    const WHITE_BISHOP_SQUARES: [i32; 64] = [
+    const BISHOP_SQUARES: [i32; 64] = [
         -20, -10, -10, -10, -10, -10, -10, -20, -10, 5, 0, 0, 0, 0, 5, -10, -10, 10, 10, 10, 10,
         10, 10, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 5, 10,
         10, 5, 0, -10, -10, 0, 0, 0, 0, 0, 0, -10, -20, -10, -10, -10, -10, -10, -10, -20,
     ];
 
-    const BLACK_ROOK_SQUARES: [i32; 64] = [
-        0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, 10, 10, 10, 10, 5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0,
-        0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0,
-        -5, 0, 0, 0, 5, 5, 0, 0, 0,
-    ];
-
-    const WHITE_ROOK_SQUARES: [i32; 64] = [
+    const ROOK_SQUARES: [i32; 64] = [
         0, 0, 0, 5, 5, 0, 0, 0, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0,
         0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, 5, 10, 10, 10, 10, 10, 10, 5,
         0, 0, 0, 0, 0, 0, 0, 0,
     ];
+
+    const KING_ENDGAME_SQUARES: [i32; 64] = [
+        -50, -30, -30, -30, -30, -30, -30, -50, -30, -30, 0, 0, 0, 0, -30, -30, -30, -10, 20, 30, 30,
+        20, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30,
+        -10, 20, 30, 30, 20, -10, -30, -30, -20, -10, 0, 0, -10, -20, -30, -50, -40, -30, -20, -20,
+        -30, -40, -50,
+    ];
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::str::FromStr;
+
+        const POSITIONS: [&str; 4] = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3",
+            "6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 30",
+        ];
+
+        /// Largest allowed difference between a position's score and its
+        /// mirror's. The evaluation is integer arithmetic over mirrored
+        /// tables, so it should be exact.
+        const SYMMETRY_TOLERANCE: i32 = 0;
+
+        /// Assert that `fen` and its colour-swapped mirror evaluate the
+        /// same from White's perspective, up to sign.
+        fn assert_eval_symmetric(fen: &str) {
+            let white_relative = |board: &Board| match board.side_to_move() {
+                Color::White => evaluate_board(board),
+                Color::Black => -evaluate_board(board),
+            };
+            let board = Board::from_str(fen).unwrap();
+            let mirrored = mirror_board(&board);
+            let (score, mirrored_score) = (white_relative(&board), white_relative(&mirrored));
+            assert!(
+                (score + mirrored_score).abs() <= SYMMETRY_TOLERANCE,
+                "{} scores {} but its mirror {} scores {}",
+                fen,
+                score,
+                mirrored,
+                mirrored_score
+            );
+        }
+
+        #[test]
+        fn test_eval_is_colour_symmetric() {
+            let suite = [
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+                // Specialized endgames and draws
+                "8/8/k7/4P3/8/8/8/4K3 w - - 0 1",
+                "k7/8/8/8/3K4/8/8/7R b - - 0 1",
+                "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            ];
+            for fen in POSITIONS.iter().chain(&suite) {
+                assert_eval_symmetric(fen);
+                let board = Board::from_str(fen).unwrap();
+                assert_eq!(mirror_board(&mirror_board(&board)), board, "{}", fen);
+            }
+        }
+
+        #[test]
+        fn test_default_weights_match_evaluate_board() {
+            let weights = WeightSet::default();
+            for fen in POSITIONS {
+                let board = Board::from_str(fen).unwrap();
+                assert_eq!(evaluate_board_with_weights(&board, &weights), evaluate_board(&board));
+            }
+            // One rook up for White, on an open file, from Black's perspective
+            let board = Board::from_str(POSITIONS[3]).unwrap();
+            let open_file = EvalParams::DEFAULT.rook_placement(&board, Color::White, game_phase(&board));
+            assert!(open_file > 0);
+            assert_eq!(evaluate_board(&board), -500 - 5 - open_file + EvalParams::DEFAULT.tempo);
+        }
+
+        #[test]
+        fn test_symmetric_position_scores_tempo() {
+            let tempo = EvalParams::DEFAULT.tempo;
+            assert!((1..10).contains(&tempo));
+            assert_eq!(evaluate_board(&Board::default()), tempo);
+            let symmetric =
+                Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4")
+                    .unwrap();
+            assert_eq!(evaluate_board(&symmetric), tempo);
+
+            let weights = WeightSet {
+                tempo: 0,
+                ..WeightSet::default()
+            };
+            assert_eq!(evaluate_board_with_weights(&Board::default(), &weights), 0);
+        }
+
+        #[test]
+        fn test_starting_material_from_eval_params() {
+            let params = EvalParams::DEFAULT;
+            let expected = 8 * 100 + 2 * 320 + 2 * 330 + 2 * 500 + 900;
+            assert_eq!(params.starting_material(), expected);
+            let board = Board::default();
+            assert_eq!(params.material(&board, Color::White), expected);
+            assert_eq!(params.material(&board, Color::Black), expected);
+            assert_eq!(params.piece_value(Piece::Queen), 900);
+            assert_eq!(params.piece_value(Piece::King), 0);
+            assert_eq!(DEFAULT_WEIGHTS.material, params.piece_values);
+        }
+
+        #[test]
+        fn test_piece_values_indexed_by_piece() {
+            assert_eq!(piece_value(Piece::Queen), 900);
+            assert_eq!(piece_values(), [100, 320, 330, 500, 900, KING_VALUE]);
+            for piece in WeightSet::PIECES {
+                assert_eq!(piece_values()[piece.to_index()], EvalParams::DEFAULT.piece_value(piece));
+            }
+            assert_eq!(piece_value(Piece::King), 20_000);
+        }
+
+        #[test]
+        fn test_rook_placement_bonuses() {
+            let params = EvalParams::DEFAULT;
+            // Same pawns, rook on the open d-file or behind its own e-pawn;
+            // d1 and e1 score the same in the rook table
+            let open = Board::from_str("6k1/pp3ppp/8/8/8/8/PP2PPPP/3R2K1 w - - 0 1").unwrap();
+            let closed = Board::from_str("6k1/pp3ppp/8/8/8/8/PP2PPPP/4R1K1 w - - 0 1").unwrap();
+            assert_eq!(game_phase(&open), 2);
+            assert_eq!(params.rook_placement(&closed, Color::White, 2), 0);
+            let expected = (25 * 2 + 10 * (MAX_GAME_PHASE - 2)) / MAX_GAME_PHASE;
+            assert_eq!(evaluate_board(&open) - evaluate_board(&closed), expected);
+
+            // Black rooks on the open a-file and White's second rank, and on
+            // the e-file with only a White pawn on it
+            let board = Board::from_str("4r1k1/8/8/8/8/4P3/r4PPP/6K1 b - - 0 1").unwrap();
+            let phase = game_phase(&board);
+            assert_eq!(phase, 4);
+            assert_eq!(
+                params.rook_placement(&board, Color::Black, phase),
+                taper(params.rook_open_file, phase)
+                    + taper(params.rook_seventh_rank, phase)
+                    + taper(params.rook_semi_open_file, phase)
+            );
+            assert_eq!(game_phase(&Board::default()), MAX_GAME_PHASE);
+        }
+
+        #[test]
+        fn test_threat_bonus() {
+            // White bishop on b5 attacks the black queen on e8
+            let board = Board::from_str("4q1k1/8/8/1B6/8/8/8/6K1 w - - 0 1").unwrap();
+            assert_eq!(threat_bonus(&board, Color::White), 30);
+            assert_eq!(threat_bonus(&board, Color::Black), 0);
+
+            // The queen attacked by both the pawn and the knight counts
+            // once; the rook on h8 only by the bishop
+            let board = Board::from_str("3q2kr/4P3/2N5/8/8/8/1B6/6K1 w - - 0 1").unwrap();
+            assert_eq!(threat_bonus(&board, Color::White), 30 + 20);
+
+            // Knights and bishops don't threaten each other, nor do pieces
+            // of equal value
+            let board = Board::from_str("6k1/3b4/8/4N3/8/8/8/r3R1K1 w - - 0 1").unwrap();
+            assert_eq!(threat_bonus(&board, Color::White), 0);
+            assert_eq!(threat_bonus(&board, Color::Black), 0);
+        }
+
+        #[test]
+        fn test_king_activity_in_endgame() {
+            // Kings and blocked pawns only: the centralized king is better
+            let central = Board::from_str("6k1/5pp1/8/8/4K3/8/5PP1/8 w - - 0 1").unwrap();
+            let cornered = Board::from_str("6k1/5pp1/8/8/8/8/5PP1/K7 w - - 0 1").unwrap();
+            assert_eq!(game_phase(&central), 0);
+            assert!(evaluate_board(&central) > evaluate_board(&cornered));
+            // e4 against a1 in the endgame table
+            assert_eq!(evaluate_board(&central) - evaluate_board(&cornered), 40 + 50);
+
+            // d4 and e4 score the same in the table, but d4 is a square
+            // closer to the passed b-pawn
+            let params = EvalParams::DEFAULT;
+            let near = Board::from_str("6k1/6p1/8/1P6/3K4/8/6P1/8 w - - 0 1").unwrap();
+            let far = Board::from_str("6k1/6p1/8/1P6/4K3/8/6P1/8 w - - 0 1").unwrap();
+            assert_eq!(
+                params.king_activity(&near, Color::White, 0) - params.king_activity(&far, Color::White, 0),
+                params.king_passer_proximity
+            );
+
+            // Nothing with all pieces on the board
+            assert_eq!(params.king_activity(&Board::default(), Color::White, MAX_GAME_PHASE), 0);
+        }
+
+        #[test]
+        fn test_spsa_params_round_trip() {
+            let params = DEFAULT_WEIGHTS.to_spsa_params();
+            assert_eq!(params.len(), WeightSet::NUM_PARAMS);
+            assert_eq!(WeightSet::from_spsa_params(&params), DEFAULT_WEIGHTS);
+
+            let mut params = params;
+            params[3] = 449.6;
+            let weights = WeightSet::from_spsa_params(&params);
+            assert_eq!(weights.material[3], 450);
+            let board = Board::from_str(POSITIONS[3]).unwrap();
+            let open_file = EvalParams::DEFAULT.rook_placement(&board, Color::White, game_phase(&board));
+            assert_eq!(
+                evaluate_board_with_weights(&board, &weights),
+                -450 - 5 - open_file + weights.tempo
+            );
+
+            // The rook terms are tunable too: no open file bonus at all
+            let mut params = DEFAULT_WEIGHTS.to_spsa_params();
+            let open_file_at = 5 + 4 * 64 + 1;
+            params[open_file_at..open_file_at + 2].fill(0.0);
+            let weights = WeightSet::from_spsa_params(&params);
+            assert_eq!(weights.rook_open_file, [0, 0]);
+            assert_eq!(weights.to_spsa_params(), params);
+            assert_eq!(
+                evaluate_board_with_weights(&board, &weights),
+                -500 - 5 + weights.tempo
+            );
+            assert_eq!(DEFAULT_WEIGHTS.eval_params(), EvalParams::DEFAULT);
+        }
+    }
+}
+
+/// Specialized evaluation of basic endgames where the piece-square tables
+/// don't know how to make progress.
+///
+/// See https://www.chessprogramming.org/Mop-up_Evaluation and
+/// https://www.chessprogramming.org/King_Pawn_versus_King
+///
+pub mod endgame_tables {
+    use super::simple::EvalParams;
+    use chess::{BitBoard, Board, Color, Piece, Square};
+
+    /// Evaluate the board with a specialized routine if it is one of the
+    /// supported endgames (KP vs K, KR vs K or KQ vs K), as seen from the
+    /// perspective of the side to move. Returns `None` for other material.
+    ///
+    pub fn evaluate_endgame(board: &Board) -> Option<i32> {
+        let (strong, piece) = lone_piece(board)?;
+        let strong_king = board.king_square(strong);
+        let weak_king = board.king_square(!strong);
+
+        let score = match piece {
+            Piece::Queen | Piece::Rook => {
+                mop_up(strong_king, weak_king, EvalParams::DEFAULT.piece_value(piece))
+            }
+            Piece::Pawn => {
+                let pawn = (board.pieces(Piece::Pawn) & board.color_combined(strong)).to_square();
+                king_and_pawn(strong, pawn, strong_king, weak_king, board.side_to_move() == strong)
+            }
+            _ => return None,
+        };
+
+        match board.side_to_move() == strong {
+            true => Some(score),
+            false => Some(-score),
+        }
+    }
+
+    /// If one side has only its king and the other side has exactly one
+    /// extra piece, return the stronger side and its piece.
+    ///
+    fn lone_piece(board: &Board) -> Option<(Color, Piece)> {
+        if board.combined().popcnt() != 3 {
+            return None;
+        }
+        let kings = board.pieces(Piece::King);
+        let strong = match (board.color_combined(Color::White) & !kings).popcnt() {
+            1 => Color::White,
+            _ => Color::Black,
+        };
+        let extra: BitBoard = board.color_combined(strong) & !kings;
+        board.piece_on(extra.to_square()).map(|piece| (strong, piece))
+    }
+
+    /// Score a lone king against king and a major piece, rewarding driving
+    /// the lone king to the edge and bringing the attacking king closer.
+    ///
+    fn mop_up(strong_king: Square, weak_king: Square, material: i32) -> i32 {
+        let edge_bonus = 10 * center_distance(weak_king);
+        let king_bonus = 4 * (14 - manhattan_distance(strong_king, weak_king));
+        material + 200 + edge_bonus + king_bonus
+    }
+
+    /// Score a king and pawn against a lone king from the stronger side's
+    /// perspective, using the rule of the square, key squares and the
+    /// opposition to tell won positions from drawn ones.
+    ///
+    fn king_and_pawn(
+        strong: Color,
+        pawn: Square,
+        strong_king: Square,
+        weak_king: Square,
+        strong_to_move: bool,
+    ) -> i32 {
+        // Work from White's point of view by mirroring Black's pieces
+        let (pawn, strong_king, weak_king) = match strong {
+            Color::White => (coords(pawn), coords(strong_king), coords(weak_king)),
+            Color::Black => (
+                mirror(coords(pawn)),
+                mirror(coords(strong_king)),
+                mirror(coords(weak_king)),
+            ),
+        };
+        let (pawn_file, pawn_rank) = pawn;
+        let promotion = (pawn_file, 7);
+        let advancement = 10 * pawn_rank;
+
+        // Rule of the square: the lone king can't catch the pawn
+        let pawn_moves = (7 - pawn_rank).min(5);
+        let defender_moves = chebyshev(weak_king, promotion) - if strong_to_move { 0 } else { 1 };
+        if defender_moves > pawn_moves {
+            return 700 + advancement;
+        }
+
+        // A rook pawn is a draw once the defending king reaches the corner
+        let is_rook_pawn = pawn_file == 0 || pawn_file == 7;
+        if is_rook_pawn {
+            return match chebyshev(weak_king, promotion) <= 1 {
+                true => 10,
+                false => 100 + advancement,
+            };
+        }
+
+        let on_key_square = key_squares(pawn).contains(&strong_king);
+        let in_front = strong_king.0 == pawn_file && strong_king.1 > pawn_rank;
+        let has_opposition = strong_king.0 == weak_king.0
+            && weak_king.1 == strong_king.1 + 2
+            && !strong_to_move;
+
+        match on_key_square || (in_front && has_opposition) {
+            true => 600 + advancement,
+            false => 50 + advancement / 2,
+        }
+    }
+
+    /// Key squares of a (White) pawn: the squares the attacking king must
+    /// reach to force promotion.
+    ///
+    fn key_squares(pawn: (i32, i32)) -> Vec<(i32, i32)> {
+        let (file, rank) = pawn;
+        let ranks = match rank {
+            1..=3 => vec![rank + 2],
+            4 | 5 => vec![rank + 1, rank + 2],
+            _ => vec![rank + 1],
+        };
+        let mut squares = Vec::new();
+        for r in ranks.into_iter().filter(|r| *r <= 7) {
+            for f in (file - 1).max(0)..=(file + 1).min(7) {
+                squares.push((f, r));
+            }
+        }
+        squares
+    }
+
+    #[inline]
+    fn coords(square: Square) -> (i32, i32) {
+        (
+            square.get_file().to_index() as i32,
+            square.get_rank().to_index() as i32,
+        )
+    }
+
+    #[inline]
+    fn mirror((file, rank): (i32, i32)) -> (i32, i32) {
+        (file, 7 - rank)
+    }
+
+    #[inline]
+    fn chebyshev(a: (i32, i32), b: (i32, i32)) -> i32 {
+        (a.0 - b.0).abs().max((a.1 - b.1).abs())
+    }
+
+    #[inline]
+    fn manhattan_distance(a: Square, b: Square) -> i32 {
+        let (a, b) = (coords(a), coords(b));
+        (a.0 - b.0).abs() + (a.1 - b.1).abs()
+    }
+
+    /// Chebyshev distance from the square to the four center squares.
+    ///
+    #[inline]
+    fn center_distance(square: Square) -> i32 {
+        let (file, rank) = coords(square);
+        let file_distance = (3 - file).max(file - 4).max(0);
+        let rank_distance = (3 - rank).max(rank - 4).max(0);
+        file_distance.max(rank_distance)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::str::FromStr;
+
+        fn eval(fen: &str) -> i32 {
+            evaluate_endgame(&Board::from_str(fen).unwrap()).unwrap()
+        }
+
+        #[test]
+        fn test_unsupported_material() {
+            assert_eq!(evaluate_endgame(&Board::default()), None);
+            let two_pawns = Board::from_str("4k3/8/8/8/8/8/PP6/4K3 w - - 0 1").unwrap();
+            assert_eq!(evaluate_endgame(&two_pawns), None);
+        }
+
+        #[test]
+        fn test_kr_vs_k_prefers_cornered_king() {
+            let corner = eval("k7/8/8/8/3K4/8/8/7R w - - 0 1");
+            let center = eval("8/8/8/3k4/8/8/8/K6R w - - 0 1");
+            assert!(corner > center, "corner {} <= center {}", corner, center);
+            assert!(center > 500);
+        }
+
+        #[test]
+        fn test_kq_vs_k_is_side_to_move_relative() {
+            let white = eval("k7/8/8/8/3K4/8/7Q/8 w - - 0 1");
+            let black = eval("k7/8/8/8/3K4/8/7Q/8 b - - 0 1");
+            assert!(white > 900);
+            assert_eq!(white, -black);
+        }
+
+        #[test]
+        fn test_kp_vs_k_advancement_and_key_squares() {
+            // Unstoppable passed pawn
+            let runaway = eval("8/8/k7/4P3/8/8/8/4K3 w - - 0 1");
+            // Defender in front of the pawn, attacker far behind
+            let blocked = eval("4k3/8/8/8/8/4P3/8/4K3 w - - 0 1");
+            assert!(runaway > blocked, "runaway {} <= blocked {}", runaway, blocked);
+
+            // Attacking king on a key square wins, even with the defender close
+            let key_square = -eval("8/3k4/8/3K4/8/4P3/8/8 b - - 0 1");
+            let behind = -eval("8/3k4/8/8/8/4P3/4K3/8 b - - 0 1");
+            assert!(key_square > behind, "key square {} <= behind {}", key_square, behind);
+            assert!(behind > 0);
+
+            // The same evaluation for Black's pawn, mirrored
+            assert_eq!(
+                eval("4k3/8/8/8/8/4P3/8/4K3 w - - 0 1"),
+                eval("4k3/8/4p3/8/8/8/8/4K3 b - - 0 1")
+            );
+        }
+    }
+}
+
+/// Scoring of drawn positions.
+///
+/// See https://www.chessprogramming.org/Draw_Evaluation and
+/// https://www.chessprogramming.org/Contempt_Factor
+///
+pub mod draw {
+    use chess::{BitBoard, Board, Color, Piece};
+
+    /// How many centipawns the side to move gives up by accepting a draw,
+    /// so the engine keeps playing equal positions instead of steering
+    /// into a draw.
+    pub const CONTEMPT: i32 = 10;
+
+    /// Light squares of the board, for telling bishops apart by colour.
+    const LIGHT_SQUARES: BitBoard = BitBoard(0x55AA_55AA_55AA_55AA);
+
+    /// Score of a drawn position `ply` plies from the root, as seen from
+    /// the side to move there. The contempt is the root side's: a draw is
+    /// worth slightly less than zero to it, and as much more to its
+    /// opponent, so the search steers away from draws in equal positions
+    /// whichever side could bring them about. Every two plies further from
+    /// the root a draw is worth one centipawn less to the root side, so
+    /// when losing it prefers the shorter draw.
+    ///
+    pub fn draw_score(_board: &Board, ply: u8) -> i32 {
+        let root_score = -CONTEMPT - (ply / 2) as i32;
+        match ply % 2 {
+            0 => root_score,
+            _ => -root_score,
+        }
+    }
+
+    /// Whether neither side has enough material left to checkmate: K vs K,
+    /// K+B vs K, K+N vs K, or K+B vs K+B with bishops on the same colour.
+    ///
+    pub fn is_insufficient_material(board: &Board) -> bool {
+        let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+        if heavy.popcnt() > 0 {
+            return false;
+        }
+        let knights = board.pieces(Piece::Knight);
+        let bishops = board.pieces(Piece::Bishop);
+        match (knights.popcnt(), bishops.popcnt()) {
+            (0, 0) | (1, 0) | (0, 1) => true,
+            (0, 2) => {
+                let white = bishops & board.color_combined(Color::White);
+                let same_colour = (bishops & LIGHT_SQUARES).popcnt() != 1;
+                white.popcnt() == 1 && same_colour
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::engine::evaluation::simple::{evaluate_board, material_balance};
+        use std::str::FromStr;
+
+        #[test]
+        fn test_insufficient_material_scores_as_draw() {
+            let drawn = [
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+                "4k3/8/8/8/8/8/8/2b1K3 w - - 0 1",
+                // Both bishops on dark squares
+                "3bk3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            ];
+            for fen in drawn {
+                let board = Board::from_str(fen).unwrap();
+                assert!(is_insufficient_material(&board), "{}", fen);
+                assert_eq!(evaluate_board(&board), 0, "{}", fen);
+            }
+            let bishop_up = Board::from_str(drawn[1]).unwrap();
+            assert_ne!(evaluate_board(&bishop_up), material_balance(&bishop_up));
+        }
+
+        #[test]
+        fn test_mating_material_is_not_a_draw() {
+            let not_drawn = [
+                // Opposite-coloured bishops
+                "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1",
+                "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            ];
+            for fen in not_drawn {
+                let board = Board::from_str(fen).unwrap();
+                assert!(!is_insufficient_material(&board), "{}", fen);
+            }
+        }
+
+        #[test]
+        fn test_draw_score_has_contempt_and_prefers_short_draws() {
+            let board = Board::default();
+            assert_eq!(draw_score(&board, 0), -CONTEMPT);
+            // The opponent of the root side is glad to draw
+            assert_eq!(draw_score(&board, 3), CONTEMPT + 1);
+            // The root side's value of a draw only falls with distance
+            let root_scores: Vec<i32> = (0..40u8)
+                .map(|ply| match ply % 2 {
+                    0 => draw_score(&board, ply),
+                    _ => -draw_score(&board, ply),
+                })
+                .collect();
+            assert!(root_scores.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", root_scores);
+            assert!(root_scores[2] > root_scores[6]);
+        }
+    }
 }
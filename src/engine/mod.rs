@@ -5,6 +5,7 @@ use player::Player;
 pub mod evaluation;
 pub mod player;
 pub mod search;
+pub mod transposition;
 
 /// Start a complete game between two players, optionally giving a specific
 /// starting board. Returns the result of the game.
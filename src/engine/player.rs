@@ -1,7 +1,12 @@
 use super::super::util::io::get_move_cli;
-use super::search::find_move;
-use chess::{Board, ChessMove};
-// use std::time::Instant;
+use super::search::{
+    clamp_depth, find_move, root_move_scores, search, search_with_deadline, search_with_history,
+    AntiShuffle, MAX_DEPTH,
+};
+use super::transposition::TranspositionTable;
+use chess::{Board, ChessMove, MoveGen};
+use rand::Rng;
+use std::time::Instant;
 
 /// A trait representing some entity that can play chess.
 ///
@@ -12,14 +17,179 @@ pub trait Player {
 /// A player representing an AI, finding it's moves through searching the game
 /// tree.
 ///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Bot {
     pub depth: u8,
+    /// Hard ceiling on `depth`, see [`clamp_depth`].
+    pub max_depth: u8,
+    /// Bias against repeating earlier positions of the game, see
+    /// [`Bot::ponder_move_in_game`].
+    pub anti_shuffle: AntiShuffle,
+}
+
+/// Remaining clock (milliseconds) below which [`Bot::adaptive_depth`]
+/// caps the depth, with the cap for each band.
+const TIME_DEPTH_CAPS: [(u64, u8); 3] = [(10_000, 2), (30_000, 3), (120_000, 4)];
+
+/// Legal move count above which a position is treated as complex and
+/// searched one ply shallower.
+const COMPLEX_POSITION_MOVES: usize = 35;
+
+/// Opening randomization, so a deterministic engine doesn't play the same
+/// opening every game: for the first `plies` half-moves, the bot picks at
+/// random among its `top_k` best moves, favouring better ones and never
+/// one scoring more than `max_loss_cp` below the best.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpeningVariety {
+    pub plies: usize,
+    pub top_k: usize,
+    pub max_loss_cp: i32,
+}
+
+impl OpeningVariety {
+    /// Whether a move made after `ply` half-moves is still randomized.
+    pub fn applies(&self, ply: usize) -> bool {
+        ply < self.plies
+    }
+}
+
+impl Default for OpeningVariety {
+    fn default() -> Self {
+        OpeningVariety {
+            plies: 8,
+            top_k: 3,
+            max_loss_cp: 50,
+        }
+    }
+}
+
+impl Bot {
+    /// Search to `depth` plies instead.
+    pub fn with_depth(mut self, depth: u8) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Bias against repetitions with `anti_shuffle` instead.
+    pub fn with_anti_shuffle(mut self, anti_shuffle: AntiShuffle) -> Self {
+        self.anti_shuffle = anti_shuffle;
+        self
+    }
+
+    /// Change the search depth in place.
+    pub fn set_depth(&mut self, depth: u8) {
+        self.depth = depth;
+    }
+
+    /// Recommended search depth for `board` with `time_ms_remaining` on
+    /// the clock: the configured depth, capped lower as time runs short,
+    /// and one ply less in positions with many legal moves. Never exceeds
+    /// the configured depth or goes below 1.
+    pub fn adaptive_depth(&self, board: &Board, time_ms_remaining: u64) -> u8 {
+        let depth = clamp_depth(self.depth, self.max_depth);
+        let time_cap = TIME_DEPTH_CAPS
+            .iter()
+            .find(|&&(below_ms, _)| time_ms_remaining < below_ms)
+            .map_or(depth, |&(_, cap)| cap);
+        let complexity = (MoveGen::new_legal(board).len() > COMPLEX_POSITION_MOVES) as u8;
+        depth.min(time_cap).saturating_sub(complexity).max(1)
+    }
+
+    /// The move to play on `board` and the opponent's expected reply, i.e.
+    /// the first two moves of the principal variation, for pondering.
+    /// The search goes at least two plies deep so the reply is searched
+    /// too; it is `None` only when the move ends the game.
+    pub fn ponder_move(&self, board: &Board) -> (ChessMove, Option<ChessMove>) {
+        self.ponder_move_in_game(board, &[])
+    }
+
+    /// Like [`Bot::ponder_move`], for a game whose earlier positions
+    /// hashed to `history`. When ahead, moves back to one of them are
+    /// avoided as set by `anti_shuffle`.
+    pub fn ponder_move_in_game(&self, board: &Board, history: &[u64]) -> (ChessMove, Option<ChessMove>) {
+        let depth = clamp_depth(self.depth, self.max_depth).max(2);
+        let mut tt = TranspositionTable::default();
+        let best_move = search_with_history(board, depth, &mut tt, history, self.anti_shuffle).best_move;
+        (best_move, expected_reply(board, best_move, &tt))
+    }
+
+    /// Like [`Bot::ponder_move_in_game`], but deepening one ply at a time
+    /// and stopping once `deadline` passes, so the move comes in time in
+    /// a time scramble. Depth 1 is always searched in full, and the reply
+    /// comes from the deepest search that finished.
+    pub fn ponder_move_until(
+        &self,
+        board: &Board,
+        history: &[u64],
+        deadline: Instant,
+    ) -> (ChessMove, Option<ChessMove>) {
+        let depth = clamp_depth(self.depth, self.max_depth).max(2);
+        let mut tt = TranspositionTable::default();
+        let result = search_with_deadline(board, depth, &mut tt, history, self.anti_shuffle, deadline);
+        (result.best_move, expected_reply(board, result.best_move, &tt))
+    }
+
+    /// Pick a move among the best ones as described by `variety`, drawing
+    /// from `rng`. A candidate's weight falls linearly with how far it
+    /// scores below the best move. The caller decides whether `variety`
+    /// still [applies](OpeningVariety::applies).
+    pub fn choose_varied_move<R: Rng + ?Sized>(
+        &self,
+        board: &Board,
+        variety: &OpeningVariety,
+        rng: &mut R,
+    ) -> ChessMove {
+        let scores = root_move_scores(board, clamp_depth(self.depth, self.max_depth));
+        let Some(&(best_move, best)) = scores.first() else {
+            return self.choose_move(board);
+        };
+        let weighted: Vec<(ChessMove, u32)> = scores
+            .iter()
+            .take(variety.top_k.max(1))
+            .map(|&(chess_move, score)| (chess_move, best - score))
+            .take_while(|&(_, loss)| loss <= variety.max_loss_cp)
+            .map(|(chess_move, loss)| (chess_move, (variety.max_loss_cp - loss + 1) as u32))
+            .collect();
+        let total: u32 = weighted.iter().map(|&(_, weight)| weight).sum();
+        let mut pick = rng.gen_range(0..total.max(1));
+        for (chess_move, weight) in weighted {
+            if pick < weight {
+                return chess_move;
+            }
+            pick -= weight;
+        }
+        best_move
+    }
+}
+
+/// The opponent's reply to `chess_move` on the principal variation stored
+/// in `tt`, or the best move of a one ply search if the table doesn't hold
+/// it (a search cut short at depth 1). `None` if `chess_move` ends the
+/// game.
+fn expected_reply(board: &Board, chess_move: ChessMove, tt: &TranspositionTable) -> Option<ChessMove> {
+    let after = board.make_move_new(chess_move);
+    if MoveGen::new_legal(&after).len() == 0 {
+        return None;
+    }
+    let stored = tt.principal_variation(&after, 1).first().map(|&(reply, _)| reply);
+    Some(stored.unwrap_or_else(|| search(&after, 1).best_move))
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Bot {
+            depth: 5,
+            max_depth: MAX_DEPTH,
+            anti_shuffle: AntiShuffle::default(),
+        }
+    }
 }
 
 impl Player for Bot {
     fn choose_move(&self, board: &Board) -> ChessMove {
         // let start = Instant::now();
-        let chosen_move = find_move(board, self.depth);
+        let chosen_move = find_move(board, clamp_depth(self.depth, self.max_depth));
         // let duration = start.elapsed();
         // println!("Chosen move: {}\nTime elapsed: {:?}\n", chosen_move, duration);
         return chosen_move;
@@ -36,3 +206,125 @@ impl Player for Human {
         return get_move_cli(board);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_depth_builders() {
+        let mut bot = Bot::default().with_depth(7);
+        assert_eq!(bot.depth, 7);
+        bot.set_depth(3);
+        assert_eq!(bot, Bot { depth: 3, ..Bot::default() });
+    }
+
+    #[test]
+    fn test_ponder_reply_is_legal_after_move() {
+        let bot = Bot::default().with_depth(2);
+        let positions = [
+            Board::default(),
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap(),
+            Board::from_str("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1").unwrap(),
+        ];
+        for board in positions {
+            let (chess_move, reply) = bot.ponder_move(&board);
+            assert!(board.legal(chess_move));
+            let after = board.make_move_new(chess_move);
+            assert!(after.legal(reply.unwrap()), "{} after {}", reply.unwrap(), chess_move);
+        }
+
+        // Mate in one: nothing to ponder on
+        let mate = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(bot.ponder_move(&mate), (ChessMove::from_str("a1a8").unwrap(), None));
+    }
+
+    #[test]
+    fn test_ponder_predicts_reply_at_depth_1() {
+        // Depth 1, set directly or by the depth cap, still searches the
+        // reply
+        let board = Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        for bot in [Bot::default().with_depth(1), Bot { max_depth: 1, ..Bot::default() }] {
+            let (chess_move, reply) = bot.ponder_move(&board);
+            assert_eq!(chess_move, search(&board, 2).best_move);
+            let after = board.make_move_new(chess_move);
+            assert!(after.legal(reply.unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_ponder_until_past_deadline_still_moves() {
+        let bot = Bot::default().with_depth(6);
+        let board = Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let (chess_move, reply) = bot.ponder_move_until(&board, &[], Instant::now());
+        assert_eq!(chess_move, search(&board, 1).best_move);
+        let after = board.make_move_new(chess_move);
+        assert!(after.legal(reply.unwrap()));
+
+        // With time to spare it searches as deep as the fixed-depth search
+        let bot = bot.with_depth(3);
+        let far = Instant::now() + std::time::Duration::from_secs(60);
+        assert_eq!(bot.ponder_move_until(&board, &[], far).0, bot.ponder_move(&board).0);
+    }
+
+    #[test]
+    fn test_opening_variety_diverges() {
+        use rand::rngs::SmallRng;
+        use rand::SeedableRng;
+
+        let bot = Bot::default().with_depth(2);
+        let variety = OpeningVariety {
+            plies: 4,
+            ..OpeningVariety::default()
+        };
+        let play = |seed: u64| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut board = Board::default();
+            let mut moves = Vec::new();
+            for ply in 0..6 {
+                let chess_move = match variety.applies(ply) {
+                    true => bot.choose_varied_move(&board, &variety, &mut rng),
+                    false => bot.choose_move(&board),
+                };
+                moves.push(chess_move);
+                board = board.make_move_new(chess_move);
+            }
+            moves
+        };
+
+        // The same seed replays the same game, different ones diverge
+        let games: Vec<_> = (0..8).map(play).collect();
+        assert_eq!(games[0], play(0));
+        assert!(games.iter().any(|game| game != &games[0]));
+
+        // Every randomized move is close to the best one
+        let scores = root_move_scores(&Board::default(), 2);
+        let best = scores[0].1;
+        for game in &games {
+            let (_, score) = scores.iter().find(|(m, _)| *m == game[0]).unwrap();
+            assert!(best - score <= variety.max_loss_cp);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_depth_drops_with_time() {
+        let bot = Bot::default().with_depth(6);
+        let board = Board::default();
+        assert_eq!(bot.adaptive_depth(&board, 600_000), 6);
+        assert_eq!(bot.adaptive_depth(&board, 60_000), 4);
+        assert_eq!(bot.adaptive_depth(&board, 20_000), 3);
+        assert_eq!(bot.adaptive_depth(&board, 5_000), 2);
+
+        // Plenty of time never raises the configured depth
+        assert_eq!(bot.with_depth(2).adaptive_depth(&board, 600_000), 2);
+
+        // "Kiwipete": 48 legal moves
+        let complex =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        assert!(MoveGen::new_legal(&complex).len() > COMPLEX_POSITION_MOVES);
+        assert_eq!(bot.adaptive_depth(&complex, 600_000), 5);
+        assert_eq!(bot.adaptive_depth(&complex, 5_000), 1);
+    }
+}
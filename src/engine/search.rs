@@ -1,43 +1,641 @@
 use super::evaluation::simple::evaluate_board;
+use super::evaluation::{draw_score, is_insufficient_material};
+use super::transposition::{Bound, TranspositionTable, TtEntry};
 use chess::{Board, ChessMove, MoveGen, EMPTY};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Default hard ceiling on search depth (plies).
+pub const MAX_DEPTH: u8 = 20;
+
+/// Score of a position where the side to move has been checkmated is
+/// `-MATE_SCORE`; a mate found `n` plies from the root scores
+/// `±(MATE_SCORE - n)`.
+pub const MATE_SCORE: i32 = 20_000;
+
+/// Longest mate (in plies) that [`mate_in`] recognises. Scores closer to
+/// zero than `MATE_SCORE - MAX_MATE_PLY` are ordinary evaluations.
+pub const MAX_MATE_PLY: i32 = 1_000;
+
+/// Number of moves until mate encoded in `score`, positive if the side to
+/// move mates and negative if it gets mated. `None` for ordinary scores.
+///
+pub fn mate_in(score: i32) -> Option<i32> {
+    let plies = MATE_SCORE - score.abs();
+    if !(0..=MAX_MATE_PLY).contains(&plies) {
+        return None;
+    }
+    let moves = (plies + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/// Score of `board` if the game is over there, `ply` plies from the
+/// root: mated, or a draw if stalemated. `None` while there are moves.
+///
+pub fn terminal_score(board: &Board, ply: u8) -> Option<i32> {
+    if MoveGen::new_legal(board).len() > 0 {
+        return None;
+    }
+    Some(match *board.checkers() == EMPTY {
+        true => draw_score(board, ply),
+        false => -(MATE_SCORE - ply as i32),
+    })
+}
+
+/// Convert a mate score from "plies from the root" to "plies from this
+/// node" before storing it in the transposition table, so it stays right
+/// when the position is reached at another ply.
+///
+fn score_to_tt(score: i32, ply: u8) -> i32 {
+    match mate_in(score) {
+        Some(_) => score + score.signum() * ply as i32,
+        None => score,
+    }
+}
+
+/// Inverse of [`score_to_tt`] for a score probed `ply` plies from the root.
+///
+fn score_from_tt(score: i32, ply: u8) -> i32 {
+    match mate_in(score) {
+        Some(_) => score - score.signum() * ply as i32,
+        None => score,
+    }
+}
+
+/// Clamp a requested search depth into `1..=max_depth`.
+///
+/// Used wherever a depth arrives from outside the engine (`go depth`,
+/// `BOT_DEPTH`) so a misconfiguration cannot start a runaway search.
+///
+pub fn clamp_depth(depth: u8, max_depth: u8) -> u8 {
+    depth.clamp(1, max_depth.max(1))
+}
+
+/// Largest score change (centipawns) between consecutive depths for
+/// [`find_move_stable`] to treat the result as settled.
+pub const STABLE_SCORE_MARGIN: i32 = 30;
+
+/// Bias against root moves that return to a position already seen in the
+/// game while the engine is ahead.
+///
+/// Repetitions within the search tree score as draws, but positions from
+/// earlier in the game are outside it: when no progress shows up within
+/// the horizon, a winning engine can shuffle back and forth forever. A
+/// root move leading back to one of those positions, scoring at least
+/// `min_advantage_cp`, loses `penalty_cp` so a move that keeps the game
+/// going is preferred.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AntiShuffle {
+    pub penalty_cp: i32,
+    pub min_advantage_cp: i32,
+}
+
+impl AntiShuffle {
+    /// No bias.
+    pub fn off() -> Self {
+        AntiShuffle {
+            penalty_cp: 0,
+            min_advantage_cp: 0,
+        }
+    }
+
+    /// `score` of a root move, lowered if the move `repeats` an earlier
+    /// position and scores at least `min_advantage_cp`.
+    pub fn adjust(&self, score: i32, repeats: bool) -> i32 {
+        match repeats && self.penalty_cp > 0 && score >= self.min_advantage_cp {
+            true => score - self.penalty_cp,
+            false => score,
+        }
+    }
+}
+
+impl Default for AntiShuffle {
+    fn default() -> Self {
+        AntiShuffle {
+            penalty_cp: 50,
+            min_advantage_cp: 200,
+        }
+    }
+}
+
+/// How many nodes are searched between checks of the deadline.
+const NODES_PER_DEADLINE_CHECK: u64 = 1024;
+
+/// Bookkeeping shared by every node of a single search: the node count,
+/// the transposition table, the positions on the current line and the
+/// conditions under which the search must stop early.
+///
+struct SearchContext<'a> {
+    nodes: u64,
+    seldepth: u8,
+    /// Hashes of the positions from the root down to the current node's
+    /// parent, for detecting repetitions.
+    path: Vec<u64>,
+    /// Whether a repetition was scored as a draw below the current node.
+    /// Such a score depends on the line leading there, so it isn't
+    /// trusted from the transposition table.
+    repetitions: bool,
+    /// Hashes of the positions played in the game before the root, for
+    /// [`AntiShuffle`].
+    history: &'a [u64],
+    anti_shuffle: AntiShuffle,
+    /// Root moves left out of the search.
+    excluded: &'a [ChessMove],
+    deadline: Option<Instant>,
+    /// Nodes after which the search stops, if limited.
+    node_limit: Option<u64>,
+    stop: &'a AtomicBool,
+    tt: &'a mut TranspositionTable,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(
+        deadline: Option<Instant>,
+        stop: &'a AtomicBool,
+        tt: &'a mut TranspositionTable,
+    ) -> Self {
+        SearchContext {
+            nodes: 0,
+            seldepth: 0,
+            path: Vec::new(),
+            repetitions: false,
+            history: &[],
+            anti_shuffle: AntiShuffle::off(),
+            excluded: &[],
+            deadline,
+            node_limit: None,
+            stop,
+            tt,
+        }
+    }
+
+    /// Count a node `ply` plies from the root and report whether the
+    /// search should unwind. The deadline is only checked every
+    /// `NODES_PER_DEADLINE_CHECK` nodes to keep `Instant::now()` out of the
+    /// hot path.
+    fn visit(&mut self, ply: u8) -> bool {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        if self.nodes.is_multiple_of(NODES_PER_DEADLINE_CHECK) {
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    self.stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        self.stopped()
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Whether the position `hash` already occurred on the current line.
+    fn is_repetition(&self, hash: u64) -> bool {
+        self.path.contains(&hash)
+    }
+}
+
+/// Outcome of a fixed-depth search.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    /// Best move found.
+    pub best_move: ChessMove,
+    /// Score of `best_move` from the side to move's perspective.
+    pub score: i32,
+    /// Nominal search depth.
+    pub depth: u8,
+    /// Deepest ply reached, including quiescence search.
+    pub seldepth: u8,
+    /// Number of nodes visited.
+    pub nodes: u64,
+    /// Transposition table occupancy in permille after the search.
+    pub hashfull: u32,
+}
 
 /// Root function of Alpha-Beta search algorithm, returning the best move
 /// found after a search with depth=`depth`.
 ///
 pub fn find_move(board: &Board, depth: u8) -> ChessMove {
+    search(board, depth).best_move
+}
+
+/// Like [`find_move`], but also reports the score and search statistics.
+///
+pub fn search(board: &Board, depth: u8) -> SearchResult {
+    search_with_tt(board, depth, &mut TranspositionTable::default())
+}
+
+/// Like [`search`], but reusing `tt` so results carry over between
+/// searches (e.g. successive `go` commands in the same game).
+///
+pub fn search_with_tt(board: &Board, depth: u8, tt: &mut TranspositionTable) -> SearchResult {
+    search_with_history(board, depth, tt, &[], AntiShuffle::off())
+}
+
+/// Like [`search_with_tt`], for a position reached in a game whose
+/// earlier positions hashed to `history`; root moves returning to one of
+/// them are biased against by `anti_shuffle`.
+///
+pub fn search_with_history(
+    board: &Board,
+    depth: u8,
+    tt: &mut TranspositionTable,
+    history: &[u64],
+    anti_shuffle: AntiShuffle,
+) -> SearchResult {
+    let stop = AtomicBool::new(false);
+    let mut context = SearchContext::new(None, &stop, tt);
+    context.history = history;
+    context.anti_shuffle = anti_shuffle;
+    let (best_move, score) = search_root(board, depth, &mut context)
+        .unwrap_or_else(|| (first_legal_move(board), -20_000));
+    SearchResult {
+        best_move,
+        score,
+        depth,
+        seldepth: context.seldepth,
+        nodes: context.nodes,
+        hashfull: context.tt.hashfull(),
+    }
+}
+
+/// Like [`search`], but never choosing one of the `excluded` root moves,
+/// e.g. to find the next best line for multi-PV. If every legal move is
+/// excluded, the first one is returned with the worst possible score.
+///
+pub fn find_move_with_exclusions(board: &Board, depth: u8, excluded: &[ChessMove]) -> SearchResult {
+    let stop = AtomicBool::new(false);
+    let mut tt = TranspositionTable::default();
+    let mut context = SearchContext::new(None, &stop, &mut tt);
+    context.excluded = excluded;
+    let (best_move, score) = search_root(board, depth, &mut context)
+        .unwrap_or_else(|| (first_legal_move(board), -20_000));
+    SearchResult {
+        best_move,
+        score,
+        depth,
+        seldepth: context.seldepth,
+        nodes: context.nodes,
+        hashfull: context.tt.hashfull(),
+    }
+}
+
+/// Like [`search_with_tt`], but giving up once `max_nodes` nodes have been
+/// visited. Returns `None` if the limit was reached, in which case exactly
+/// `max_nodes` nodes were searched, or if `board` has no legal moves.
+///
+pub fn search_with_node_limit(
+    board: &Board,
+    depth: u8,
+    tt: &mut TranspositionTable,
+    max_nodes: u64,
+) -> Option<SearchResult> {
+    let stop = AtomicBool::new(false);
+    let mut context = SearchContext::new(None, &stop, tt);
+    context.node_limit = Some(max_nodes);
+    let iteration = search_root(board, depth, &mut context);
+    if context.stopped() {
+        return None;
+    }
+    let (best_move, score) = iteration
+        .or_else(|| MoveGen::new_legal(board).next().map(|chess_move| (chess_move, -20_000)))?;
+    Some(SearchResult {
+        best_move,
+        score,
+        depth,
+        seldepth: context.seldepth,
+        nodes: context.nodes,
+        hashfull: context.tt.hashfull(),
+    })
+}
+
+/// Search with iterative deepening until `max_depth` is completed or
+/// `deadline` passes, returning the best move of the deepest iteration
+/// that finished. An interrupted iteration is discarded.
+///
+/// Depth 1 is always completed, even past the deadline, so a search with
+/// no time left still returns a searched move instead of an arbitrary
+/// legal one.
+///
+pub fn find_move_timeout(board: &Board, max_depth: u8, deadline: Instant) -> ChessMove {
+    let mut tt = TranspositionTable::default();
+    search_with_deadline(board, max_depth, &mut tt, &[], AntiShuffle::off(), deadline).best_move
+}
+
+/// Like [`find_move_timeout`], for a position reached in a game as in
+/// [`search_with_history`]. `depth` in the result is that of the deepest
+/// iteration that finished.
+///
+pub fn search_with_deadline(
+    board: &Board,
+    max_depth: u8,
+    tt: &mut TranspositionTable,
+    history: &[u64],
+    anti_shuffle: AntiShuffle,
+    deadline: Instant,
+) -> SearchResult {
+    let stop = AtomicBool::new(false);
+    let mut context = SearchContext::new(None, &stop, tt);
+    context.history = history;
+    context.anti_shuffle = anti_shuffle;
+    let (mut best_move, mut score) = search_root(board, 1, &mut context)
+        .unwrap_or_else(|| (first_legal_move(board), -20_000));
+    let mut depth = 1;
+    context.deadline = Some(deadline);
+    for next_depth in 2..=max_depth {
+        if Instant::now() >= deadline {
+            break;
+        }
+        let iteration = search_root(board, next_depth, &mut context);
+        if context.stopped() {
+            break;
+        }
+        if let Some((chess_move, iteration_score)) = iteration {
+            (best_move, score) = (chess_move, iteration_score);
+        }
+        depth = next_depth;
+    }
+    SearchResult {
+        best_move,
+        score,
+        depth,
+        seldepth: context.seldepth,
+        nodes: context.nodes,
+        hashfull: context.tt.hashfull(),
+    }
+}
+
+/// Search with iterative deepening up to `max_depth` until `stop` is set,
+/// e.g. by another thread when a ponder search is cancelled. Returns the
+/// deepest iteration that finished, or `None` if none did or `board` has
+/// no legal moves.
+///
+pub fn search_until_stopped(
+    board: &Board,
+    max_depth: u8,
+    tt: &mut TranspositionTable,
+    stop: &AtomicBool,
+) -> Option<SearchResult> {
+    let mut context = SearchContext::new(None, stop, tt);
+    let mut result = None;
+    for depth in 1..=max_depth.max(1) {
+        let iteration = search_root(board, depth, &mut context);
+        if context.stopped() {
+            break;
+        }
+        let Some((best_move, score)) = iteration
+            .or_else(|| MoveGen::new_legal(board).next().map(|chess_move| (chess_move, -20_000)))
+        else {
+            break;
+        };
+        result = Some(SearchResult {
+            best_move,
+            score,
+            depth,
+            seldepth: context.seldepth,
+            nodes: context.nodes,
+            hashfull: context.tt.hashfull(),
+        });
+    }
+    result
+}
+
+/// Search at `initial_depth`, then keep deepening one ply at a time until
+/// two consecutive depths agree on the best move with scores within
+/// [`STABLE_SCORE_MARGIN`], or `max_depth` is reached.
+///
+/// Guards against horizon-effect blunders where the choice flips between
+/// odd and even depths. Returns the deepest search performed, with
+/// `nodes` summed over every iteration.
+///
+pub fn find_move_stable(board: &Board, initial_depth: u8, max_depth: u8) -> SearchResult {
+    search_until_stable(board, initial_depth, max_depth, &mut TranspositionTable::default())
+}
+
+/// Like [`find_move_stable`], but reusing `tt` across iterations and
+/// calls.
+///
+pub fn search_until_stable(
+    board: &Board,
+    initial_depth: u8,
+    max_depth: u8,
+    tt: &mut TranspositionTable,
+) -> SearchResult {
+    let initial_depth = clamp_depth(initial_depth, max_depth);
+    let mut result = search_with_tt(board, initial_depth, tt);
+    let mut nodes = result.nodes;
+    for depth in initial_depth + 1..=max_depth {
+        let deeper = search_with_tt(board, depth, tt);
+        nodes += deeper.nodes;
+        let stable = deeper.best_move == result.best_move
+            && (deeper.score - result.score).abs() <= STABLE_SCORE_MARGIN;
+        result = deeper;
+        if stable {
+            break;
+        }
+    }
+    SearchResult { nodes, ..result }
+}
+
+/// Search every root move to `depth`, returning the best one with its
+/// score, or `None` if every move loses to checkmate (or the search was
+/// stopped before any move was scored).
+///
+fn search_root(
+    board: &Board,
+    depth: u8,
+    context: &mut SearchContext,
+) -> Option<(ChessMove, i32)> {
     let mut movegen = MoveGen::new_legal(board);
     let mut best_move: Option<ChessMove> = None;
     let mut best_move_score = -20_000;
     let mut resulting_board = Board::default();
+    context.path.push(board.get_hash());
+    context.repetitions = false;
     for cmove in &mut movegen {
+        if context.excluded.contains(&cmove) {
+            continue;
+        }
         board.make_move(cmove, &mut resulting_board);
-        let score = -alpha_beta_search(&resulting_board, depth - 1, -20_000, 20_000, true);
+        let score =
+            -alpha_beta_search(&resulting_board, depth - 1, 1, -20_000, 20_000, true, context);
+        if context.stopped() {
+            break;
+        }
+        let repeats = context.history.contains(&resulting_board.get_hash());
+        let score = context.anti_shuffle.adjust(score, repeats);
         // println!("Move: {}, Score: {}", cmove, score);
         if score > best_move_score {
             best_move = Some(cmove);
             best_move_score = score;
         }
     }
-    return match best_move {
-        Some(chosen_move) => chosen_move,
-        // If checkmate is inevitable, no move will have been selected
-        None => MoveGen::new_legal(board)
-            .next()
-            .expect("No legal moves for the given board!"),
-    };
+    context.path.pop();
+    // Stored so the line can be read back from the table from the root on
+    if best_move.is_some() {
+        store(context, board.get_hash(), depth, best_move_score, Bound::Exact, best_move, true);
+    }
+    best_move.map(|chess_move| (chess_move, best_move_score))
+}
+
+/// Score every legal move of `board` with a `depth` ply search, best
+/// first, from the side to move's perspective.
+///
+/// Unlike [`search`], which only needs the best move, each move is
+/// searched with a full window so near-best alternatives can be compared.
+///
+pub fn root_move_scores(board: &Board, depth: u8) -> Vec<(ChessMove, i32)> {
+    let stop = AtomicBool::new(false);
+    let mut tt = TranspositionTable::default();
+    let mut context = SearchContext::new(None, &stop, &mut tt);
+    context.path.push(board.get_hash());
+    let mut resulting_board = Board::default();
+    let mut scores: Vec<_> = MoveGen::new_legal(board)
+        .map(|cmove| {
+            board.make_move(cmove, &mut resulting_board);
+            let score = -alpha_beta_search(
+                &resulting_board,
+                depth.max(1) - 1,
+                1,
+                -20_000,
+                20_000,
+                true,
+                &mut context,
+            );
+            (cmove, score)
+        })
+        .collect();
+    scores.sort_by_key(|&(_, score)| Reverse(score));
+    scores
+}
+
+/// Like [`search`], but with the root moves searched in parallel on
+/// rayon's thread pool.
+///
+/// Root moves are independent subtrees, so each is searched with a full
+/// window on its own copy of the board, with a transposition table per
+/// rayon work split, and the best score wins. Ties go to the earliest
+/// move in generation order, as in [`search`]. `nodes` is summed over the
+/// moves and `hashfull` is the fullest table's.
+///
+#[cfg(feature = "parallel")]
+pub fn parallel_root_search(board: &Board, depth: u8) -> SearchResult {
+    use rayon::prelude::*;
+
+    let moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+    let stop = AtomicBool::new(false);
+    let searched: Vec<(ChessMove, i32, u64, u8, u32)> = moves
+        .par_iter()
+        .map_init(TranspositionTable::default, |tt, &cmove| {
+            let mut context = SearchContext::new(None, &stop, tt);
+            context.path.push(board.get_hash());
+            let resulting_board = board.make_move_new(cmove);
+            let score = -alpha_beta_search(
+                &resulting_board,
+                depth.max(1) - 1,
+                1,
+                -20_000,
+                20_000,
+                true,
+                &mut context,
+            );
+            (cmove, score, context.nodes, context.seldepth, context.tt.hashfull())
+        })
+        .collect();
+
+    // In generation order, so the first of equal scores is kept
+    let (best_move, score) = searched
+        .iter()
+        .filter(|&&(_, score, ..)| score > -20_000)
+        .fold(None, |best: Option<(ChessMove, i32)>, &(cmove, score, ..)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((cmove, score)),
+        })
+        .unwrap_or_else(|| (first_legal_move(board), -20_000));
+
+    SearchResult {
+        best_move,
+        score,
+        depth,
+        seldepth: searched.iter().map(|&(.., seldepth, _)| seldepth).max().unwrap_or(0),
+        nodes: searched.iter().map(|&(_, _, nodes, ..)| nodes).sum(),
+        hashfull: searched.iter().map(|&(.., hashfull)| hashfull).max().unwrap_or(0),
+    }
+}
+
+/// Fallback when no move was selected, e.g. because checkmate is
+/// inevitable.
+///
+fn first_legal_move(board: &Board) -> ChessMove {
+    MoveGen::new_legal(board)
+        .next()
+        .expect("No legal moves for the given board!")
 }
 
 /// Recursivley search the move-tree using a min-max strategy (NegaMax) with
 /// alpha-beta pruning, returning an evaluation score for the given board
 /// state.
 ///
-/// As a simple sorting of the legal moves, capturing moves are iterated first.
+/// As a simple sorting of the legal moves, the transposition table move is
+/// tried first, then captures. Subtrees below a null move skip the table,
+/// since they are searched without null-move pruning.
+///
+/// Positions repeating one earlier on the line, or without enough material
+/// left to mate, score as a draw.
 ///
 /// See https://www.chessprogramming.org/Alpha-Beta#Negamax_Framework
 ///
-fn alpha_beta_search(board: &Board, depth: u8, alpha: i32, beta: i32, can_null: bool) -> i32 {
+fn alpha_beta_search(
+    board: &Board,
+    depth: u8,
+    ply: u8,
+    alpha: i32,
+    beta: i32,
+    can_null: bool,
+    context: &mut SearchContext,
+) -> i32 {
+    if context.visit(ply) {
+        // The result is discarded by the caller
+        return alpha;
+    }
+    let hash = board.get_hash();
+    if context.is_repetition(hash) {
+        context.repetitions = true;
+        return draw_score(board, ply);
+    }
+    if is_insufficient_material(board) {
+        return draw_score(board, ply);
+    }
     if depth == 0 {
-        return quiescence_search(&board, alpha, beta);
+        // Only mates are worth the move generation here
+        if *board.checkers() != EMPTY {
+            if let Some(score) = terminal_score(board, ply) {
+                return score;
+            }
+        }
+        return quiescence_search(&board, ply, alpha, beta, context);
+    }
+    let mut tt_move = None;
+    if can_null {
+        if let Some(entry) = context.tt.probe(hash) {
+            let score = score_from_tt(entry.score, ply);
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score.clamp(alpha, beta),
+                    Bound::Lower if score >= beta => return beta,
+                    Bound::Upper if score <= alpha => return alpha,
+                    _ => {}
+                }
+            }
+            tt_move = entry.best_move.filter(|&m| board.legal(m));
+        }
     }
     if can_null {
         if let Some(resulting_board) = board.null_move() {
@@ -46,40 +644,95 @@ fn alpha_beta_search(board: &Board, depth: u8, alpha: i32, beta: i32, can_null:
                 false => depth - 2,
             };
             let score =
-                -alpha_beta_search(&resulting_board, adjusted_depth - 1, -beta, -alpha, false);
+                -alpha_beta_search(&resulting_board, adjusted_depth - 1, ply + 1, -beta, -alpha, false, context);
             if score >= beta {
                 return beta;
             }
         }
     }
+    context.path.push(hash);
+    let outer_repetitions = std::mem::take(&mut context.repetitions);
     let mut movegen = MoveGen::new_legal(board);
     let mut new_alpha = alpha;
+    let mut best_move = None;
     let mut resulting_board = Board::default();
     let targets = board.color_combined(!board.side_to_move());
 
     movegen.set_iterator_mask(*targets);
-    for cmove in &mut movegen {
+    let captures = tt_move
+        .into_iter()
+        .chain((&mut movegen).filter(|&m| Some(m) != tt_move));
+    let mut searched = false;
+    for cmove in captures {
+        searched = true;
         board.make_move(cmove, &mut resulting_board);
-        let score = -alpha_beta_search(&resulting_board, depth - 1, -beta, -new_alpha, can_null);
+        let score =
+            -alpha_beta_search(&resulting_board, depth - 1, ply + 1, -beta, -new_alpha, can_null, context);
         if score >= beta {
+            context.path.pop();
+            store(context, hash, depth, score_to_tt(beta, ply), Bound::Lower, Some(cmove), can_null);
+            context.repetitions |= outer_repetitions;
             return beta;
         }
         if score > new_alpha {
             new_alpha = score;
+            best_move = Some(cmove);
         }
     }
     movegen.set_iterator_mask(!EMPTY);
-    for cmove in &mut movegen {
+    for cmove in (&mut movegen).filter(|&m| Some(m) != tt_move) {
+        searched = true;
         board.make_move(cmove, &mut resulting_board);
-        let score = -alpha_beta_search(&resulting_board, depth - 1, -beta, -new_alpha, can_null);
+        let score =
+            -alpha_beta_search(&resulting_board, depth - 1, ply + 1, -beta, -new_alpha, can_null, context);
         if score >= beta {
+            context.path.pop();
+            store(context, hash, depth, score_to_tt(beta, ply), Bound::Lower, Some(cmove), can_null);
+            context.repetitions |= outer_repetitions;
             return beta;
         }
         if score > new_alpha {
             new_alpha = score;
+            best_move = Some(cmove);
         }
     }
-    return new_alpha;
+    context.path.pop();
+    if !searched {
+        context.repetitions |= outer_repetitions;
+        return terminal_score(board, ply).unwrap_or(alpha);
+    }
+    let bound = match best_move {
+        Some(_) => Bound::Exact,
+        None => Bound::Upper,
+    };
+    store(context, hash, depth, score_to_tt(new_alpha, ply), bound, best_move, can_null);
+    context.repetitions |= outer_repetitions;
+    new_alpha
+}
+
+/// Record a node's result in the transposition table, unless the search
+/// was interrupted (the score is meaningless) or the node sits below a
+/// null move. A score resting on a repetition draw is kept at depth 0,
+/// so the move still orders the search but the score never cuts it off.
+///
+fn store(
+    context: &mut SearchContext,
+    key: u64,
+    depth: u8,
+    score: i32,
+    bound: Bound,
+    best_move: Option<ChessMove>,
+    can_null: bool,
+) {
+    if can_null && !context.stopped() {
+        context.tt.store(TtEntry {
+            key,
+            depth: if context.repetitions { 0 } else { depth },
+            score,
+            bound,
+            best_move,
+        });
+    }
 }
 
 /// Perform an Quiescence search, used to only evaluate "quiet" positions in
@@ -87,7 +740,16 @@ fn alpha_beta_search(board: &Board, depth: u8, alpha: i32, beta: i32, can_null:
 ///
 /// See https://www.chessprogramming.org/Quiescence_Search
 ///
-fn quiescence_search(board: &Board, alpha: i32, beta: i32) -> i32 {
+fn quiescence_search(
+    board: &Board,
+    ply: u8,
+    alpha: i32,
+    beta: i32,
+    context: &mut SearchContext,
+) -> i32 {
+    if context.visit(ply) {
+        return alpha;
+    }
     let stand_pat = evaluate_board(&board);
     let mut new_alpha = alpha;
     if stand_pat >= beta {
@@ -105,7 +767,7 @@ fn quiescence_search(board: &Board, alpha: i32, beta: i32) -> i32 {
     movegen.set_iterator_mask(*targets);
     for cmove in &mut movegen {
         board.make_move(cmove, &mut resulting_board);
-        let score = -quiescence_search(&resulting_board, -beta, -new_alpha);
+        let score = -quiescence_search(&resulting_board, ply.saturating_add(1), -beta, -new_alpha, context);
         if score >= beta {
             return beta;
         }
@@ -115,3 +777,270 @@ fn quiescence_search(board: &Board, alpha: i32, beta: i32) -> i32 {
     }
     return new_alpha;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_find_move_timeout_respects_deadline() {
+        // Busy middlegame where a depth-10 search takes far too long.
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8")
+                .unwrap();
+        let start = Instant::now();
+        let chosen = find_move_timeout(&board, 10, start + Duration::from_millis(10));
+        let elapsed = start.elapsed();
+
+        assert!(elapsed < Duration::from_millis(50), "took {:?}", elapsed);
+        assert!(board.legal(chosen));
+    }
+
+    #[test]
+    fn test_find_move_timeout_without_time_still_searches() {
+        // The first legal move is a king move; depth 1 takes the queen.
+        let board = Board::from_str("k7/8/8/3q4/8/8/8/K2R4 w - - 0 1").unwrap();
+        let chosen = find_move_timeout(&board, 10, Instant::now());
+        assert_eq!(chosen, ChessMove::from_str("d1d5").unwrap());
+
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8")
+                .unwrap();
+        let chosen = find_move_timeout(&board, 10, Instant::now() - Duration::from_millis(1));
+        assert!(board.legal(chosen));
+        assert_eq!(chosen, find_move(&board, 1));
+    }
+
+    #[test]
+    fn test_search_scores_mate_distance() {
+        // Back-rank mate in one
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let result = search(&board, 3);
+        assert_eq!(result.best_move, ChessMove::from_str("a1a8").unwrap());
+        assert_eq!(mate_in(result.score), Some(1));
+
+        // 1. Rd8+ Rxd8 2. Rxd8#
+        let board = Board::from_str("r5k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1").unwrap();
+        let result = search(&board, 4);
+        assert_eq!(result.best_move, ChessMove::from_str("d2d8").unwrap());
+        assert_eq!(mate_in(result.score), Some(2));
+        // The same scores read back through a warm table
+        let mut tt = TranspositionTable::default();
+        search_with_tt(&board, 4, &mut tt);
+        assert_eq!(mate_in(search_with_tt(&board, 4, &mut tt).score), Some(2));
+    }
+
+    #[test]
+    fn test_terminal_score_tells_mate_from_stalemate() {
+        let mated = Board::from_str("R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        assert_eq!(terminal_score(&mated, 1), Some(-(MATE_SCORE - 1)));
+        let stalemated = Board::from_str("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(terminal_score(&stalemated, 1), Some(draw_score(&stalemated, 1)));
+        assert_eq!(terminal_score(&Board::default(), 0), None);
+    }
+
+    #[test]
+    fn test_find_move_with_exclusions() {
+        let board = Board::default();
+        let mut top = Vec::new();
+        for _ in 0..3 {
+            let result = find_move_with_exclusions(&board, 3, &top);
+            assert!(board.legal(result.best_move));
+            assert!(!top.contains(&result.best_move));
+            top.push(result.best_move);
+        }
+        assert_eq!(top[0], find_move(&board, 3));
+
+        let excluded = [
+            ChessMove::from_str("e2e4").unwrap(),
+            ChessMove::from_str("d2d4").unwrap(),
+        ];
+        let result = find_move_with_exclusions(&board, 3, &excluded);
+        assert!(board.legal(result.best_move));
+        assert!(!excluded.contains(&result.best_move));
+    }
+
+    #[test]
+    fn test_node_limit_stops_search() {
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3")
+                .unwrap();
+        let mut tt = TranspositionTable::new(4096);
+        assert_eq!(search_with_node_limit(&board, 3, &mut tt, 100), None);
+
+        let full = search(&board, 2);
+        let limited = search_with_node_limit(&board, 2, &mut tt, u64::MAX).unwrap();
+        assert_eq!(limited.score, full.score);
+        assert!(board.legal(limited.best_move));
+    }
+
+    #[test]
+    fn test_find_move_stable_sees_fork() {
+        // Nf7+ forks king and queen, but only pays off after Black's reply.
+        let board = Board::from_str("3q3k/6pp/8/4N3/8/8/6PP/6K1 w - - 0 1").unwrap();
+        let fork = ChessMove::from_str("e5f7").unwrap();
+        assert_ne!(find_move(&board, 1), fork);
+
+        let result = find_move_stable(&board, 2, 6);
+        assert_eq!(result.best_move, fork);
+        assert!(result.depth >= 3 && result.depth <= 6, "depth {}", result.depth);
+        assert!(result.score > 0, "score {}", result.score);
+    }
+
+    #[test]
+    fn test_seldepth_extends_past_depth_on_captures() {
+        // Center tension: exd4 Nxd4 Nxd4 Qxd4 ... keeps quiescence going.
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3")
+                .unwrap();
+        let result = search(&board, 2);
+        assert_eq!(result.depth, 2);
+        assert!(result.seldepth > result.depth, "seldepth {}", result.seldepth);
+        assert!(result.nodes > 0);
+        assert!(board.legal(result.best_move));
+
+        // No captures anywhere near the start position at depth 1.
+        let result = search(&Board::default(), 1);
+        assert_eq!(result.seldepth, 1);
+    }
+
+    #[test]
+    fn test_hashfull_in_range_after_deep_search() {
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8")
+                .unwrap();
+        let mut tt = TranspositionTable::new(4096);
+        let result = search_with_tt(&board, 4, &mut tt);
+        assert!(result.hashfull > 0 && result.hashfull <= 1000, "hashfull {}", result.hashfull);
+        assert_eq!(result.hashfull, tt.hashfull());
+    }
+
+    #[test]
+    fn test_tt_does_not_change_best_move() {
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3")
+                .unwrap();
+        let mut tt = TranspositionTable::default();
+        let first = search_with_tt(&board, 3, &mut tt);
+        // A warm table must not change the answer, only the effort
+        let second = search_with_tt(&board, 3, &mut tt);
+        assert_eq!(first.best_move, second.best_move);
+        assert_eq!(first.score, second.score);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_root_search_matches_search() {
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+        ];
+        for fen in positions {
+            let board = Board::from_str(fen).unwrap();
+            let serial = search(&board, 3);
+            let parallel = parallel_root_search(&board, 3);
+            assert_eq!((parallel.best_move, parallel.score), (serial.best_move, serial.score), "{}", fen);
+        }
+    }
+
+    /// Timing comparison: the parallel search must beat the serial one
+    /// wherever there is more than one core. Run with
+    /// `cargo test --features parallel bench_parallel_root_search -- --nocapture`
+    /// to see the times.
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn bench_parallel_root_search() {
+        let board = Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+        let start = Instant::now();
+        let serial = search(&board, 5);
+        let serial_time = start.elapsed();
+        let start = Instant::now();
+        let parallel = parallel_root_search(&board, 5);
+        let parallel_time = start.elapsed();
+        let threads = rayon::current_num_threads();
+        println!(
+            "serial {:?}, parallel {:?} on {} threads ({:.1}x)",
+            serial_time,
+            parallel_time,
+            threads,
+            serial_time.as_secs_f64() / parallel_time.as_secs_f64()
+        );
+        assert_eq!(parallel.best_move, serial.best_move);
+        if threads > 1 {
+            assert!(parallel_time < serial_time);
+        }
+    }
+
+    #[test]
+    fn test_search_until_stopped() {
+        let board = Board::default();
+        let mut tt = TranspositionTable::default();
+        let stop = AtomicBool::new(false);
+        let result = search_until_stopped(&board, 3, &mut tt, &stop).unwrap();
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.best_move, search(&board, 3).best_move);
+
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(search_until_stopped(&board, 3, &mut tt, &stop), None);
+
+        let mated = Board::from_str("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        let stop = AtomicBool::new(false);
+        assert_eq!(search_until_stopped(&mated, 3, &mut tt, &stop), None);
+    }
+
+    #[test]
+    fn test_find_move_timeout_completes_shallow_search() {
+        // With plenty of time the result matches a plain fixed-depth search.
+        let board = Board::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(find_move_timeout(&board, 2, deadline), find_move(&board, 2));
+    }
+
+    #[test]
+    fn test_anti_shuffle_avoids_earlier_position_when_ahead() {
+        // A rook up; pretend the position after the preferred move was
+        // already played earlier in the game.
+        let board = Board::from_str("8/8/8/4k3/8/8/R7/4K3 w - - 0 1").unwrap();
+        let preferred = search(&board, 3);
+        let history = [board.make_move_new(preferred.best_move).get_hash()];
+
+        let anti_shuffle = AntiShuffle::default();
+        let mut tt = TranspositionTable::default();
+        let progress = search_with_history(&board, 3, &mut tt, &history, anti_shuffle);
+        assert_ne!(progress.best_move, preferred.best_move);
+        assert!(progress.score >= anti_shuffle.min_advantage_cp);
+
+        let mut tt = TranspositionTable::default();
+        let off = search_with_history(&board, 3, &mut tt, &history, AntiShuffle::off());
+        assert_eq!(off.best_move, preferred.best_move);
+        assert_eq!(anti_shuffle.adjust(-300, true), -300);
+    }
+
+    #[test]
+    fn test_declines_repetition_in_equal_position() {
+        // Pretend the position after the preferred move already occurred
+        // on the line being searched
+        let board = Board::default();
+        let preferred = search(&board, 2);
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::default();
+        let mut context = SearchContext::new(None, &stop, &mut tt);
+        context.path.push(board.make_move_new(preferred.best_move).get_hash());
+
+        let (best_move, score) = search_root(&board, 2, &mut context).unwrap();
+        assert_ne!(best_move, preferred.best_move);
+        assert!(score >= 0, "{}", score);
+
+        // The root's score rests on that repetition, so the table keeps
+        // its move but not a depth to trust the score at
+        let entry = tt.probe(board.get_hash()).unwrap();
+        assert_eq!((entry.best_move, entry.depth), (Some(best_move), 0));
+        let mut context = SearchContext::new(None, &stop, &mut tt);
+        search_root(&board, 2, &mut context).unwrap();
+        assert_eq!(tt.probe(board.get_hash()).unwrap().depth, 2);
+    }
+}
@@ -0,0 +1,164 @@
+use chess::{Board, ChessMove};
+
+/// Default number of entries in a transposition table (about 1.5 MB).
+pub const DEFAULT_TT_ENTRIES: usize = 1 << 16;
+
+/// Number of slots [`TranspositionTable::hashfull`] samples.
+const HASHFULL_SAMPLE: usize = 1000;
+
+/// How a stored score relates to the true value of the position.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high: the true value is at least the score.
+    Lower,
+    /// The search failed low: the true value is at most the score.
+    Upper,
+}
+
+/// A search result cached for one position.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+    /// Zobrist hash of the position.
+    pub key: u64,
+    /// Remaining depth the position was searched to.
+    pub depth: u8,
+    /// Score from the side to move's perspective.
+    pub score: i32,
+    pub bound: Bound,
+    /// Move that raised alpha or caused the cutoff, if any.
+    pub best_move: Option<ChessMove>,
+}
+
+/// Fixed-size, always-replace transposition table indexed by Zobrist hash.
+///
+/// See https://www.chessprogramming.org/Transposition_Table
+///
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+}
+
+impl TranspositionTable {
+    /// Create an empty table with `size` slots (at least 1).
+    pub fn new(size: usize) -> Self {
+        TranspositionTable {
+            entries: vec![None; size.max(1)],
+        }
+    }
+
+    /// Number of slots.
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Look up the entry for the position with hash `key`.
+    pub fn probe(&self, key: u64) -> Option<&TtEntry> {
+        self.entries[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    /// Store `entry`, replacing whatever occupied its slot.
+    pub fn store(&mut self, entry: TtEntry) {
+        let index = self.index(entry.key);
+        self.entries[index] = Some(entry);
+    }
+
+    /// Empty every slot, e.g. on `ucinewgame`.
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    /// Occupancy in permille, estimated from the first
+    /// `HASHFULL_SAMPLE` slots as UCI's `hashfull` expects.
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.entries[..self.entries.len().min(HASHFULL_SAMPLE)];
+        let used = sample.iter().filter(|entry| entry.is_some()).count();
+        (used * 1000 / sample.len()) as u32
+    }
+
+    /// The line stored for `board`: its entry's best move, then the best
+    /// move stored for the resulting position, and so on. The walk stops
+    /// at a missing entry or illegal move, after `max_len` moves, or when
+    /// a position repeats, since stored moves can form a cycle.
+    pub fn principal_variation(&self, board: &Board, max_len: usize) -> Vec<(ChessMove, TtEntry)> {
+        let mut line = Vec::new();
+        let mut seen = vec![board.get_hash()];
+        let mut board = *board;
+        while line.len() < max_len {
+            let Some(&entry) = self.probe(board.get_hash()) else {
+                break;
+            };
+            let Some(chess_move) = entry.best_move.filter(|&m| board.legal(m)) else {
+                break;
+            };
+            line.push((chess_move, entry));
+            board = board.make_move_new(chess_move);
+            if seen.contains(&board.get_hash()) {
+                break;
+            }
+            seen.push(board.get_hash());
+        }
+        line
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64) -> TtEntry {
+        TtEntry {
+            key,
+            depth: 3,
+            score: 42,
+            bound: Bound::Exact,
+            best_move: None,
+        }
+    }
+
+    #[test]
+    fn test_probe_checks_full_key() {
+        let mut tt = TranspositionTable::new(16);
+        tt.store(entry(5));
+        assert_eq!(tt.probe(5), Some(&entry(5)));
+        // Same slot, different position
+        assert_eq!(tt.probe(21), None);
+
+        tt.store(entry(21));
+        assert_eq!(tt.probe(5), None);
+        assert_eq!(tt.probe(21).map(|e| e.key), Some(21));
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let mut tt = TranspositionTable::new(4000);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..500 {
+            tt.store(entry(key));
+        }
+        assert_eq!(tt.hashfull(), 500);
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+
+        let mut small = TranspositionTable::new(10);
+        for key in 0..10 {
+            small.store(entry(key));
+        }
+        assert_eq!(small.hashfull(), 1000);
+    }
+}
@@ -0,0 +1,39 @@
+//! Error types shared by the Lichess bot and the harvest sinks.
+//!
+//! Errors are grouped by where they come from so callers can decide how
+//! to react, e.g. reconnect on `Stream` but give up on `Config`.
+
+use licheszter::error::LicheszterError;
+use thiserror::Error;
+
+/// Error returned by the Lichess bot, game handlers and harvest sinks.
+#[derive(Debug, Error)]
+pub enum BotError {
+    /// Streaming events or game state from Lichess failed.
+    #[error("Lichess stream error: {0:?}")]
+    Stream(LicheszterError),
+    /// Sending a move to Lichess failed.
+    #[error("Failed to send move {uci}: {source:?}")]
+    Move {
+        uci: String,
+        source: LicheszterError,
+    },
+    /// A harvest sink could not record or encode data.
+    #[error("Harvest error: {0}")]
+    Harvest(String),
+    /// A Lichess API call outside the licheszter client failed.
+    #[error("Lichess request failed: {0}")]
+    Request(String),
+    /// The bot is misconfigured and retrying won't help.
+    #[error("Configuration error: {0}")]
+    Config(String),
+    /// Saved game state could not be encoded or read back.
+    #[error("Game state error: {0}")]
+    State(String),
+    /// A game task ran longer than the configured game timeout.
+    #[error("Game timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    /// Reading or writing local files failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,312 @@
+//! Aggregate analysis over harvested games.
+//!
+//! Used for repertoire drilling and opening preparation: which positions
+//! the bot keeps reaching, and in which phase of the game; and for
+//! estimating playing strength from the centipawns lost per move.
+
+use chess::{Board, Color};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::GameRecord;
+use crate::engine::search::root_move_scores;
+use crate::util::position::parse_uci_move;
+
+/// How often each position appears across a set of games.
+///
+/// Positions are keyed by the first four FEN fields (placement, side to
+/// move, castling rights, en passant square), so the same position
+/// reached at different move numbers counts together.
+#[derive(Debug, Clone, Default)]
+pub struct PositionFrequencyMap {
+    /// Position key → (occurrences, phase of the first occurrence).
+    positions: HashMap<String, (u32, String)>,
+}
+
+impl PositionFrequencyMap {
+    /// Count every recorded position in `games`.
+    pub fn from_game_records(games: &[GameRecord]) -> Self {
+        let mut map = Self::default();
+        for mr in games.iter().flat_map(|game| &game.moves) {
+            map.positions
+                .entry(position_key(&mr.fen_before))
+                .or_insert_with(|| (0, mr.phase.clone()))
+                .0 += 1;
+        }
+        map
+    }
+
+    /// Number of distinct positions.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Whether no positions were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// How often the position given by `fen` appeared.
+    pub fn frequency(&self, fen: &str) -> u32 {
+        self.positions
+            .get(&position_key(fen))
+            .map(|(count, _)| *count)
+            .unwrap_or(0)
+    }
+
+    /// The `n` most frequent positions with their counts, most frequent
+    /// first (ties broken by position key).
+    pub fn most_common(&self, n: usize) -> Vec<(String, u32)> {
+        self.sorted()
+            .into_iter()
+            .take(n)
+            .map(|(key, count)| (key.to_string(), count))
+            .collect()
+    }
+
+    /// Positions seen more than `n` times, most frequent first.
+    pub fn positions_with_freq_above(&self, n: u32) -> Vec<String> {
+        self.sorted()
+            .into_iter()
+            .filter(|&(_, count)| count > n)
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    /// Total occurrences per game phase.
+    pub fn phase_breakdown(&self) -> HashMap<String, u32> {
+        let mut phases = HashMap::new();
+        for (count, phase) in self.positions.values() {
+            *phases.entry(phase.clone()).or_insert(0) += count;
+        }
+        phases
+    }
+
+    fn sorted(&self) -> Vec<(&str, u32)> {
+        let mut entries: Vec<(&str, u32)> = self
+            .positions
+            .iter()
+            .map(|(key, (count, _))| (key.as_str(), *count))
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        entries
+    }
+}
+
+/// Largest loss counted for a single move, so one missed mate doesn't
+/// swamp the average.
+const MAX_MOVE_LOSS_CP: i32 = 1000;
+
+/// (average centipawn loss, Elo) points, interpolated linearly: a rough
+/// fit of published studies relating ACPL to rating, from engine-like
+/// play down to beginners.
+const ACPL_ELO: [(f64, u32); 11] = [
+    (0.0, 3000),
+    (10.0, 2800),
+    (20.0, 2500),
+    (30.0, 2200),
+    (40.0, 2000),
+    (50.0, 1800),
+    (60.0, 1650),
+    (80.0, 1400),
+    (100.0, 1200),
+    (150.0, 900),
+    (200.0, 700),
+];
+
+/// Elo matching an average centipawn loss of `acpl`, per [`ACPL_ELO`].
+/// Losses beyond the table get its lowest rating.
+pub fn elo_from_acpl(acpl: f64) -> u32 {
+    let acpl = acpl.max(0.0);
+    for pair in ACPL_ELO.windows(2) {
+        let ((low_cpl, high_elo), (high_cpl, low_elo)) = (pair[0], pair[1]);
+        if acpl <= high_cpl {
+            let t = (acpl - low_cpl) / (high_cpl - low_cpl);
+            return (high_elo as f64 - t * (high_elo - low_elo) as f64).round() as u32;
+        }
+    }
+    ACPL_ELO[ACPL_ELO.len() - 1].1
+}
+
+/// Centipawns lost over a number of moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct CentipawnLoss {
+    total: u64,
+    moves: u32,
+}
+
+impl CentipawnLoss {
+    fn average(&self) -> Option<f64> {
+        (self.moves > 0).then(|| self.total as f64 / self.moves as f64)
+    }
+}
+
+/// Playing strength of each side, estimated from the average centipawn
+/// loss (ACPL) of their recorded moves.
+///
+/// A move's loss is how far it scores below the engine's own best move in
+/// the same position, searched to a fixed depth, capped at
+/// [`MAX_MOVE_LOSS_CP`]. The estimate is only as good as the search
+/// depth: a shallow engine can't tell a deep move from a mistake.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EloEstimator {
+    white: CentipawnLoss,
+    black: CentipawnLoss,
+}
+
+impl EloEstimator {
+    /// Score every recorded move of `games` against a `depth` ply search.
+    /// Moves whose position or UCI string doesn't parse are skipped.
+    pub fn from_game_records(games: &[GameRecord], depth: u8) -> Self {
+        let mut estimator = Self::default();
+        for mr in games.iter().flat_map(|game| &game.moves) {
+            let Ok(board) = Board::from_str(&mr.fen_before) else {
+                continue;
+            };
+            let Some(played) = parse_uci_move(&board, &mr.uci) else {
+                continue;
+            };
+            let scores = root_move_scores(&board, depth.max(1));
+            let (Some(&(_, best)), Some(&(_, score))) =
+                (scores.first(), scores.iter().find(|(m, _)| *m == played))
+            else {
+                continue;
+            };
+            let side = match board.side_to_move() {
+                Color::White => &mut estimator.white,
+                Color::Black => &mut estimator.black,
+            };
+            side.total += (best - score).clamp(0, MAX_MOVE_LOSS_CP) as u64;
+            side.moves += 1;
+        }
+        estimator
+    }
+
+    /// White's average centipawn loss, if any White moves were scored.
+    pub fn white_acpl(&self) -> Option<f64> {
+        self.white.average()
+    }
+
+    /// Black's average centipawn loss, if any Black moves were scored.
+    pub fn black_acpl(&self) -> Option<f64> {
+        self.black.average()
+    }
+
+    /// Estimated rating of White, or 0 if no White moves were scored.
+    pub fn estimate_white_elo(&self) -> u32 {
+        self.white_acpl().map_or(0, elo_from_acpl)
+    }
+
+    /// Estimated rating of Black, or 0 if no Black moves were scored.
+    pub fn estimate_black_elo(&self) -> u32 {
+        self.black_acpl().map_or(0, elo_from_acpl)
+    }
+}
+
+/// FEN without the halfmove clock and fullmove number.
+fn position_key(fen: &str) -> String {
+    fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harvest::MoveRecord;
+    use crate::uci::classify_phase;
+    use crate::util::position::board_from_moves;
+
+    fn record(moves: &[&str]) -> MoveRecord {
+        let board = board_from_moves(None, moves).unwrap();
+        MoveRecord {
+            move_number: moves.len() as u32 + 1,
+            side: "black".to_string(),
+            fen_before: board.to_string(),
+            phase: classify_phase(&board).to_string(),
+            piece_count: 32,
+            alternatives: 20,
+            ..Default::default()
+        }
+    }
+
+    fn game(id: &str, line: &[&str]) -> GameRecord {
+        let mut game = GameRecord::new(id.to_string());
+        // The bot plays black: record the position before each black move.
+        for ply in (1..=line.len()).step_by(2) {
+            game.moves.push(record(&line[..ply]));
+        }
+        game
+    }
+
+    #[test]
+    fn test_common_first_move_counted_across_games() {
+        let games = vec![
+            game("g1", &["e2e4", "e7e5", "g1f3"]),
+            game("g2", &["e2e4", "c7c5", "g1f3"]),
+            game("g3", &["e2e4", "e7e6", "d2d4"]),
+        ];
+        let map = PositionFrequencyMap::from_game_records(&games);
+        let after_e4 = board_from_moves(None, &["e2e4"]).unwrap().to_string();
+
+        assert_eq!(map.frequency(&after_e4), 3);
+        assert_eq!(map.len(), 4);
+
+        let top = map.most_common(1);
+        assert_eq!(top, vec![(position_key(&after_e4), 3)]);
+        assert_eq!(map.positions_with_freq_above(1), vec![position_key(&after_e4)]);
+        assert_eq!(map.positions_with_freq_above(3), Vec::<String>::new());
+        assert_eq!(map.phase_breakdown().get("opening"), Some(&6));
+    }
+
+    #[test]
+    fn test_elo_from_acpl() {
+        assert_eq!(elo_from_acpl(0.0), 3000);
+        assert_eq!(elo_from_acpl(25.0), 2350);
+        assert_eq!(elo_from_acpl(100.0), 1200);
+        assert_eq!(elo_from_acpl(1000.0), 700);
+        assert!(elo_from_acpl(5.0) > elo_from_acpl(6.0));
+    }
+
+    #[test]
+    fn test_best_moves_rate_above_blunders() {
+        // White always plays the engine's best move, Black its worst
+        let depth = 1;
+        let mut game = GameRecord::new("elo".to_string());
+        let mut board = Board::default();
+        for ply in 0..8 {
+            let scores = root_move_scores(&board, depth);
+            let (chess_move, _) = match ply % 2 {
+                0 => scores[0],
+                _ => scores[scores.len() - 1],
+            };
+            game.moves.push(MoveRecord {
+                uci: chess_move.to_string(),
+                fen_before: board.to_string(),
+                ..record(&[])
+            });
+            board = board.make_move_new(chess_move);
+        }
+
+        let estimator = EloEstimator::from_game_records(&[game], depth);
+        assert_eq!(estimator.white_acpl(), Some(0.0));
+        assert!(estimator.black_acpl().unwrap() >= 50.0, "{:?}", estimator.black_acpl());
+        assert_eq!(estimator.estimate_white_elo(), 3000);
+        assert!(estimator.estimate_black_elo() < 1800);
+
+        let empty = EloEstimator::from_game_records(&[], depth);
+        assert_eq!((empty.estimate_white_elo(), empty.black_acpl()), (0, None));
+    }
+
+    #[test]
+    fn test_move_counters_ignored() {
+        let fen = "8/8/8/4k3/8/8/8/4K3 w - - 0 1";
+        let later = "8/8/8/4k3/8/8/8/4K3 w - - 12 40";
+        let mut game = GameRecord::new("g".to_string());
+        for fen in [fen, later] {
+            let mut mr = record(&[]);
+            mr.fen_before = fen.to_string();
+            game.moves.push(mr);
+        }
+        let map = PositionFrequencyMap::from_game_records(&[game]);
+        assert_eq!(map.frequency(fen), 2);
+    }
+}
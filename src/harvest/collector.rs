@@ -4,28 +4,179 @@
 //! consumed by crewai-rust agents for training and analysis.
 
 use async_trait::async_trait;
-use log::info;
+use tracing::info;
 use serde_json::json;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use super::{GameRecord, HarvestSink};
+use super::eco;
+use super::{prepare_output_dir, EvalClamp, EvalPerspective, GameRecord, HarvestSink};
+use crate::error::BotError;
+use crate::uci::PositionAnalysis;
 use crate::whatif::BranchTree;
 
 /// Harvester that writes JSONL files for agent consumption.
 pub struct JsonHarvester {
-    output_dir: PathBuf,
+    path: PathBuf,
     buffer: Vec<serde_json::Value>,
+    perspective: EvalPerspective,
+    clamp: EvalClamp,
+    records_before: usize,
 }
 
 impl JsonHarvester {
-    pub fn new(output_dir: PathBuf) -> Self {
-        std::fs::create_dir_all(&output_dir).ok();
-        Self {
-            output_dir,
+    /// Create a harvester writing `live_games.jsonl` in `output_dir`,
+    /// creating the directory if needed. Fails if the directory can't be
+    /// created or written to.
+    pub fn new(output_dir: PathBuf) -> Result<Self, BotError> {
+        prepare_output_dir(&output_dir)?;
+        Ok(Self {
+            path: output_dir.join("live_games.jsonl"),
             buffer: Vec::new(),
-        }
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+            records_before: 0,
+        })
+    }
+
+    /// Create a harvester appending to the JSONL file at `path`, left by
+    /// an earlier session or not yet created. The records already in it
+    /// are counted, and a `session_start` record marking where this
+    /// session's records begin goes out with the first flush.
+    pub fn from_existing_file(path: &Path) -> Result<Self, BotError> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        prepare_output_dir(dir)?;
+        let records_before = match count_records(path) {
+            Ok(records) => records,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => {
+                return Err(BotError::Harvest(format!("Can't read {}: {}", path.display(), e)))
+            }
+        };
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        info!("Appending to {} after {} records", path.display(), records_before);
+        Ok(Self {
+            path: path.to_path_buf(),
+            buffer: vec![json!({
+                "type": "session_start",
+                "timestamp": timestamp,
+                "records_before": records_before,
+            })],
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+            records_before,
+        })
+    }
+
+    /// Records the file already held when this harvester was created.
+    pub fn records_before(&self) -> usize {
+        self.records_before
+    }
+
+    /// Emit evaluations in the given perspective instead of side-to-move.
+    pub fn with_perspective(mut self, perspective: EvalPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+
+    /// Clamp emitted evaluations and split out mate scores.
+    pub fn with_eval_clamp(mut self, clamp: EvalClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+}
+
+/// Non-blank lines in the file at `path`, read a buffer at a time so a
+/// large harvest file isn't loaded whole.
+fn count_records(path: &Path) -> std::io::Result<usize> {
+    let mut records = 0;
+    for line in BufReader::new(File::open(path)?).split(b'\n') {
+        records += line?.iter().any(|byte| !byte.is_ascii_whitespace()) as usize;
     }
+    Ok(records)
+}
+
+/// JSON record of a finished game, as written by [`JsonHarvester`].
+///
+/// `material_progression` is White-relative; `eval_progression` repeats
+/// each move's `eval_cp` in the output perspective. `opening` is
+/// classified from [`GameRecord::uci_line`].
+pub(crate) fn game_json(
+    game: &GameRecord,
+    perspective: EvalPerspective,
+    clamp: EvalClamp,
+) -> serde_json::Value {
+    let mut eval_progression = Vec::with_capacity(game.moves.len());
+    let moves: Vec<serde_json::Value> = game
+        .moves
+        .iter()
+        .map(|mr| {
+            let (eval_cp, mate_in) = clamp.apply(mr.eval_cp_as(perspective));
+            eval_progression.push(eval_cp);
+            let mut record = json!({
+                "move_number": mr.move_number,
+                "side": mr.side,
+                "uci": mr.uci,
+                "fen_before": mr.fen_before,
+                "eval_cp": eval_cp,
+                "phase": mr.phase,
+                "piece_count": mr.piece_count,
+                "think_time_ms": mr.think_time_ms,
+                "clock_ms_remaining": mr.clock_ms_remaining,
+                "clock_ms_used": mr.clock_ms_used,
+                "eval_delta_cp": mr.eval_delta_cp,
+                "is_book": mr.is_book,
+                "alternatives": mr.alternatives,
+                "is_blunder": mr.is_blunder,
+                "best_move_missed": mr.best_move_missed,
+            });
+            if let Some(mate_in) = mate_in {
+                record["mate_in"] = json!(mate_in);
+            }
+            record
+        })
+        .collect();
+
+    let opening = eco::classify(&game.uci_line());
+    json!({
+        "type": "game",
+        "game_id": game.game_id,
+        "white": game.white,
+        "black": game.black,
+        "result": game.result,
+        "bot_color": game.bot_color,
+        "started_at": game.started_at,
+        "book_exit_move": game.book_exit_move,
+        "opening": { "eco": opening.eco, "name": opening.name },
+        "checksum": game.checksum_hex(),
+        "perspective": perspective.as_str(),
+        "lichess_url": game.lichess_url(),
+        "pgn_url": game.lichess_pgn_url(),
+        "analysis_url": game.analysis_url(),
+        "total_moves": game.moves.len(),
+        "material_progression": game.material_progression(),
+        "eval_progression": eval_progression,
+        "moves": moves,
+    })
+}
+
+/// JSON summary of a what-if tree, as written by [`JsonHarvester`].
+pub(crate) fn branch_tree_json(game_id: &str, tree: &BranchTree) -> serde_json::Value {
+    json!({
+        "type": "branch_tree",
+        "game_id": game_id,
+        "root_fen": tree.root_fen,
+        "total_nodes": tree.total_nodes,
+        "max_depth_reached": tree.max_depth_reached,
+        "principal_variation": tree.principal_variation,
+    })
 }
 
 #[async_trait]
@@ -33,37 +184,8 @@ impl HarvestSink for JsonHarvester {
     async fn record_game(
         &mut self,
         game: GameRecord,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let moves: Vec<serde_json::Value> = game
-            .moves
-            .iter()
-            .map(|mr| {
-                json!({
-                    "move_number": mr.move_number,
-                    "side": mr.side,
-                    "uci": mr.uci,
-                    "fen_before": mr.fen_before,
-                    "eval_cp": mr.eval_cp,
-                    "phase": mr.phase,
-                    "piece_count": mr.piece_count,
-                    "think_time_ms": mr.think_time_ms,
-                    "is_book": mr.is_book,
-                    "alternatives": mr.alternatives,
-                })
-            })
-            .collect();
-
-        self.buffer.push(json!({
-            "type": "game",
-            "game_id": game.game_id,
-            "white": game.white,
-            "black": game.black,
-            "result": game.result,
-            "bot_color": game.bot_color,
-            "started_at": game.started_at,
-            "total_moves": game.moves.len(),
-            "moves": moves,
-        }));
+    ) -> Result<(), BotError> {
+        self.buffer.push(game_json(&game, self.perspective, self.clamp));
 
         info!(
             "Collected game {} for JSON harvest ({} moves)",
@@ -73,46 +195,82 @@ impl HarvestSink for JsonHarvester {
         Ok(())
     }
 
+    async fn record_game_batch(
+        &mut self,
+        games: Vec<GameRecord>,
+    ) -> Result<(), BotError> {
+        self.buffer.extend(
+            games
+                .iter()
+                .map(|game| game_json(game, self.perspective, self.clamp)),
+        );
+        info!("Collected a batch of {} games for JSON harvest", games.len());
+        Ok(())
+    }
+
     async fn record_branch_tree(
         &mut self,
         game_id: &str,
         tree: &BranchTree,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.buffer.push(json!({
-            "type": "branch_tree",
-            "game_id": game_id,
-            "root_fen": tree.root_fen,
-            "total_nodes": tree.total_nodes,
-            "max_depth_reached": tree.max_depth_reached,
-            "principal_variation": tree.principal_variation,
-        }));
+    ) -> Result<(), BotError> {
+        self.buffer.push(branch_tree_json(game_id, tree));
+        Ok(())
+    }
+
+    async fn record_analysis(
+        &mut self,
+        game_id: &str,
+        analysis: &PositionAnalysis,
+    ) -> Result<(), BotError> {
+        let eval_cp = self.perspective.convert_fen(analysis.eval_cp, &analysis.fen);
+        let (clamped_cp, mate_in) = self.clamp.apply(eval_cp);
+        let mut record = analysis.to_json_value();
+        record["type"] = json!("analysis");
+        record["game_id"] = json!(game_id);
+        record["eval_cp"] = json!(clamped_cp);
+        if let Some(mate_in) = mate_in {
+            record["mate_in"] = json!(mate_in);
+        }
+        record["perspective"] = json!(self.perspective.as_str());
+        record["best_move_uci"] = json!(analysis.best_move_uci());
+        record["win_prob"] = json!(crate::uci::win_probability(eval_cp));
+        record["legal_move_count"] = json!(analysis.legal_moves.len());
+        self.buffer.push(record);
         Ok(())
     }
 
-    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn flush(&mut self) -> Result<(), BotError> {
         if self.buffer.is_empty() {
             return Ok(());
         }
 
-        let path = self.output_dir.join("live_games.jsonl");
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)?;
+        let mut file = BufWriter::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?,
+        );
 
         for entry in &self.buffer {
             writeln!(file, "{}", entry)?;
         }
+        file.flush()?;
 
         info!(
             "Flushed {} JSON records to {}",
             self.buffer.len(),
-            path.display()
+            self.path.display()
         );
         self.buffer.clear();
 
         Ok(())
     }
+
+    /// Drop unflushed records. Output always goes to the same file, so
+    /// after rotating it the next flush simply starts a new one.
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
 }
 
 /// Multi-harvester that fans out to multiple sinks.
@@ -131,28 +289,186 @@ impl HarvestSink for MultiHarvester {
     async fn record_game(
         &mut self,
         game: GameRecord,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(), BotError> {
         for sink in &mut self.sinks {
             sink.record_game(game.clone()).await?;
         }
         Ok(())
     }
 
+    async fn record_game_batch(
+        &mut self,
+        games: Vec<GameRecord>,
+    ) -> Result<(), BotError> {
+        for sink in &mut self.sinks {
+            sink.record_game_batch(games.clone()).await?;
+        }
+        Ok(())
+    }
+
     async fn record_branch_tree(
         &mut self,
         game_id: &str,
         tree: &BranchTree,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(), BotError> {
         for sink in &mut self.sinks {
             sink.record_branch_tree(game_id, tree).await?;
         }
         Ok(())
     }
 
-    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn record_analysis(
+        &mut self,
+        game_id: &str,
+        analysis: &PositionAnalysis,
+    ) -> Result<(), BotError> {
+        for sink in &mut self.sinks {
+            sink.record_analysis(game_id, analysis).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
         for sink in &mut self.sinks {
             sink.flush().await?;
         }
         Ok(())
     }
+
+    fn reset(&mut self) {
+        for sink in &mut self.sinks {
+            sink.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harvest::MoveRecord;
+    use crate::util::position::board_from_moves;
+
+    #[test]
+    fn test_game_json_names_opening() {
+        // The bot plays Black, so only Black's moves are recorded
+        let line = ["e2e4", "e7e5", "g1f3", "g8f6"];
+        let mut game = GameRecord::new("eco1".to_string());
+        for ply in (1..line.len()).step_by(2) {
+            game.push_move(MoveRecord {
+                move_number: ply as u32 + 1,
+                side: "black".to_string(),
+                uci: line[ply].to_string(),
+                fen_before: board_from_moves(None, &line[..ply]).unwrap().to_string(),
+                phase: "opening".to_string(),
+                piece_count: 32,
+                alternatives: 20,
+                ..Default::default()
+            });
+        }
+        assert_eq!(game.uci_line(), line);
+
+        let json = game_json(&game, EvalPerspective::SideToMove, EvalClamp::none());
+        assert_eq!(json["opening"]["eco"], "C42");
+        assert_eq!(json["opening"]["name"], "Petrov's Defense");
+
+        game.moves.truncate(1);
+        let json = game_json(&game, EvalPerspective::SideToMove, EvalClamp::none());
+        assert_eq!(json["opening"]["eco"], "C20");
+
+        game.moves.clear();
+        let json = game_json(&game, EvalPerspective::SideToMove, EvalClamp::none());
+        assert_eq!(json["opening"], json!({ "eco": "A00", "name": "Unknown" }));
+    }
+
+    #[tokio::test]
+    async fn test_batch_matches_individual_records() {
+        let games: Vec<GameRecord> = (0..100)
+            .map(|n| {
+                let mut game = GameRecord::new(format!("batch{}", n));
+                game.result = "mate".to_string();
+                game.started_at = 0;
+                game
+            })
+            .collect();
+
+        let mut written = Vec::new();
+        for batch in [false, true] {
+            let dir = std::env::temp_dir().join(format!("stonksfish_test_json_batch_{}", batch));
+            std::fs::remove_dir_all(&dir).ok();
+            let mut harvester = JsonHarvester::new(dir.clone()).unwrap();
+            if batch {
+                harvester.record_game_batch(games.clone()).await.unwrap();
+            } else {
+                for game in games.clone() {
+                    harvester.record_game(game).await.unwrap();
+                }
+            }
+            harvester.flush().await.unwrap();
+            written.push(std::fs::read_to_string(dir.join("live_games.jsonl")).unwrap());
+        }
+
+        assert_eq!(written[0].lines().count(), 100);
+        assert_eq!(written[0], written[1]);
+    }
+
+    #[tokio::test]
+    async fn test_from_existing_file_appends() {
+        let dir = std::env::temp_dir().join(format!("stonksfish_test_json_append_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let mut earlier = JsonHarvester::new(dir.clone()).unwrap();
+        for n in 0..5 {
+            earlier.record_game(GameRecord::new(format!("earlier{}", n))).await.unwrap();
+        }
+        earlier.flush().await.unwrap();
+
+        let path = dir.join("live_games.jsonl");
+        let mut harvester = JsonHarvester::from_existing_file(&path).unwrap();
+        assert_eq!(harvester.records_before(), 5);
+        for n in 0..2 {
+            harvester.record_game(GameRecord::new(format!("later{}", n))).await.unwrap();
+        }
+        harvester.flush().await.unwrap();
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 8);
+        assert_eq!(records.iter().filter(|r| r["type"] == "game").count(), 7);
+        assert_eq!(records[5]["type"], "session_start");
+        assert_eq!(records[5]["records_before"], 5);
+        assert_eq!(records[7]["game_id"], "later1");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_count_records_skips_blank_lines() {
+        let path = std::env::temp_dir().join(format!("stonksfish_test_count_{}.jsonl", std::process::id()));
+        // CRLF endings, a blank line, a stray invalid byte and no final newline
+        std::fs::write(&path, b"{}\r\n\n  \n{\"a\":\"\xff\"}\n{}").unwrap();
+        assert_eq!(count_records(&path).unwrap(), 3);
+        std::fs::remove_file(&path).ok();
+        assert!(count_records(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multi_harvester_resets_every_sink() {
+        let dir = std::env::temp_dir().join(format!("stonksfish_test_json_reset_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let json = JsonHarvester::new(dir.clone()).unwrap();
+        let mut multi = MultiHarvester::new(vec![Box::new(json)]);
+
+        multi.record_game(GameRecord::new("dropped".to_string())).await.unwrap();
+        multi.reset();
+        multi.flush().await.unwrap();
+        assert!(!dir.join("live_games.jsonl").exists());
+
+        multi.record_game(GameRecord::new("kept".to_string())).await.unwrap();
+        multi.flush().await.unwrap();
+        let written = std::fs::read_to_string(dir.join("live_games.jsonl")).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.contains("\"kept\""));
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -9,11 +9,15 @@
 //! - Opening identification via ECO codes
 
 use async_trait::async_trait;
-use log::info;
-use std::io::Write;
-use std::path::PathBuf;
+use tracing::{info, warn};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use super::{GameRecord, HarvestSink, MoveRecord};
+use super::{prepare_output_dir, EvalClamp, EvalPerspective, GameRecord, HarvestSink, MoveRecord};
+use crate::error::BotError;
+use crate::uci::PositionAnalysis;
 use crate::whatif::BranchTree;
 
 /// Harvester that writes Cypher statements to files.
@@ -27,25 +31,54 @@ pub struct CypherHarvester {
     buffer: Vec<String>,
     /// Number of games recorded.
     game_count: u32,
+    /// Perspective of the emitted `eval_cp` properties.
+    perspective: EvalPerspective,
+    /// Clamp applied to the emitted `eval_cp` properties.
+    clamp: EvalClamp,
 }
 
 impl CypherHarvester {
-    pub fn new(output_dir: PathBuf) -> Self {
-        std::fs::create_dir_all(&output_dir).ok();
-        Self {
+    /// Create a harvester writing to `output_dir`, creating it if needed.
+    /// Fails if the directory can't be created or written to.
+    pub fn new(output_dir: PathBuf) -> Result<Self, BotError> {
+        prepare_output_dir(&output_dir)?;
+        Ok(Self {
             output_dir,
             buffer: Vec::new(),
             game_count: 0,
-        }
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+        })
+    }
+
+    /// Emit evaluations in the given perspective instead of side-to-move.
+    pub fn with_perspective(mut self, perspective: EvalPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+
+    /// Clamp emitted evaluations and split out mate scores.
+    pub fn with_eval_clamp(mut self, clamp: EvalClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// Just the constraint and index statements of the schema, one per
+    /// line, for setting up an empty database before a bulk import.
+    pub fn emit_constraints_only() -> String {
+        SCHEMA.iter().map(|stmt| format!("{}\n", stmt)).collect()
     }
 
     /// Generate Cypher for a Game node.
-    fn game_cypher(game: &GameRecord) -> String {
+    fn game_cypher(game: &GameRecord, perspective: EvalPerspective) -> String {
         format!(
             "MERGE (g:Game:LiveGame {{id: '{game_id}'}}) \
              SET g.white = '{white}', g.black = '{black}', \
              g.result = '{result}', g.bot_color = '{bot_color}', \
-             g.started_at = {started_at}, g.total_moves = {total_moves};\n",
+             g.started_at = {started_at}, g.total_moves = {total_moves}, \
+             g.book_exit_move = {book_exit_move}, \
+             g.checksum = '{checksum}', \
+             g.eval_perspective = '{perspective}';\n",
             game_id = escape_cypher(&game.game_id),
             white = escape_cypher(&game.white),
             black = escape_cypher(&game.black),
@@ -53,11 +86,16 @@ impl CypherHarvester {
             bot_color = escape_cypher(&game.bot_color),
             started_at = game.started_at,
             total_moves = game.moves.len(),
+            book_exit_move = game
+                .book_exit_move
+                .map_or_else(|| "null".to_string(), |m| m.to_string()),
+            checksum = game.checksum_hex(),
+            perspective = perspective.as_str(),
         )
     }
 
     /// Generate Cypher for a Position node with phase-based multi-label.
-    fn position_cypher(mr: &MoveRecord) -> String {
+    fn position_cypher(mr: &MoveRecord, perspective: EvalPerspective, clamp: EvalClamp) -> String {
         let phase_label = match mr.phase.as_str() {
             "opening" => ":Opening",
             "middlegame" => ":Middlegame",
@@ -65,32 +103,48 @@ impl CypherHarvester {
             _ => "",
         };
 
+        let (eval_cp, mate_in) = clamp.apply(mr.eval_cp_as(perspective));
         format!(
             "MERGE (p:Position{phase_label} {{fen: '{fen}'}}) \
-             SET p.eval_cp = {eval_cp}, p.phase = '{phase}', \
+             SET p.eval_cp = {eval_cp}{mate_in}, p.phase = '{phase}', \
              p.piece_count = {piece_count};\n",
             phase_label = phase_label,
             fen = escape_cypher(&mr.fen_before),
-            eval_cp = mr.eval_cp,
+            eval_cp = eval_cp,
+            mate_in = mate_in_set("p", mate_in),
             phase = escape_cypher(&mr.phase),
             piece_count = mr.piece_count,
         )
     }
 
-    /// Generate Cypher for a MOVE relationship between positions.
-    fn move_cypher(from: &MoveRecord, to_fen: &str, game_id: &str) -> String {
+    /// Generate Cypher for a MOVE relationship between positions. The
+    /// relationship is merged on `game_id` and `move_number` alone, so
+    /// harvesting a game again updates it instead of adding another.
+    fn move_cypher(
+        from: &MoveRecord,
+        to_fen: &str,
+        game_id: &str,
+        perspective: EvalPerspective,
+        clamp: EvalClamp,
+    ) -> String {
+        let (eval_cp, mate_in) = clamp.apply(from.eval_cp_as(perspective));
         format!(
             "MATCH (from:Position {{fen: '{from_fen}'}}), \
              (to:Position {{fen: '{to_fen}'}}) \
-             MERGE (from)-[:MOVE {{uci: '{uci}', eval_cp: {eval_cp}, \
-             think_time_ms: {think_ms}, move_number: {move_num}, \
-             game_id: '{game_id}', side: '{side}', \
-             alternatives: {alts}, is_book: {is_book}}}]->(to);\n",
+             MERGE (from)-[m:MOVE {{game_id: '{game_id}', move_number: {move_num}}}]->(to) \
+             SET m.uci = '{uci}', m.eval_cp = {eval_cp}{mate_in}, \
+             m.think_time_ms = {think_ms}, m.clock_ms_remaining = {clock_remaining}, \
+             m.clock_ms_used = {clock_used}, m.eval_delta_cp = {eval_delta}, \
+             m.side = '{side}', m.alternatives = {alts}, m.is_book = {is_book};\n",
             from_fen = escape_cypher(&from.fen_before),
             to_fen = escape_cypher(to_fen),
             uci = escape_cypher(&from.uci),
-            eval_cp = from.eval_cp,
+            eval_cp = eval_cp,
+            mate_in = mate_in_set("m", mate_in),
             think_ms = from.think_time_ms,
+            clock_remaining = from.clock_ms_remaining,
+            clock_used = from.clock_ms_used,
+            eval_delta = from.eval_delta_cp,
             move_num = from.move_number,
             game_id = escape_cypher(game_id),
             side = escape_cypher(&from.side),
@@ -99,23 +153,52 @@ impl CypherHarvester {
         )
     }
 
-    /// Generate Cypher for linking a Game to its positions.
+    /// Generate Cypher for linking a Game to its positions, merged on
+    /// `game_id` and `move_number` like the MOVE relationships.
     fn game_position_cypher(game_id: &str, fen: &str, move_number: u32) -> String {
         format!(
             "MATCH (g:Game {{id: '{game_id}'}}), \
              (p:Position {{fen: '{fen}'}}) \
-             MERGE (g)-[:PLAYED_MOVE {{move_number: {move_number}}}]->(p);\n",
+             MERGE (g)-[:PLAYED_MOVE {{game_id: '{game_id}', move_number: {move_number}}}]->(p);\n",
             game_id = escape_cypher(game_id),
             fen = escape_cypher(fen),
             move_number = move_number,
         )
     }
 
+    /// Generate Cypher for the analysis properties of a Position node.
+    fn analysis_cypher(analysis: &PositionAnalysis, perspective: EvalPerspective) -> String {
+        let eval_cp = perspective.convert_fen(analysis.eval_cp, &analysis.fen);
+        format!(
+            "MERGE (p:Position {{fen: '{fen}'}}) \
+             SET p.best_move_uci = '{best_move}', p.win_prob = {win_prob:.4}, \
+             p.legal_move_count = {legal_move_count};\n",
+            fen = escape_cypher(&analysis.fen),
+            best_move = escape_cypher(analysis.best_move_uci().unwrap_or("")),
+            win_prob = crate::uci::win_probability(eval_cp),
+            legal_move_count = analysis.legal_moves.len(),
+        )
+    }
+
     /// Generate Cypher for a BranchTree (what-if analysis).
-    fn branch_tree_cypher(game_id: &str, tree: &BranchTree) -> Vec<String> {
+    fn branch_tree_cypher(
+        game_id: &str,
+        tree: &BranchTree,
+        perspective: EvalPerspective,
+        clamp: EvalClamp,
+    ) -> Vec<String> {
         let mut stmts = Vec::new();
 
         for node in &tree.nodes {
+            // A child's `eval_cp` is from the side that moved into it, i.e.
+            // its parent's side to move; the position itself is scored
+            // for its own side to move like every other Position node
+            let parent = node.parent_id.as_deref().and_then(|id| tree.node(id));
+            let own_eval = match parent {
+                Some(_) => -node.eval_cp,
+                None => node.eval_cp,
+            };
+            let (eval_cp, mate_in) = clamp.apply(perspective.convert_fen(own_eval, &node.fen));
             let phase_label = match node.phase.as_str() {
                 "opening" => ":Opening",
                 "middlegame" => ":Middlegame",
@@ -126,66 +209,75 @@ impl CypherHarvester {
             // Create position node for each branch position
             stmts.push(format!(
                 "MERGE (p:Position{phase_label} {{fen: '{fen}'}}) \
-                 SET p.eval_cp = {eval_cp}, p.phase = '{phase}', \
+                 SET p.eval_cp = {eval_cp}{mate_in}, p.phase = '{phase}', \
                  p.piece_count = {piece_count};\n",
                 phase_label = phase_label,
                 fen = escape_cypher(&node.fen),
-                eval_cp = node.eval_cp,
+                eval_cp = eval_cp,
+                mate_in = mate_in_set("p", mate_in),
                 phase = escape_cypher(&node.phase),
                 piece_count = node.piece_count,
             ));
 
             // Create branch relationship
-            if let (Some(ref parent_id), Some(ref move_uci)) =
-                (&node.parent_id, &node.move_uci)
-            {
-                // Find parent FEN
-                if let Some(parent) = tree.nodes.iter().find(|n| &n.branch_id == parent_id) {
-                    stmts.push(format!(
-                        "MATCH (from:Position {{fen: '{from_fen}'}}), \
-                         (to:Position {{fen: '{to_fen}'}}) \
-                         MERGE (from)-[:WHATIF_MOVE {{uci: '{uci}', \
-                         game_id: '{game_id}', branch_id: '{branch_id}', \
-                         depth: {depth}, eval_cp: {eval_cp}}}]->(to);\n",
-                        from_fen = escape_cypher(&parent.fen),
-                        to_fen = escape_cypher(&node.fen),
-                        uci = escape_cypher(move_uci),
-                        game_id = escape_cypher(game_id),
-                        branch_id = escape_cypher(&node.branch_id),
-                        depth = node.depth,
-                        eval_cp = node.eval_cp,
-                    ));
-                }
+            if let (Some(parent), Some(move_uci)) = (parent, &node.move_uci) {
+                let (move_eval, move_mate_in) =
+                    clamp.apply(perspective.convert_fen(node.eval_cp, &parent.fen));
+                stmts.push(format!(
+                    "MATCH (from:Position {{fen: '{from_fen}'}}), \
+                     (to:Position {{fen: '{to_fen}'}}) \
+                     MERGE (from)-[:WHATIF_MOVE {{uci: '{uci}', \
+                     game_id: '{game_id}', branch_id: '{branch_id}', \
+                     depth: {depth}, eval_cp: {eval_cp}{mate_in}}}]->(to);\n",
+                    from_fen = escape_cypher(&parent.fen),
+                    to_fen = escape_cypher(&node.fen),
+                    uci = escape_cypher(move_uci),
+                    game_id = escape_cypher(game_id),
+                    branch_id = escape_cypher(&node.branch_id),
+                    depth = node.depth,
+                    eval_cp = move_eval,
+                    mate_in = mate_in_prop(move_mate_in),
+                ));
             }
         }
 
         stmts
     }
-}
 
-#[async_trait]
-impl HarvestSink for CypherHarvester {
-    async fn record_game(
-        &mut self,
-        game: GameRecord,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// Generate all Cypher for a completed game: the Game node, its
+    /// positions and the MOVE edges between them.
+    fn game_statements(
+        game: &GameRecord,
+        perspective: EvalPerspective,
+        clamp: EvalClamp,
+    ) -> Vec<String> {
         // Game node
-        self.buffer.push(Self::game_cypher(&game));
+        let mut stmts = vec![Self::game_cypher(game, perspective)];
 
         // Position nodes and MOVE relationships
         for (i, mr) in game.moves.iter().enumerate() {
-            self.buffer.push(Self::position_cypher(mr));
-            self.buffer
-                .push(Self::game_position_cypher(&game.game_id, &mr.fen_before, mr.move_number));
+            stmts.push(Self::position_cypher(mr, perspective, clamp));
+            stmts.push(Self::game_position_cypher(&game.game_id, &mr.fen_before, mr.move_number));
 
-            // MOVE edge to the next position
-            if i + 1 < game.moves.len() {
-                let next_fen = &game.moves[i + 1].fen_before;
-                self.buffer
-                    .push(Self::move_cypher(mr, next_fen, &game.game_id));
+            // MOVE edge from the previous position, now that both exist
+            if let Some(prev) = i.checked_sub(1).map(|prev| &game.moves[prev]) {
+                stmts.push(Self::move_cypher(prev, &mr.fen_before, &game.game_id, perspective, clamp));
             }
         }
 
+        stmts
+    }
+}
+
+#[async_trait]
+impl HarvestSink for CypherHarvester {
+    async fn record_game(
+        &mut self,
+        game: GameRecord,
+    ) -> Result<(), BotError> {
+        self.buffer
+            .extend(Self::game_statements(&game, self.perspective, self.clamp));
+
         self.game_count += 1;
         info!(
             "Harvested game {} ({} moves, {} positions)",
@@ -197,12 +289,25 @@ impl HarvestSink for CypherHarvester {
         Ok(())
     }
 
+    async fn record_game_batch(
+        &mut self,
+        games: Vec<GameRecord>,
+    ) -> Result<(), BotError> {
+        for game in &games {
+            self.buffer
+                .extend(Self::game_statements(game, self.perspective, self.clamp));
+        }
+        self.game_count += games.len() as u32;
+        info!("Harvested a batch of {} games", games.len());
+        Ok(())
+    }
+
     async fn record_branch_tree(
         &mut self,
         game_id: &str,
         tree: &BranchTree,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let stmts = Self::branch_tree_cypher(game_id, tree);
+    ) -> Result<(), BotError> {
+        let stmts = Self::branch_tree_cypher(game_id, tree, self.perspective, self.clamp);
         self.buffer.extend(stmts);
         info!(
             "Harvested branch tree for game {} ({} nodes)",
@@ -211,7 +316,18 @@ impl HarvestSink for CypherHarvester {
         Ok(())
     }
 
-    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn record_analysis(
+        &mut self,
+        game_id: &str,
+        analysis: &PositionAnalysis,
+    ) -> Result<(), BotError> {
+        self.buffer
+            .push(Self::analysis_cypher(analysis, self.perspective));
+        info!("Harvested analysis for game {} ({})", game_id, analysis.fen);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
         if self.buffer.is_empty() {
             return Ok(());
         }
@@ -219,7 +335,7 @@ impl HarvestSink for CypherHarvester {
         let filename = format!("live_games_{:04}.cypher", self.game_count);
         let path = self.output_dir.join(&filename);
 
-        let mut file = std::fs::File::create(&path)?;
+        let mut file = BufWriter::new(std::fs::File::create(&path)?);
 
         // Write header
         writeln!(
@@ -232,29 +348,724 @@ impl HarvestSink for CypherHarvester {
         )?;
         writeln!(file, "// Games harvested: {}\n", self.game_count)?;
 
-        // Write constraints (idempotent)
+        // Write constraints and indexes (idempotent)
+        for stmt in SCHEMA {
+            writeln!(file, "{}", stmt)?;
+        }
+        writeln!(file)?;
+
+        // Write all buffered statements
+        for stmt in &self.buffer {
+            write!(file, "{}", stmt)?;
+        }
+        file.flush()?;
+
+        drop(file);
+        warn_if_invalid(&path);
+
+        info!("Flushed {} Cypher statements to {}", self.buffer.len(), path.display());
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    /// Drop unflushed statements and restart the game count, so the next
+    /// file is `live_games_0001.cypher` again. Move earlier files away
+    /// first, or they will be overwritten.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.game_count = 0;
+    }
+}
+
+/// Uniqueness constraints and indexes written at the top of every Cypher
+/// file.
+const SCHEMA: [&str; 5] = [
+    "CREATE CONSTRAINT game_id IF NOT EXISTS FOR (g:Game) REQUIRE g.id IS UNIQUE;",
+    "CREATE CONSTRAINT position_fen IF NOT EXISTS FOR (p:Position) REQUIRE p.fen IS UNIQUE;",
+    "CREATE INDEX position_phase IF NOT EXISTS FOR (p:Position) ON (p.phase);",
+    "CREATE INDEX move_game_id IF NOT EXISTS FOR ()-[m:MOVE]-() ON (m.game_id);",
+    "CREATE INDEX move_uci IF NOT EXISTS FOR ()-[m:MOVE]-() ON (m.uci);",
+];
+
+/// Default number of statements buffered before a batch harvester
+/// writes a file on its own.
+pub const DEFAULT_BATCH_SIZE: usize = 5_000;
+
+/// Harvester that groups the Cypher statements for each game (or branch
+/// tree) into an explicit transaction.
+///
+/// Output uses the `:BEGIN` / `:COMMIT` markers understood by
+/// `cypher-shell --format plain`, so Neo4j commits once per game instead of
+/// once per statement. Once `batch_size` statements are buffered the
+/// harvester flushes on its own.
+pub struct CypherBatchHarvester {
+    /// Output directory for .cypher files.
+    output_dir: PathBuf,
+    /// Buffered transactions, each a list of Cypher statements.
+    transactions: Vec<Vec<String>>,
+    /// Total statements across all buffered transactions.
+    pending_statements: usize,
+    /// Statements to buffer before flushing automatically.
+    batch_size: usize,
+    /// Number of files written.
+    batch_count: u32,
+    /// Perspective of the emitted `eval_cp` properties.
+    perspective: EvalPerspective,
+    /// Clamp applied to the emitted `eval_cp` properties.
+    clamp: EvalClamp,
+}
+
+impl CypherBatchHarvester {
+    /// Create a harvester writing to `output_dir`, creating it if needed.
+    /// Fails if the directory can't be created or written to.
+    pub fn new(output_dir: PathBuf) -> Result<Self, BotError> {
+        prepare_output_dir(&output_dir)?;
+        Ok(Self {
+            output_dir,
+            transactions: Vec::new(),
+            pending_statements: 0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch_count: 0,
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+        })
+    }
+
+    /// Flush automatically once `batch_size` statements are buffered.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Emit evaluations in the given perspective instead of side-to-move.
+    pub fn with_perspective(mut self, perspective: EvalPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+
+    /// Clamp emitted evaluations and split out mate scores.
+    pub fn with_eval_clamp(mut self, clamp: EvalClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// Render statements as a single `:BEGIN` / `:COMMIT` transaction.
+    fn transaction_block(stmts: &[String]) -> String {
+        let mut block = String::from(":BEGIN\n");
+        for stmt in stmts {
+            block.push_str(stmt);
+        }
+        block.push_str(":COMMIT\n");
+        block
+    }
+
+    /// Buffer a transaction and flush if the batch is full.
+    async fn push_transaction(&mut self, stmts: Vec<String>) -> Result<(), BotError> {
+        if stmts.is_empty() {
+            return Ok(());
+        }
+        self.pending_statements += stmts.len();
+        self.transactions.push(stmts);
+        if self.pending_statements >= self.batch_size {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HarvestSink for CypherBatchHarvester {
+    async fn record_game(
+        &mut self,
+        game: GameRecord,
+    ) -> Result<(), BotError> {
+        let stmts = CypherHarvester::game_statements(&game, self.perspective, self.clamp);
+        info!(
+            "Harvested game {} into a transaction of {} statements",
+            game.game_id,
+            stmts.len()
+        );
+        self.push_transaction(stmts).await
+    }
+
+    async fn record_branch_tree(
+        &mut self,
+        game_id: &str,
+        tree: &BranchTree,
+    ) -> Result<(), BotError> {
+        let stmts =
+            CypherHarvester::branch_tree_cypher(game_id, tree, self.perspective, self.clamp);
+        self.push_transaction(stmts).await
+    }
+
+    async fn record_analysis(
+        &mut self,
+        _game_id: &str,
+        analysis: &PositionAnalysis,
+    ) -> Result<(), BotError> {
+        let stmt = CypherHarvester::analysis_cypher(analysis, self.perspective);
+        self.push_transaction(vec![stmt]).await
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
+        if self.transactions.is_empty() {
+            return Ok(());
+        }
+
+        self.batch_count += 1;
+        let filename = format!("live_games_batch_{:04}.cypher", self.batch_count);
+        let path = self.output_dir.join(&filename);
+
+        let mut file = std::fs::File::create(&path)?;
+
+        // Write header
         writeln!(
             file,
-            "CREATE CONSTRAINT IF NOT EXISTS FOR (g:Game) REQUIRE g.id IS UNIQUE;"
+            "// Auto-generated by stonksfish-ada live game harvester"
         )?;
         writeln!(
             file,
-            "CREATE CONSTRAINT IF NOT EXISTS FOR (p:Position) REQUIRE p.fen IS UNIQUE;\n"
+            "// Compatible with aiwar-neo4j-harvest chess schema"
         )?;
+        writeln!(file, "// Transactions: {}\n", self.transactions.len())?;
 
-        // Write all buffered statements
-        for stmt in &self.buffer {
-            write!(file, "{}", stmt)?;
+        // Schema changes can't share a transaction with data writes
+        let schema: Vec<String> = SCHEMA.iter().map(|stmt| format!("{}\n", stmt)).collect();
+        write!(file, "{}", Self::transaction_block(&schema))?;
+
+        for stmts in &self.transactions {
+            write!(file, "{}", Self::transaction_block(stmts))?;
         }
+        drop(file);
+        warn_if_invalid(&path);
 
-        info!("Flushed {} Cypher statements to {}", self.buffer.len(), path.display());
-        self.buffer.clear();
+        info!(
+            "Flushed {} Cypher statements in {} transactions to {}",
+            self.pending_statements,
+            self.transactions.len(),
+            path.display()
+        );
+        self.transactions.clear();
+        self.pending_statements = 0;
 
         Ok(())
     }
+
+    /// Drop unflushed transactions and restart the batch numbering, so
+    /// the next file is `live_games_batch_0001.cypher` again.
+    fn reset(&mut self) {
+        self.transactions.clear();
+        self.pending_statements = 0;
+        self.batch_count = 0;
+    }
+}
+
+/// A problem found in a generated Cypher file. `line` is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CypherError {
+    /// A `MATCH` on a Position whose FEN no earlier statement `MERGE`d.
+    UnresolvedFen { line: usize, fen: String },
+    /// A matched Game id, or the `game_id` of a MOVE relationship, with
+    /// no Game node `MERGE`d before it.
+    UnresolvedGameId { line: usize, game_id: String },
+    /// An unterminated string, unbalanced bracket or missing `;`. A file
+    /// that can't be read is reported at line 0.
+    SyntaxError { line: usize, message: String },
+}
+
+impl fmt::Display for CypherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CypherError::UnresolvedFen { line, fen } => {
+                write!(f, "line {}: no Position node for FEN '{}'", line, fen)
+            }
+            CypherError::UnresolvedGameId { line, game_id } => {
+                write!(f, "line {}: no Game node for id '{}'", line, game_id)
+            }
+            CypherError::SyntaxError { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// Checks generated Cypher before it is imported into Neo4j.
+///
+/// A `MATCH` on a node that doesn't exist yet matches nothing, so the
+/// relationship merged after it is silently dropped. The validator
+/// catches this by requiring every matched Position FEN and Game id to
+/// have been `MERGE`d earlier in the same file. It reads the harvesters'
+/// one-statement-per-line output, skipping comments and the
+/// `:BEGIN` / `:COMMIT` markers.
+pub struct CypherValidator;
+
+impl CypherValidator {
+    /// Every problem found in the file at `path`, in file order.
+    pub fn validate_file(path: &Path) -> Vec<CypherError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::validate_str(&text),
+            Err(e) => vec![CypherError::SyntaxError {
+                line: 0,
+                message: format!("Cannot read {}: {}", path.display(), e),
+            }],
+        }
+    }
+
+    /// Every problem found in `text`, in order.
+    pub fn validate_str(text: &str) -> Vec<CypherError> {
+        let mut errors = Vec::new();
+        let mut fens = HashSet::new();
+        let mut game_ids = HashSet::new();
+
+        for (index, stmt) in text.lines().enumerate() {
+            let line = index + 1;
+            let stmt = stmt.trim();
+            if stmt.is_empty() || stmt.starts_with("//") || stmt.starts_with(':') {
+                continue;
+            }
+
+            let (literals, tail) = match split_literals(stmt) {
+                Ok(parts) => parts,
+                Err(message) => {
+                    errors.push(CypherError::SyntaxError { line, message });
+                    continue;
+                }
+            };
+            let syntax = check_brackets(literals.iter().map(|(text, _)| *text).chain([tail]))
+                .and_then(|()| match tail.trim_end().ends_with(';') {
+                    true => Ok(()),
+                    false => Err("statement not terminated by ';'".to_string()),
+                });
+            if let Err(message) = syntax {
+                errors.push(CypherError::SyntaxError { line, message });
+                continue;
+            }
+
+            let merges = stmt.starts_with("MERGE");
+            let is_move = stmt.contains(":MOVE {");
+            for (before, literal) in literals {
+                let before = before.trim_end();
+                if before.ends_with("fen:") {
+                    if merges {
+                        fens.insert(literal);
+                    } else if !fens.contains(literal) {
+                        errors.push(CypherError::UnresolvedFen {
+                            line,
+                            fen: literal.to_string(),
+                        });
+                    }
+                } else if before.ends_with("game_id:") {
+                    if is_move && !game_ids.contains(literal) {
+                        errors.push(CypherError::UnresolvedGameId {
+                            line,
+                            game_id: literal.to_string(),
+                        });
+                    }
+                } else if before.ends_with("id:") && before.contains(":Game") {
+                    if merges {
+                        game_ids.insert(literal);
+                    } else if !game_ids.contains(literal) {
+                        errors.push(CypherError::UnresolvedGameId {
+                            line,
+                            game_id: literal.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        errors
+    }
 }
 
-/// Escape single quotes for Cypher string literals.
+/// A statement's (text before, literal) pairs, one per string literal,
+/// plus the text after the last one.
+type SplitStatement<'a> = (Vec<(&'a str, &'a str)>, &'a str);
+
+/// Split a statement around its string literals. Literals are returned
+/// still escaped, which is enough to compare them.
+fn split_literals(stmt: &str) -> Result<SplitStatement<'_>, String> {
+    let mut parts = Vec::new();
+    let mut text_start = 0;
+    let mut chars = stmt.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '\'' && c != '"' {
+            continue;
+        }
+        let mut end = None;
+        while let Some((i, next)) = chars.next() {
+            match next {
+                '\\' => {
+                    chars.next();
+                }
+                next if next == c => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.ok_or_else(|| format!("unterminated string starting at column {}", start + 1))?;
+        parts.push((&stmt[text_start..start], &stmt[start + 1..end]));
+        text_start = end + 1;
+    }
+    Ok((parts, &stmt[text_start..]))
+}
+
+/// Check that the brackets in the text outside string literals balance.
+fn check_brackets<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+    let mut open = Vec::new();
+    for c in chunks.into_iter().flat_map(str::chars) {
+        let expected = match c {
+            '(' | '[' | '{' => {
+                open.push(c);
+                continue;
+            }
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => continue,
+        };
+        if open.pop() != Some(expected) {
+            return Err(format!("unmatched '{}'", c));
+        }
+    }
+    match open.last() {
+        Some(c) => Err(format!("unclosed '{}'", c)),
+        None => Ok(()),
+    }
+}
+
+/// Log whatever [`CypherValidator`] finds wrong with a file just written.
+fn warn_if_invalid(path: &Path) {
+    for error in CypherValidator::validate_file(path) {
+        warn!("Invalid Cypher in {}: {}", path.display(), error);
+    }
+}
+
+/// Escape backslashes and single quotes for Cypher string literals.
 fn escape_cypher(s: &str) -> String {
-    s.replace('\'', "\\'").replace('\\', "\\\\")
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// `, <var>.mate_in = N` for a SET clause, or nothing if there's no mate.
+fn mate_in_set(var: &str, mate_in: Option<i32>) -> String {
+    mate_in
+        .map(|m| format!(", {}.mate_in = {}", var, m))
+        .unwrap_or_default()
+}
+
+/// `, mate_in: N` for a relationship property map, or nothing if there's
+/// no mate.
+fn mate_in_prop(mate_in: Option<i32>) -> String {
+    mate_in.map(|m| format!(", mate_in: {}", m)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::analyze_position;
+    use chess::Board;
+
+    #[test]
+    fn test_game_cypher_book_exit_move() {
+        let mut game = sample_game();
+        let stmt = CypherHarvester::game_cypher(&game, EvalPerspective::SideToMove);
+        assert!(stmt.contains("g.book_exit_move = null,"));
+
+        game.book_exit_move = Some(3);
+        let stmt = CypherHarvester::game_cypher(&game, EvalPerspective::SideToMove);
+        assert!(stmt.contains("g.book_exit_move = 3,"));
+        assert!(stmt.contains(&format!("g.checksum = '{}'", game.checksum_hex())));
+    }
+
+    #[test]
+    fn test_eval_clamp_splits_out_mate_scores() {
+        let mut mr = sample_game().moves.remove(0);
+        mr.eval_cp = crate::engine::search::MATE_SCORE - 1;
+        let clamp = EvalClamp::new(2000);
+
+        let stmt = CypherHarvester::position_cypher(&mr, EvalPerspective::SideToMove, clamp);
+        assert!(stmt.contains("SET p.eval_cp = 2000, p.mate_in = 1,"));
+        let stmt = CypherHarvester::move_cypher(&mr, "next", "g1", EvalPerspective::SideToMove, clamp);
+        assert!(stmt.contains("m.eval_cp = 2000, m.mate_in = 1,"));
+
+        // No clamp: output is unchanged
+        let stmt = CypherHarvester::position_cypher(&mr, EvalPerspective::SideToMove, EvalClamp::none());
+        assert!(stmt.contains(&format!("SET p.eval_cp = {}, p.phase", mr.eval_cp)));
+    }
+
+    #[test]
+    fn test_reharvested_game_merges_same_relationships() {
+        // The MERGE part of each statement, which decides what is created
+        let merges = |game: &GameRecord| -> Vec<String> {
+            CypherHarvester::game_statements(game, EvalPerspective::SideToMove, EvalClamp::none())
+                .iter()
+                .map(|stmt| {
+                    let merge = &stmt[stmt.find("MERGE").unwrap()..];
+                    merge.split(" SET ").next().unwrap().trim_end_matches(";\n").to_string()
+                })
+                .collect()
+        };
+        let game = sample_game();
+        let mut resumed = sample_game();
+        for mr in &mut resumed.moves {
+            mr.eval_cp += 15;
+            mr.think_time_ms += 250;
+            mr.clock_ms_remaining -= 250;
+        }
+        assert_eq!(merges(&game), merges(&resumed));
+
+        let merges = merges(&game);
+        assert!(merges.contains(
+            &"MERGE (from)-[m:MOVE {game_id: 'abc123', move_number: 1}]->(to)".to_string()
+        ));
+        assert!(merges.contains(
+            &"MERGE (g)-[:PLAYED_MOVE {game_id: 'abc123', move_number: 3}]->(p)".to_string()
+        ));
+        let unique: HashSet<&String> = merges.iter().collect();
+        assert_eq!(unique.len(), merges.len());
+    }
+
+    #[test]
+    fn test_branch_tree_child_eval_perspective() {
+        let mut tree = crate::whatif::generate_branch_tree(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &crate::whatif::BranchConfig::quick(),
+        )
+        .unwrap();
+        // After a White move: +37 for White, who moved into it
+        let child = tree.nodes.iter().position(|n| n.depth == 1).unwrap();
+        tree.nodes[child].eval_cp = 37;
+        let fen = escape_cypher(&tree.nodes[child].fen);
+        let branch_id = tree.nodes[child].branch_id.clone();
+
+        let statements = |perspective| {
+            CypherHarvester::branch_tree_cypher("g1", &tree, perspective, EvalClamp::none())
+        };
+        let position = |stmts: &[String]| {
+            stmts
+                .iter()
+                .find(|s| s.starts_with("MERGE (p:Position") && s.contains(&fen))
+                .cloned()
+                .unwrap()
+        };
+        let edge = |stmts: &[String]| {
+            stmts
+                .iter()
+                .find(|s| s.contains(&format!("branch_id: '{}'", branch_id)))
+                .cloned()
+                .unwrap()
+        };
+
+        let white = statements(EvalPerspective::White);
+        assert!(position(&white).contains("p.eval_cp = 37,"));
+        assert!(edge(&white).contains("eval_cp: 37}"));
+
+        // Side to move: the position is Black's to move, the edge White's
+        let stm = statements(EvalPerspective::SideToMove);
+        assert!(position(&stm).contains("p.eval_cp = -37,"));
+        assert!(edge(&stm).contains("eval_cp: 37}"));
+    }
+
+    #[test]
+    fn test_emit_constraints_only() {
+        let schema = CypherHarvester::emit_constraints_only();
+        let names: Vec<(&str, &str)> = schema
+            .lines()
+            .map(|stmt| {
+                let mut words = stmt.split_whitespace().skip(1);
+                (words.next().unwrap(), words.next().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("CONSTRAINT", "game_id"),
+                ("CONSTRAINT", "position_fen"),
+                ("INDEX", "position_phase"),
+                ("INDEX", "move_game_id"),
+                ("INDEX", "move_uci"),
+            ]
+        );
+        assert!(schema.lines().all(|stmt| stmt.starts_with("CREATE ") && stmt.contains(" IF NOT EXISTS ")));
+        assert!(CypherValidator::validate_str(&schema).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_analysis_emits_set_clause() {
+        let dir = std::env::temp_dir().join("stonksfish_test_cypher_analysis");
+        let mut harvester = CypherHarvester::new(dir).unwrap();
+        let analysis = analyze_position(&Board::default(), 1);
+
+        harvester.record_analysis("abc123", &analysis).await.unwrap();
+
+        assert_eq!(harvester.buffer.len(), 1);
+        let stmt = &harvester.buffer[0];
+        assert!(stmt.starts_with("MERGE (p:Position {fen: 'rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1'})"));
+        assert!(stmt.contains(&format!(
+            "SET p.best_move_uci = '{}', p.win_prob = ",
+            analysis.legal_moves[0].uci
+        )));
+        assert!(stmt.contains("p.legal_move_count = 20;"));
+    }
+
+    fn sample_game() -> GameRecord {
+        let mut game = GameRecord::new("abc123".to_string());
+        for (i, uci) in ["e2e4", "e7e5", "g1f3"].iter().enumerate() {
+            game.moves.push(MoveRecord {
+                move_number: i as u32 + 1,
+                side: if i % 2 == 0 { "white" } else { "black" }.to_string(),
+                uci: uci.to_string(),
+                fen_before: format!("fen-{}", i),
+                phase: "opening".to_string(),
+                piece_count: 32,
+                think_time_ms: 10,
+                alternatives: 20,
+                clock_ms_remaining: 60_000,
+                clock_ms_used: 10,
+                ..Default::default()
+            });
+        }
+        game
+    }
+
+    #[tokio::test]
+    async fn test_validator_reports_unresolved_fen() {
+        let dir = std::env::temp_dir().join(format!(
+            "stonksfish_test_cypher_validate_{}",
+            std::process::id()
+        ));
+        let mut harvester = CypherHarvester::new(dir.clone()).unwrap();
+        let mut game = sample_game();
+        game.white = "O'Brien\\".to_string();
+        harvester.record_game(game.clone()).await.unwrap();
+        harvester.flush().await.unwrap();
+        let path = dir.join("live_games_0001.cypher");
+        assert_eq!(CypherValidator::validate_file(&path), Vec::new());
+
+        // A move out of a position that was never merged
+        let mut orphan = game.moves[0].clone();
+        orphan.fen_before = "missing-fen".to_string();
+        harvester.record_game(game).await.unwrap();
+        harvester.buffer.push(CypherHarvester::move_cypher(
+            &orphan,
+            "fen-0",
+            "abc123",
+            EvalPerspective::SideToMove,
+            EvalClamp::none(),
+        ));
+        harvester.flush().await.unwrap();
+        let errors = CypherValidator::validate_file(&dir.join("live_games_0002.cypher"));
+        assert!(matches!(
+            errors.as_slice(),
+            [CypherError::UnresolvedFen { fen, .. }] if fen == "missing-fen"
+        ));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_validator_reports_game_ids_and_syntax() {
+        let text = "// header\n\
+            :BEGIN\n\
+            MERGE (p:Position {fen: 'a'}) SET p.phase = 'opening';\n\
+            MATCH (from:Position {fen: 'a'}), (to:Position {fen: 'a'}) MERGE (from)-[:MOVE {game_id: 'g1'}]->(to);\n\
+            MATCH (g:Game {id: 'g2'}), (p:Position {fen: 'a'}) MERGE (g)-[:PLAYED_MOVE]->(p);\n\
+            MERGE (p:Position {fen: 'it\\'s}) SET p.phase = 'x';\n\
+            MERGE (p:Position {fen: 'b'} SET p.phase = 'x';\n\
+            :COMMIT\n";
+        let errors = CypherValidator::validate_str(text);
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert_eq!(
+            errors[0],
+            CypherError::UnresolvedGameId {
+                line: 4,
+                game_id: "g1".to_string()
+            }
+        );
+        assert_eq!(
+            errors[1],
+            CypherError::UnresolvedGameId {
+                line: 5,
+                game_id: "g2".to_string()
+            }
+        );
+        assert!(matches!(errors[2], CypherError::SyntaxError { line: 6, .. }));
+        assert!(matches!(errors[3], CypherError::SyntaxError { line: 7, .. }));
+
+        let missing = CypherValidator::validate_file(Path::new("/nonexistent/file.cypher"));
+        assert!(matches!(missing[..], [CypherError::SyntaxError { line: 0, .. }]));
+    }
+
+    #[tokio::test]
+    async fn test_reset_restarts_file_sequence() {
+        let dir = std::env::temp_dir().join(format!(
+            "stonksfish_test_cypher_reset_{}",
+            std::process::id()
+        ));
+        let mut harvester = CypherHarvester::new(dir.clone()).unwrap();
+        harvester.record_game(sample_game()).await.unwrap();
+        harvester.flush().await.unwrap();
+        harvester.record_game(sample_game()).await.unwrap();
+        harvester.flush().await.unwrap();
+        assert!(dir.join("live_games_0002.cypher").exists());
+
+        // Rotate the output away, then start over
+        let rotated = dir.join("rotated");
+        std::fs::create_dir_all(&rotated).unwrap();
+        for name in ["live_games_0001.cypher", "live_games_0002.cypher"] {
+            std::fs::rename(dir.join(name), rotated.join(name)).unwrap();
+        }
+        harvester.record_game(sample_game()).await.unwrap();
+        harvester.reset();
+        assert!(harvester.buffer.is_empty());
+
+        harvester.record_game(sample_game()).await.unwrap();
+        harvester.flush().await.unwrap();
+        let output = std::fs::read_to_string(dir.join("live_games_0001.cypher")).unwrap();
+        assert!(output.contains("// Games harvested: 1\n"));
+        assert!(!dir.join("live_games_0003.cypher").exists());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_batch_harvester_wraps_game_in_transaction() {
+        let dir = std::env::temp_dir().join("stonksfish_test_cypher_batch_tx");
+        let mut harvester = CypherBatchHarvester::new(dir).unwrap();
+        harvester.record_game(sample_game()).await.unwrap();
+
+        // 1 game node + 3 positions + 3 PLAYED_MOVE links + 2 MOVE edges
+        assert_eq!(harvester.transactions.len(), 1);
+        assert_eq!(harvester.pending_statements, 9);
+
+        let block = CypherBatchHarvester::transaction_block(&harvester.transactions[0]);
+        assert!(block.starts_with(":BEGIN\n"));
+        assert!(block.ends_with(":COMMIT\n"));
+        assert_eq!(block.lines().filter(|l| l.ends_with(';')).count(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_batch_harvester_auto_flushes() {
+        let dir = std::env::temp_dir().join(format!(
+            "stonksfish_test_cypher_batch_flush_{}",
+            std::process::id()
+        ));
+        let mut harvester = CypherBatchHarvester::new(dir.clone()).unwrap().with_batch_size(10);
+
+        harvester.record_game(sample_game()).await.unwrap();
+        assert_eq!(harvester.batch_count, 0);
+        harvester.record_game(sample_game()).await.unwrap();
+        assert_eq!(harvester.batch_count, 1);
+        assert!(harvester.transactions.is_empty());
+
+        let output = std::fs::read_to_string(dir.join("live_games_batch_0001.cypher")).unwrap();
+        let body: Vec<&str> = output
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with("//"))
+            .collect();
+        assert_eq!(body.first(), Some(&":BEGIN"));
+        assert_eq!(body.last(), Some(&":COMMIT"));
+        // 2 constraints and 3 indexes + 2 games of 9 statements
+        assert_eq!(body.iter().filter(|l| l.ends_with(';')).count(), 23);
+        std::fs::remove_dir_all(dir).ok();
+    }
 }
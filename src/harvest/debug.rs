@@ -0,0 +1,163 @@
+//! Human-readable game recaps for developers auditing bot decisions.
+//!
+//! Writes one `.txt` file per game, e.g.
+//!
+//! ```text
+//! 1. e4 (+0.12, 45ms, opening) 1... e5 (+0.08, 31ms, opening) ...
+//! ```
+//!
+//! Evaluations are from White's perspective in pawns; mate scores are
+//! shown as `#N`.
+
+use async_trait::async_trait;
+use chess::Board;
+use tracing::info;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::{prepare_output_dir, EvalPerspective, GameRecord, HarvestSink, MoveRecord};
+use crate::engine::search::mate_in;
+use crate::error::BotError;
+use crate::util::position::{parse_uci_move, to_san};
+use crate::whatif::BranchTree;
+
+/// Harvester that writes a plain-text recap of each game.
+pub struct DebugHarvester {
+    output_dir: PathBuf,
+    /// (game ID, rendered recap) per recorded game.
+    buffer: Vec<(String, String)>,
+}
+
+impl DebugHarvester {
+    /// Create a harvester writing to `output_dir`, creating it if needed.
+    /// Fails if the directory can't be created or written to.
+    pub fn new(output_dir: PathBuf) -> Result<Self, BotError> {
+        prepare_output_dir(&output_dir)?;
+        Ok(Self {
+            output_dir,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Render the recap of `game`: a header with the players and result,
+    /// then one entry per recorded half-move.
+    pub fn recap(game: &GameRecord) -> String {
+        let mut out = String::new();
+        writeln!(out, "Game {} ({})", game.game_id, game.lichess_url()).ok();
+        writeln!(
+            out,
+            "{} vs {}, bot plays {}, result: {}",
+            game.white, game.black, game.bot_color, game.result
+        )
+        .ok();
+        let entries: Vec<String> = game.moves.iter().map(move_entry).collect();
+        writeln!(out, "{}", entries.join(" ")).ok();
+        out
+    }
+}
+
+/// `1. e4 (+0.12, 45ms, opening)` or `1... e5 (...)` for Black. Falls back
+/// to the UCI move if the recorded FEN or move doesn't parse.
+fn move_entry(mr: &MoveRecord) -> String {
+    let black_to_move = mr.fen_before.split_whitespace().nth(1) == Some("b");
+    // `move_number` counts plies from 1; the FEN's own fullmove counter
+    // isn't reliable once it has been through `Board::to_string`.
+    let number = mr.move_number.div_ceil(2);
+    let san = Board::from_str(&mr.fen_before)
+        .ok()
+        .and_then(|board| parse_uci_move(&board, &mr.uci).map(|m| to_san(&board, m)))
+        .unwrap_or_else(|| mr.uci.clone());
+    let eval_cp = mr.eval_cp_as(EvalPerspective::White);
+    let eval = match mate_in(eval_cp) {
+        Some(moves) => format!("#{}", moves),
+        None => format!("{:+.2}", eval_cp as f64 / 100.0),
+    };
+    format!(
+        "{}{} {} ({}, {}ms, {})",
+        number,
+        if black_to_move { "..." } else { "." },
+        san,
+        eval,
+        mr.think_time_ms,
+        mr.phase
+    )
+}
+
+#[async_trait]
+impl HarvestSink for DebugHarvester {
+    async fn record_game(&mut self, game: GameRecord) -> Result<(), BotError> {
+        self.buffer.push((game.game_id.clone(), Self::recap(&game)));
+        Ok(())
+    }
+
+    async fn record_branch_tree(&mut self, _game_id: &str, _tree: &BranchTree) -> Result<(), BotError> {
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
+        for (game_id, recap) in self.buffer.drain(..) {
+            let path = self.output_dir.join(format!("{}.txt", game_id));
+            std::fs::write(&path, recap)?;
+            info!("Wrote game recap to {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::position::board_from_moves;
+
+    fn game() -> GameRecord {
+        let line = ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"];
+        let mut game = GameRecord::new("debug1".to_string());
+        game.white = "stonksfish".to_string();
+        game.black = "opponent".to_string();
+        game.result = "resign".to_string();
+        for (ply, uci) in line.iter().enumerate() {
+            let board = board_from_moves(None, &line[..ply]).unwrap();
+            game.push_move(MoveRecord {
+                move_number: ply as u32 + 1,
+                side: if ply % 2 == 0 { "white" } else { "black" }.to_string(),
+                uci: uci.to_string(),
+                fen_before: board.to_string(),
+                eval_cp: 12,
+                phase: "opening".to_string(),
+                piece_count: 32,
+                think_time_ms: 40 + ply as u64,
+                alternatives: 20,
+                ..Default::default()
+            });
+        }
+        game
+    }
+
+    #[test]
+    fn test_recap_lists_every_half_move() {
+        let recap = DebugHarvester::recap(&game());
+        assert_eq!(recap.matches("ms, opening)").count(), 5);
+        assert!(recap.contains("1. e4 (+0.12, 40ms, opening)"));
+        // Black's eval is flipped to White's perspective
+        assert!(recap.contains("1... e5 (-0.12, 41ms, opening)"));
+        assert!(recap.contains("2. Nf3 (+0.12, 42ms, opening)"));
+        assert!(recap.contains("3. Bb5 (+0.12, 44ms, opening)"));
+    }
+
+    #[tokio::test]
+    async fn test_flush_writes_one_file_per_game() {
+        let dir = std::env::temp_dir().join("stonksfish_test_debug_harvest");
+        let mut harvester = DebugHarvester::new(dir.clone()).unwrap();
+        harvester.record_game(game()).await.unwrap();
+        harvester.flush().await.unwrap();
+
+        let written = std::fs::read_to_string(dir.join("debug1.txt")).unwrap();
+        assert!(written.starts_with("Game debug1"));
+        assert!(harvester.buffer.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! Opening classification by ECO (Encyclopaedia of Chess Openings) code.
+//!
+//! A compact table of the main openings, matched by move sequence: a game
+//! is labeled with the longest entry its moves start with, so
+//! `e2e4 e7e5 g1f3 b8c6 f1b5` is a Ruy Lopez rather than just a King's
+//! Pawn Game. Games matching no entry get [`Opening::UNKNOWN`].
+
+/// An opening's ECO code and name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+}
+
+impl Opening {
+    /// Fallback for games that match no known opening.
+    pub const UNKNOWN: Opening = Opening {
+        eco: "A00",
+        name: "Unknown",
+    };
+}
+
+/// (ECO code, name, opening moves in UCI).
+const OPENINGS: &[(&str, &str, &[&str])] = &[
+    ("B00", "King's Pawn Opening", &["e2e4"]),
+    ("C20", "King's Pawn Game", &["e2e4", "e7e5"]),
+    ("C25", "Vienna Game", &["e2e4", "e7e5", "b1c3"]),
+    ("C30", "King's Gambit", &["e2e4", "e7e5", "f2f4"]),
+    ("C40", "King's Knight Opening", &["e2e4", "e7e5", "g1f3"]),
+    ("C41", "Philidor Defense", &["e2e4", "e7e5", "g1f3", "d7d6"]),
+    ("C42", "Petrov's Defense", &["e2e4", "e7e5", "g1f3", "g8f6"]),
+    ("C44", "King's Pawn Game", &["e2e4", "e7e5", "g1f3", "b8c6"]),
+    ("C45", "Scotch Game", &["e2e4", "e7e5", "g1f3", "b8c6", "d2d4"]),
+    ("C50", "Italian Game", &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"]),
+    ("C60", "Ruy Lopez", &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]),
+    ("B01", "Scandinavian Defense", &["e2e4", "d7d5"]),
+    ("B02", "Alekhine's Defense", &["e2e4", "g8f6"]),
+    ("B06", "Modern Defense", &["e2e4", "g7g6"]),
+    ("B07", "Pirc Defense", &["e2e4", "d7d6"]),
+    ("B10", "Caro-Kann Defense", &["e2e4", "c7c6"]),
+    ("B20", "Sicilian Defense", &["e2e4", "c7c5"]),
+    ("B27", "Sicilian Defense", &["e2e4", "c7c5", "g1f3"]),
+    ("B50", "Sicilian Defense", &["e2e4", "c7c5", "g1f3", "d7d6"]),
+    (
+        "B90",
+        "Sicilian Defense: Najdorf Variation",
+        &["e2e4", "c7c5", "g1f3", "d7d6", "d2d4", "c5d4", "f3d4", "g8f6", "b1c3", "a7a6"],
+    ),
+    ("C00", "French Defense", &["e2e4", "e7e6"]),
+    ("A40", "Queen's Pawn Game", &["d2d4"]),
+    ("D00", "Queen's Pawn Game", &["d2d4", "d7d5"]),
+    ("D00", "London System", &["d2d4", "d7d5", "c1f4"]),
+    ("D06", "Queen's Gambit", &["d2d4", "d7d5", "c2c4"]),
+    ("D10", "Slav Defense", &["d2d4", "d7d5", "c2c4", "c7c6"]),
+    ("D20", "Queen's Gambit Accepted", &["d2d4", "d7d5", "c2c4", "d5c4"]),
+    ("D30", "Queen's Gambit Declined", &["d2d4", "d7d5", "c2c4", "e7e6"]),
+    ("A45", "Indian Defense", &["d2d4", "g8f6"]),
+    ("E60", "King's Indian Defense", &["d2d4", "g8f6", "c2c4", "g7g6"]),
+    ("E12", "Queen's Indian Defense", &["d2d4", "g8f6", "c2c4", "e7e6", "g1f3", "b7b6"]),
+    ("E20", "Nimzo-Indian Defense", &["d2d4", "g8f6", "c2c4", "e7e6", "b1c3", "f8b4"]),
+    ("A80", "Dutch Defense", &["d2d4", "f7f5"]),
+    ("A10", "English Opening", &["c2c4"]),
+    ("A04", "Zukertort Opening", &["g1f3"]),
+    ("A09", "Reti Opening", &["g1f3", "d7d5", "c2c4"]),
+    ("A02", "Bird's Opening", &["f2f4"]),
+];
+
+/// Classify a game by its moves in UCI notation from the starting
+/// position.
+pub fn classify<S: AsRef<str>>(moves: &[S]) -> Opening {
+    OPENINGS
+        .iter()
+        .filter(|(_, _, line)| {
+            line.len() <= moves.len() && line.iter().zip(moves).all(|(a, b)| *a == b.as_ref())
+        })
+        .max_by_key(|(_, _, line)| line.len())
+        .map_or(Opening::UNKNOWN, |&(eco, name, _)| Opening { eco, name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::position::board_from_moves;
+
+    #[test]
+    fn test_longest_matching_line_wins() {
+        assert_eq!(classify(&["e2e4", "e7e5"]).eco, "C20");
+        assert_eq!(classify(&["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]).name, "Ruy Lopez");
+        assert_eq!(classify(&["e2e4", "c7c5", "b1c3"]).eco, "B20");
+        assert_eq!(classify(&["a2a3"]), Opening::UNKNOWN);
+        assert_eq!(classify::<&str>(&[]), Opening::UNKNOWN);
+    }
+
+    #[test]
+    fn test_every_line_is_legal() {
+        for (eco, name, line) in OPENINGS {
+            assert!(board_from_moves(None, line).is_ok(), "{} {}", eco, name);
+        }
+    }
+}
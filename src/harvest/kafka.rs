@@ -0,0 +1,375 @@
+//! Kafka topic sink for streaming consumers.
+//!
+//! Every game and what-if tree is published as the same JSON the
+//! [`JsonHarvester`] writes, keyed by game id so all records of a game
+//! land on the same partition:
+//!
+//! ```text
+//! <game topic>    key <game_id>  value <game json>
+//! <branch topic>  key <game_id>  value <branch_tree json>
+//! ```
+//!
+//! Records go through librdkafka (the `rdkafka` crate), so this module is
+//! only built with the `kafka` feature. They wait in librdkafka's queue,
+//! which holds at most [`KafkaConfig::max_queued`] records, and are
+//! delivered on flush. Records a flush couldn't deliver stay queued for
+//! the next one. Partitions are chosen like the Java client's default
+//! partitioner, from the murmur2 hash of the key.
+//!
+//! [`JsonHarvester`]: super::collector::JsonHarvester
+
+use async_trait::async_trait;
+use tracing::{debug, info, warn};
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::ClientContext;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::collector::{branch_tree_json, game_json};
+use super::{EvalClamp, EvalPerspective, GameRecord, HarvestSink};
+use crate::error::BotError;
+use crate::whatif::BranchTree;
+
+/// Topic games are published to unless `HARVEST_KAFKA_TOPIC_GAME` is set.
+pub const DEFAULT_GAME_TOPIC: &str = "stonksfish.games";
+
+/// Topic what-if trees are published to unless
+/// `HARVEST_KAFKA_TOPIC_BRANCH` is set.
+pub const DEFAULT_BRANCH_TOPIC: &str = "stonksfish.branches";
+
+/// Longest a flush may take to deliver everything queued.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Records waiting for delivery at most, unless `HARVEST_KAFKA_MAX_QUEUED`
+/// is set.
+pub const DEFAULT_MAX_QUEUED: usize = 100_000;
+
+/// The producer calls the harvester needs.
+#[async_trait]
+pub trait KafkaProducer: Send {
+    /// Queue `value` for `topic`, keyed by `key`.
+    fn send(&mut self, topic: &str, key: &str, value: &str) -> Result<(), BotError>;
+
+    /// Deliver everything queued, giving up after `timeout`.
+    async fn flush(&mut self, timeout: Duration) -> Result<(), BotError>;
+}
+
+/// Harvester that publishes records to Kafka topics.
+pub struct KafkaHarvester<P: KafkaProducer = RdKafkaProducer> {
+    producer: P,
+    game_topic: String,
+    branch_topic: String,
+    perspective: EvalPerspective,
+    clamp: EvalClamp,
+}
+
+impl<P: KafkaProducer> KafkaHarvester<P> {
+    /// Publish through `producer` to the default topics.
+    pub fn new(producer: P) -> Self {
+        Self {
+            producer,
+            game_topic: DEFAULT_GAME_TOPIC.to_string(),
+            branch_topic: DEFAULT_BRANCH_TOPIC.to_string(),
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+        }
+    }
+
+    /// Publish games and what-if trees to the given topics.
+    pub fn with_topics(mut self, game_topic: String, branch_topic: String) -> Self {
+        self.game_topic = game_topic;
+        self.branch_topic = branch_topic;
+        self
+    }
+
+    /// Emit evaluations in the given perspective instead of side-to-move.
+    pub fn with_perspective(mut self, perspective: EvalPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+
+    /// Clamp emitted evaluations and split out mate scores.
+    pub fn with_eval_clamp(mut self, clamp: EvalClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+}
+
+#[async_trait]
+impl<P: KafkaProducer> HarvestSink for KafkaHarvester<P> {
+    async fn record_game(&mut self, game: GameRecord) -> Result<(), BotError> {
+        let value = game_json(&game, self.perspective, self.clamp).to_string();
+        self.producer.send(&self.game_topic, &game.game_id, &value)?;
+        debug!("Queued game {} for {}", game.game_id, self.game_topic);
+        Ok(())
+    }
+
+    async fn record_branch_tree(&mut self, game_id: &str, tree: &BranchTree) -> Result<(), BotError> {
+        let value = branch_tree_json(game_id, tree).to_string();
+        self.producer.send(&self.branch_topic, game_id, &value)
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
+        self.producer.flush(FLUSH_TIMEOUT).await
+    }
+}
+
+/// How [`RdKafkaProducer`] reaches the cluster.
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    /// Comma-separated `host:port` list of bootstrap brokers.
+    pub brokers: String,
+    /// `plaintext`, `ssl`, `sasl_plaintext` or `sasl_ssl`; librdkafka's
+    /// default (plaintext) when `None`.
+    pub security_protocol: Option<String>,
+    /// SASL mechanism such as `PLAIN` or `SCRAM-SHA-512`.
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    /// CA certificate file to verify the brokers with, instead of the
+    /// system's.
+    pub ssl_ca_location: Option<String>,
+    /// Records waiting for delivery at most; sending more fails.
+    pub max_queued: usize,
+}
+
+impl KafkaConfig {
+    /// Plaintext connection to `brokers`.
+    pub fn new(brokers: impl Into<String>) -> Self {
+        Self {
+            brokers: brokers.into(),
+            security_protocol: None,
+            sasl_mechanism: None,
+            sasl_username: None,
+            sasl_password: None,
+            ssl_ca_location: None,
+            max_queued: DEFAULT_MAX_QUEUED,
+        }
+    }
+
+    /// The librdkafka settings for a producer.
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("client.id", "stonksfish")
+            .set("partitioner", "murmur2_random")
+            .set("queue.buffering.max.messages", self.max_queued.to_string())
+            // Keep retrying undelivered records until a flush gets them out
+            .set("message.timeout.ms", "0");
+        let optional = [
+            ("security.protocol", &self.security_protocol),
+            ("sasl.mechanism", &self.sasl_mechanism),
+            ("sasl.username", &self.sasl_username),
+            ("sasl.password", &self.sasl_password),
+            ("ssl.ca.location", &self.ssl_ca_location),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                config.set(key, value);
+            }
+        }
+        config
+    }
+}
+
+/// Counts records librdkafka gave up on, so the next flush can report
+/// them.
+#[derive(Default)]
+pub struct DeliveryTracker {
+    failed: AtomicUsize,
+}
+
+impl ClientContext for DeliveryTracker {}
+
+impl ProducerContext for DeliveryTracker {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((e, _)) = result {
+            warn!("Kafka record not delivered: {}", e);
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Producer backed by librdkafka.
+pub struct RdKafkaProducer {
+    producer: Arc<BaseProducer<DeliveryTracker>>,
+}
+
+impl RdKafkaProducer {
+    /// Create a producer for `config`, checking that a broker answers a
+    /// metadata request.
+    pub async fn connect(config: &KafkaConfig) -> Result<Self, BotError> {
+        if config.brokers.split(',').all(|broker| broker.trim().is_empty()) {
+            return Err(BotError::Config("No Kafka brokers given".to_string()));
+        }
+        let producer: BaseProducer<DeliveryTracker> = config
+            .client_config()
+            .create_with_context(DeliveryTracker::default())
+            .map_err(kafka_error)?;
+        let producer = Arc::new(producer);
+        let probe = Arc::clone(&producer);
+        tokio::task::spawn_blocking(move || probe.client().fetch_metadata(None, FLUSH_TIMEOUT))
+            .await
+            .map_err(|e| BotError::Harvest(format!("Kafka: metadata request panicked: {}", e)))?
+            .map_err(kafka_error)?;
+        info!("Connected to Kafka at {}", config.brokers);
+        Ok(RdKafkaProducer { producer })
+    }
+
+    /// Records queued or awaiting acknowledgement.
+    pub fn in_flight(&self) -> usize {
+        self.producer.in_flight_count().max(0) as usize
+    }
+}
+
+#[async_trait]
+impl KafkaProducer for RdKafkaProducer {
+    fn send(&mut self, topic: &str, key: &str, value: &str) -> Result<(), BotError> {
+        // Serve delivery reports so acknowledged records leave the queue
+        self.producer.poll(Duration::ZERO);
+        self.producer
+            .send(BaseRecord::to(topic).key(key).payload(value))
+            .map_err(|(e, _)| kafka_error(e))
+    }
+
+    async fn flush(&mut self, timeout: Duration) -> Result<(), BotError> {
+        let producer = Arc::clone(&self.producer);
+        let flushed = tokio::task::spawn_blocking(move || producer.flush(timeout))
+            .await
+            .map_err(|e| BotError::Harvest(format!("Kafka: flush panicked: {}", e)))?;
+        if let Err(e) = flushed {
+            return Err(BotError::Harvest(format!(
+                "Kafka: flush failed with {} records still queued: {}",
+                self.in_flight(),
+                e
+            )));
+        }
+        match self.producer.context().failed.swap(0, Ordering::Relaxed) {
+            0 => {
+                debug!("Flushed Kafka producer");
+                Ok(())
+            }
+            failed => Err(BotError::Harvest(format!("Kafka: {} records could not be delivered", failed))),
+        }
+    }
+}
+
+fn kafka_error(e: impl std::fmt::Display) -> BotError {
+    BotError::Harvest(format!("Kafka: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whatif::{generate_branch_tree, BranchConfig};
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::Message;
+    use std::time::Instant;
+
+    /// Read `count` messages from `topics` as (topic, partition, key, value).
+    fn consume(brokers: &str, topics: &[&str], count: usize) -> Vec<(String, i32, String, String)> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", "stonksfish-test")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .unwrap();
+        consumer.subscribe(topics).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(20);
+        let mut messages = Vec::new();
+        while messages.len() < count && Instant::now() < deadline {
+            if let Some(message) = consumer.poll(Duration::from_millis(100)) {
+                let message = message.unwrap();
+                let text = |bytes: Option<&[u8]>| String::from_utf8_lossy(bytes.unwrap_or_default()).into_owned();
+                messages.push((
+                    message.topic().to_string(),
+                    message.partition(),
+                    text(message.key()),
+                    text(message.payload()),
+                ));
+            }
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_publishes_keyed_messages() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("games", 2, 1).unwrap();
+        cluster.create_topic("trees", 1, 1).unwrap();
+        let brokers = cluster.bootstrap_servers();
+        let producer = RdKafkaProducer::connect(&KafkaConfig::new(&brokers)).await.unwrap();
+        let mut harvester = KafkaHarvester::new(producer).with_topics("games".to_string(), "trees".to_string());
+        for id in ["abc123", "def456", "abc123"] {
+            harvester.record_game(GameRecord::new(id.to_string())).await.unwrap();
+        }
+        let tree = generate_branch_tree(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &BranchConfig::quick(),
+        )
+        .unwrap();
+        harvester.record_branch_tree("abc123", &tree).await.unwrap();
+        harvester.flush().await.unwrap();
+        assert_eq!(harvester.producer.in_flight(), 0);
+
+        let mut messages = consume(&brokers, &["games", "trees"], 4);
+        messages.sort_by(|a, b| (&a.0, &a.2).cmp(&(&b.0, &b.2)));
+        let routed: Vec<(&str, &str)> = messages.iter().map(|(t, _, k, _)| (t.as_str(), k.as_str())).collect();
+        assert_eq!(routed, [("games", "abc123"), ("games", "abc123"), ("games", "def456"), ("trees", "abc123")]);
+        // Records of one game share a partition
+        assert_eq!(messages[0].1, messages[1].1);
+        let game: serde_json::Value = serde_json::from_str(&messages[2].3).unwrap();
+        assert_eq!(game["game_id"], "def456");
+        let branch: serde_json::Value = serde_json::from_str(&messages[3].3).unwrap();
+        assert_eq!(branch["total_nodes"], tree.total_nodes);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_records_and_queue_is_bounded() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("games", 1, 1).unwrap();
+        let brokers = cluster.bootstrap_servers();
+        let mut config = KafkaConfig::new(&brokers);
+        config.max_queued = 2;
+        let mut producer = RdKafkaProducer::connect(&config).await.unwrap();
+
+        cluster.broker_down(1).unwrap();
+        producer.send("games", "abc123", "{\"n\":1}").unwrap();
+        producer.send("games", "def456", "{\"n\":2}").unwrap();
+        assert!(producer.send("games", "ghi789", "{\"n\":3}").is_err());
+        let err = producer.flush(Duration::from_millis(500)).await.unwrap_err();
+        assert!(err.to_string().contains("2 records still queued"), "{}", err);
+
+        cluster.broker_up(1).unwrap();
+        producer.flush(FLUSH_TIMEOUT).await.unwrap();
+        let mut values: Vec<String> = consume(&brokers, &["games"], 2).into_iter().map(|m| m.3).collect();
+        values.sort();
+        assert_eq!(values, ["{\"n\":1}", "{\"n\":2}"]);
+    }
+
+    #[test]
+    fn test_client_config_carries_tls_and_sasl() {
+        let mut config = KafkaConfig::new("kafka:9093");
+        config.security_protocol = Some("sasl_ssl".to_string());
+        config.sasl_mechanism = Some("SCRAM-SHA-512".to_string());
+        config.sasl_username = Some("bot".to_string());
+        config.sasl_password = Some("secret".to_string());
+        config.ssl_ca_location = Some("/etc/ssl/kafka-ca.pem".to_string());
+        let client = config.client_config();
+        assert_eq!(client.get("security.protocol"), Some("sasl_ssl"));
+        assert_eq!(client.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+        assert_eq!(client.get("sasl.username"), Some("bot"));
+        assert_eq!(client.get("sasl.password"), Some("secret"));
+        assert_eq!(client.get("ssl.ca.location"), Some("/etc/ssl/kafka-ca.pem"));
+        assert_eq!(client.get("queue.buffering.max.messages"), Some("100000"));
+
+        let plain = KafkaConfig::new("kafka:9092").client_config();
+        assert_eq!(plain.get("security.protocol"), None);
+    }
+}
@@ -10,9 +10,9 @@
 //!
 //! ```text
 //! (:Game {id, white, black, result, bot_color})
-//!     -[:PLAYED_MOVE {move_number}]->
-//! (:Position {fen, eval_cp, phase, piece_count})
-//!     -[:MOVE {uci, eval_cp, think_time_ms}]->
+//!     -[:PLAYED_MOVE {game_id, move_number}]->
+//! (:Position {fen, eval_cp, phase, piece_count, best_move_uci, win_prob, legal_move_count})
+//!     -[:MOVE {game_id, move_number, uci, eval_cp, think_time_ms, clock_ms_remaining, clock_ms_used}]->
 //! (:Position {fen, ...})
 //!     -[:BELONGS_TO]->
 //! (:Opening {eco, name})
@@ -20,13 +20,130 @@
 //!
 //! This schema is compatible with aiwar-neo4j-harvest's chess model.
 
+pub mod analysis;
 pub mod collector;
 pub mod cypher;
+pub mod debug;
+pub mod eco;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod queue;
+pub mod redis;
 
 use async_trait::async_trait;
+use chess::{Board, Color, MoveGen};
+use std::path::Path;
+use std::str::FromStr;
 
+use crate::engine::evaluation::simple::material_balance;
+use crate::engine::search::root_move_scores;
+use crate::error::BotError;
+use crate::uci::PositionAnalysis;
+use crate::util::position::parse_uci_move;
 use crate::whatif::BranchTree;
 
+/// Which side centipawn evaluations are relative to in harvest output.
+///
+/// The engine always evaluates from the side-to-move's perspective, but
+/// charting consumers usually want a single, White-relative scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvalPerspective {
+    /// Positive scores favor the side to move (engine convention).
+    #[default]
+    SideToMove,
+    /// Positive scores favor White.
+    White,
+}
+
+impl EvalPerspective {
+    /// Name recorded in the harvest output so consumers know which
+    /// convention they got.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvalPerspective::SideToMove => "side_to_move",
+            EvalPerspective::White => "white",
+        }
+    }
+
+    /// Convert a side-to-move relative evaluation into this perspective.
+    pub fn convert(&self, eval_cp: i32, side_to_move: Color) -> i32 {
+        match (self, side_to_move) {
+            (EvalPerspective::White, Color::Black) => -eval_cp,
+            _ => eval_cp,
+        }
+    }
+
+    /// Convert a side-to-move relative evaluation of the position given by
+    /// `fen` into this perspective.
+    pub fn convert_fen(&self, eval_cp: i32, fen: &str) -> i32 {
+        let side_to_move = match fen.split_whitespace().nth(1) {
+            Some("b") => Color::Black,
+            _ => Color::White,
+        };
+        self.convert(eval_cp, side_to_move)
+    }
+}
+
+impl FromStr for EvalPerspective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "side_to_move" | "stm" => Ok(EvalPerspective::SideToMove),
+            "white" => Ok(EvalPerspective::White),
+            other => Err(format!("Unknown eval perspective '{}'", other)),
+        }
+    }
+}
+
+/// Optional clamp on harvested `eval_cp` values.
+///
+/// Mate scores (see [`crate::engine::search::mate_in`]) show up as huge
+/// centipawn numbers that skew any averaging downstream. With a clamp set,
+/// ordinary evaluations are limited to `±max_cp`, and mate scores are
+/// reported separately as `mate_in` with `eval_cp` pinned to the band edge.
+/// The default leaves evaluations untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvalClamp {
+    max_cp: Option<i32>,
+}
+
+impl EvalClamp {
+    /// Clamp evaluations to `±max_cp`. A non-positive bound disables the
+    /// clamp.
+    pub fn new(max_cp: i32) -> Self {
+        Self {
+            max_cp: (max_cp > 0).then_some(max_cp),
+        }
+    }
+
+    /// Leave evaluations untouched.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// The configured bound, if any.
+    pub fn max_cp(&self) -> Option<i32> {
+        self.max_cp
+    }
+
+    /// Apply the clamp, returning the evaluation to emit and, for mate
+    /// scores, the number of moves to mate.
+    pub fn apply(&self, eval_cp: i32) -> (i32, Option<i32>) {
+        match self.max_cp {
+            None => (eval_cp, None),
+            Some(max_cp) => (
+                eval_cp.clamp(-max_cp, max_cp),
+                crate::engine::search::mate_in(eval_cp),
+            ),
+        }
+    }
+}
+
+/// How far below the engine's best move (centipawns) a move must score
+/// to count as a blunder.
+pub const BLUNDER_THRESHOLD_CP: i32 = 200;
+
 /// Record of a complete game played on Lichess.
 #[derive(Debug, Clone)]
 pub struct GameRecord {
@@ -44,6 +161,9 @@ pub struct GameRecord {
     pub moves: Vec<MoveRecord>,
     /// Unix timestamp when the game started.
     pub started_at: u64,
+    /// Move number of the bot's first non-book move, `None` until the bot
+    /// has played one. Without an opening book this is the bot's first move.
+    pub book_exit_move: Option<u32>,
 }
 
 impl GameRecord {
@@ -59,12 +179,163 @@ impl GameRecord {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            book_exit_move: None,
+        }
+    }
+
+    /// Append a move played by the bot, noting where the game left book
+    /// and how the evaluation moved since the previous record.
+    pub fn push_move(&mut self, mut record: MoveRecord) {
+        if self.book_exit_move.is_none() && !record.is_book {
+            self.book_exit_move = Some(record.move_number);
+        }
+        record.eval_delta_cp = self.moves.last().map_or(0, |previous| {
+            record.eval_cp_as(EvalPerspective::White) - previous.eval_cp_as(EvalPerspective::White)
+        });
+        self.moves.push(record);
+    }
+
+    /// Link to the game on Lichess.
+    pub fn lichess_url(&self) -> String {
+        format!("https://lichess.org/{}", self.game_id)
+    }
+
+    /// Link to the PGN export of the game.
+    pub fn lichess_pgn_url(&self) -> String {
+        format!("https://lichess.org/game/export/{}.pgn", self.game_id)
+    }
+
+    /// Link to the Lichess computer analysis of the game.
+    pub fn analysis_url(&self) -> String {
+        format!("https://lichess.org/{}/analysis", self.game_id)
+    }
+
+    /// Integrity checksum: the Zobrist hash of every recorded position
+    /// XORed with hashes of `game_id`, `result` and the player names.
+    /// Positions whose FEN doesn't parse are hashed as text.
+    pub fn checksum(&self) -> u64 {
+        let positions = self.moves.iter().map(|mr| {
+            Board::from_str(&mr.fen_before)
+                .map(|board| board.get_hash())
+                .unwrap_or_else(|_| fnv1a("fen", &mr.fen_before))
+        });
+        let fields = [
+            fnv1a("game_id", &self.game_id),
+            fnv1a("result", &self.result),
+            fnv1a("white", &self.white),
+            fnv1a("black", &self.black),
+        ];
+        positions.chain(fields).fold(0, |sum, hash| sum ^ hash)
+    }
+
+    /// [`GameRecord::checksum`] as the 16-digit hex string stored in
+    /// harvest output (Neo4j and JavaScript can't hold every `u64`).
+    pub fn checksum_hex(&self) -> String {
+        format!("{:016x}", self.checksum())
+    }
+
+    /// Material balance (White minus Black, centipawns) of each recorded
+    /// position, in the order of `moves`. Positions whose FEN doesn't
+    /// parse count as 0.
+    pub fn material_progression(&self) -> Vec<i32> {
+        self.moves
+            .iter()
+            .map(|mr| {
+                Board::from_str(&mr.fen_before)
+                    .map(|board| material_balance(&board))
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Evaluation of each recorded position from White's perspective, in
+    /// the order of `moves`.
+    pub fn eval_progression(&self) -> Vec<i32> {
+        self.moves
+            .iter()
+            .map(|mr| mr.eval_cp_as(EvalPerspective::White))
+            .collect()
+    }
+
+    /// The game's moves in UCI from the starting position, rebuilt from
+    /// the recorded positions. Moves that weren't recorded (usually the
+    /// opponent's) are recovered as the one move linking consecutive
+    /// positions; the line stops wherever that fails, e.g. for games
+    /// started from a custom FEN.
+    pub fn uci_line(&self) -> Vec<String> {
+        let mut board = Board::default();
+        let mut line = Vec::new();
+        for mr in &self.moves {
+            let Ok(recorded) = Board::from_str(&mr.fen_before) else {
+                break;
+            };
+            if board.get_hash() != recorded.get_hash() {
+                let linking = MoveGen::new_legal(&board)
+                    .find(|&m| board.make_move_new(m).get_hash() == recorded.get_hash());
+                let Some(linking) = linking else {
+                    break;
+                };
+                line.push(linking.to_string());
+                board = recorded;
+            }
+            let Some(played) = parse_uci_move(&board, &mr.uci) else {
+                break;
+            };
+            line.push(played.to_string());
+            board = board.make_move_new(played);
+        }
+        line
+    }
+
+    /// Search every recorded position to `depth` plies and mark the
+    /// moves that were blunders, with the best move they missed. Records
+    /// whose position or move doesn't parse are left alone.
+    ///
+    /// Each position gets a full-window search of every legal move, so
+    /// this is meant for finished games, not during play.
+    pub fn annotate_with_engine(&mut self, depth: u8) {
+        for mr in &mut self.moves {
+            let Ok(board) = Board::from_str(&mr.fen_before) else {
+                continue;
+            };
+            let Some(played) = parse_uci_move(&board, &mr.uci) else {
+                continue;
+            };
+            let scores = root_move_scores(&board, depth.max(1));
+            let (Some(&(best_move, best)), Some(&(_, score))) =
+                (scores.first(), scores.iter().find(|(m, _)| *m == played))
+            else {
+                continue;
+            };
+            mr.is_blunder = best - score > BLUNDER_THRESHOLD_CP;
+            mr.best_move_missed = mr.is_blunder.then(|| best_move.to_string());
         }
     }
+
+    /// Whether `stored` matches the checksum of the record as it is now.
+    pub fn verify_checksum(&self, stored: u64) -> bool {
+        self.checksum() == stored
+    }
+}
+
+/// The JSON record of `game` that [`collector::JsonHarvester`] writes
+/// with its default settings: side-to-move evaluations, unclamped.
+pub fn game_to_json(game: &GameRecord) -> serde_json::Value {
+    collector::game_json(game, EvalPerspective::default(), EvalClamp::none())
+}
+
+/// FNV-1a hash of `value`, tagged with its field name so that e.g. swapping
+/// the player names changes the checksum. Stable across builds, unlike
+/// `DefaultHasher`.
+fn fnv1a(field: &str, value: &str) -> u64 {
+    let bytes = field.bytes().chain([0]).chain(value.bytes());
+    bytes.fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
 }
 
 /// Record of a single move/position during a game.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct MoveRecord {
     /// Half-move number (1-based).
     pub move_number: u32,
@@ -86,6 +357,38 @@ pub struct MoveRecord {
     pub is_book: bool,
     /// Number of legal alternatives at this position.
     pub alternatives: u32,
+    /// Mover's remaining clock right after the move (milliseconds, 0 if
+    /// unknown).
+    pub clock_ms_remaining: u64,
+    /// Clock the mover spent on the move, i.e. previous remaining minus
+    /// current remaining plus increment (milliseconds, 0 if unknown).
+    pub clock_ms_used: u64,
+    /// Change in White-relative evaluation since the previous recorded
+    /// move (0 for the first), filled in by [`GameRecord::push_move`].
+    pub eval_delta_cp: i32,
+    /// Whether the move scored more than [`BLUNDER_THRESHOLD_CP`] below
+    /// the engine's best, set by [`GameRecord::annotate_with_engine`].
+    pub is_blunder: bool,
+    /// For a blunder, the engine's best move in UCI.
+    pub best_move_missed: Option<String>,
+}
+
+impl MoveRecord {
+    /// Fill in the clock fields from the mover's clock before and after
+    /// the move and the increment it gained.
+    pub fn set_clock(&mut self, before_ms: u64, after_ms: u64, increment_ms: u64) {
+        self.clock_ms_remaining = after_ms;
+        self.clock_ms_used = (before_ms + increment_ms).saturating_sub(after_ms);
+    }
+
+    /// The evaluation of this move's position in the given perspective.
+    pub fn eval_cp_as(&self, perspective: EvalPerspective) -> i32 {
+        let side_to_move = match self.side.as_str() {
+            "black" => Color::Black,
+            _ => Color::White,
+        };
+        perspective.convert(self.eval_cp, side_to_move)
+    }
 }
 
 /// Trait for harvest data sinks.
@@ -100,17 +403,61 @@ pub trait HarvestSink: Send {
     async fn record_game(
         &mut self,
         game: GameRecord,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    ) -> Result<(), BotError>;
+
+    /// Record many completed games at once, e.g. when importing
+    /// historical games.
+    ///
+    /// The default records them one at a time; buffering sinks override
+    /// it to do their per-game bookkeeping once for the whole batch.
+    async fn record_game_batch(
+        &mut self,
+        games: Vec<GameRecord>,
+    ) -> Result<(), BotError> {
+        for game in games {
+            self.record_game(game).await?;
+        }
+        Ok(())
+    }
 
     /// Record a what-if branch tree for a position.
     async fn record_branch_tree(
         &mut self,
         game_id: &str,
         tree: &BranchTree,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    ) -> Result<(), BotError>;
+
+    /// Record the result of `analyze_position` for a position in a game.
+    ///
+    /// Sinks that don't store analysis can rely on the default no-op.
+    async fn record_analysis(
+        &mut self,
+        _game_id: &str,
+        _analysis: &PositionAnalysis,
+    ) -> Result<(), BotError> {
+        Ok(())
+    }
 
     /// Flush any buffered data.
-    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    async fn flush(&mut self) -> Result<(), BotError>;
+
+    /// Discard buffered data and restart any output file numbering, e.g.
+    /// after rotating the files written so far. The default does nothing,
+    /// for sinks without such state.
+    fn reset(&mut self) {}
+}
+
+/// Create `dir` if needed and check that files can be written to it, so
+/// a file-based sink fails at construction rather than on its first flush.
+pub(crate) fn prepare_output_dir(dir: &Path) -> Result<(), BotError> {
+    let not_writable = |e: std::io::Error| {
+        BotError::Config(format!("{} is not writable: {}", dir.display(), e))
+    };
+    std::fs::create_dir_all(dir).map_err(not_writable)?;
+    let probe = dir.join(".stonksfish-write-test");
+    std::fs::write(&probe, b"").map_err(not_writable)?;
+    std::fs::remove_file(&probe).ok();
+    Ok(())
 }
 
 /// Null harvester that discards all data (for testing or when harvesting is disabled).
@@ -121,7 +468,7 @@ impl HarvestSink for NullHarvester {
     async fn record_game(
         &mut self,
         _game: GameRecord,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(), BotError> {
         Ok(())
     }
 
@@ -129,11 +476,237 @@ impl HarvestSink for NullHarvester {
         &mut self,
         _game_id: &str,
         _tree: &BranchTree,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(), BotError> {
         Ok(())
     }
 
-    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn flush(&mut self) -> Result<(), BotError> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::search::MATE_SCORE;
+
+    #[test]
+    fn test_eval_clamp() {
+        assert_eq!(EvalClamp::none().apply(MATE_SCORE - 3), (MATE_SCORE - 3, None));
+        assert_eq!(EvalClamp::new(0), EvalClamp::none());
+
+        let clamp = EvalClamp::new(2000);
+        assert_eq!(clamp.apply(150), (150, None));
+        assert_eq!(clamp.apply(-4500), (-2000, None));
+        // Mate in 2 for the side to move (3 plies away)
+        assert_eq!(clamp.apply(MATE_SCORE - 3), (2000, Some(2)));
+        // Mated in 1 (2 plies away)
+        assert_eq!(clamp.apply(-(MATE_SCORE - 2)), (-2000, Some(-1)));
+    }
+
+    #[test]
+    fn test_eval_perspective_convert() {
+        assert_eq!(EvalPerspective::SideToMove.convert(50, Color::Black), 50);
+        assert_eq!(EvalPerspective::White.convert(50, Color::White), 50);
+        assert_eq!(EvalPerspective::White.convert(50, Color::Black), -50);
+        assert_eq!(
+            EvalPerspective::White.convert_fen(
+                30,
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+            ),
+            -30
+        );
+    }
+
+    #[test]
+    fn test_game_record_urls() {
+        let record = GameRecord::new("abc123".to_string());
+        assert_eq!(record.lichess_url(), "https://lichess.org/abc123");
+        assert_eq!(
+            record.lichess_pgn_url(),
+            "https://lichess.org/game/export/abc123.pgn"
+        );
+        assert_eq!(record.analysis_url(), "https://lichess.org/abc123/analysis");
+    }
+
+    fn move_record(move_number: u32, is_book: bool) -> MoveRecord {
+        MoveRecord {
+            move_number,
+            side: "white".to_string(),
+            uci: "e2e4".to_string(),
+            phase: "opening".to_string(),
+            piece_count: 32,
+            is_book,
+            alternatives: 20,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_material_progression_shows_capture() {
+        use crate::util::position::board_from_moves;
+
+        // White wins the knight on d4 with its fifth move, 5. Nxd4
+        let line = [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "c6d4", "f3d4", "d8g5",
+        ];
+        let mut record = GameRecord::new("abc123".to_string());
+        for ply in 0..line.len() {
+            let mut mr = move_record(ply as u32 + 1, false);
+            mr.side = if ply % 2 == 0 { "white" } else { "black" }.to_string();
+            mr.fen_before = board_from_moves(None, &line[..ply]).unwrap().to_string();
+            mr.eval_cp = 25;
+            record.push_move(mr);
+        }
+
+        let material = record.material_progression();
+        assert_eq!(material.len(), line.len());
+        assert_eq!(material[8], 0);
+        assert_eq!(material[9] - material[8], 320);
+        assert_eq!(record.eval_progression()[..2], [25, -25]);
+    }
+
+    #[test]
+    fn test_game_to_json() {
+        let mut record = GameRecord::new("abc123".to_string());
+        record.white = "stonksfish".to_string();
+        record.push_move(move_record(1, true));
+        record.push_move(move_record(2, false));
+
+        let json = game_to_json(&record);
+        for key in ["type", "game_id", "white", "black", "result", "opening", "checksum", "moves"] {
+            assert!(json.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(json["game_id"], "abc123");
+        assert_eq!(json["white"], "stonksfish");
+        assert_eq!(json["total_moves"], 2);
+        assert_eq!(json["moves"].as_array().unwrap().len(), 2);
+        assert_eq!(json["moves"][1]["move_number"], 2);
+    }
+
+    #[test]
+    fn test_annotate_with_engine_finds_blunder() {
+        use crate::util::position::board_from_moves;
+
+        // 2. Qg4?? hangs the queen to Bxg4
+        let line = ["e2e4", "d7d5"];
+        let mut record = GameRecord::new("abc123".to_string());
+        for (ply, uci) in [(0, "e2e4"), (2, "d1g4")] {
+            let mut mr = move_record(ply as u32 + 1, false);
+            mr.uci = uci.to_string();
+            mr.fen_before = board_from_moves(None, &line[..ply]).unwrap().to_string();
+            record.push_move(mr);
+        }
+        record.annotate_with_engine(2);
+
+        assert!(!record.moves[0].is_blunder);
+        assert_eq!(record.moves[0].best_move_missed, None);
+        assert!(record.moves[1].is_blunder);
+        let missed = record.moves[1].best_move_missed.as_deref().unwrap();
+        assert_ne!(missed, "d1g4");
+    }
+
+    #[test]
+    fn test_book_exit_move() {
+        // Two book moves, then the bot is on its own from move 5.
+        let mut record = GameRecord::new("abc123".to_string());
+        record.push_move(move_record(1, true));
+        record.push_move(move_record(3, true));
+        assert_eq!(record.book_exit_move, None);
+        record.push_move(move_record(5, false));
+        record.push_move(move_record(7, true));
+        assert_eq!(record.book_exit_move, Some(5));
+        assert_eq!(record.moves.len(), 4);
+
+        // Without a book the game leaves book on the bot's first move.
+        let mut record = GameRecord::new("def456".to_string());
+        record.push_move(move_record(2, false));
+        assert_eq!(record.book_exit_move, Some(2));
+    }
+
+    #[test]
+    fn test_eval_deltas_follow_eval_progression() {
+        // Side-to-move evals of a game where Black blunders on move 4
+        let evals = [30, -25, 40, 300, 310, -320];
+        let mut record = GameRecord::new("abc123".to_string());
+        for (i, &eval_cp) in evals.iter().enumerate() {
+            record.push_move(MoveRecord {
+                side: if i % 2 == 0 { "white" } else { "black" }.to_string(),
+                eval_cp,
+                eval_delta_cp: 12345,
+                ..move_record(i as u32 + 1, false)
+            });
+        }
+
+        let progression = record.eval_progression();
+        assert_eq!(progression, vec![30, 25, 40, -300, 310, 320]);
+        let deltas: Vec<i32> = record.moves.iter().map(|mr| mr.eval_delta_cp).collect();
+        assert_eq!(deltas, vec![0, -5, 15, -340, 610, 10]);
+        assert!(deltas[1..]
+            .iter()
+            .zip(progression.windows(2))
+            .all(|(delta, pair)| *delta == pair[1] - pair[0]));
+    }
+
+    #[test]
+    fn test_checksum_detects_changes() {
+        let mut record = GameRecord::new("abc123".to_string());
+        record.white = "stonksfish".to_string();
+        record.black = "opponent".to_string();
+        record.result = "mate".to_string();
+        for (n, fen) in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut mr = move_record(n as u32 * 2 + 1, false);
+            mr.fen_before = fen.to_string();
+            record.push_move(mr);
+        }
+        let stored = record.checksum();
+        assert!(record.verify_checksum(stored));
+        assert_eq!(record.checksum_hex(), format!("{:016x}", stored));
+
+        let mut changed = record.clone();
+        changed.result = "resign".to_string();
+        assert!(!changed.verify_checksum(stored));
+
+        let mut swapped = record.clone();
+        std::mem::swap(&mut swapped.white, &mut swapped.black);
+        assert!(!swapped.verify_checksum(stored));
+
+        let mut moved = record.clone();
+        moved.moves[1].fen_before =
+            "rnbqkbnr/pppp1ppp/8/4p3/3PP3/8/PPP2PPP/RNBQKBNR b KQkq - 0 2".to_string();
+        assert!(!moved.verify_checksum(stored));
+    }
+
+    #[test]
+    fn test_unwritable_output_dir_is_rejected() {
+        // A path below a regular file can never be created, even as root.
+        let file = std::env::temp_dir().join(format!("stonksfish_test_not_a_dir_{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let dir = file.join("harvest");
+
+        let err = collector::JsonHarvester::new(dir.clone()).err().unwrap();
+        assert!(matches!(err, BotError::Config(ref msg) if msg.contains("not writable")));
+        assert!(cypher::CypherHarvester::new(dir.clone()).is_err());
+        assert!(cypher::CypherBatchHarvester::new(dir).is_err());
+
+        let ok = std::env::temp_dir().join(format!("stonksfish_test_writable_dir_{}", std::process::id()));
+        assert!(collector::JsonHarvester::new(ok.clone()).is_ok());
+        assert!(!ok.join(".stonksfish-write-test").exists());
+
+        std::fs::remove_file(&file).ok();
+        std::fs::remove_dir_all(&ok).ok();
+    }
+
+    #[test]
+    fn test_eval_perspective_from_str() {
+        assert_eq!("white".parse(), Ok(EvalPerspective::White));
+        assert_eq!("side_to_move".parse(), Ok(EvalPerspective::SideToMove));
+        assert!("black".parse::<EvalPerspective>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Harvesting off the game tasks.
+//!
+//! Sinks write files and talk to databases, and a game or the event loop
+//! waiting on that could fall behind. A [`HarvestQueue`] hands records
+//! over a bounded channel to a dedicated task that owns the sink and
+//! feeds it in order, so the only thing a sender ever waits for is room
+//! in the queue, and with [`OverflowPolicy::Drop`] not even that.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+use super::{GameRecord, HarvestSink};
+use crate::error::BotError;
+use crate::whatif::BranchTree;
+
+/// Default number of messages waiting for the harvester task.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+/// What a sender does when the harvest queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for room, so nothing is lost.
+    #[default]
+    Block,
+    /// Drop the record (logged and counted) and carry on.
+    Drop,
+}
+
+impl OverflowPolicy {
+    /// Name accepted by [`OverflowPolicy::from_str`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::Drop => "drop",
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop" => Ok(OverflowPolicy::Drop),
+            other => Err(format!("Unknown harvest overflow policy '{}'", other)),
+        }
+    }
+}
+
+enum HarvestMessage {
+    Game(GameRecord),
+    BranchTree(String, BranchTree),
+    /// Flush the sink, reporting the result if anyone is waiting for it.
+    Flush(Option<oneshot::Sender<Result<(), BotError>>>),
+}
+
+/// Cloneable handle queueing harvest data for the task owning the sink.
+///
+/// The task runs until every handle is dropped.
+#[derive(Debug, Clone)]
+pub struct HarvestQueue {
+    sender: mpsc::Sender<HarvestMessage>,
+    overflow: OverflowPolicy,
+    dropped: Arc<AtomicU64>,
+}
+
+impl HarvestQueue {
+    /// Start the task feeding `sink`, with room for `capacity` (at least
+    /// 1) pending messages. Must be called within a Tokio runtime.
+    pub fn spawn(sink: Box<dyn HarvestSink + Send>, capacity: usize, overflow: OverflowPolicy) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        tokio::spawn(run_harvester(sink, receiver));
+        Self {
+            sender,
+            overflow,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Queue a finished game.
+    pub async fn record_game(&self, game: GameRecord) {
+        self.send(HarvestMessage::Game(game)).await;
+    }
+
+    /// Queue a what-if tree explored during `game_id`.
+    pub async fn record_branch_tree(&self, game_id: &str, tree: BranchTree) {
+        self.send(HarvestMessage::BranchTree(game_id.to_string(), tree)).await;
+    }
+
+    /// Ask for a flush without waiting for it. Skipped when the queue is
+    /// full: the harvester is busy then, and what it holds is flushed by a
+    /// later request or by [`HarvestQueue::flush`].
+    pub fn request_flush(&self) {
+        if self.sender.try_send(HarvestMessage::Flush(None)).is_err() {
+            debug!("Harvest queue full, skipping flush request");
+        }
+    }
+
+    /// Flush everything queued so far and wait for the result. This waits
+    /// for room in the queue whatever the overflow policy.
+    pub async fn flush(&self) -> Result<(), BotError> {
+        let (ack, result) = oneshot::channel();
+        self.sender
+            .send(HarvestMessage::Flush(Some(ack)))
+            .await
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    /// Records dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    async fn send(&self, message: HarvestMessage) {
+        let sent = match self.overflow {
+            OverflowPolicy::Block => self.sender.send(message).await.map_err(|_| None),
+            OverflowPolicy::Drop => self.sender.try_send(message).map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => Some(()),
+                mpsc::error::TrySendError::Closed(_) => None,
+            }),
+        };
+        match sent {
+            Ok(()) => {}
+            Err(Some(())) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!("Harvest queue full, dropped a record ({} so far)", dropped);
+            }
+            Err(None) => warn!("{}", stopped()),
+        }
+    }
+}
+
+fn stopped() -> BotError {
+    BotError::Harvest("Harvester task has stopped".to_string())
+}
+
+/// Feed queued messages to `sink` until every sender is gone.
+async fn run_harvester(
+    mut sink: Box<dyn HarvestSink + Send>,
+    mut receiver: mpsc::Receiver<HarvestMessage>,
+) {
+    while let Some(message) = receiver.recv().await {
+        match message {
+            HarvestMessage::Game(game) => {
+                let game_id = game.game_id.clone();
+                if let Err(e) = sink.record_game(game).await {
+                    warn!(game_id = %game_id, "Harvest error: {:?}", e);
+                }
+            }
+            HarvestMessage::BranchTree(game_id, tree) => {
+                if let Err(e) = sink.record_branch_tree(&game_id, &tree).await {
+                    debug!(game_id = %game_id, "Branch harvest error: {:?}", e);
+                }
+            }
+            HarvestMessage::Flush(ack) => {
+                let result = sink.flush().await;
+                match ack {
+                    Some(ack) => {
+                        ack.send(result).ok();
+                    }
+                    None => {
+                        if let Err(e) = result {
+                            warn!("Harvest flush error: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Sink that stalls on every game until a permit is released.
+    struct GatedSink {
+        gate: Arc<tokio::sync::Semaphore>,
+        games: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl HarvestSink for GatedSink {
+        async fn record_game(&mut self, game: GameRecord) -> Result<(), BotError> {
+            self.gate.acquire().await.unwrap().forget();
+            self.games.lock().unwrap().push(game.game_id);
+            Ok(())
+        }
+
+        async fn record_branch_tree(&mut self, _: &str, _: &BranchTree) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), BotError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_or_waits() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let games = Arc::new(Mutex::new(Vec::new()));
+        let sink = GatedSink {
+            gate: Arc::clone(&gate),
+            games: Arc::clone(&games),
+        };
+        let queue = HarvestQueue::spawn(Box::new(sink), 1, OverflowPolicy::Drop);
+
+        // The first game stalls in the sink, the second fills the queue
+        for id in ["first", "second", "third"] {
+            queue.record_game(GameRecord::new(id.to_string())).await;
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.dropped(), 1);
+
+        let blocking = HarvestQueue {
+            overflow: OverflowPolicy::Block,
+            ..queue.clone()
+        };
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(20),
+            blocking.record_game(GameRecord::new("fourth".to_string())),
+        );
+        assert!(waiting.await.is_err(), "a full queue should make Block wait");
+
+        gate.add_permits(10);
+        queue.flush().await.unwrap();
+        assert_eq!(*games.lock().unwrap(), vec!["first", "second"]);
+        assert_eq!("DROP".parse::<OverflowPolicy>(), Ok(OverflowPolicy::Drop));
+    }
+}
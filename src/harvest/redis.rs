@@ -0,0 +1,387 @@
+//! Redis stream sink for real-time consumption by crewai-rust agents.
+//!
+//! Every game and what-if tree is published with `XADD` as soon as it is
+//! recorded, serialized as the same JSON the [`JsonHarvester`] writes:
+//!
+//! ```text
+//! XADD <game stream> * type game game_id <id> data <json>
+//! XADD <branch stream> * type branch_tree game_id <id> data <json>
+//! ```
+//!
+//! The client speaks just enough RESP for these commands, so no Redis
+//! client library is needed. Each command is bounded by a timeout, and a
+//! broken connection is reopened on the next command.
+//!
+//! [`JsonHarvester`]: super::collector::JsonHarvester
+
+use async_trait::async_trait;
+use tracing::{debug, info, warn};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::collector::{branch_tree_json, game_json};
+use super::{EvalClamp, EvalPerspective, GameRecord, HarvestSink};
+use crate::error::BotError;
+use crate::whatif::BranchTree;
+
+/// Stream games are published to unless `HARVEST_REDIS_STREAM_GAME` is set.
+pub const DEFAULT_GAME_STREAM: &str = "stonksfish:games";
+
+/// Stream what-if trees are published to unless
+/// `HARVEST_REDIS_STREAM_BRANCH` is set.
+pub const DEFAULT_BRANCH_STREAM: &str = "stonksfish:branches";
+
+/// The one Redis command the harvester needs.
+#[async_trait]
+pub trait StreamClient: Send {
+    /// Append an entry with `fields` to `stream`, returning its entry ID.
+    async fn xadd(&mut self, stream: &str, fields: &[(&str, &str)]) -> Result<String, BotError>;
+}
+
+/// How long a Redis command may take before the connection is given up.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimal RESP client over a single TCP connection, reopened when it
+/// breaks.
+pub struct RespClient {
+    addr: String,
+    password: Option<String>,
+    db: Option<String>,
+    timeout: Duration,
+    conn: Option<BufReader<TcpStream>>,
+}
+
+impl RespClient {
+    /// Connect to `url` of the form `redis://[:password@]host[:port][/db]`.
+    pub async fn connect(url: &str) -> Result<Self, BotError> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| BotError::Config(format!("Unsupported Redis URL '{}'", url)))?;
+        let (password, rest) = match rest.rsplit_once('@') {
+            Some((auth, host)) => (Some(auth.rsplit(':').next().unwrap_or(auth)), host),
+            None => (None, rest),
+        };
+        let (host, db) = match rest.split_once('/') {
+            Some((host, db)) => (host, Some(db).filter(|db| !db.is_empty())),
+            None => (rest, None),
+        };
+        let addr = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{}:6379", host),
+        };
+
+        let mut client = RespClient {
+            addr,
+            password: password.map(str::to_string),
+            db: db.map(str::to_string),
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+            conn: None,
+        };
+        client.open().await?;
+        info!("Connected to Redis at {}", client.addr);
+        Ok(client)
+    }
+
+    /// Give up on a command, and the connection it was sent on, after
+    /// `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Open a fresh connection, authenticating and selecting the database
+    /// if the URL asked for it.
+    async fn open(&mut self) -> Result<(), BotError> {
+        let conn = tokio::time::timeout(self.timeout, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| timed_out("connect", self.timeout))??;
+        let mut conn = BufReader::new(conn);
+        let setup = [
+            self.password.as_deref().map(|password| ["AUTH", password]),
+            self.db.as_deref().map(|db| ["SELECT", db]),
+        ];
+        for args in setup.iter().flatten() {
+            tokio::time::timeout(self.timeout, exchange(&mut conn, args))
+                .await
+                .map_err(|_| timed_out(args[0], self.timeout))??;
+        }
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    /// Send one command and return its reply as text. Error replies become
+    /// `BotError::Harvest`.
+    ///
+    /// A command that fails with an I/O error or times out is retried once
+    /// on a new connection, so a Redis restart costs at most one record.
+    /// The retry may repeat a command Redis did apply before the
+    /// connection broke.
+    async fn command(&mut self, args: &[&str]) -> Result<String, BotError> {
+        match self.send(args).await {
+            Err(BotError::Io(e)) => {
+                warn!("Redis connection to {} lost ({}), reconnecting", self.addr, e);
+                self.open().await?;
+                self.send(args).await
+            }
+            reply => reply,
+        }
+    }
+
+    /// Send one command on the current connection, opening one if there
+    /// is none. On an I/O error or timeout the connection is dropped.
+    async fn send(&mut self, args: &[&str]) -> Result<String, BotError> {
+        if self.conn.is_none() {
+            self.open().await?;
+        }
+        let conn = self.conn.as_mut().expect("connection was just opened");
+        let reply = match tokio::time::timeout(self.timeout, exchange(conn, args)).await {
+            Ok(reply) => reply,
+            Err(_) => Err(timed_out(args[0], self.timeout)),
+        };
+        if let Err(BotError::Io(_)) = reply {
+            self.conn = None;
+        }
+        reply
+    }
+}
+
+/// Write `args` to `conn` and read back one reply.
+async fn exchange(conn: &mut BufReader<TcpStream>, args: &[&str]) -> Result<String, BotError> {
+    conn.get_mut().write_all(&encode_command(args)).await?;
+
+    let mut line = String::new();
+    if conn.read_line(&mut line).await? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let line = line.trim_end();
+    let (kind, payload) = line.split_at(line.len().min(1));
+    match kind {
+        "+" | ":" => Ok(payload.to_string()),
+        "-" => Err(BotError::Harvest(format!("Redis error: {}", payload))),
+        "$" => {
+            let len: i64 = payload
+                .parse()
+                .map_err(|_| BotError::Harvest(format!("Bad Redis reply '{}'", line)))?;
+            if len < 0 {
+                return Ok(String::new());
+            }
+            let mut data = vec![0u8; len as usize + 2];
+            conn.read_exact(&mut data).await?;
+            data.truncate(len as usize);
+            Ok(String::from_utf8_lossy(&data).into_owned())
+        }
+        _ => Err(BotError::Harvest(format!("Unexpected Redis reply '{}'", line))),
+    }
+}
+
+/// I/O error for a Redis `what` that got no answer within `timeout`.
+fn timed_out(what: &str, timeout: Duration) -> BotError {
+    let message = format!("Redis {} timed out after {:?}", what, timeout);
+    io::Error::new(io::ErrorKind::TimedOut, message).into()
+}
+
+#[async_trait]
+impl StreamClient for RespClient {
+    async fn xadd(&mut self, stream: &str, fields: &[(&str, &str)]) -> Result<String, BotError> {
+        let mut args = vec!["XADD", stream, "*"];
+        for (field, value) in fields {
+            args.push(field);
+            args.push(value);
+        }
+        self.command(&args).await
+    }
+}
+
+/// Encode `args` as a RESP array of bulk strings.
+fn encode_command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// Harvester that publishes records to Redis streams.
+pub struct RedisHarvester<C: StreamClient = RespClient> {
+    client: C,
+    game_stream: String,
+    branch_stream: String,
+    perspective: EvalPerspective,
+    clamp: EvalClamp,
+}
+
+impl<C: StreamClient> RedisHarvester<C> {
+    /// Publish through `client` to the default streams.
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            game_stream: DEFAULT_GAME_STREAM.to_string(),
+            branch_stream: DEFAULT_BRANCH_STREAM.to_string(),
+            perspective: EvalPerspective::default(),
+            clamp: EvalClamp::default(),
+        }
+    }
+
+    /// Publish games and what-if trees to the given streams.
+    pub fn with_streams(mut self, game_stream: String, branch_stream: String) -> Self {
+        self.game_stream = game_stream;
+        self.branch_stream = branch_stream;
+        self
+    }
+
+    /// Emit evaluations in the given perspective instead of side-to-move.
+    pub fn with_perspective(mut self, perspective: EvalPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+
+    /// Clamp emitted evaluations and split out mate scores.
+    pub fn with_eval_clamp(mut self, clamp: EvalClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+}
+
+#[async_trait]
+impl<C: StreamClient> HarvestSink for RedisHarvester<C> {
+    async fn record_game(&mut self, game: GameRecord) -> Result<(), BotError> {
+        let data = game_json(&game, self.perspective, self.clamp).to_string();
+        let fields = [("type", "game"), ("game_id", game.game_id.as_str()), ("data", data.as_str())];
+        let id = self.client.xadd(&self.game_stream, &fields).await?;
+        debug!("Published game {} to {} as {}", game.game_id, self.game_stream, id);
+        Ok(())
+    }
+
+    async fn record_branch_tree(&mut self, game_id: &str, tree: &BranchTree) -> Result<(), BotError> {
+        let data = branch_tree_json(game_id, tree).to_string();
+        let fields = [("type", "branch_tree"), ("game_id", game_id), ("data", data.as_str())];
+        self.client.xadd(&self.branch_stream, &fields).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), BotError> {
+        // Records are published as they arrive; nothing is buffered.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whatif::{generate_branch_tree, BranchConfig};
+    use tokio::net::TcpListener;
+
+    /// Records every XADD instead of talking to Redis.
+    #[derive(Default)]
+    struct MockClient {
+        calls: Vec<(String, Vec<(String, String)>)>,
+    }
+
+    #[async_trait]
+    impl StreamClient for MockClient {
+        async fn xadd(&mut self, stream: &str, fields: &[(&str, &str)]) -> Result<String, BotError> {
+            let fields = fields.iter().map(|(f, v)| (f.to_string(), v.to_string())).collect();
+            self.calls.push((stream.to_string(), fields));
+            Ok(format!("{}-0", self.calls.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publishes_to_configured_streams() {
+        let mut harvester = RedisHarvester::new(MockClient::default())
+            .with_streams("games".to_string(), "trees".to_string());
+        let mut game = GameRecord::new("abc123".to_string());
+        game.result = "mate".to_string();
+        harvester.record_game(game).await.unwrap();
+        let tree = generate_branch_tree(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &BranchConfig::quick(),
+        )
+        .unwrap();
+        harvester.record_branch_tree("abc123", &tree).await.unwrap();
+
+        let calls = &harvester.client.calls;
+        assert_eq!(calls.len(), 2);
+
+        let (stream, fields) = &calls[0];
+        assert_eq!(stream, "games");
+        assert_eq!(fields[0], ("type".to_string(), "game".to_string()));
+        assert_eq!(fields[1], ("game_id".to_string(), "abc123".to_string()));
+        let data: serde_json::Value = serde_json::from_str(&fields[2].1).unwrap();
+        assert_eq!(data["result"], "mate");
+
+        let (stream, fields) = &calls[1];
+        assert_eq!(stream, "trees");
+        assert_eq!(fields[0].1, "branch_tree");
+        let data: serde_json::Value = serde_json::from_str(&fields[2].1).unwrap();
+        assert_eq!(data["total_nodes"], tree.total_nodes);
+    }
+
+    #[tokio::test]
+    async fn test_resp_client_xadd() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let read = socket.read(&mut request).await.unwrap();
+            socket.write_all(b"$15\r\n1526919030474-0\r\n").await.unwrap();
+            request.truncate(read);
+            request
+        });
+
+        let mut client = RespClient::connect(&url).await.unwrap();
+        let id = client.xadd("games", &[("type", "game")]).await.unwrap();
+        assert_eq!(id, "1526919030474-0");
+        assert_eq!(
+            server.await.unwrap(),
+            b"*5\r\n$4\r\nXADD\r\n$5\r\ngames\r\n$1\r\n*\r\n$4\r\ntype\r\n$4\r\ngame\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resp_client_reconnects_after_dropped_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            // The first connection dies before answering
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            assert!(socket.read(&mut request).await.unwrap() > 0);
+            drop(socket);
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert!(socket.read(&mut request).await.unwrap() > 0);
+            socket.write_all(b"+1-0\r\n").await.unwrap();
+        });
+
+        let mut client = RespClient::connect(&url).await.unwrap();
+        assert_eq!(client.xadd("games", &[("type", "game")]).await.unwrap(), "1-0");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resp_client_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            // Accept connections but never answer
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let mut client = RespClient::connect(&url)
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_millis(50));
+        match client.xadd("games", &[("type", "game")]).await {
+            Err(BotError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+}
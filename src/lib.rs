@@ -1,4 +1,5 @@
 pub mod engine;
+pub mod error;
 pub mod harvest;
 pub mod lichess;
 pub mod uci;
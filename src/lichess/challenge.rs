@@ -3,11 +3,219 @@
 //! Inspired by lichess-bot's challenge filter, but implemented in Rust
 //! with configurable rules for time controls, variants, and ratings.
 
+use chrono::{Datelike, Local, NaiveDateTime, Timelike, Weekday};
 use licheszter::models::board::Challenge;
-use log::debug;
+use licheszter::models::game::Clock;
+use serde::Deserialize;
+use tracing::debug;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::BotError;
+
+/// Variants the engine can actually play. Everything else, including
+/// `fromPosition` (games always start from the standard position), is
+/// declined even if listed in `accepted_variants`.
+pub const SUPPORTED_VARIANTS: [&str; 1] = ["standard"];
+
+/// Whether the engine can play the variant with Lichess key `key`.
+pub fn is_supported_variant(key: &str) -> bool {
+    SUPPORTED_VARIANTS
+        .iter()
+        .any(|supported| supported.eq_ignore_ascii_case(key))
+}
+
+/// Speed category of a time control, as Lichess classifies it.
+///
+/// Ordered from fastest to slowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Speed {
+    UltraBullet,
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+    Correspondence,
+}
+
+impl Speed {
+    /// Every speed, fastest first.
+    pub const ALL: [Speed; 6] = [
+        Speed::UltraBullet,
+        Speed::Bullet,
+        Speed::Blitz,
+        Speed::Rapid,
+        Speed::Classical,
+        Speed::Correspondence,
+    ];
+
+    /// Classify a clock of `initial` seconds plus `increment` seconds per
+    /// move by its estimated duration, `initial + 40 * increment`.
+    ///
+    /// See https://lichess.org/faq#time-controls
+    pub fn from_clock(initial: u32, increment: u32) -> Self {
+        match initial + 40 * increment {
+            0..=29 => Speed::UltraBullet,
+            30..=179 => Speed::Bullet,
+            180..=479 => Speed::Blitz,
+            480..=1499 => Speed::Rapid,
+            _ => Speed::Classical,
+        }
+    }
+
+    /// Speed of a challenge's time control. Games without a clock
+    /// (correspondence or unlimited) count as correspondence.
+    pub fn of(time_control: &Clock) -> Self {
+        match (time_control.limit, time_control.increment) {
+            (Some(limit), Some(increment)) => Self::from_clock(limit.into(), increment.into()),
+            _ => Speed::Correspondence,
+        }
+    }
+
+    /// The speed key used by Lichess.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Speed::UltraBullet => "ultraBullet",
+            Speed::Bullet => "bullet",
+            Speed::Blitz => "blitz",
+            Speed::Rapid => "rapid",
+            Speed::Classical => "classical",
+            Speed::Correspondence => "correspondence",
+        }
+    }
+
+    /// Speed with the given key (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|speed| speed.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Local hours during which rated challenges are accepted, e.g.
+/// `mon-fri 18-23, sat-sun 10-22`.
+///
+/// Each comma-separated window is an optional day or day range (every day
+/// if left out) and an hour range, start inclusive and end exclusive
+/// (`0-24` is the whole day). Ranges may wrap: `fri-mon`, `22-2`; the
+/// hours past midnight fall on the next day, so `sun 22-2` runs into
+/// Monday morning. An empty
+/// schedule accepts rated challenges at any time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RatedSchedule {
+    windows: Vec<ScheduleWindow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScheduleWindow {
+    first_day: Weekday,
+    last_day: Weekday,
+    start_hour: u32,
+    end_hour: u32,
+}
+
+impl ScheduleWindow {
+    fn contains(&self, day: Weekday, hour: u32) -> bool {
+        let offset = |d: Weekday| (d.num_days_from_monday() + 7 - self.first_day.num_days_from_monday()) % 7;
+        let in_days = |d: Weekday| offset(d) <= offset(self.last_day);
+        match self.start_hour <= self.end_hour {
+            true => in_days(day) && (self.start_hour..self.end_hour).contains(&hour),
+            false if hour >= self.start_hour => in_days(day),
+            // Past midnight, the window started the day before
+            false => hour < self.end_hour && in_days(day.pred()),
+        }
+    }
+}
+
+impl RatedSchedule {
+    /// Whether rated challenges are accepted at local time `now`.
+    pub fn allows(&self, now: &NaiveDateTime) -> bool {
+        self.windows.is_empty()
+            || self
+                .windows
+                .iter()
+                .any(|window| window.contains(now.weekday(), now.hour()))
+    }
+}
+
+impl FromStr for RatedSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let day = |name: &str| {
+            Weekday::from_str(name).map_err(|_| format!("Unknown day '{}' in rated schedule", name))
+        };
+        let hour = |value: &str| match value.parse::<u32>() {
+            Ok(hour) if hour <= 24 => Ok(hour),
+            _ => Err(format!("Invalid hour '{}' in rated schedule", value)),
+        };
+        let mut windows = Vec::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (days, hours) = match entry.split_once(char::is_whitespace) {
+                Some((days, hours)) => (Some(days), hours.trim()),
+                None => (None, entry),
+            };
+            let (first_day, last_day) = match days.map(|d| d.split_once('-').unwrap_or((d, d))) {
+                Some((first, last)) => (day(first)?, day(last)?),
+                None => (Weekday::Mon, Weekday::Sun),
+            };
+            let (start, end) = hours
+                .split_once('-')
+                .ok_or_else(|| format!("Expected an hour range like 18-23, got '{}'", hours))?;
+            windows.push(ScheduleWindow {
+                first_day,
+                last_day,
+                start_hour: hour(start)?,
+                end_hour: hour(end)?,
+            });
+        }
+        Ok(RatedSchedule { windows })
+    }
+}
+
+impl TryFrom<String> for RatedSchedule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for RatedSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, window) in self.windows.iter().enumerate() {
+            let day = |d: Weekday| d.to_string().to_lowercase();
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(
+                f,
+                "{}-{} {}-{}",
+                day(window.first_day),
+                day(window.last_day),
+                window.start_hour,
+                window.end_hour
+            )?;
+        }
+        Ok(())
+    }
+}
 
 /// Configuration for which challenges to accept.
-#[derive(Debug, Clone)]
+///
+/// Deserializes from the file format of [`ChallengeConfig::from_yaml`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ChallengeConfig {
     /// Accept challenges from bots.
     pub accept_bot: bool,
@@ -25,10 +233,21 @@ pub struct ChallengeConfig {
     pub min_increment: u32,
     /// Maximum increment in seconds (0 = no maximum).
     pub max_increment: u32,
-    /// Accepted variants (empty = accept all).
+    /// Accepted speed categories, e.g. `["blitz", "rapid"]` (empty =
+    /// accept all). Checked in addition to the time bounds above.
+    pub accepted_speeds: Vec<String>,
+    /// Accepted variants (empty = accept all). Only variants in
+    /// [`SUPPORTED_VARIANTS`] are ever accepted.
     pub accepted_variants: Vec<String>,
     /// Blocked usernames (case-insensitive).
     pub blocked_users: Vec<String>,
+    /// When rated challenges are accepted (empty = whenever
+    /// `accept_rated` allows them).
+    #[serde(rename = "rated_hours")]
+    pub rated_schedule: RatedSchedule,
+    /// How rematches of our games are answered.
+    #[serde(rename = "rematch")]
+    pub rematch_policy: RematchPolicy,
 }
 
 impl Default for ChallengeConfig {
@@ -42,77 +261,773 @@ impl Default for ChallengeConfig {
             max_initial_time: 0,
             min_increment: 0,
             max_increment: 0,
+            accepted_speeds: Vec::new(),
             accepted_variants: vec!["standard".to_string()],
             blocked_users: Vec::new(),
+            rated_schedule: RatedSchedule::default(),
+            rematch_policy: RematchPolicy::default(),
         }
     }
 }
 
 impl ChallengeConfig {
     /// Create config from environment variables.
-    pub fn from_env() -> Self {
-        let variants = std::env::var("BOT_VARIANTS")
-            .unwrap_or_else(|_| "standard".to_string())
-            .split(',')
-            .map(|s| s.trim().to_lowercase())
-            .collect();
+    ///
+    /// If `BOT_CHALLENGE_CONFIG` names a YAML file (see
+    /// [`ChallengeConfig::from_yaml`]), it is loaded first and the other
+    /// variables override it. A file that can't be loaded, or an invalid
+    /// `BOT_RATED_HOURS` or `BOT_REMATCH`, is an error, so a typo can't
+    /// quietly open the bot up to every challenge.
+    pub fn from_env() -> Result<Self, BotError> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
 
-        let blocked = std::env::var("BOT_BLOCKED_USERS")
-            .unwrap_or_default()
-            .split(',')
-            .filter(|s| !s.is_empty())
-            .map(|s| s.trim().to_lowercase())
-            .collect();
+    /// Like [`ChallengeConfig::from_env`], but reading each variable
+    /// through `var` instead of the process environment.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, BotError> {
+        let mut config = match var("BOT_CHALLENGE_CONFIG") {
+            Some(path) if !path.is_empty() => Self::from_yaml(Path::new(&path))?,
+            _ => Self::default(),
+        };
+
+        if let Some(variants) = var("BOT_VARIANTS") {
+            config.accepted_variants = variants.split(',').map(|s| s.trim().to_lowercase()).collect();
+        }
+        if let Some(speeds) = var("BOT_SPEEDS") {
+            config.accepted_speeds = speeds
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(blocked) = var("BOT_BLOCKED_USERS") {
+            config.blocked_users = blocked
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.trim().to_lowercase())
+                .collect();
+        }
+        if let Some(hours) = var("BOT_RATED_HOURS") {
+            config.rated_schedule = hours
+                .parse()
+                .map_err(|e| BotError::Config(format!("BOT_RATED_HOURS: {}", e)))?;
+        }
+        if let Some(policy) = var("BOT_REMATCH") {
+            config.rematch_policy = policy
+                .parse()
+                .map_err(|e| BotError::Config(format!("BOT_REMATCH: {}", e)))?;
+        }
+        if let Some(v) = var("BOT_ACCEPT_BOT") {
+            config.accept_bot = v != "false" && v != "0";
+        }
+        if let Some(v) = var("BOT_ACCEPT_HUMAN") {
+            config.accept_human = v != "false" && v != "0";
+        }
+        Ok(config)
+    }
+
+    /// Load config from a YAML file. Keys are the field names, with
+    /// `rated_hours` and `rematch` for the schedule and rematch policy;
+    /// missing keys keep their defaults:
+    ///
+    /// ```yaml
+    /// accept_rated: false
+    /// min_initial_time: 180
+    /// accepted_variants: [standard]
+    /// blocked_users:
+    ///   - spammer
+    ///   - "another_one"
+    /// rated_hours: mon-fri 18-23, sat-sun 10-22
+    /// rematch: always
+    /// ```
+    ///
+    /// Unknown keys and values of the wrong type are errors. Variants and
+    /// usernames are lowercased.
+    pub fn from_yaml(path: &Path) -> Result<Self, BotError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| BotError::Config(format!("Reading {}: {}", path.display(), e)))?;
+        let mut config: Self = serde_yaml::from_str(&text)
+            .map_err(|e| BotError::Config(format!("{}: {}", path.display(), e)))?;
+        let lowercase = |items: &mut Vec<String>| items.iter_mut().for_each(|s| *s = s.to_lowercase());
+        lowercase(&mut config.accepted_variants);
+        lowercase(&mut config.blocked_users);
+        Ok(config)
+    }
+
+    /// Entries of `accepted_variants` the engine can't play, which will
+    /// be declined anyway.
+    pub fn unsupported_variants(&self) -> Vec<&str> {
+        self.accepted_variants
+            .iter()
+            .map(String::as_str)
+            .filter(|variant| !is_supported_variant(variant))
+            .collect()
+    }
+
+    /// Entries of `accepted_speeds` that name no speed category.
+    pub fn unknown_speeds(&self) -> Vec<&str> {
+        self.accepted_speeds
+            .iter()
+            .map(String::as_str)
+            .filter(|speed| Speed::from_name(speed).is_none())
+            .collect()
+    }
+
+    /// Format the settings [`ChallengeConfig::from_env`] reads as
+    /// `KEY=VALUE` lines.
+    pub fn to_env_string(&self) -> String {
+        format!(
+            "BOT_ACCEPT_BOT={}\nBOT_ACCEPT_HUMAN={}\nBOT_VARIANTS={}\nBOT_SPEEDS={}\nBOT_BLOCKED_USERS={}\nBOT_RATED_HOURS=\"{}\"\nBOT_REMATCH={}\n",
+            self.accept_bot,
+            self.accept_human,
+            self.accepted_variants.join(","),
+            self.accepted_speeds.join(","),
+            self.blocked_users.join(","),
+            self.rated_schedule,
+            self.rematch_policy.as_str()
+        )
+    }
+}
+
+/// How to answer a challenge that is a rematch of one of our games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RematchPolicy {
+    /// Accept it, even at the concurrent game limit or when the rules
+    /// would decline it.
+    Always,
+    /// Decline it.
+    Never,
+    /// Treat it like any other challenge.
+    #[default]
+    Rules,
+}
+
+impl RematchPolicy {
+    /// Name accepted by [`RematchPolicy::from_str`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RematchPolicy::Always => "always",
+            RematchPolicy::Never => "never",
+            RematchPolicy::Rules => "rules",
+        }
+    }
+}
+
+impl FromStr for RematchPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(RematchPolicy::Always),
+            "never" => Ok(RematchPolicy::Never),
+            "rules" => Ok(RematchPolicy::Rules),
+            other => Err(format!("Unknown rematch policy '{}', use always, never or rules", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for RematchPolicy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// How long after one of our games ends a challenge from its opponent
+/// counts as a rematch.
+pub const REMATCH_OFFER_TTL: Duration = Duration::from_secs(120);
+
+/// Rematches offered at the end of our games.
+///
+/// The challenge event doesn't say what it is a rematch of, and the game
+/// stream usually ends before a final state naming the rematch arrives.
+/// Game tasks therefore record the opponent of every finished game here,
+/// along with the rematch's challenge ID when they do see it. A challenge
+/// with that ID, or from that opponent within [`REMATCH_OFFER_TTL`], is
+/// then known to be a rematch. Older offers expire.
+#[derive(Debug, Clone, Default)]
+pub struct RematchOffers {
+    offers: Arc<Mutex<Vec<RematchOffer>>>,
+}
+
+#[derive(Debug)]
+struct RematchOffer {
+    /// Lowercased username of the opponent.
+    opponent: String,
+    challenge_id: Option<String>,
+    ended: Instant,
+}
+
+impl RematchOffers {
+    /// Remember that a game against `opponent` ended at `now`, with the
+    /// rematch `challenge_id` if the game state named one.
+    pub fn record(&self, opponent: &str, challenge_id: Option<&str>, now: Instant) {
+        let mut offers = self.offers.lock().unwrap();
+        expire_offers(&mut offers, now);
+        offers.push(RematchOffer {
+            opponent: opponent.to_lowercase(),
+            challenge_id: challenge_id.map(str::to_string),
+            ended: now,
+        });
+    }
+
+    /// Whether the challenge `challenge_id` from `challenger` is a rematch
+    /// still on offer at `now`, forgetting the offer if so.
+    pub fn take(&self, challenge_id: &str, challenger: Option<&str>, now: Instant) -> bool {
+        let challenger = challenger.map(str::to_lowercase);
+        let mut offers = self.offers.lock().unwrap();
+        expire_offers(&mut offers, now);
+        let found = offers.iter().position(|offer| {
+            offer.challenge_id.as_deref() == Some(challenge_id)
+                || challenger.as_deref() == Some(offer.opponent.as_str())
+        });
+        found.map(|index| offers.remove(index)).is_some()
+    }
+}
 
+fn expire_offers(offers: &mut Vec<RematchOffer>, now: Instant) {
+    offers.retain(|offer| now.saturating_duration_since(offer.ended) < REMATCH_OFFER_TTL);
+}
+
+/// Minimum spacing between accepted challenges.
+///
+/// When several games end at once, a burst of challenges could otherwise
+/// fill every free slot at the same moment. Only the first challenge
+/// within each window is accepted; a zero window disables the limit.
+#[derive(Debug, Default)]
+pub struct ChallengeCooldown {
+    window: Duration,
+    last_accepted: Mutex<Option<Instant>>,
+}
+
+impl ChallengeCooldown {
+    pub fn new(window: Duration) -> Self {
         Self {
-            accept_bot: std::env::var("BOT_ACCEPT_BOT")
-                .map(|v| v != "false" && v != "0")
-                .unwrap_or(true),
-            accept_human: std::env::var("BOT_ACCEPT_HUMAN")
-                .map(|v| v != "false" && v != "0")
-                .unwrap_or(true),
-            accept_rated: true,
-            accept_casual: true,
-            min_initial_time: 0,
-            max_initial_time: 0,
-            min_increment: 0,
-            max_increment: 0,
-            accepted_variants: variants,
-            blocked_users: blocked,
+            window,
+            last_accepted: Mutex::new(None),
+        }
+    }
+
+    /// Claim an acceptance at `now`. Returns `false` if the previous
+    /// acceptance was less than the window ago; otherwise records `now`
+    /// as the latest acceptance and returns `true`.
+    pub fn try_accept(&self, now: Instant) -> bool {
+        let mut last_accepted = self.last_accepted.lock().unwrap();
+        let cooling = last_accepted
+            .is_some_and(|last| now.saturating_duration_since(last) < self.window);
+        if !cooling {
+            *last_accepted = Some(now);
+        }
+        !cooling
+    }
+}
+
+/// Reason sent to Lichess when declining a challenge.
+///
+/// Lichess shows the challenger a localized message for each reason.
+/// See https://lichess.org/api#tag/Challenges/operation/challengeDecline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeDeclineReason {
+    Generic,
+    Later,
+    TooFast,
+    TooSlow,
+    TimeControl,
+    Rated,
+    Casual,
+    Standard,
+    Variant,
+    NoBot,
+    OnlyBot,
+}
+
+impl ChallengeDeclineReason {
+    /// Every reason, in declaration order.
+    pub const ALL: [ChallengeDeclineReason; 11] = [
+        ChallengeDeclineReason::Generic,
+        ChallengeDeclineReason::Later,
+        ChallengeDeclineReason::TooFast,
+        ChallengeDeclineReason::TooSlow,
+        ChallengeDeclineReason::TimeControl,
+        ChallengeDeclineReason::Rated,
+        ChallengeDeclineReason::Casual,
+        ChallengeDeclineReason::Standard,
+        ChallengeDeclineReason::Variant,
+        ChallengeDeclineReason::NoBot,
+        ChallengeDeclineReason::OnlyBot,
+    ];
+
+    /// Position of this reason in [`ChallengeDeclineReason::ALL`].
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// The reason key expected by the Lichess API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengeDeclineReason::Generic => "generic",
+            ChallengeDeclineReason::Later => "later",
+            ChallengeDeclineReason::TooFast => "tooFast",
+            ChallengeDeclineReason::TooSlow => "tooSlow",
+            ChallengeDeclineReason::TimeControl => "timeControl",
+            ChallengeDeclineReason::Rated => "rated",
+            ChallengeDeclineReason::Casual => "casual",
+            ChallengeDeclineReason::Standard => "standard",
+            ChallengeDeclineReason::Variant => "variant",
+            ChallengeDeclineReason::NoBot => "noBot",
+            ChallengeDeclineReason::OnlyBot => "onlyBot",
         }
     }
 }
 
+impl fmt::Display for ChallengeDeclineReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// Decide whether to accept a challenge based on the config rules.
 ///
+/// Returns `true` if no rule in [`decline_reason`] rejects the challenge.
+pub fn should_accept(challenge: &Challenge, config: &ChallengeConfig) -> bool {
+    decline_reason(challenge, config).is_none()
+}
+
+/// Run the challenge through the config rules, returning the reason to
+/// decline it with, or `None` if it should be accepted.
+///
 /// Decision tree (mirrors lichess-bot's challenge filter):
 /// 1. Check if challenger is blocked
 /// 2. Check if bot/human challenges are accepted
-/// 3. Check if rated/casual is accepted
-/// 4. Check variant
+/// 3. Check if rated/casual is accepted, and rated at this local time
+/// 4. Check variant (configured and supported by the engine)
 /// 5. Check time control bounds
-pub fn should_accept(challenge: &Challenge, config: &ChallengeConfig) -> bool {
+/// 6. Check speed category
+pub fn decline_reason(
+    challenge: &Challenge,
+    config: &ChallengeConfig,
+) -> Option<ChallengeDeclineReason> {
+    decline_reason_at(challenge, config, &Local::now().naive_local())
+}
+
+/// Like [`decline_reason`], at local time `now`.
+pub fn decline_reason_at(
+    challenge: &Challenge,
+    config: &ChallengeConfig,
+    now: &NaiveDateTime,
+) -> Option<ChallengeDeclineReason> {
     // 1. Check blocked users
     if let Some(ref challenger) = challenge.challenger {
         let username_lower = challenger.username.to_lowercase();
         if config.blocked_users.contains(&username_lower) {
             debug!("Declining: user {} is blocked", challenger.username);
-            return false;
+            return Some(ChallengeDeclineReason::Generic);
+        }
+    }
+
+    // 2. Check bot/human
+    let is_bot = challenge
+        .challenger
+        .as_ref()
+        .and_then(|u| u.title.as_deref())
+        .map(|t| t.eq_ignore_ascii_case("BOT"))
+        .unwrap_or(false);
+    if is_bot && !config.accept_bot {
+        debug!("Declining: bot challenges not accepted");
+        return Some(ChallengeDeclineReason::NoBot);
+    }
+    if !is_bot && !config.accept_human {
+        debug!("Declining: human challenges not accepted");
+        return Some(ChallengeDeclineReason::OnlyBot);
+    }
+
+    // 3. Check rated/casual
+    if challenge.rated && !config.accept_rated {
+        debug!("Declining: rated games not accepted");
+        return Some(ChallengeDeclineReason::Casual);
+    }
+    if challenge.rated && !config.rated_schedule.allows(now) {
+        debug!("Declining: rated games not accepted at {}", now.format("%a %H:%M"));
+        return Some(ChallengeDeclineReason::Casual);
+    }
+    if !challenge.rated && !config.accept_casual {
+        debug!("Declining: casual games not accepted");
+        return Some(ChallengeDeclineReason::Rated);
+    }
+
+    // 4. Check variant (if restrictions are configured)
+    let variant = challenge
+        .variant
+        .key
+        .to_lowercase();
+    if !config.accepted_variants.is_empty() && !config.accepted_variants.contains(&variant) {
+        debug!("Declining: variant {} not accepted", variant);
+        return Some(ChallengeDeclineReason::Variant);
+    }
+    if !is_supported_variant(&variant) {
+        debug!("Declining: variant {} not supported by the engine", variant);
+        return Some(ChallengeDeclineReason::Variant);
+    }
+
+    // 5. Check time control bounds
+    let has_time_bounds = config.min_initial_time > 0
+        || config.max_initial_time > 0
+        || config.min_increment > 0
+        || config.max_increment > 0;
+    if has_time_bounds {
+        let initial = challenge.time_control.limit.map(u32::from);
+        let increment = challenge.time_control.increment.map(u32::from);
+        let (initial, increment) = match (initial, increment) {
+            (Some(initial), Some(increment)) => (initial, increment),
+            // Correspondence or unlimited games have no clock to check
+            _ => {
+                debug!("Declining: no clock to check against time bounds");
+                return Some(ChallengeDeclineReason::TimeControl);
+            }
+        };
+        if initial < config.min_initial_time || increment < config.min_increment {
+            debug!("Declining: time control {}+{} too fast", initial, increment);
+            return Some(ChallengeDeclineReason::TooFast);
+        }
+        if (config.max_initial_time > 0 && initial > config.max_initial_time)
+            || (config.max_increment > 0 && increment > config.max_increment)
+        {
+            debug!("Declining: time control {}+{} too slow", initial, increment);
+            return Some(ChallengeDeclineReason::TooSlow);
         }
     }
 
-    // 2. Check variant (if restrictions are configured)
-    if !config.accepted_variants.is_empty() {
-        let variant = challenge
-            .variant
-            .key
-            .to_lowercase();
-        if !config.accepted_variants.contains(&variant) {
-            debug!("Declining: variant {} not accepted", variant);
-            return false;
+    // 6. Check speed category (if restrictions are configured)
+    if !config.accepted_speeds.is_empty() {
+        let speed = Speed::of(&challenge.time_control);
+        let accepted: Vec<Speed> = config
+            .accepted_speeds
+            .iter()
+            .filter_map(|name| Speed::from_name(name))
+            .collect();
+        if !accepted.contains(&speed) {
+            debug!("Declining: speed {} not accepted", speed);
+            return Some(match (accepted.iter().min(), accepted.iter().max()) {
+                (Some(&fastest), _) if speed < fastest => ChallengeDeclineReason::TooFast,
+                (_, Some(&slowest)) if speed > slowest => ChallengeDeclineReason::TooSlow,
+                _ => ChallengeDeclineReason::TimeControl,
+            });
         }
     }
 
     // Accept by default if all checks pass
-    true
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn challenge(rated: bool, variant: &str, title: Option<&str>, limit: Option<u32>) -> Challenge {
+        let mut time_control = json!({ "type": "unlimited" });
+        if let Some(limit) = limit {
+            time_control = json!({ "type": "clock", "limit": limit, "increment": 2, "show": "n/a" });
+        }
+        serde_json::from_value(json!({
+            "id": "abc123",
+            "url": "https://lichess.org/abc123",
+            "finalColor": "white",
+            "color": "random",
+            "timeControl": time_control,
+            "variant": { "key": variant, "name": variant },
+            "challenger": { "id": "opponent", "name": "Opponent", "title": title },
+            "perf": { "name": "Blitz" },
+            "rated": rated,
+            "speed": "blitz",
+            "status": "created",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decline_reason_accepts_default() {
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &ChallengeConfig::default()), None);
+        assert!(should_accept(&c, &ChallengeConfig::default()));
+    }
+
+    #[test]
+    fn test_decline_reason_blocked_user() {
+        let config = ChallengeConfig {
+            blocked_users: vec!["opponent".to_string()],
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &config), Some(ChallengeDeclineReason::Generic));
+        assert!(!should_accept(&c, &config));
+    }
+
+    #[test]
+    fn test_decline_reason_bot_and_human() {
+        let no_bots = ChallengeConfig {
+            accept_bot: false,
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(true, "standard", Some("BOT"), Some(300));
+        assert_eq!(decline_reason(&c, &no_bots), Some(ChallengeDeclineReason::NoBot));
+
+        let no_humans = ChallengeConfig {
+            accept_human: false,
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &no_humans), Some(ChallengeDeclineReason::OnlyBot));
+    }
+
+    #[test]
+    fn test_decline_reason_rated_and_casual() {
+        let no_rated = ChallengeConfig {
+            accept_rated: false,
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &no_rated), Some(ChallengeDeclineReason::Casual));
+
+        let no_casual = ChallengeConfig {
+            accept_casual: false,
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(false, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &no_casual), Some(ChallengeDeclineReason::Rated));
+    }
+
+    #[test]
+    fn test_rated_schedule_window() {
+        let schedule: RatedSchedule = "mon-fri 18-23, sun 22-2".parse().unwrap();
+        assert_eq!(schedule.to_string(), "mon-fri 18-23,sun-sun 22-2");
+        assert_eq!(schedule.to_string().parse::<RatedSchedule>(), Ok(schedule.clone()));
+        assert!("mon-fri 18".parse::<RatedSchedule>().is_err());
+        assert!("someday 1-2".parse::<RatedSchedule>().is_err());
+        assert!("25-26".parse::<RatedSchedule>().is_err());
+
+        let config = ChallengeConfig {
+            rated_schedule: schedule,
+            ..ChallengeConfig::default()
+        };
+        // 2024-01-01 is a Monday
+        let at = |day: u32, hour: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, 30, 0).unwrap()
+        };
+        let rated = challenge(true, "standard", None, Some(300));
+        let casual = challenge(false, "standard", None, Some(300));
+        assert_eq!(decline_reason_at(&rated, &config, &at(1, 18)), None);
+        assert_eq!(decline_reason_at(&rated, &config, &at(5, 22)), None);
+        assert_eq!(
+            decline_reason_at(&rated, &config, &at(1, 23)),
+            Some(ChallengeDeclineReason::Casual)
+        );
+        assert_eq!(
+            decline_reason_at(&rated, &config, &at(6, 19)),
+            Some(ChallengeDeclineReason::Casual)
+        );
+        // Sunday's window runs past midnight into Monday
+        assert_eq!(decline_reason_at(&rated, &config, &at(7, 23)), None);
+        assert_eq!(decline_reason_at(&rated, &config, &at(8, 1)), None);
+        assert_eq!(
+            decline_reason_at(&rated, &config, &at(7, 1)),
+            Some(ChallengeDeclineReason::Casual)
+        );
+        assert_eq!(
+            decline_reason_at(&rated, &config, &at(8, 2)),
+            Some(ChallengeDeclineReason::Casual)
+        );
+        for hour in [3, 12, 23] {
+            assert_eq!(decline_reason_at(&casual, &config, &at(6, hour)), None);
+        }
+    }
+
+    #[test]
+    fn test_decline_reason_variant() {
+        let c = challenge(true, "atomic", None, Some(300));
+        assert_eq!(
+            decline_reason(&c, &ChallengeConfig::default()),
+            Some(ChallengeDeclineReason::Variant)
+        );
+    }
+
+    #[test]
+    fn test_decline_reason_unsupported_variant() {
+        let config = ChallengeConfig {
+            accepted_variants: vec!["standard".to_string(), "atomic".to_string()],
+            ..ChallengeConfig::default()
+        };
+        assert_eq!(config.unsupported_variants(), vec!["atomic"]);
+        let c = challenge(true, "atomic", None, Some(300));
+        assert_eq!(decline_reason(&c, &config), Some(ChallengeDeclineReason::Variant));
+
+        // An empty list accepts every variant the engine supports
+        let any = ChallengeConfig {
+            accepted_variants: Vec::new(),
+            ..ChallengeConfig::default()
+        };
+        assert_eq!(decline_reason(&c, &any), Some(ChallengeDeclineReason::Variant));
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &any), None);
+    }
+
+    #[test]
+    fn test_decline_reason_time_control() {
+        let config = ChallengeConfig {
+            min_initial_time: 60,
+            max_initial_time: 600,
+            ..ChallengeConfig::default()
+        };
+        let c = challenge(true, "standard", None, Some(30));
+        assert_eq!(decline_reason(&c, &config), Some(ChallengeDeclineReason::TooFast));
+
+        let c = challenge(true, "standard", None, Some(1800));
+        assert_eq!(decline_reason(&c, &config), Some(ChallengeDeclineReason::TooSlow));
+
+        let c = challenge(true, "standard", None, None);
+        assert_eq!(decline_reason(&c, &config), Some(ChallengeDeclineReason::TimeControl));
+
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &config), None);
+    }
+
+    #[test]
+    fn test_speed_from_clock() {
+        assert_eq!(Speed::from_clock(15, 0), Speed::UltraBullet);
+        assert_eq!(Speed::from_clock(60, 0), Speed::Bullet);
+        assert_eq!(Speed::from_clock(120, 1), Speed::Bullet);
+        assert_eq!(Speed::from_clock(180, 0), Speed::Blitz);
+        assert_eq!(Speed::from_clock(180, 2), Speed::Blitz);
+        assert_eq!(Speed::from_clock(600, 0), Speed::Rapid);
+        assert_eq!(Speed::from_clock(900, 10), Speed::Rapid);
+        assert_eq!(Speed::from_clock(1800, 0), Speed::Classical);
+        assert_eq!(Speed::of(&challenge(true, "standard", None, None).time_control), Speed::Correspondence);
+        assert_eq!(Speed::from_name("Blitz"), Some(Speed::Blitz));
+        assert_eq!(Speed::from_name("ultrabullet"), Some(Speed::UltraBullet));
+        assert_eq!(Speed::from_name("hyper"), None);
+    }
+
+    #[test]
+    fn test_decline_reason_speed() {
+        let only_blitz = ChallengeConfig {
+            accepted_speeds: vec!["blitz".to_string()],
+            ..ChallengeConfig::default()
+        };
+        // Every test challenge has a 2 second increment
+        let c = challenge(true, "standard", None, Some(180));
+        assert_eq!(decline_reason(&c, &only_blitz), None);
+        let c = challenge(true, "standard", None, Some(60));
+        assert_eq!(decline_reason(&c, &only_blitz), Some(ChallengeDeclineReason::TooFast));
+        let c = challenge(true, "standard", None, Some(600));
+        assert_eq!(decline_reason(&c, &only_blitz), Some(ChallengeDeclineReason::TooSlow));
+        let c = challenge(true, "standard", None, None);
+        assert_eq!(decline_reason(&c, &only_blitz), Some(ChallengeDeclineReason::TooSlow));
+
+        let bullet_or_classical = ChallengeConfig {
+            accepted_speeds: vec!["bullet".to_string(), "classical".to_string(), "hyper".to_string()],
+            ..ChallengeConfig::default()
+        };
+        assert_eq!(bullet_or_classical.unknown_speeds(), vec!["hyper"]);
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &bullet_or_classical), Some(ChallengeDeclineReason::TimeControl));
+
+        // Speeds compose with the raw bounds
+        let blitz_with_bounds = ChallengeConfig {
+            max_initial_time: 200,
+            ..only_blitz
+        };
+        let c = challenge(true, "standard", None, Some(300));
+        assert_eq!(decline_reason(&c, &blitz_with_bounds), Some(ChallengeDeclineReason::TooSlow));
+        let c = challenge(true, "standard", None, Some(180));
+        assert_eq!(decline_reason(&c, &blitz_with_bounds), None);
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let path = std::env::temp_dir().join(format!("stonksfish-challenge-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Challenge filter\n\
+             accept_bot: false\n\
+             accept_casual: false\n\
+             min_initial_time: 180\n\
+             max_increment: 5\n\
+             accepted_speeds: [blitz, \"rapid\"]\n\
+             accepted_variants:\n  - Standard\n  - chess960  # declined anyway\n\
+             blocked_users:\n  - Spammer\n  - 'troll'\n",
+        )
+        .unwrap();
+        let config = ChallengeConfig::from_yaml(&path).unwrap();
+        assert_eq!(
+            config,
+            ChallengeConfig {
+                accept_bot: false,
+                accept_casual: false,
+                min_initial_time: 180,
+                max_increment: 5,
+                accepted_speeds: vec!["blitz".to_string(), "rapid".to_string()],
+                accepted_variants: vec!["standard".to_string(), "chess960".to_string()],
+                blocked_users: vec!["spammer".to_string(), "troll".to_string()],
+                ..ChallengeConfig::default()
+            }
+        );
+
+        // Quoted '#', flow mappings and multi-line values are full YAML
+        std::fs::write(
+            &path,
+            "{blocked_users: [\"no#1\", 'x # y'], rematch: never,\n rated_hours: \"mon-fri 18-23,\n   sat 10-12\"}\n",
+        )
+        .unwrap();
+        let config = ChallengeConfig::from_yaml(&path).unwrap();
+        assert_eq!(config.blocked_users, ["no#1", "x # y"]);
+        assert_eq!(config.rematch_policy, RematchPolicy::Never);
+        assert_eq!(config.rated_schedule, "mon-fri 18-23, sat 10-12".parse().unwrap());
+
+        std::fs::write(&path, "accept_bots: false\n").unwrap();
+        assert!(matches!(ChallengeConfig::from_yaml(&path), Err(BotError::Config(_))));
+        std::fs::write(&path, "min_increment: [1]\n").unwrap();
+        assert!(ChallengeConfig::from_yaml(&path).is_err());
+        std::fs::write(&path, "rematch: sometimes\n").unwrap();
+        assert!(ChallengeConfig::from_yaml(&path).is_err());
+        std::fs::remove_file(&path).ok();
+        assert!(ChallengeConfig::from_yaml(&path).is_err());
+    }
+
+    #[test]
+    fn test_cooldown_spaces_acceptances() {
+        let cooldown = ChallengeCooldown::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(cooldown.try_accept(start));
+        assert!(!cooldown.try_accept(start + Duration::from_secs(9)));
+        assert!(cooldown.try_accept(start + Duration::from_secs(10)));
+
+        let disabled = ChallengeCooldown::default();
+        assert!(disabled.try_accept(start));
+        assert!(disabled.try_accept(start));
+    }
+
+    #[test]
+    fn test_rematch_offers_match_opponent_and_expire() {
+        let offers = RematchOffers::default();
+        let start = Instant::now();
+        offers.record("Opponent", None, start);
+        offers.record("Other", Some("named1"), start);
+        assert!(!offers.take("abc123", Some("stranger"), start));
+        assert!(offers.take("abc123", Some("OPPONENT"), start));
+        assert!(!offers.take("abc123", Some("opponent"), start));
+        assert!(offers.take("named1", None, start));
+
+        offers.record("Opponent", None, start);
+        assert!(!offers.take("abc123", Some("opponent"), start + REMATCH_OFFER_TTL));
+        assert!(offers.offers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decline_reason_as_str() {
+        assert_eq!(ChallengeDeclineReason::TooFast.as_str(), "tooFast");
+        assert_eq!(ChallengeDeclineReason::NoBot.to_string(), "noBot");
+    }
 }
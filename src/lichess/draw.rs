@@ -0,0 +1,190 @@
+//! Draw offers.
+//!
+//! Lichess reports open draw offers on every game state update, as the
+//! `wdraw`/`bdraw` flags. The bot answers an opponent's offer through its
+//! next move: moving with `offeringDraw=true` while the opponent offers a
+//! draw accepts it, and moving without declines it. Whether to accept is
+//! up to the [`DrawPolicy`].
+
+use chess::{Board, Color};
+use licheszter::models::board::GameState;
+use tracing::info;
+
+use crate::engine::evaluation::simple::evaluate_board;
+
+/// When to accept a draw offered by the opponent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawPolicy {
+    /// Whether draw offers are considered at all.
+    pub accept: bool,
+    /// Accept only when our evaluation is at most this (centipawns, from
+    /// our side), i.e. when we aren't clearly better.
+    pub max_eval_cp: i32,
+    /// Accept only once at least this many half-moves have been played.
+    pub min_plies: usize,
+}
+
+impl Default for DrawPolicy {
+    fn default() -> Self {
+        Self {
+            accept: false,
+            max_eval_cp: 0,
+            min_plies: 40,
+        }
+    }
+}
+
+impl DrawPolicy {
+    /// Create policy from environment variables.
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Like [`DrawPolicy::from_env`], but reading each variable through
+    /// `var` instead of the process environment.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        Self {
+            accept: var("BOT_ACCEPT_DRAWS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(default.accept),
+            max_eval_cp: var("BOT_DRAW_MAX_EVAL_CP")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default.max_eval_cp),
+            min_plies: var("BOT_DRAW_MIN_PLIES")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default.min_plies),
+        }
+    }
+
+    /// Format the policy as `KEY=VALUE` lines read back by
+    /// [`DrawPolicy::from_env`].
+    pub fn to_env_string(&self) -> String {
+        format!(
+            "BOT_ACCEPT_DRAWS={}\nBOT_DRAW_MAX_EVAL_CP={}\nBOT_DRAW_MIN_PLIES={}\n",
+            self.accept, self.max_eval_cp, self.min_plies
+        )
+    }
+
+    /// Whether to accept a draw in `board`, with us to move, after
+    /// `plies` half-moves.
+    pub fn accepts(&self, board: &Board, plies: usize) -> bool {
+        self.accept && plies >= self.min_plies && evaluate_board(board) <= self.max_eval_cp
+    }
+}
+
+/// The draw flags of `state` as (ours, the opponent's).
+fn draw_flags(state: &GameState, bot_color: Color) -> (bool, bool) {
+    let white = state.wdraw.unwrap_or(false);
+    let black = state.bdraw.unwrap_or(false);
+    match bot_color {
+        Color::White => (white, black),
+        Color::Black => (black, white),
+    }
+}
+
+/// Draw offers in one game: whether ours is still open.
+#[derive(Debug, Default)]
+pub struct DrawOffers {
+    pending: bool,
+}
+
+impl DrawOffers {
+    /// Whether we have an offer the opponent hasn't answered yet.
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Record that our move went out with a draw offer.
+    pub fn offered(&mut self) {
+        self.pending = true;
+    }
+
+    /// Follow the flags of a state update. Our offer disappearing from
+    /// the state means the opponent declined it.
+    pub fn observe(&mut self, state: &GameState, bot_color: Color) {
+        let (ours, _) = draw_flags(state, bot_color);
+        if self.pending && !ours {
+            info!("Opponent declined our draw offer");
+            self.pending = false;
+        }
+    }
+
+    /// With us to move on `board`, whether our move should carry a draw
+    /// offer: only when the opponent offers one and `policy` accepts it.
+    pub fn respond(&mut self, state: &GameState, bot_color: Color, policy: &DrawPolicy, board: &Board) -> bool {
+        self.observe(state, bot_color);
+        let (_, theirs) = draw_flags(state, bot_color);
+        if !theirs {
+            return false;
+        }
+        let accept = policy.accepts(board, state.moves.split_whitespace().count());
+        match accept {
+            true => info!("Accepting the opponent's draw offer"),
+            false => info!("Declining the opponent's draw offer"),
+        }
+        accept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::position::board_from_moves;
+    use serde_json::json;
+
+    fn state(moves: &str, wdraw: bool, bdraw: bool) -> GameState {
+        serde_json::from_value(json!({
+            "type": "gameState",
+            "moves": moves,
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "wdraw": wdraw,
+            "bdraw": bdraw,
+            "status": "started"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_opponent_offer_consults_policy() {
+        let board = board_from_moves(None, &["e2e4", "e7e5"]).unwrap();
+        let offered = state("e2e4 e7e5", false, true);
+        let willing = DrawPolicy {
+            accept: true,
+            max_eval_cp: 100,
+            min_plies: 2,
+        };
+        let mut offers = DrawOffers::default();
+
+        assert!(offers.respond(&offered, Color::White, &willing, &board));
+        assert!(!offers.respond(&state("e2e4 e7e5", false, false), Color::White, &willing, &board));
+        assert!(!offers.respond(&offered, Color::Black, &willing, &board));
+
+        let later = DrawPolicy {
+            min_plies: 3,
+            ..willing.clone()
+        };
+        assert!(!offers.respond(&offered, Color::White, &later, &board));
+        let greedy = DrawPolicy {
+            max_eval_cp: -100,
+            ..willing
+        };
+        assert!(!offers.respond(&offered, Color::White, &greedy, &board));
+        assert!(!offers.respond(&offered, Color::White, &DrawPolicy::default(), &board));
+    }
+
+    #[test]
+    fn test_declined_offer_is_cleared() {
+        let mut offers = DrawOffers::default();
+        offers.offered();
+        offers.observe(&state("e2e4", true, false), Color::White);
+        assert!(offers.pending());
+
+        // The opponent moved on without accepting
+        offers.observe(&state("e2e4 e7e5", false, false), Color::White);
+        assert!(!offers.pending());
+    }
+}
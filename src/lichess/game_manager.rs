@@ -1,66 +1,510 @@
 //! Concurrent game handler.
 //!
-//! Each active game runs in its own tokio task. The game manager:
-//! - Streams game state from Lichess
+//! Each active game runs in its own tokio task, tracked by a
+//! [`GameSupervisor`]. The game manager:
+//! - Streams game state from Lichess, catching up on moves already
+//!   played when resuming a game
 //! - Applies opponent moves
-//! - Computes engine responses via Bot::choose_move()
-//! - Collects positions and decisions for the harvester
-//! - Optionally runs what-if branching on critical positions
-
-use chess::{Board, ChessMove, Color, Game, MoveGen};
-use licheszter::client::Licheszter;
-use licheszter::models::board::{BoardState, Challenger};
-use log::{debug, error, info, warn};
+//! - Computes engine responses via Bot::ponder_move(), noting the
+//!   reply it expects from the opponent, on the blocking pool
+//! - Collects positions and decisions for the harvester, which writes
+//!   them out on its own task
+//! - Optionally runs what-if branching on critical positions, on a
+//!   dedicated thread pool so it never delays live move computation
+
+use async_trait::async_trait;
+use chess::{Action, Board, ChessMove, Color, Game, MoveGen};
+use licheszter::client::{Licheszter, LicheszterResult};
+use licheszter::models::board::{BoardState, Challenger, GameState};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::Mutex;
-use tokio_stream::StreamExt;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_stream::{Stream, StreamExt};
 
-use crate::engine::evaluation::simple::evaluate_board;
-use crate::engine::player::{Bot, Player};
-use crate::harvest::{GameRecord, HarvestSink, MoveRecord};
+use super::challenge::{is_supported_variant, RematchOffers};
+use super::draw::{DrawOffers, DrawPolicy};
+use super::persistence::{GameStore, PersistedGame};
+use crate::engine::evaluation::simple::{evaluate_board, PAWN_VALUE};
+use crate::engine::player::{Bot, OpeningVariety};
+use crate::engine::search::AntiShuffle;
+use crate::error::BotError;
+use crate::harvest::queue::HarvestQueue;
+use crate::harvest::{GameRecord, MoveRecord};
 use crate::uci::{classify_phase, count_pieces};
-use crate::whatif::{generate_branch_tree, BranchConfig};
+use crate::util::pool::ThreadPool;
+use crate::whatif::{generate_branch_tree, prune_tree, BranchConfig, SessionBudget};
+
+/// Per-game settings handed to [`play_game`] by the bot.
+#[derive(Debug, Clone)]
+pub struct GameSettings {
+    /// Engine search depth (plies).
+    pub depth: u8,
+    /// Hard ceiling on search depth.
+    pub max_depth: u8,
+    /// Bias against repeating earlier positions when ahead.
+    pub anti_shuffle: AntiShuffle,
+    /// Whether to run what-if branching on critical positions.
+    pub whatif_enabled: bool,
+    /// What-if generation slots shared by all games.
+    pub whatif_permits: Arc<Semaphore>,
+    /// Threads what-if trees are generated on, shared by all games.
+    pub whatif_pool: ThreadPool,
+    /// Node budget for all what-if trees in this game.
+    pub whatif_budget: SessionBudget,
+    /// Estimated memory ceiling for each what-if tree, in bytes.
+    pub whatif_max_memory: Option<usize>,
+    /// Bot's username on Lichess.
+    pub bot_username: String,
+    /// Longest a game task may run before it is abandoned.
+    pub game_timeout: Duration,
+    /// Fewest half-moves a finished game needs to be harvested.
+    pub min_harvest_moves: usize,
+    /// How many times a failed move submission is retried.
+    pub move_retries: u32,
+    /// Opening randomization, if enabled.
+    pub opening_variety: Option<OpeningVariety>,
+    /// Seed for this game's opening randomization.
+    pub opening_seed: u64,
+    /// Where to save the game's progress so it can be resumed after a
+    /// restart, if anywhere.
+    pub game_store: Option<Arc<GameStore>>,
+    /// When to accept the opponent's draw offers.
+    pub draw_policy: DrawPolicy,
+    /// Whether to greet the opponent and say goodbye in the game chat.
+    pub chat_enabled: bool,
+    /// Where the rematch offered at the end of the game is recorded.
+    pub rematch_offers: RematchOffers,
+}
+
+/// Sent in the game chat when a game starts, if chat is enabled.
+pub const CHAT_GREETING: &str = "Good luck!";
+
+/// Sent in the game chat before resigning, if chat is enabled.
+pub const CHAT_RESIGNING: &str = "I can't play this variant, so I resign. Good game!";
+
+/// Pause before the first retry of a failed move; later retries wait
+/// proportionally longer.
+const MOVE_RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// How long to wait for Lichess when checking whether a move went through.
+const MOVE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The Lichess calls needed to submit a move, so retries can be tested
+/// without a server.
+#[async_trait]
+trait MoveClient {
+    /// Send `uci_move` in `game_id`, offering (or accepting) a draw with
+    /// it if `offer_draw` is set.
+    async fn send_move(&self, game_id: &str, uci_move: &str, offer_draw: bool) -> LicheszterResult<()>;
+
+    /// Number of half-moves Lichess has recorded in `game_id`, or `None`
+    /// if the game state couldn't be read.
+    async fn played_plies(&self, game_id: &str) -> Option<usize>;
+}
+
+/// The rest of the Lichess calls a game makes, so a whole game can be
+/// played against a fake client in tests.
+#[async_trait]
+trait GameClient: MoveClient {
+    /// Abort `game_id`, possible only before both sides have moved.
+    async fn abort(&self, game_id: &str) -> LicheszterResult<()>;
+
+    /// Resign `game_id`.
+    async fn resign(&self, game_id: &str) -> LicheszterResult<()>;
+
+    /// Post `text` in the player chat of `game_id`.
+    async fn send_chat(&self, game_id: &str, text: &str) -> LicheszterResult<()>;
+}
+
+#[async_trait]
+impl MoveClient for Licheszter {
+    async fn send_move(&self, game_id: &str, uci_move: &str, offer_draw: bool) -> LicheszterResult<()> {
+        self.make_move(game_id, uci_move, offer_draw).await
+    }
+
+    async fn played_plies(&self, game_id: &str) -> Option<usize> {
+        let read_state = async {
+            let stream = self.stream_game_state(game_id).await.ok()?;
+            let moves = match Box::pin(stream).next().await?.ok()? {
+                BoardState::GameFull(game_full) => game_full.state.moves,
+                BoardState::GameState(game_state) => game_state.moves,
+                _ => return None,
+            };
+            Some(moves.split_whitespace().count())
+        };
+        tokio::time::timeout(MOVE_CHECK_TIMEOUT, read_state)
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+#[async_trait]
+impl GameClient for Licheszter {
+    async fn abort(&self, game_id: &str) -> LicheszterResult<()> {
+        self.abort_game(game_id).await
+    }
+
+    async fn resign(&self, game_id: &str) -> LicheszterResult<()> {
+        self.resign_game(game_id).await
+    }
+
+    async fn send_chat(&self, game_id: &str, text: &str) -> LicheszterResult<()> {
+        self.write_to_chat(game_id, "player", text).await
+    }
+}
+
+/// How long a game task may keep running after Lichess reports its game
+/// finished.
+pub const GAME_FINISH_GRACE: Duration = Duration::from_secs(10);
+
+/// Owner of the tasks playing games: at most one task per game, each
+/// removed once it ends.
+///
+/// A panic in a game task is caught, logged with the game's context and
+/// counted, so a bug in one game neither takes down the bot nor leaves a
+/// dead entry occupying a game slot.
+#[derive(Clone)]
+pub struct GameSupervisor {
+    games: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    panics: Arc<AtomicU32>,
+    finish_grace: Duration,
+}
+
+impl Default for GameSupervisor {
+    fn default() -> Self {
+        Self {
+            games: Arc::default(),
+            panics: Arc::default(),
+            finish_grace: GAME_FINISH_GRACE,
+        }
+    }
+}
+
+impl GameSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give game tasks `grace` to wrap up after Lichess reports their game
+    /// finished, instead of [`GAME_FINISH_GRACE`].
+    pub fn with_finish_grace(mut self, grace: Duration) -> Self {
+        self.finish_grace = grace;
+        self
+    }
+
+    /// Spawn a task playing `game_id`, unless one is already running for
+    /// it (e.g. a game resumed at startup that Lichess also reports
+    /// through a `GameStart` event). Returns whether a task was started.
+    pub async fn spawn_game(
+        &self,
+        client: Licheszter,
+        game_id: String,
+        settings: GameSettings,
+        harvester: HarvestQueue,
+    ) -> bool {
+        // Color and opponent are recorded once the game's full state
+        // arrives.
+        let span = info_span!(
+            "game",
+            game_id = %game_id,
+            depth = settings.depth,
+            color = field::Empty,
+            opponent = field::Empty,
+        );
+        let task_game_id = game_id.clone();
+        let task = async move {
+            if let Err(e) = play_game(client, &task_game_id, settings, harvester).await {
+                error!("Game error: {:?}", e);
+            }
+        };
+        self.spawn_task(game_id, task.instrument(span)).await
+    }
+
+    /// Run `task` as the task playing `game_id`, with the same
+    /// supervision as [`GameSupervisor::spawn_game`].
+    pub async fn spawn_task<F>(&self, game_id: String, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut games = self.games.lock().await;
+        if games.get(&game_id).is_some_and(|handle| !handle.is_finished()) {
+            debug!(game_id = %game_id, "Game already being played");
+            return false;
+        }
+
+        let task_games = Arc::clone(&self.games);
+        let panics = Arc::clone(&self.panics);
+        let task_game_id = game_id.clone();
+        let handle = tokio::spawn(async move {
+            if let Err(panic) = CatchPanic(Box::pin(task)).await {
+                panics.fetch_add(1, Ordering::Relaxed);
+                error!(game_id = %task_game_id, "Game task panicked: {}", panic_message(&*panic));
+            }
+            // Free the slot even if Lichess never sends GameFinish (e.g.
+            // after a timeout).
+            task_games.lock().await.remove(&task_game_id);
+        });
+        games.insert(game_id, handle);
+        true
+    }
+
+    /// Number of games being played.
+    pub async fn active_count(&self) -> usize {
+        self.games.lock().await.len()
+    }
+
+    /// Stop playing `game_id`, if it is being played.
+    pub async fn abort_game(&self, game_id: &str) {
+        if let Some(handle) = self.games.lock().await.remove(game_id) {
+            handle.abort();
+        }
+    }
+
+    /// Let the task playing the finished game `game_id` wrap up, aborting
+    /// it if it is still running after the finish grace. Returns whether it
+    /// ended on its own.
+    ///
+    /// The task harvests the game, says goodbye, forgets its saved state and
+    /// records the rematch offer once its own stream reports the end, which
+    /// may arrive after the event stream's `GameFinish`.
+    pub async fn finish_game(&self, game_id: &str) -> bool {
+        let Some(mut handle) = self.games.lock().await.remove(game_id) else {
+            return true;
+        };
+        if tokio::time::timeout(self.finish_grace, &mut handle).await.is_ok() {
+            return true;
+        }
+        warn!(game_id = %game_id, "Game task still running {:?} after the game finished; aborting", self.finish_grace);
+        handle.abort();
+        false
+    }
+
+    /// Wait up to `timeout` for every running game to finish, returning
+    /// how many did. Games still running afterwards stay supervised.
+    pub async fn wait_for_all(&self, timeout: Duration) -> usize {
+        // Taken out of the map so finishing tasks can remove themselves
+        // while we wait.
+        let mut handles: Vec<_> = std::mem::take(&mut *self.games.lock().await)
+            .into_iter()
+            .collect();
+        if !handles.is_empty() {
+            info!("Waiting up to {:?} for {} game(s) to finish", timeout, handles.len());
+        }
+        let _ = tokio::time::timeout(timeout, async {
+            for (_, handle) in handles.iter_mut() {
+                let _ = handle.await;
+            }
+        })
+        .await;
+
+        let mut finished = 0;
+        let mut games = self.games.lock().await;
+        for (game_id, handle) in handles {
+            match handle.is_finished() {
+                true => finished += 1,
+                false => {
+                    games.entry(game_id).or_insert(handle);
+                }
+            }
+        }
+        finished
+    }
+
+    /// Abort every running game, returning how many were aborted.
+    pub async fn abort_all(&self) -> usize {
+        let games = std::mem::take(&mut *self.games.lock().await);
+        games
+            .into_values()
+            .filter(|handle| !handle.is_finished())
+            .map(|handle| handle.abort())
+            .count()
+    }
+
+    /// Number of game tasks that ended with a panic.
+    pub fn game_panic_count(&self) -> u32 {
+        self.panics.load(Ordering::Relaxed)
+    }
+}
+
+/// Future returning `Err` with the panic payload if polling the inner
+/// future panics, instead of unwinding into the runtime.
+struct CatchPanic<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchPanic<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// The message a panic was raised with, if it had one.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic>")
+}
 
 /// Play a single game on Lichess.
 ///
 /// This function runs in its own tokio task and handles the complete
 /// game lifecycle: determining color, making moves, recording positions,
 /// and optionally running what-if analysis.
+///
+/// What-if trees are generated on `settings.whatif_pool`, gated by
+/// `settings.whatif_permits` which is shared across all games. When every permit
+/// is taken the position is skipped rather than queued, so a busy bot
+/// sheds analysis work instead of falling behind on the clock. Every tree
+/// debits `settings.whatif_budget`, and analysis stops for the rest of the
+/// game once it is spent.
 pub async fn play_game(
     client: Licheszter,
     game_id: &str,
-    depth: u8,
-    whatif_enabled: bool,
-    bot_username: &str,
-    harvester: Arc<Mutex<Box<dyn HarvestSink + Send>>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let bot = Bot { depth };
+    settings: GameSettings,
+    harvester: HarvestQueue,
+) -> Result<(), BotError> {
+    let stream = client
+        .stream_game_state(game_id)
+        .await
+        .map_err(BotError::Stream)?;
+
+    play_game_stream(&client, game_id, stream, settings, harvester).await
+}
+
+/// Play a game from an already opened game state stream.
+///
+/// The game is abandoned once `settings.game_timeout` has elapsed, so a
+/// stalled stream can't keep the task alive forever. A timed out game is
+/// still sent to the harvester, with whatever moves were played and the
+/// result `"timeout"`, if it's long enough to keep.
+pub async fn play_game_stream<S>(
+    client: &Licheszter,
+    game_id: &str,
+    stream: S,
+    settings: GameSettings,
+    harvester: HarvestQueue,
+) -> Result<(), BotError>
+where
+    S: Stream<Item = LicheszterResult<BoardState>> + Unpin,
+{
+    let game_timeout = settings.game_timeout;
+    let min_harvest_moves = settings.min_harvest_moves;
+    let mut game_record = GameRecord::new(game_id.to_string());
+    let mut plies_seen = None;
+
+    let played = tokio::time::timeout(
+        game_timeout,
+        drive_game(client, game_id, stream, settings, &harvester, &mut game_record, &mut plies_seen),
+    )
+    .await;
+
+    match played {
+        Ok(result) => result,
+        Err(_) => {
+            game_record.result = "timeout".to_string();
+            if should_harvest(&game_record.result, plies_seen.unwrap_or(0), min_harvest_moves) {
+                harvester.record_game(game_record).await;
+            }
+            Err(BotError::Timeout(game_timeout))
+        }
+    }
+}
+
+/// Consume game state events until the game ends or the stream closes.
+///
+/// `plies_seen` holds the half-moves in the last position handled, to tell
+/// updates that only change the clock (extra time granted, offers) from
+/// new moves, and how far a game got if it's abandoned.
+async fn drive_game<C, S>(
+    client: &C,
+    game_id: &str,
+    mut stream: S,
+    settings: GameSettings,
+    harvester: &HarvestQueue,
+    game_record: &mut GameRecord,
+    plies_seen: &mut Option<usize>,
+) -> Result<(), BotError>
+where
+    C: GameClient + Sync,
+    S: Stream<Item = LicheszterResult<BoardState>> + Unpin,
+{
+    let GameSettings {
+        depth,
+        max_depth,
+        anti_shuffle,
+        whatif_enabled,
+        whatif_permits,
+        whatif_pool,
+        whatif_budget,
+        whatif_max_memory,
+        bot_username,
+        min_harvest_moves,
+        move_retries,
+        opening_variety,
+        opening_seed,
+        game_store,
+        draw_policy,
+        chat_enabled,
+        rematch_offers,
+        ..
+    } = settings;
+    let bot = Bot {
+        depth,
+        max_depth,
+        anti_shuffle,
+    };
+    let mut opening_rng = SmallRng::seed_from_u64(opening_seed);
     let mut game = Game::new();
     let mut bot_color = Color::White;
-    let mut game_record = GameRecord::new(game_id.to_string());
     let mut move_number: u32 = 0;
-
-    let mut stream = client
-        .stream_game_state(game_id)
-        .await
-        .map_err(|e| format!("Stream error: {:?}", e))?;
+    let mut whatif_budget_logged = false;
+    // Our clock and the ply count when we last started thinking, kept
+    // until a state update shows the clock after that move.
+    let mut clock_before_move: Option<(u64, usize)> = None;
+    // The opponent reply our last search expected, if it predicted one.
+    let mut expected_reply: Option<ChessMove> = None;
+    let mut draw_offers = DrawOffers::default();
 
     while let Ok(Some(state)) = stream.try_next().await {
         match state {
             BoardState::GameFull(game_full) => {
-                // Determine our color
-                bot_color = match &game_full.white {
-                    Challenger::LightUser(white_user) => {
-                        if white_user.username.to_lowercase() == bot_username.to_lowercase() {
-                            Color::White
-                        } else {
-                            Color::Black
+                // Games can start without going through challenge
+                // filtering (e.g. ones created on the website), so check
+                // here too rather than play illegal moves.
+                if !is_supported_variant(&game_full.variant.key) {
+                    warn!(
+                        "Variant {} is not supported, leaving the game",
+                        game_full.variant.key
+                    );
+                    if client.abort(game_id).await.is_err() {
+                        if chat_enabled {
+                            send_chat(client, game_id, CHAT_RESIGNING).await;
                         }
+                        client.resign(game_id).await.map_err(BotError::Stream)?;
                     }
-                    _ => Color::Black,
-                };
+                    return Ok(());
+                }
+
+                bot_color = bot_color_in(&game_full.white, &bot_username);
 
                 // Record game metadata
                 let (white_name, black_name) = match &game_full.white {
@@ -78,52 +522,118 @@ pub async fn play_game(
                 game_record.black = black_name;
                 game_record.bot_color = format!("{:?}", bot_color);
 
-                info!(
-                    "[{}] Playing as {:?}. {} vs {}",
-                    game_id, bot_color, game_record.white, game_record.black
-                );
-
-                // If we're white, make the first move
-                if bot_color == Color::White {
-                    let board = game.current_position();
-                    let start = Instant::now();
-                    let chosen_move = bot.choose_move(&board);
-                    let think_time = start.elapsed();
-
-                    let uci_move = format!("{}", chosen_move);
-                    let eval = evaluate_board(&board);
-
-                    // Record the move
-                    game_record.moves.push(MoveRecord {
-                        move_number: 1,
-                        side: "white".to_string(),
-                        uci: uci_move.clone(),
-                        fen_before: format!("{}", board),
-                        eval_cp: eval,
-                        phase: classify_phase(&board).to_string(),
-                        piece_count: count_pieces(&board),
-                        think_time_ms: think_time.as_millis() as u64,
-                        is_book: false,
-                        alternatives: count_legal_moves(&board),
-                    });
-
-                    client
-                        .make_move(game_id, &uci_move, false)
-                        .await
-                        .map_err(|e| format!("Move error: {:?}", e))?;
+                let opponent = match bot_color {
+                    Color::White => &game_record.black,
+                    Color::Black => &game_record.white,
+                };
+                Span::current()
+                    .record("color", field::debug(bot_color))
+                    .record("opponent", opponent.as_str());
+                info!("{} vs {}", game_record.white, game_record.black);
+
+                // Catch up on moves already played (e.g. when resuming a
+                // game after a restart)
+                let move_list: Vec<&str> = game_full.state.moves.split_whitespace().collect();
+                if !move_list.is_empty() {
+                    info!("Resuming after {} moves", move_list.len());
+                }
+                game = game_from_moves(&move_list);
+                move_number = move_list.len() as u32;
+                *plies_seen = Some(move_list.len());
+
+                // Greet once, not again when resuming after our first move
+                let our_moves = (move_list.len() + (bot_color == Color::White) as usize) / 2;
+                if chat_enabled && our_moves == 0 {
+                    send_chat(client, game_id, CHAT_GREETING).await;
+                }
+
+                if let Some(store) = &game_store {
+                    if let Ok(Some(saved)) = store.load(game_id).await {
+                        info!(
+                            "Resuming saved game: {} half-move(s) played since it was saved",
+                            move_list.len().saturating_sub(saved.moves_processed)
+                        );
+                    }
+                }
+                save_progress(game_store.as_deref(), game_id, bot_color, move_list.len()).await;
+
+                // If it's our turn (e.g. we're white), make a move
+                let board = game.current_position();
+                if game.side_to_move() == bot_color && MoveGen::new_legal(&board).len() > 0 {
+                    let (remaining, increment) = clock_ms(&game_full.state, bot_color);
+                    clock_before_move = Some((remaining, move_list.len()));
+                    let bot = bot_for_clock(&bot, &board, remaining);
+                    let opening = opening_variety.filter(|variety| variety.applies(move_list.len()));
+                    let (record, reply) = think_blocking(
+                        bot,
+                        &game,
+                        bot_color,
+                        move_number + 1,
+                        opening,
+                        &mut opening_rng,
+                        move_deadline(remaining, increment),
+                    )
+                    .await;
+                    expected_reply = reply;
+                    let uci_move = record.uci.clone();
+                    game_record.push_move(record);
+
+                    let offer_draw =
+                        draw_offers.respond(&game_full.state, bot_color, &draw_policy, &board);
+                    let plies = move_list.len();
+                    send_move(client, game_id, &uci_move, offer_draw, plies, move_retries).await?;
+                    if offer_draw {
+                        draw_offers.offered();
+                    }
                 }
             }
 
             BoardState::GameState(game_state) => {
+                // The first update that includes our move carries our clock
+                // after it
+                if let Some((before, plies_before)) = clock_before_move {
+                    if game_state.moves.split_whitespace().count() > plies_before {
+                        if let Some(record) = game_record.moves.last_mut() {
+                            let (after, increment) = clock_ms(&game_state, bot_color);
+                            record.set_clock(before, after, increment);
+                        }
+                        clock_before_move = None;
+                    }
+                }
+
+                draw_offers.observe(&game_state, bot_color);
+
                 if game_state.status != "started" {
                     // Game ended
                     game_record.result = game_state.status.clone();
-                    info!("[{}] Game ended: {}", game_id, game_state.status);
+                    info!("Game ended: {}", game_state.status);
+                    // A rematch arrives as a challenge from the opponent
+                    let opponent = match bot_color {
+                        Color::White => &game_record.black,
+                        Color::Black => &game_record.white,
+                    };
+                    if !opponent.is_empty() {
+                        if let Some(rematch) = &game_state.rematch {
+                            debug!("Rematch offered: {}", rematch);
+                        }
+                        rematch_offers.record(opponent, game_state.rematch.as_deref(), Instant::now());
+                    }
+                    if chat_enabled {
+                        let moves: Vec<&str> = game_state.moves.split_whitespace().collect();
+                        let board = game_from_moves(&moves).current_position();
+                        send_chat(client, game_id, &post_game_message(&board, bot_color)).await;
+                    }
+                    if let Some(store) = &game_store {
+                        if let Err(e) = store.remove(game_id).await {
+                            warn!("Failed to delete saved game: {}", e);
+                        }
+                    }
 
-                    // Send completed game to harvester
-                    if let Err(e) = harvester.lock().await.record_game(game_record.clone()).await
-                    {
-                        warn!("[{}] Harvest error: {:?}", game_id, e);
+                    // Send completed game to harvester, unless it never
+                    // really got going
+                    let half_moves = game_state.moves.split_whitespace().count();
+                    if should_harvest(&game_state.status, half_moves, min_harvest_moves) {
+                        harvester.record_game(game_record.clone()).await;
                     }
                     break;
                 }
@@ -136,7 +646,17 @@ pub async fn play_game(
 
                 // Rebuild game state from full move list
                 let move_list: Vec<&str> = moves_str.split_whitespace().collect();
+                if *plies_seen == Some(move_list.len()) {
+                    // No new move: our clock budget is read afresh from
+                    // the next state we search in, so there is nothing to
+                    // do until then
+                    let (remaining, _) = clock_ms(&game_state, bot_color);
+                    debug!("Clock update without a move: {} ms left", remaining);
+                    continue;
+                }
+                *plies_seen = Some(move_list.len());
                 move_number = move_list.len() as u32;
+                save_progress(game_store.as_deref(), game_id, bot_color, move_list.len()).await;
 
                 // Apply the last move if it's new
                 let last_move_str = move_list.last().unwrap_or(&"");
@@ -144,79 +664,91 @@ pub async fn play_game(
                     let move_result = game.make_move(chess_move);
                     if !move_result {
                         // Game state diverged - rebuild from scratch
-                        game = Game::new();
-                        for ms in &move_list {
-                            if let Ok(m) = ChessMove::from_str(ms) {
-                                game.make_move(m);
-                            }
-                        }
+                        game = game_from_moves(&move_list);
                     }
 
                     // Check if it's our turn
                     if game.side_to_move() == bot_color {
                         let board = game.current_position();
+                        if let Some(expected) = expected_reply.take() {
+                            match expected == chess_move {
+                                true => debug!("Ponder hit: opponent played {}", chess_move),
+                                false => debug!("Ponder miss: expected {}, opponent played {}", expected, chess_move),
+                            }
+                        }
 
                         // Check for game-over positions
                         if MoveGen::new_legal(&board).len() == 0 {
-                            debug!("[{}] No legal moves, game should end", game_id);
+                            debug!("No legal moves, game should end");
                             continue;
                         }
 
                         // Optional: what-if branching on critical positions
-                        if whatif_enabled && is_critical_position(&board) {
-                            let branch_config = BranchConfig::quick();
-                            let fen = format!("{}", board);
-                            if let Some(tree) = generate_branch_tree(&fen, &branch_config) {
-                                if let Err(e) = harvester
-                                    .lock()
-                                    .await
-                                    .record_branch_tree(game_id, &tree)
-                                    .await
-                                {
-                                    debug!("[{}] Branch harvest error: {:?}", game_id, e);
+                        if whatif_enabled && whatif_budget.is_exhausted() {
+                            if !whatif_budget_logged {
+                                info!("What-if session budget exhausted");
+                                whatif_budget_logged = true;
+                            }
+                        } else if whatif_enabled && is_critical_position(&board) {
+                            match Arc::clone(&whatif_permits).try_acquire_owned() {
+                                Ok(permit) => spawn_whatif(
+                                    &whatif_pool,
+                                    game_id.to_string(),
+                                    format!("{}", board),
+                                    BranchConfig {
+                                        max_memory_bytes: whatif_max_memory,
+                                        ..BranchConfig::preset_from_phase(classify_phase(&board))
+                                    },
+                                    permit,
+                                    whatif_budget.clone(),
+                                    harvester.clone(),
+                                ),
+                                Err(_) => {
+                                    debug!("Skipping what-if: all slots busy");
                                 }
                             }
                         }
 
-                        // Compute our move
-                        let start = Instant::now();
-                        let chosen_move = bot.choose_move(&board);
-                        let think_time = start.elapsed();
-
-                        let uci_move = format!("{}", chosen_move);
-                        let eval = evaluate_board(&board);
-                        let side = if bot_color == Color::White {
-                            "white"
-                        } else {
-                            "black"
-                        };
+                        // Compute our move, budgeting from this update's clock
+                        // so extra time granted since our last move counts
+                        let (remaining, increment) = clock_ms(&game_state, bot_color);
+                        clock_before_move = Some((remaining, move_list.len()));
+                        let bot = bot_for_clock(&bot, &board, remaining);
+                        let opening =
+                            opening_variety.filter(|variety| variety.applies(move_list.len()));
+                        let (record, reply) = think_blocking(
+                            bot,
+                            &game,
+                            bot_color,
+                            move_number + 1,
+                            opening,
+                            &mut opening_rng,
+                            move_deadline(remaining, increment),
+                        )
+                        .await;
+                        expected_reply = reply;
+                        let uci_move = record.uci.clone();
+                        game_record.push_move(record);
 
-                        // Record the move
-                        game_record.moves.push(MoveRecord {
-                            move_number,
-                            side: side.to_string(),
-                            uci: uci_move.clone(),
-                            fen_before: format!("{}", board),
-                            eval_cp: eval,
-                            phase: classify_phase(&board).to_string(),
-                            piece_count: count_pieces(&board),
-                            think_time_ms: think_time.as_millis() as u64,
-                            is_book: false,
-                            alternatives: count_legal_moves(&board),
-                        });
-
-                        // Send move to Lichess
-                        if let Err(e) = client.make_move(game_id, &uci_move, false).await {
-                            error!("[{}] Failed to send move {}: {:?}", game_id, uci_move, e);
+                        // Send move to Lichess, answering any draw offer
+                        let offer_draw =
+                            draw_offers.respond(&game_state, bot_color, &draw_policy, &board);
+                        let plies = move_list.len();
+                        match send_move(client, game_id, &uci_move, offer_draw, plies, move_retries)
+                            .await
+                        {
+                            Ok(()) if offer_draw => draw_offers.offered(),
+                            Ok(()) => {}
+                            Err(e) => error!("{}", e),
                         }
                     }
                 } else {
-                    warn!("[{}] Could not parse move: '{}'", game_id, last_move_str);
+                    warn!("Could not parse move: '{}'", last_move_str);
                 }
             }
 
             other => {
-                debug!("[{}] Other state: {:?}", game_id, other);
+                debug!("Other state: {:?}", other);
             }
         }
     }
@@ -224,6 +756,314 @@ pub async fn play_game(
     Ok(())
 }
 
+/// Send `uci_move`, made after `plies_before` half-moves and carrying a
+/// draw offer if `offer_draw` is set, retrying up to `retries` times with
+/// a growing pause when Lichess doesn't accept it.
+///
+/// A request can fail after Lichess already applied the move (e.g. the
+/// response was lost), so before each retry the game is re-read and the
+/// move counts as sent if it shows up there.
+async fn send_move<C: MoveClient + Sync + ?Sized>(
+    client: &C,
+    game_id: &str,
+    uci_move: &str,
+    offer_draw: bool,
+    plies_before: usize,
+    retries: u32,
+) -> Result<(), BotError> {
+    let mut attempt = 0;
+    loop {
+        let source = match client.send_move(game_id, uci_move, offer_draw).await {
+            Ok(()) => return Ok(()),
+            Err(source) => source,
+        };
+        if attempt == retries {
+            return Err(BotError::Move {
+                uci: uci_move.to_string(),
+                source,
+            });
+        }
+        attempt += 1;
+        warn!(
+            "Failed to send move {} ({:?}), retry {}/{}",
+            uci_move, source, attempt, retries
+        );
+        tokio::time::sleep(MOVE_RETRY_BACKOFF * attempt).await;
+
+        if client
+            .played_plies(game_id)
+            .await
+            .is_some_and(|plies| plies > plies_before)
+        {
+            info!("Move {} was accepted despite the error", uci_move);
+            return Ok(());
+        }
+    }
+}
+
+/// Post `text` in the game chat. Chat is a courtesy, so a failure is
+/// only logged.
+async fn send_chat<C: GameClient + Sync + ?Sized>(client: &C, game_id: &str, text: &str) {
+    match client.send_chat(game_id, text).await {
+        Ok(()) => debug!("Sent chat message: {}", text),
+        Err(e) => warn!("Failed to send chat message: {:?}", e),
+    }
+}
+
+/// Goodbye for the game chat, with the phase and our evaluation of the
+/// final `board` as an engine `info string`.
+fn post_game_message(board: &Board, bot_color: Color) -> String {
+    let eval = match board.side_to_move() == bot_color {
+        true => evaluate_board(board),
+        false => -evaluate_board(board),
+    };
+    format!(
+        "Good game! info string phase {} eval cp {}",
+        classify_phase(board),
+        eval
+    )
+}
+
+/// What-if branches scoring more than this below their best sibling are
+/// pruned before harvest (see [`prune_tree`]).
+const HARVEST_KEEP_WITHIN_CP: i32 = 3 * PAWN_VALUE;
+
+/// Generate a what-if tree for `fen` with `branch_config` on `pool` and
+/// hand it to the harvester once done, with dominated branches
+/// pruned. The permit is held until generation finishes, and
+/// the tree's node budget is reserved from (and partly refunded to) the
+/// game's session budget.
+fn spawn_whatif(
+    pool: &ThreadPool,
+    game_id: String,
+    fen: String,
+    mut branch_config: BranchConfig,
+    permit: OwnedSemaphorePermit,
+    budget: SessionBudget,
+    harvester: HarvestQueue,
+) {
+    let span = Span::current();
+    let blocking_span = span.clone();
+    let generated = pool.spawn(move || {
+        let _permit = permit;
+        let _entered = blocking_span.enter();
+        let granted = budget.reserve(branch_config.node_budget);
+        if granted == 0 {
+            return None;
+        }
+        branch_config.node_budget = granted;
+        let tree = generate_branch_tree(&fen, &branch_config);
+        let used = tree.as_ref().map(|t| t.total_nodes).unwrap_or(0);
+        budget.refund(granted.saturating_sub(used));
+        tree.map(|mut tree| {
+            prune_tree(&mut tree, HARVEST_KEEP_WITHIN_CP);
+            tree
+        })
+    });
+    tokio::spawn(async move {
+        match generated.await {
+            Ok(Some(tree)) => harvester.record_branch_tree(&game_id, tree).await,
+            Ok(None) => {}
+            Err(_) => warn!("What-if generation panicked"),
+        }
+    }
+    .instrument(span));
+}
+
+/// Final statuses of games that were called off before they really
+/// started. Their records hold at most a move or two and would only
+/// pollute the dataset.
+const UNHARVESTABLE_STATUSES: [&str; 2] = ["aborted", "noStart"];
+
+/// Whether a game that ended with `status` should be sent to the harvester.
+fn is_harvestable(status: &str) -> bool {
+    !UNHARVESTABLE_STATUSES.contains(&status)
+}
+
+/// Whether a game that ended with `status` after `half_moves` should be
+/// sent to the harvester, logging why not.
+fn should_harvest(status: &str, half_moves: usize, min_harvest_moves: usize) -> bool {
+    if !is_harvestable(status) {
+        info!("Not harvesting {} game", status);
+        return false;
+    }
+    if half_moves < min_harvest_moves {
+        info!(
+            "Not harvesting game of {} half-moves (minimum {})",
+            half_moves, min_harvest_moves
+        );
+        return false;
+    }
+    true
+}
+
+/// The bot's color, given White in a game and the bot's account name.
+///
+/// Lichess user IDs are lowercased usernames, so both are compared
+/// case-insensitively.
+fn bot_color_in(white: &Challenger, bot_username: &str) -> Color {
+    let is_bot = |name: &str| name.eq_ignore_ascii_case(bot_username);
+    match white {
+        Challenger::LightUser(user)
+            if is_bot(&user.username) || user.id.as_deref().is_some_and(is_bot) =>
+        {
+            Color::White
+        }
+        _ => Color::Black,
+    }
+}
+
+/// Save how far `game_id` has got to `store`, if there is one. Failures
+/// are only logged: losing the saved state must not stop the game.
+async fn save_progress(
+    store: Option<&GameStore>,
+    game_id: &str,
+    bot_color: Color,
+    moves_processed: usize,
+) {
+    if let Some(store) = store {
+        let state = PersistedGame::new(game_id, &format!("{:?}", bot_color), moves_processed);
+        if let Err(e) = store.save(&state).await {
+            warn!("Failed to save game state: {}", e);
+        }
+    }
+}
+
+/// `bot` with its depth adapted to `remaining_ms` on our clock. A zero
+/// clock means Lichess sent none (e.g. unlimited games), so the
+/// configured depth is kept.
+fn bot_for_clock(bot: &Bot, board: &Board, remaining_ms: u64) -> Bot {
+    match remaining_ms {
+        0 => *bot,
+        ms => bot.with_depth(bot.adaptive_depth(board, ms)),
+    }
+}
+
+/// Moves assumed left in the game when sharing out the clock.
+const MOVES_TO_GO: u64 = 30;
+
+/// When the search for a move must end, given `remaining_ms` on our clock
+/// and `increment_ms` per move: an even share of the clock plus most of
+/// the increment, never more than half the clock. `None` without a clock,
+/// as in [`bot_for_clock`].
+fn move_deadline(remaining_ms: u64, increment_ms: u64) -> Option<Instant> {
+    let budget = (remaining_ms / MOVES_TO_GO + increment_ms * 3 / 4).min(remaining_ms / 2);
+    (remaining_ms > 0).then(|| Instant::now() + Duration::from_millis(budget))
+}
+
+/// Run [`think`] for the current position of `game` on the blocking
+/// pool.
+///
+/// A search can take seconds; on an async worker it would hold up every
+/// other task scheduled there, the event loop included.
+async fn think_blocking(
+    bot: Bot,
+    game: &Game,
+    bot_color: Color,
+    move_number: u32,
+    opening: Option<OpeningVariety>,
+    opening_rng: &mut SmallRng,
+    deadline: Option<Instant>,
+) -> (MoveRecord, Option<ChessMove>) {
+    let span = Span::current();
+    let board = game.current_position();
+    let history = position_history(game);
+    let mut rng = opening_rng.clone();
+    let searched = tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let opening = opening.map(|variety| (variety, &mut rng));
+        let (record, reply) = think(&bot, &board, &history, bot_color, move_number, opening, deadline);
+        (record, reply, rng)
+    })
+    .await;
+    match searched {
+        Ok((record, reply, rng)) => {
+            *opening_rng = rng;
+            (record, reply)
+        }
+        // Re-raised so the supervisor reports it like any game panic
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Choose the bot's move and record the decision, along with the reply
+/// expected from the opponent. `history` holds the game's earlier
+/// positions, see [`Bot::ponder_move_in_game`]. The search stops at
+/// `deadline` if there is one, see [`Bot::ponder_move_until`]. With
+/// `opening`, the move is picked at random among the best ones and no
+/// reply is predicted.
+fn think(
+    bot: &Bot,
+    board: &Board,
+    history: &[u64],
+    bot_color: Color,
+    move_number: u32,
+    opening: Option<(OpeningVariety, &mut SmallRng)>,
+    deadline: Option<Instant>,
+) -> (MoveRecord, Option<ChessMove>) {
+    let start = Instant::now();
+    let (chosen_move, expected_reply) = match (opening, deadline) {
+        (Some((variety, rng)), _) => (bot.choose_varied_move(board, &variety, rng), None),
+        (None, Some(deadline)) => bot.ponder_move_until(board, history, deadline),
+        (None, None) => bot.ponder_move_in_game(board, history),
+    };
+    let think_time = start.elapsed();
+
+    let side = if bot_color == Color::White {
+        "white"
+    } else {
+        "black"
+    };
+
+    let record = MoveRecord {
+        move_number,
+        side: side.to_string(),
+        uci: format!("{}", chosen_move),
+        fen_before: format!("{}", board),
+        eval_cp: evaluate_board(board),
+        phase: classify_phase(board).to_string(),
+        piece_count: count_pieces(board),
+        think_time_ms: think_time.as_millis() as u64,
+        alternatives: count_legal_moves(board),
+        // Clock fields and annotations are filled in later
+        ..Default::default()
+    };
+    (record, expected_reply)
+}
+
+/// Remaining clock and increment for `color` in a game state, in
+/// milliseconds.
+fn clock_ms(state: &GameState, color: Color) -> (u64, u64) {
+    match color {
+        Color::White => (state.wtime as u64, state.winc as u64),
+        Color::Black => (state.btime as u64, state.binc as u64),
+    }
+}
+
+/// Hashes of the positions of `game` before the current one.
+fn position_history(game: &Game) -> Vec<u64> {
+    let mut board = Board::default();
+    let mut history = Vec::new();
+    for action in game.actions() {
+        if let Action::MakeMove(chess_move) = action {
+            history.push(board.get_hash());
+            board = board.make_move_new(*chess_move);
+        }
+    }
+    history
+}
+
+/// Rebuild a game from the full list of UCI moves sent by Lichess.
+fn game_from_moves(move_list: &[&str]) -> Game {
+    let mut game = Game::new();
+    for ms in move_list {
+        if let Ok(m) = ChessMove::from_str(ms) {
+            game.make_move(m);
+        }
+    }
+    game
+}
+
 /// Count legal moves in a position (for recording decision breadth).
 fn count_legal_moves(board: &Board) -> u32 {
     MoveGen::new_legal(board).len() as u32
@@ -243,3 +1083,552 @@ fn is_critical_position(board: &Board) -> bool {
         // Or if there's a big swing potential (complex tactics)
         || (eval > 200 && eval < 500 && pieces > 14)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harvest::queue::{OverflowPolicy, DEFAULT_QUEUE_CAPACITY};
+    use crate::harvest::HarvestSink;
+    use crate::whatif::BranchTree;
+    use licheszter::error::LicheszterError;
+    use serde_json::json;
+
+    /// Sink that keeps recorded games for inspection.
+    struct RecordingSink(Arc<std::sync::Mutex<Vec<GameRecord>>>);
+
+    #[async_trait]
+    impl HarvestSink for RecordingSink {
+        async fn record_game(&mut self, game: GameRecord) -> Result<(), BotError> {
+            self.0.lock().unwrap().push(game);
+            Ok(())
+        }
+
+        async fn record_branch_tree(&mut self, _: &str, _: &BranchTree) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), BotError> {
+            Ok(())
+        }
+    }
+
+    /// Sink that keeps recorded what-if trees for inspection.
+    struct TreeSink(Arc<std::sync::Mutex<Vec<BranchTree>>>);
+
+    #[async_trait]
+    impl HarvestSink for TreeSink {
+        async fn record_game(&mut self, _: GameRecord) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn record_branch_tree(&mut self, _: &str, tree: &BranchTree) -> Result<(), BotError> {
+            self.0.lock().unwrap().push(tree.clone());
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), BotError> {
+            Ok(())
+        }
+    }
+
+    /// Queue feeding a [`RecordingSink`] that keeps games in `games`.
+    fn recording_queue(games: &Arc<std::sync::Mutex<Vec<GameRecord>>>) -> HarvestQueue {
+        let sink = RecordingSink(Arc::clone(games));
+        HarvestQueue::spawn(Box::new(sink), DEFAULT_QUEUE_CAPACITY, OverflowPolicy::Block)
+    }
+
+    /// Client whose first `failures` move submissions fail, reporting
+    /// `plies` half-moves when the game is re-read.
+    struct FlakyClient {
+        failures: u32,
+        plies: usize,
+        sent: std::sync::atomic::AtomicU32,
+    }
+
+    impl FlakyClient {
+        fn new(failures: u32, plies: usize) -> Self {
+            Self {
+                failures,
+                plies,
+                sent: Default::default(),
+            }
+        }
+
+        fn sent(&self) -> u32 {
+            self.sent.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl MoveClient for FlakyClient {
+        async fn send_move(&self, _: &str, _: &str, _: bool) -> LicheszterResult<()> {
+            let sent = self.sent.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            match sent <= self.failures {
+                true => Err(LicheszterError::StatusCode(502, "Bad Gateway".to_string())),
+                false => Ok(()),
+            }
+        }
+
+        async fn played_plies(&self, _: &str) -> Option<usize> {
+            Some(self.plies)
+        }
+    }
+
+    /// Client accepting every call, logging chat messages and moves in
+    /// the order they were sent.
+    #[derive(Default)]
+    struct RecordingClient(std::sync::Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl MoveClient for RecordingClient {
+        async fn send_move(&self, _: &str, uci_move: &str, _: bool) -> LicheszterResult<()> {
+            self.0.lock().unwrap().push(format!("move {}", uci_move));
+            Ok(())
+        }
+
+        async fn played_plies(&self, _: &str) -> Option<usize> {
+            None
+        }
+    }
+
+    #[async_trait]
+    impl GameClient for RecordingClient {
+        async fn abort(&self, _: &str) -> LicheszterResult<()> {
+            Ok(())
+        }
+
+        async fn resign(&self, _: &str) -> LicheszterResult<()> {
+            Ok(())
+        }
+
+        async fn send_chat(&self, _: &str, text: &str) -> LicheszterResult<()> {
+            self.0.lock().unwrap().push(format!("chat {}", text));
+            Ok(())
+        }
+    }
+
+    /// Settings for a depth-1 game as "StonksBot" with every optional
+    /// feature off.
+    fn test_settings() -> GameSettings {
+        GameSettings {
+            depth: 1,
+            max_depth: 1,
+            anti_shuffle: AntiShuffle::default(),
+            whatif_enabled: false,
+            whatif_permits: Arc::new(Semaphore::new(1)),
+            whatif_pool: ThreadPool::new("test-whatif", 1),
+            whatif_budget: SessionBudget::unlimited(),
+            whatif_max_memory: None,
+            bot_username: "StonksBot".to_string(),
+            game_timeout: Duration::from_secs(5),
+            min_harvest_moves: 0,
+            move_retries: 0,
+            opening_variety: None,
+            opening_seed: 0,
+            game_store: None,
+            draw_policy: DrawPolicy::default(),
+            chat_enabled: false,
+            rematch_offers: RematchOffers::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_whatif_respects_memory_cap() {
+        let trees = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = TreeSink(Arc::clone(&trees));
+        let queue = HarvestQueue::spawn(Box::new(sink), DEFAULT_QUEUE_CAPACITY, OverflowPolicy::Block);
+        let permits = Arc::new(Semaphore::new(1));
+        spawn_whatif(
+            &ThreadPool::new("test-whatif", 1),
+            "capped01".to_string(),
+            Board::default().to_string(),
+            BranchConfig {
+                max_memory_bytes: Some(4_096),
+                ..BranchConfig::quick()
+            },
+            Arc::clone(&permits).try_acquire_owned().unwrap(),
+            SessionBudget::unlimited(),
+            queue.clone(),
+        );
+
+        let tree = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                queue.flush().await.unwrap();
+                if let Some(tree) = trees.lock().unwrap().pop() {
+                    return tree;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(tree.truncated);
+        assert!(tree.total_nodes < BranchConfig::quick().node_budget);
+    }
+
+    fn game_full(bot_username: &str) -> BoardState {
+        serde_json::from_value(json!({
+            "type": "gameFull",
+            "id": "stalled1",
+            "rated": false,
+            "variant": { "key": "standard", "name": "Standard" },
+            "clock": null,
+            "speed": "correspondence",
+            "perf": { "name": "Correspondence" },
+            "createdAt": 1_600_000_000_000u64,
+            "white": { "id": "opponent", "name": "Opponent" },
+            "black": { "id": bot_username.to_lowercase(), "name": bot_username },
+            "initialFen": "startpos",
+            "state": {
+                "type": "gameState",
+                "moves": "",
+                "wtime": 0,
+                "btime": 0,
+                "winc": 0,
+                "binc": 0,
+                "status": "started"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_aborted_game_is_not_harvested() {
+        let settings = test_settings();
+        let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let harvester = recording_queue(&games);
+
+        // The opponent (White) never moves and Lichess aborts the game.
+        let aborted: BoardState = serde_json::from_value(json!({
+            "type": "gameState",
+            "moves": "",
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "status": "aborted"
+        }))
+        .unwrap();
+        let stream = tokio_stream::iter(vec![Ok(game_full("StonksBot")), Ok(aborted)]);
+        let client = Licheszter::new(String::new());
+
+        let result = play_game_stream(&client, "stalled1", stream, settings, harvester.clone()).await;
+        harvester.flush().await.unwrap();
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(games.lock().unwrap().is_empty());
+        assert!(is_harvestable("mate"));
+        assert!(!is_harvestable("noStart"));
+    }
+
+    #[tokio::test]
+    async fn test_short_game_is_not_harvested() {
+        for (min_harvest_moves, harvested) in [(10, 0), (2, 1)] {
+            let settings = GameSettings {
+                min_harvest_moves,
+                ..test_settings()
+            };
+            let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let harvester = recording_queue(&games);
+
+            // White resigns after two half-moves
+            let resigned: BoardState = serde_json::from_value(json!({
+                "type": "gameState",
+                "moves": "e2e4 e7e5",
+                "wtime": 0,
+                "btime": 0,
+                "winc": 0,
+                "binc": 0,
+                "status": "resign"
+            }))
+            .unwrap();
+            let stream = tokio_stream::iter(vec![Ok(game_full("StonksBot")), Ok(resigned)]);
+            let client = Licheszter::new(String::new());
+
+            let result =
+                play_game_stream(&client, "short1", stream, settings, harvester.clone()).await;
+            harvester.flush().await.unwrap();
+
+            assert!(result.is_ok(), "{:?}", result);
+            assert_eq!(games.lock().unwrap().len(), harvested, "minimum {}", min_harvest_moves);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_game_offers_rematch_to_opponent() {
+        let settings = test_settings();
+        let offers = settings.rematch_offers.clone();
+        let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let harvester = recording_queue(&games);
+
+        // The stream ends on the final state, before any rematch is named
+        let resigned: BoardState = serde_json::from_value(json!({
+            "type": "gameState",
+            "moves": "e2e4 e7e5",
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "status": "resign"
+        }))
+        .unwrap();
+        let stream = tokio_stream::iter(vec![Ok(game_full("StonksBot")), Ok(resigned)]);
+        let client = Licheszter::new(String::new());
+        play_game_stream(&client, "rematch1", stream, settings, harvester).await.unwrap();
+
+        let now = Instant::now();
+        assert!(!offers.take("next1", Some("Stranger"), now));
+        assert!(offers.take("next1", Some("Opponent"), now));
+    }
+
+    #[tokio::test]
+    async fn test_chat_greets_at_game_start() {
+        for chat_enabled in [true, false] {
+            let settings = GameSettings {
+                chat_enabled,
+                ..test_settings()
+            };
+            let state = |moves: &str, status: &str| -> BoardState {
+                serde_json::from_value(json!({
+                    "type": "gameState",
+                    "moves": moves,
+                    "wtime": 0,
+                    "btime": 0,
+                    "winc": 0,
+                    "binc": 0,
+                    "status": status
+                }))
+                .unwrap()
+            };
+            // We play Black: greet, answer 1.e4, then White resigns
+            let stream = tokio_stream::iter(vec![
+                Ok(game_full("StonksBot")),
+                Ok(state("e2e4", "started")),
+                Ok(state("e2e4 e7e5", "resign")),
+            ]);
+            let client = RecordingClient::default();
+            let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let harvester = recording_queue(&games);
+            let mut record = GameRecord::new("chat1".to_string());
+
+            drive_game(&client, "chat1", stream, settings, &harvester, &mut record, &mut None)
+                .await
+                .unwrap();
+
+            let sent = client.0.into_inner().unwrap();
+            let reply = format!("move {}", record.moves[0].uci);
+            match chat_enabled {
+                true => {
+                    assert_eq!(sent.len(), 3, "{:?}", sent);
+                    assert_eq!(sent[0], format!("chat {}", CHAT_GREETING));
+                    assert_eq!(sent[1], reply);
+                    assert!(sent[2].starts_with("chat Good game! info string phase opening eval cp "));
+                }
+                false => assert_eq!(sent, vec![reply]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_move_numbers_count_plies() {
+        let settings = test_settings();
+        let state: BoardState = serde_json::from_value(json!({
+            "type": "gameState",
+            "moves": "e2e4",
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "status": "started"
+        }))
+        .unwrap();
+        // We play Black, so our reply to 1.e4 is the second half-move
+        let stream = tokio_stream::iter(vec![Ok(game_full("StonksBot")), Ok(state)]);
+        let client = RecordingClient::default();
+        let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let harvester = recording_queue(&games);
+        let mut record = GameRecord::new("numbers1".to_string());
+
+        drive_game(&client, "numbers1", stream, settings, &harvester, &mut record, &mut None)
+            .await
+            .unwrap();
+
+        assert_eq!(record.moves.len(), 1);
+        assert_eq!(record.moves[0].move_number, 2);
+        assert_eq!(record.moves[0].side, "black");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_times_out() {
+        // Timed out games face the same minimum length as finished ones
+        for (min_harvest_moves, harvested) in [(0, 1), (10, 0)] {
+            let settings = GameSettings {
+                game_timeout: Duration::from_millis(100),
+                min_harvest_moves,
+                ..test_settings()
+            };
+            let games = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let harvester = recording_queue(&games);
+
+            // GameFull arrives, then the stream goes quiet forever.
+            let stream = tokio_stream::iter(vec![Ok(game_full("StonksBot"))])
+                .chain(tokio_stream::pending());
+            let client = Licheszter::new(String::new());
+
+            let result = tokio::time::timeout(
+                Duration::from_millis(200),
+                play_game_stream(&client, "stalled1", stream, settings, harvester.clone()),
+            )
+            .await
+            .expect("game task should give up before the outer timeout");
+            harvester.flush().await.unwrap();
+
+            assert!(matches!(result, Err(BotError::Timeout(_))));
+            let games = games.lock().unwrap();
+            assert_eq!(games.len(), harvested, "minimum {}", min_harvest_moves);
+            if let Some(game) = games.first() {
+                assert_eq!(game.result, "timeout");
+                assert_eq!(game.bot_color, "Black");
+            }
+        }
+    }
+
+    #[test]
+    fn test_clock_ms_used_tracks_think_time() {
+        let bot = Bot {
+            depth: 3,
+            max_depth: 3,
+            ..Bot::default()
+        };
+        let (mut record, _) = think(&bot, &Board::default(), &[], Color::White, 1, None, None);
+
+        // Lichess deducts our think time plus some network latency, then
+        // adds the increment.
+        let (before, increment, latency) = (60_000u64, 2_000u64, 40u64);
+        let after = before - record.think_time_ms - latency + increment;
+        record.set_clock(before, after, increment);
+
+        assert_eq!(record.clock_ms_remaining, after);
+        let drift = record.clock_ms_used.abs_diff(record.think_time_ms);
+        assert!(drift <= 100, "clock used {} vs think {}", record.clock_ms_used, record.think_time_ms);
+    }
+
+    #[test]
+    fn test_clock_ms_picks_mover_side() {
+        let state: GameState = serde_json::from_value(json!({
+            "moves": "e2e4",
+            "wtime": 59_000,
+            "btime": 61_000,
+            "winc": 1_000,
+            "binc": 2_000,
+            "status": "started"
+        }))
+        .unwrap();
+        assert_eq!(clock_ms(&state, Color::White), (59_000, 1_000));
+        assert_eq!(clock_ms(&state, Color::Black), (61_000, 2_000));
+    }
+
+    #[test]
+    fn test_move_deadline_shares_clock() {
+        let in_ms = |deadline: Option<Instant>| {
+            deadline.unwrap().saturating_duration_since(Instant::now()).as_millis() as u64
+        };
+        assert!(move_deadline(0, 2_000).is_none());
+        assert!((1_900..=2_000).contains(&in_ms(move_deadline(60_000, 0))));
+        assert!((3_400..=3_500).contains(&in_ms(move_deadline(60_000, 2_000))));
+        // An increment never lets a move use more than half the clock
+        assert!(in_ms(move_deadline(1_000, 5_000)) <= 500);
+    }
+
+    #[test]
+    fn test_extra_time_raises_move_budget() {
+        let state = |wtime: u64| -> GameState {
+            serde_json::from_value(json!({
+                "moves": "e2e4 e7e5",
+                "wtime": wtime,
+                "btime": 60_000,
+                "winc": 0,
+                "binc": 0,
+                "status": "started"
+            }))
+            .unwrap()
+        };
+        let bot = Bot::default().with_depth(6);
+        let board = Board::default();
+        let budget = |state: &GameState| bot_for_clock(&bot, &board, clock_ms(state, Color::White).0).depth;
+
+        // The opponent grants us 15 more seconds
+        let before = budget(&state(5_000));
+        let after = budget(&state(20_000));
+        assert!(after > before, "depth {} after extra time vs {}", after, before);
+    }
+
+    #[test]
+    fn test_bot_color_from_account_name() {
+        let BoardState::GameFull(game) = game_full("StonksBot") else {
+            panic!("expected gameFull");
+        };
+        assert_eq!(bot_color_in(&game.white, "StonksBot"), Color::Black);
+
+        let white: Challenger =
+            serde_json::from_value(json!({ "id": "stonksbot", "name": "StonksBot" })).unwrap();
+        assert_eq!(bot_color_in(&white, "StonksBot"), Color::White);
+        assert_eq!(bot_color_in(&white, "stonksBOT"), Color::White);
+        assert_eq!(bot_color_in(&white, "AdaChessBot"), Color::Black);
+    }
+
+    #[tokio::test]
+    async fn test_send_move_retries_until_accepted() {
+        // Fails once, then goes through
+        let client = FlakyClient::new(1, 4);
+        assert!(send_move(&client, "g1", "e2e4", false, 4, 3).await.is_ok());
+        assert_eq!(client.sent(), 2);
+
+        // The failed request was applied anyway, so it isn't resent
+        let client = FlakyClient::new(1, 5);
+        assert!(send_move(&client, "g1", "e2e4", false, 4, 3).await.is_ok());
+        assert_eq!(client.sent(), 1);
+
+        let client = FlakyClient::new(u32::MAX, 4);
+        let result = send_move(&client, "g1", "e2e4", false, 4, 2).await;
+        assert!(matches!(result, Err(BotError::Move { ref uci, .. }) if uci == "e2e4"));
+        assert_eq!(client.sent(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_counts_panicked_games() {
+        let supervisor = GameSupervisor::new();
+        assert!(supervisor.spawn_task("boom".to_string(), async { panic!("engine bug") }).await);
+        assert!(supervisor.spawn_task("fine".to_string(), async {}).await);
+
+        assert_eq!(supervisor.wait_for_all(Duration::from_secs(1)).await, 2);
+        assert_eq!(supervisor.game_panic_count(), 1);
+        assert_eq!(supervisor.active_count().await, 0);
+
+        // A panicked game frees its slot, so it can be played again
+        assert!(supervisor.spawn_task("boom".to_string(), std::future::pending()).await);
+        assert!(!supervisor.spawn_task("boom".to_string(), async {}).await);
+        assert_eq!(supervisor.abort_all().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_finished_game_task_may_wrap_up() {
+        let supervisor = GameSupervisor::new().with_finish_grace(Duration::from_millis(200));
+        let wrapped_up = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let task_wrapped_up = Arc::clone(&wrapped_up);
+        supervisor
+            .spawn_task("ending".to_string(), async move {
+                // The game stream reports the end a little after GameFinish
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                task_wrapped_up.store(true, Ordering::SeqCst);
+            })
+            .await;
+        assert!(supervisor.finish_game("ending").await);
+        assert!(wrapped_up.load(Ordering::SeqCst));
+
+        supervisor.spawn_task("stuck".to_string(), std::future::pending()).await;
+        assert!(!supervisor.finish_game("stuck").await);
+        assert_eq!(supervisor.active_count().await, 0);
+        assert!(supervisor.finish_game("unknown").await);
+    }
+}
@@ -0,0 +1,188 @@
+//! Challenge accept/decline counters.
+//!
+//! The event loop tallies every challenge decision here so operators can
+//! see why the bot isn't getting games and tune `ChallengeConfig`
+//! accordingly. Counters are atomics, so the periodic summary and the
+//! optional `/metrics` endpoint can read them while challenges arrive.
+
+use tracing::{debug, info, warn};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::challenge::ChallengeDeclineReason;
+
+/// Thread-safe tally of challenge decisions.
+#[derive(Debug, Default)]
+pub struct ChallengeMetrics {
+    accepted: AtomicU64,
+    declined: [AtomicU64; ChallengeDeclineReason::ALL.len()],
+}
+
+impl ChallengeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an accepted challenge.
+    pub fn record_accepted(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a challenge declined for `reason`.
+    pub fn record_declined(&self, reason: ChallengeDeclineReason) {
+        self.declined[reason.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Challenges accepted so far.
+    pub fn accepted(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Challenges declined for `reason` so far.
+    pub fn declined(&self, reason: ChallengeDeclineReason) -> u64 {
+        self.declined[reason.index()].load(Ordering::Relaxed)
+    }
+
+    /// Challenges declined for any reason so far.
+    pub fn total_declined(&self) -> u64 {
+        ChallengeDeclineReason::ALL
+            .iter()
+            .map(|&reason| self.declined(reason))
+            .sum()
+    }
+
+    /// One-line summary for the log, e.g.
+    /// `accepted=3 declined=5 (variant=4, tooFast=1)`.
+    pub fn summary(&self) -> String {
+        let reasons: Vec<String> = ChallengeDeclineReason::ALL
+            .iter()
+            .filter(|&&reason| self.declined(reason) > 0)
+            .map(|&reason| format!("{}={}", reason, self.declined(reason)))
+            .collect();
+        format!(
+            "accepted={} declined={} ({})",
+            self.accepted(),
+            self.total_declined(),
+            reasons.join(", ")
+        )
+    }
+
+    /// Counters in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP stonksfish_challenges_accepted_total Challenges accepted.\n");
+        out.push_str("# TYPE stonksfish_challenges_accepted_total counter\n");
+        writeln!(out, "stonksfish_challenges_accepted_total {}", self.accepted()).ok();
+        out.push_str("# HELP stonksfish_challenges_declined_total Challenges declined, by reason.\n");
+        out.push_str("# TYPE stonksfish_challenges_declined_total counter\n");
+        for reason in ChallengeDeclineReason::ALL {
+            writeln!(
+                out,
+                "stonksfish_challenges_declined_total{{reason=\"{}\"}} {}",
+                reason,
+                self.declined(reason)
+            )
+            .ok();
+        }
+        out
+    }
+}
+
+/// Log [`ChallengeMetrics::summary`] every `interval`, forever.
+pub async fn log_summaries(metrics: Arc<ChallengeMetrics>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately; skip it so the first summary
+    // covers a full interval.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        info!("Challenge summary: {}", metrics.summary());
+    }
+}
+
+/// Serve the counters at `GET /metrics` on `listener`, forever.
+///
+/// This is a deliberately tiny HTTP/1.0 responder: one request per
+/// connection, no keep-alive, anything other than `/metrics` gets a 404.
+pub async fn serve_metrics(listener: TcpListener, metrics: Arc<ChallengeMetrics>) {
+    loop {
+        let (mut socket, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Metrics endpoint accept error: {}", e);
+                continue;
+            }
+        };
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let read = match socket.read(&mut request).await {
+                Ok(read) => read,
+                Err(e) => {
+                    debug!("Metrics request from {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            let request = String::from_utf8_lossy(&request[..read]);
+            let response = match request.split_whitespace().nth(1) {
+                Some("/metrics") => {
+                    let body = metrics.render_prometheus();
+                    format!(
+                        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                }
+                _ => "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+            };
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                debug!("Metrics response to {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn test_counts_by_reason() {
+        let metrics = ChallengeMetrics::new();
+        metrics.record_accepted();
+        metrics.record_declined(ChallengeDeclineReason::Variant);
+        metrics.record_declined(ChallengeDeclineReason::Variant);
+        metrics.record_declined(ChallengeDeclineReason::TooFast);
+
+        assert_eq!(metrics.accepted(), 1);
+        assert_eq!(metrics.declined(ChallengeDeclineReason::Variant), 2);
+        assert_eq!(metrics.declined(ChallengeDeclineReason::Rated), 0);
+        assert_eq!(metrics.total_declined(), 3);
+        assert_eq!(metrics.summary(), "accepted=1 declined=3 (tooFast=1, variant=2)");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let metrics = Arc::new(ChallengeMetrics::new());
+        metrics.record_declined(ChallengeDeclineReason::Generic);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_metrics(listener, Arc::clone(&metrics)));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.0 200 OK"));
+        assert!(response.contains("stonksfish_challenges_accepted_total 0"));
+        assert!(response.contains("stonksfish_challenges_declined_total{reason=\"generic\"} 1"));
+        server.abort();
+    }
+}
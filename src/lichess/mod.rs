@@ -12,43 +12,133 @@
 //! Lichess API (NDJSON stream)
 //!     ↕ licheszter client
 //! LichessBot::run()
+//!     ├── Startup → resume in-progress and saved games (get_ongoing_games, persistence.rs)
+//!     │            → join configured tournaments (tournament.rs)
 //!     ├── Challenge → accept/decline (challenge.rs rules)
-//!     ├── GameStart → spawn game_manager::play_game()
-//!     │       ├── Bot::choose_move()  (engine)
+//!     ├── GameStart → GameSupervisor::spawn_game() → play_game()
+//!     │       ├── Bot::choose_move()  (engine, blocking pool)
 //!     │       ├── harvest::Collector  (records positions)
 //!     │       └── whatif::branch      (optional deep analysis)
-//!     └── GameFinish → harvest::flush()
+//!     ├── GameFinish → GameSupervisor::finish_game(), then a harvest flush
+//!     └── Stop → withdraw from tournaments, wait for games, harvest flush
+//!
+//! HarvestQueue (harvest/queue.rs) → harvester task → HarvestSink
 //! ```
+//!
+//! Games and the event loop hand harvest data to the harvester task over
+//! a bounded channel and never wait on the sink itself.
 
 pub mod challenge;
+pub mod draw;
 pub mod game_manager;
+pub mod metrics;
+pub mod persistence;
+pub mod tournament;
 
-use licheszter::client::Licheszter;
-use licheszter::models::board::Event;
-use log::{debug, error, info, warn};
-use std::collections::HashMap;
+use fnv::FnvHasher;
+use licheszter::client::{Licheszter, LicheszterResult};
+use licheszter::models::board::{Challenge, Event};
+use std::hash::Hasher;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio_stream::StreamExt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::{watch, Semaphore};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
+use crate::engine::player::OpeningVariety;
+use crate::engine::search::{AntiShuffle, MAX_DEPTH};
+use crate::error::BotError;
+use crate::harvest::queue::{HarvestQueue, OverflowPolicy, DEFAULT_QUEUE_CAPACITY};
 use crate::harvest::HarvestSink;
-use challenge::ChallengeConfig;
+use crate::util::pool::ThreadPool;
+use crate::whatif::SessionBudget;
+use challenge::{ChallengeConfig, ChallengeCooldown, ChallengeDeclineReason, RematchOffers, RematchPolicy};
+use draw::DrawPolicy;
+use game_manager::GameSupervisor;
+use metrics::ChallengeMetrics;
+use persistence::GameStore;
+use tournament::{Tournament, Tournaments};
+
+/// Maximum number of ongoing games Lichess returns (API limit).
+const MAX_ONGOING_GAMES: u8 = 50;
+
+/// Default limit on how long a single game task may run (one hour).
+const DEFAULT_GAME_TIMEOUT_SECS: u64 = 3600;
+
+/// Default interval between challenge summaries in the log (ten minutes).
+const DEFAULT_CHALLENGE_SUMMARY_SECS: u64 = 600;
+
+/// Default number of retries for a move Lichess failed to accept.
+const DEFAULT_MOVE_RETRIES: u32 = 3;
 
 /// Configuration for the Lichess bot.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BotConfig {
     /// Lichess API token.
     pub token: String,
     /// Engine search depth (plies).
     pub depth: u8,
+    /// Hard ceiling on search depth, applied on top of `depth`.
+    pub max_depth: u8,
+    /// Bias against moves back to earlier positions of a game when ahead.
+    pub anti_shuffle: AntiShuffle,
     /// Maximum concurrent games.
     pub max_concurrent_games: usize,
     /// Challenge acceptance rules.
     pub challenge: ChallengeConfig,
+    /// When to accept draw offers.
+    pub draw: DrawPolicy,
+    /// Whether to greet opponents and say goodbye in the game chat.
+    pub chat_enabled: bool,
     /// Whether to run what-if branching on critical positions.
     pub whatif_enabled: bool,
+    /// Threads dedicated to what-if tree generation, apart from the
+    /// runtime's blocking pool used by live searches.
+    pub whatif_pool_size: usize,
+    /// Maximum what-if trees generated at once across all games.
+    pub max_concurrent_whatif: usize,
+    /// Total what-if nodes a single game may generate (0 = unlimited).
+    pub whatif_session_budget: usize,
+    /// Estimated memory a single what-if tree may use, in bytes (0 =
+    /// unlimited).
+    pub whatif_max_memory: usize,
     /// Bot's username on Lichess (determined at startup).
     pub bot_username: String,
+    /// Longest a single game task may run before it is abandoned.
+    pub game_timeout: Duration,
+    /// Fewest half-moves a finished game needs to be harvested (0 =
+    /// harvest every game).
+    pub min_harvest_moves: usize,
+    /// How many times a failed move submission is retried.
+    pub move_retries: u32,
+    /// Whether to randomize the first moves of each game among the best
+    /// candidates, see [`OpeningVariety`].
+    pub opening_variety: bool,
+    /// Half-moves from the start of a game that are randomized.
+    pub opening_variety_plies: usize,
+    /// Seed for opening randomization, combined with each game's id
+    /// (`None` = seed from the clock).
+    pub opening_seed: Option<u64>,
+    /// Directory where in-progress games are saved so they can be resumed
+    /// after a restart (`None` = don't save).
+    pub state_dir: Option<PathBuf>,
+    /// How often to log the challenge accept/decline tally (zero disables).
+    pub challenge_summary_interval: Duration,
+    /// Least time between two accepted challenges (zero disables).
+    /// Challenges arriving sooner are declined with `later`.
+    pub challenge_cooldown: Duration,
+    /// Arena and Swiss tournaments to join at startup. Each joined
+    /// tournament keeps one of the `max_concurrent_games` slots free for
+    /// its pairings.
+    pub tournaments: Vec<Tournament>,
+    /// Address to serve challenge metrics on at `/metrics`, if any.
+    pub metrics_addr: Option<String>,
+    /// Harvest records waiting for the harvester task at most.
+    pub harvest_queue_capacity: usize,
+    /// What games do when the harvest queue is full.
+    pub harvest_overflow: OverflowPolicy,
 }
 
 impl Default for BotConfig {
@@ -56,36 +146,250 @@ impl Default for BotConfig {
         Self {
             token: String::new(),
             depth: 5,
+            max_depth: MAX_DEPTH,
+            anti_shuffle: AntiShuffle::default(),
             max_concurrent_games: 4,
             challenge: ChallengeConfig::default(),
+            draw: DrawPolicy::default(),
+            chat_enabled: false,
             whatif_enabled: false,
+            whatif_pool_size: 2,
+            max_concurrent_whatif: 2,
+            whatif_session_budget: 0,
+            whatif_max_memory: 0,
             bot_username: String::new(),
+            game_timeout: Duration::from_secs(DEFAULT_GAME_TIMEOUT_SECS),
+            min_harvest_moves: 0,
+            move_retries: DEFAULT_MOVE_RETRIES,
+            opening_variety: false,
+            opening_variety_plies: OpeningVariety::default().plies,
+            opening_seed: None,
+            state_dir: None,
+            challenge_summary_interval: Duration::from_secs(DEFAULT_CHALLENGE_SUMMARY_SECS),
+            challenge_cooldown: Duration::ZERO,
+            tournaments: Vec::new(),
+            metrics_addr: None,
+            harvest_queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            harvest_overflow: OverflowPolicy::default(),
         }
     }
 }
 
 impl BotConfig {
-    /// Create config from environment variables.
-    pub fn from_env() -> Self {
-        Self {
-            token: std::env::var("RUST_BOT_TOKEN").unwrap_or_default(),
-            depth: std::env::var("BOT_DEPTH")
-                .ok()
+    /// Create config from environment variables. Fails if the challenge
+    /// filter can't be loaded, see [`ChallengeConfig::from_env`].
+    pub fn from_env() -> Result<Self, BotError> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Like [`BotConfig::from_env`], but reading each variable through
+    /// `var` instead of the process environment.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, BotError> {
+        Ok(Self {
+            token: var("RUST_BOT_TOKEN").unwrap_or_default(),
+            depth: var("BOT_DEPTH")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(5),
-            max_concurrent_games: std::env::var("BOT_MAX_GAMES")
-                .ok()
+            max_depth: var("BOT_MAX_DEPTH")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(MAX_DEPTH),
+            anti_shuffle: AntiShuffle {
+                penalty_cp: var("BOT_ANTI_SHUFFLE_CP")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(AntiShuffle::default().penalty_cp),
+                min_advantage_cp: var("BOT_ANTI_SHUFFLE_MIN_CP")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(AntiShuffle::default().min_advantage_cp),
+            },
+            max_concurrent_games: var("BOT_MAX_GAMES")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(4),
-            challenge: ChallengeConfig::from_env(),
-            whatif_enabled: std::env::var("BOT_WHATIF")
+            challenge: ChallengeConfig::from_vars(&var)?,
+            draw: DrawPolicy::from_vars(&var),
+            chat_enabled: var("BOT_CHAT")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            whatif_enabled: var("BOT_WHATIF")
                 .map(|v| v == "true" || v == "1")
                 .unwrap_or(false),
+            whatif_pool_size: var("BOT_WHATIF_POOL_SIZE")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2),
+            max_concurrent_whatif: var("BOT_WHATIF_MAX_CONCURRENT")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2),
+            whatif_session_budget: var("BOT_WHATIF_SESSION_BUDGET")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            whatif_max_memory: var("BOT_WHATIF_MAX_MEMORY")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
             bot_username: String::new(),
-        }
+            game_timeout: Duration::from_secs(
+                var("BOT_GAME_TIMEOUT_SECS")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_GAME_TIMEOUT_SECS),
+            ),
+            min_harvest_moves: var("BOT_MIN_HARVEST_MOVES")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            move_retries: var("BOT_MOVE_RETRIES")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_MOVE_RETRIES),
+            opening_variety: var("BOT_OPENING_VARIETY")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            opening_variety_plies: var("BOT_OPENING_VARIETY_PLIES")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(OpeningVariety::default().plies),
+            opening_seed: var("BOT_OPENING_SEED")
+                .and_then(|s| s.parse().ok()),
+            state_dir: var("BOT_STATE_DIR")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+            challenge_summary_interval: Duration::from_secs(
+                var("BOT_CHALLENGE_SUMMARY_SECS")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_CHALLENGE_SUMMARY_SECS),
+            ),
+            challenge_cooldown: Duration::from_secs(
+                var("BOT_CHALLENGE_COOLDOWN_SECS")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+            ),
+            tournaments: var("BOT_TOURNAMENTS")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|spec| !spec.trim().is_empty())
+                .filter_map(|spec| {
+                    let tournament = Tournament::parse(spec);
+                    if tournament.is_none() {
+                        warn!("Ignoring invalid tournament {:?} in BOT_TOURNAMENTS", spec);
+                    }
+                    tournament
+                })
+                .collect(),
+            metrics_addr: var("BOT_METRICS_ADDR")
+                .filter(|addr| !addr.is_empty()),
+            harvest_queue_capacity: var("BOT_HARVEST_QUEUE")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_QUEUE_CAPACITY),
+            harvest_overflow: var("BOT_HARVEST_OVERFLOW")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Format the config as `KEY=VALUE` lines that can be saved to a
+    /// `.env` file and read back by [`BotConfig::from_env`], e.g. to audit
+    /// or reproduce a running bot.
+    ///
+    /// WARNING: the output contains the Lichess API token in plain text.
+    /// Treat it like the token itself — never log it or commit it.
+    ///
+    /// Only settings `from_env` reads are written; durations are rounded
+    /// down to whole seconds.
+    pub fn to_env_string(&self) -> String {
+        let mut out = String::new();
+        let mut line = |key: &str, value: &dyn std::fmt::Display| {
+            out.push_str(&format!("{}={}\n", key, value));
+        };
+        line("RUST_BOT_TOKEN", &self.token);
+        line("BOT_DEPTH", &self.depth);
+        line("BOT_MAX_DEPTH", &self.max_depth);
+        line("BOT_ANTI_SHUFFLE_CP", &self.anti_shuffle.penalty_cp);
+        line("BOT_ANTI_SHUFFLE_MIN_CP", &self.anti_shuffle.min_advantage_cp);
+        line("BOT_MAX_GAMES", &self.max_concurrent_games);
+        line("BOT_CHAT", &self.chat_enabled);
+        line("BOT_WHATIF", &self.whatif_enabled);
+        line("BOT_WHATIF_POOL_SIZE", &self.whatif_pool_size);
+        line("BOT_WHATIF_MAX_CONCURRENT", &self.max_concurrent_whatif);
+        line("BOT_WHATIF_SESSION_BUDGET", &self.whatif_session_budget);
+        line("BOT_WHATIF_MAX_MEMORY", &self.whatif_max_memory);
+        line("BOT_GAME_TIMEOUT_SECS", &self.game_timeout.as_secs());
+        line("BOT_MIN_HARVEST_MOVES", &self.min_harvest_moves);
+        line("BOT_MOVE_RETRIES", &self.move_retries);
+        line("BOT_OPENING_VARIETY", &self.opening_variety);
+        line("BOT_OPENING_VARIETY_PLIES", &self.opening_variety_plies);
+        let opening_seed = self.opening_seed.map(|seed| seed.to_string());
+        line("BOT_OPENING_SEED", &opening_seed.as_deref().unwrap_or(""));
+        let state_dir = self.state_dir.as_ref().map(|dir| dir.display().to_string());
+        line("BOT_STATE_DIR", &state_dir.as_deref().unwrap_or(""));
+        line(
+            "BOT_CHALLENGE_SUMMARY_SECS",
+            &self.challenge_summary_interval.as_secs(),
+        );
+        line("BOT_CHALLENGE_COOLDOWN_SECS", &self.challenge_cooldown.as_secs());
+        let tournaments: Vec<String> = self.tournaments.iter().map(Tournament::to_string).collect();
+        line("BOT_TOURNAMENTS", &tournaments.join(","));
+        line("BOT_METRICS_ADDR", &self.metrics_addr.as_deref().unwrap_or(""));
+        line("BOT_HARVEST_QUEUE", &self.harvest_queue_capacity);
+        line("BOT_HARVEST_OVERFLOW", &self.harvest_overflow.as_str());
+        out.push_str(&self.challenge.to_env_string());
+        out.push_str(&self.draw.to_env_string());
+        out
     }
 }
 
+/// Lichess endpoint describing the account a token belongs to.
+const ACCOUNT_URL: &str = "https://lichess.org/api/account";
+
+/// Look up the username of the account `token` belongs to.
+///
+/// Used at startup to fill [`BotConfig::bot_username`], so the bot's color
+/// in each game comes from its real account rather than a guess.
+pub async fn fetch_account_username(token: &str) -> Result<String, BotError> {
+    let response = reqwest::Client::new()
+        .get(ACCOUNT_URL)
+        .bearer_auth(token)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| BotError::Config(format!("Failed to fetch Lichess account: {}", e)))?;
+    let body = response
+        .text()
+        .await
+        .map_err(|e| BotError::Config(format!("Failed to read Lichess account: {}", e)))?;
+    parse_account_username(&body)
+}
+
+/// Extract `username` from an `/api/account` response body.
+fn parse_account_username(body: &str) -> Result<String, BotError> {
+    let account: serde_json::Value = serde_json::from_str(body)
+        .map_err(|e| BotError::Config(format!("Bad Lichess account response: {}", e)))?;
+    account["username"]
+        .as_str()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| BotError::Config("Lichess account response has no username".to_string()))
+}
+
+/// How long shutdown waits for running games before aborting them.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Asks a running [`LichessBot`] to stop, e.g. from a signal handler.
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<watch::Sender<bool>>);
+
+impl StopHandle {
+    /// Set the stop flag. The bot stops taking new events and shuts down
+    /// gracefully.
+    pub fn stop(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// What happened to running games during a graceful shutdown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownStats {
+    /// Games that finished within the shutdown timeout.
+    pub games_finished: usize,
+    /// Games still running at the timeout, which were aborted.
+    pub games_aborted: usize,
+    /// Whether the final harvest flush succeeded.
+    pub flushed: bool,
+}
+
 /// The main Lichess bot.
 ///
 /// Owns the API client, manages concurrent games, and routes
@@ -93,136 +397,185 @@ impl BotConfig {
 pub struct LichessBot {
     client: Licheszter,
     config: BotConfig,
-    harvester: Arc<Mutex<Box<dyn HarvestSink + Send>>>,
-    active_games: Arc<Mutex<HashMap<String, tokio::task::JoinHandle<()>>>>,
+    harvester: HarvestQueue,
+    games: GameSupervisor,
+    game_store: Option<Arc<GameStore>>,
+    whatif_permits: Arc<Semaphore>,
+    whatif_pool: ThreadPool,
+    stop: Arc<watch::Sender<bool>>,
+    challenge_metrics: Arc<ChallengeMetrics>,
+    challenge_cooldown: ChallengeCooldown,
+    rematch_offers: RematchOffers,
+    tournaments: Tournaments,
 }
 
 impl LichessBot {
-    /// Create a new bot with the given config and harvest sink.
+    /// Create a new bot with the given config and harvest sink. The sink
+    /// is moved to its own task, so this must be called within a Tokio
+    /// runtime.
     pub fn new(config: BotConfig, harvester: Box<dyn HarvestSink + Send>) -> Self {
         let client = Licheszter::new(config.token.clone());
+        let harvester =
+            HarvestQueue::spawn(harvester, config.harvest_queue_capacity, config.harvest_overflow);
+        let whatif_permits = Arc::new(Semaphore::new(config.max_concurrent_whatif.max(1)));
+        let whatif_pool = ThreadPool::new("whatif", config.whatif_pool_size);
+        let game_store = config.state_dir.as_ref().and_then(|dir| match GameStore::new(dir) {
+            Ok(store) => Some(Arc::new(store)),
+            Err(e) => {
+                warn!("Not saving game state to {}: {}", dir.display(), e);
+                None
+            }
+        });
+        let mut tournaments = config.tournaments.clone();
+        if tournaments.len() > config.max_concurrent_games {
+            warn!(
+                "Only joining the first {} tournaments, one per concurrent game",
+                config.max_concurrent_games
+            );
+            tournaments.truncate(config.max_concurrent_games);
+        }
         Self {
             client,
+            challenge_cooldown: ChallengeCooldown::new(config.challenge_cooldown),
+            rematch_offers: RematchOffers::default(),
+            tournaments: Tournaments::new(&config.token, tournaments),
             config,
-            harvester: Arc::new(Mutex::new(harvester)),
-            active_games: Arc::new(Mutex::new(HashMap::new())),
+            whatif_permits,
+            whatif_pool,
+            harvester,
+            games: GameSupervisor::new(),
+            game_store,
+            stop: Arc::new(watch::channel(false).0),
+            challenge_metrics: Arc::new(ChallengeMetrics::new()),
         }
     }
 
+    /// Challenge accept/decline counters.
+    pub fn challenge_metrics(&self) -> Arc<ChallengeMetrics> {
+        Arc::clone(&self.challenge_metrics)
+    }
+
+    /// Handle for stopping the bot from another task.
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(Arc::clone(&self.stop))
+    }
+
     /// Run the bot event loop. This is the main entry point.
     ///
     /// Streams events from Lichess and dispatches them:
     /// - Challenge → accept or decline
     /// - GameStart → spawn concurrent game handler
     /// - GameFinish → clean up and flush harvest data
-    pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn run(&self) -> Result<(), BotError> {
+        if self.config.token.is_empty() {
+            return Err(BotError::Config("Lichess API token is empty".to_string()));
+        }
+
         info!(
-            "Starting Lichess bot (depth={}, max_games={}, whatif={})",
-            self.config.depth, self.config.max_concurrent_games, self.config.whatif_enabled
+            "Starting Lichess bot (depth={}, max_depth={}, max_games={}, whatif={}, max_whatif={})",
+            self.config.depth,
+            self.config.max_depth,
+            self.config.max_concurrent_games,
+            self.config.whatif_enabled,
+            self.config.max_concurrent_whatif
         );
 
-        let mut stream = self
-            .client
-            .stream_events()
-            .await
-            .map_err(|e| format!("Failed to stream events: {:?}", e))?;
+        let unsupported = self.config.challenge.unsupported_variants();
+        if !unsupported.is_empty() {
+            warn!(
+                "Variants {} are not supported by the engine; challenges for them will be declined",
+                unsupported.join(", ")
+            );
+        }
+        let unknown = self.config.challenge.unknown_speeds();
+        if !unknown.is_empty() {
+            warn!("Ignoring unknown speeds {} in BOT_SPEEDS", unknown.join(", "));
+        }
+
+        let background = self.spawn_metrics_tasks().await?;
+        self.resume_ongoing_games().await;
+
+        let stream = match self.client.stream_events().await {
+            Ok(stream) => stream,
+            Err(e) => {
+                background.iter().for_each(|task| task.abort());
+                return Err(BotError::Stream(e));
+            }
+        };
 
         info!("Event stream connected. Waiting for events...");
 
-        while let Ok(Some(event)) = stream.try_next().await {
+        // Pairings arrive as ordinary gameStart events on the stream
+        if !*self.stop.borrow() {
+            self.tournaments.join_all().await;
+        }
+
+        self.run_stream(stream).await;
+        background.iter().for_each(|task| task.abort());
+        info!("Challenge summary: {}", self.challenge_metrics.summary());
+        Ok(())
+    }
+
+    /// Start the periodic challenge summary and, if configured, the
+    /// metrics endpoint.
+    async fn spawn_metrics_tasks(&self) -> Result<Vec<tokio::task::JoinHandle<()>>, BotError> {
+        let mut tasks = Vec::new();
+        if !self.config.challenge_summary_interval.is_zero() {
+            tasks.push(tokio::spawn(metrics::log_summaries(
+                self.challenge_metrics(),
+                self.config.challenge_summary_interval,
+            )));
+        }
+        if let Some(addr) = &self.config.metrics_addr {
+            let listener = TcpListener::bind(addr).await.map_err(|e| {
+                BotError::Config(format!("Cannot serve metrics on {}: {}", addr, e))
+            })?;
+            info!("Serving challenge metrics on http://{}/metrics", addr);
+            tasks.push(tokio::spawn(metrics::serve_metrics(
+                listener,
+                self.challenge_metrics(),
+            )));
+        }
+        Ok(tasks)
+    }
+
+    /// Dispatch events from an already opened event stream until it ends
+    /// or a stop is requested, then shut down gracefully.
+    pub async fn run_stream<S>(&self, mut stream: S) -> ShutdownStats
+    where
+        S: Stream<Item = LicheszterResult<Event>> + Unpin,
+    {
+        let mut stop = self.stop.subscribe();
+
+        while let Some(event) = next_event(&mut stream, &mut stop).await {
             match event {
                 Event::Challenge {
                     challenge,
                     compat: _,
                 } => {
-                    let challenger_name = challenge
-                        .challenger
-                        .as_ref()
-                        .map(|u| u.username.as_str())
-                        .unwrap_or("unknown");
-
-                    let time_control = challenge
-                        .time_control
-                        .show
-                        .as_deref()
-                        .unwrap_or("n/a");
-
-                    info!(
-                        "[{}] Challenge from {} ({})",
-                        challenge.id, challenger_name, time_control
-                    );
-
-                    // Check concurrent game limit
-                    let active_count = self.active_games.lock().await.len();
-                    if active_count >= self.config.max_concurrent_games {
-                        info!(
-                            "[{}] Declining: at max concurrent games ({}/{})",
-                            challenge.id, active_count, self.config.max_concurrent_games
-                        );
-                        if let Err(e) = self.client.challenge_decline(&challenge.id, None).await {
-                            warn!("[{}] Failed to decline: {:?}", challenge.id, e);
-                        }
-                        continue;
-                    }
-
-                    // Apply challenge rules
-                    if challenge::should_accept(&challenge, &self.config.challenge) {
-                        info!("[{}] Accepting challenge", challenge.id);
-                        if let Err(e) = self.client.challenge_accept(&challenge.id).await {
-                            error!("[{}] Failed to accept: {:?}", challenge.id, e);
-                        }
-                    } else {
-                        info!("[{}] Declining: does not match rules", challenge.id);
-                        if let Err(e) = self.client.challenge_decline(&challenge.id, None).await {
-                            warn!("[{}] Failed to decline: {:?}", challenge.id, e);
-                        }
-                    }
+                    let span = info_span!("challenge", challenge_id = %challenge.id);
+                    self.handle_challenge(&challenge).instrument(span).await;
                 }
 
                 Event::GameStart { game: game_id } => {
-                    let game_id_str = game_id.id.clone();
-                    info!("[{}] Game started", game_id_str);
-
-                    let client = Licheszter::new(self.config.token.clone());
-                    let depth = self.config.depth;
-                    let whatif = self.config.whatif_enabled;
-                    let harvester = Arc::clone(&self.harvester);
-                    let bot_username = self.config.bot_username.clone();
-
-                    let handle = tokio::spawn(async move {
-                        if let Err(e) = game_manager::play_game(
-                            client,
-                            &game_id_str,
-                            depth,
-                            whatif,
-                            &bot_username,
-                            harvester,
-                        )
-                        .await
-                        {
-                            error!("[{}] Game error: {:?}", game_id_str, e);
-                        }
-                    });
-
-                    self.active_games
-                        .lock()
-                        .await
-                        .insert(game_id.id.clone(), handle);
+                    info!(game_id = %game_id.id, "Game started");
+                    self.spawn_game(game_id.id).await;
                 }
 
                 Event::GameFinish { game: game_id } => {
-                    info!("[{}] Game finished", game_id.id);
-                    if let Some(handle) = self.active_games.lock().await.remove(&game_id.id) {
-                        handle.abort();
-                    }
-                    // Flush harvest data
-                    if let Err(e) = self.harvester.lock().await.flush().await {
-                        warn!("Harvest flush error: {:?}", e);
-                    }
+                    info!(game_id = %game_id.id, "Game finished");
+                    // Waited for off the event loop, so other events
+                    // aren't held up by a slow game task
+                    let games = self.games.clone();
+                    let harvester = self.harvester.clone();
+                    tokio::spawn(async move {
+                        games.finish_game(&game_id.id).await;
+                        harvester.request_flush();
+                    });
                 }
 
                 Event::ChallengeCanceled { challenge } => {
-                    debug!("[{}] Challenge cancelled", challenge.id);
+                    debug!(challenge_id = %challenge.id, "Challenge cancelled");
                 }
 
                 event => {
@@ -231,13 +584,549 @@ impl LichessBot {
             }
         }
 
-        info!("Event stream ended. Shutting down...");
+        if *stop.borrow() {
+            info!("Stop requested. Shutting down...");
+        } else {
+            info!("Event stream ended. Shutting down...");
+        }
+
+        self.graceful_shutdown(SHUTDOWN_TIMEOUT).await
+    }
+
+    /// Accept or decline a challenge according to the concurrent game
+    /// limit and the challenge rules.
+    async fn handle_challenge(&self, challenge: &Challenge) {
+        let challenger_name = challenge
+            .challenger
+            .as_ref()
+            .map(|u| u.username.as_str())
+            .unwrap_or("unknown");
+
+        let time_control = challenge
+            .time_control
+            .show
+            .as_deref()
+            .unwrap_or("n/a");
+
+        info!("Challenge from {} ({})", challenger_name, time_control);
+
+        match self.challenge_decision(challenge).await {
+            None => {
+                info!("Accepting challenge");
+                self.challenge_metrics.record_accepted();
+                if let Err(e) = self.client.challenge_accept(&challenge.id).await {
+                    error!("Failed to accept: {:?}", e);
+                }
+            }
+            Some(reason) => {
+                self.challenge_metrics.record_declined(reason);
+                self.decline_with_reason(&challenge.id, reason).await;
+            }
+        }
+    }
+
+    /// Reason to decline `challenge`, or `None` to accept it, in which
+    /// case the acceptance starts a new cooldown window. Rematches of our
+    /// games skip all of this unless the rematch policy is `rules`.
+    async fn challenge_decision(&self, challenge: &Challenge) -> Option<ChallengeDeclineReason> {
+        let challenger = challenge.challenger.as_ref().map(|u| u.username.as_str());
+        if self.rematch_offers.take(&challenge.id, challenger, Instant::now()) {
+            match self.config.challenge.rematch_policy {
+                RematchPolicy::Always => {
+                    info!("Accepting rematch");
+                    return None;
+                }
+                RematchPolicy::Never => {
+                    info!("Declining: rematches not accepted");
+                    return Some(ChallengeDeclineReason::Generic);
+                }
+                RematchPolicy::Rules => debug!("Challenge is a rematch"),
+            }
+        }
+
+        // Check concurrent game limit, keeping a slot free for each
+        // tournament's pairings
+        let active_count = self.games.active_count().await;
+        let limit = self
+            .config
+            .max_concurrent_games
+            .saturating_sub(self.tournaments.joined_count());
+        if active_count >= limit {
+            info!(
+                "Declining: at max concurrent games ({}/{}, {} reserved for tournaments)",
+                active_count,
+                self.config.max_concurrent_games,
+                self.config.max_concurrent_games - limit
+            );
+            return Some(ChallengeDeclineReason::Later);
+        }
+
+        // Apply challenge rules
+        if let Some(reason) = challenge::decline_reason(challenge, &self.config.challenge) {
+            info!("Declining: does not match rules ({})", reason);
+            return Some(reason);
+        }
+
+        // Space out acceptances
+        if !self.challenge_cooldown.try_accept(Instant::now()) {
+            info!(
+                "Declining: accepted a challenge less than {}s ago",
+                self.config.challenge_cooldown.as_secs()
+            );
+            return Some(ChallengeDeclineReason::Later);
+        }
+        None
+    }
+
+    /// Withdraw from tournaments, wait up to `timeout` for running games
+    /// to finish, abort the rest, and flush the harvester.
+    pub async fn graceful_shutdown(&self, timeout: Duration) -> ShutdownStats {
+        // Stop new pairings before waiting for the current games
+        self.tournaments.leave_all().await;
+
+        let mut stats = ShutdownStats {
+            games_finished: self.games.wait_for_all(timeout).await,
+            games_aborted: self.games.abort_all().await,
+            ..ShutdownStats::default()
+        };
 
         // Final harvest flush
-        if let Err(e) = self.harvester.lock().await.flush().await {
-            warn!("Final harvest flush error: {:?}", e);
+        match self.harvester.flush().await {
+            Ok(()) => stats.flushed = true,
+            Err(e) => warn!("Final harvest flush error: {:?}", e),
         }
 
-        Ok(())
+        info!(
+            "Shutdown complete: {} game(s) finished, {} aborted, harvest flushed: {}",
+            stats.games_finished, stats.games_aborted, stats.flushed
+        );
+        stats
+    }
+
+    /// Spawn a task playing the given game, unless one is already running
+    /// for it.
+    async fn spawn_game(&self, game_id: String) {
+        let client = Licheszter::new(self.config.token.clone());
+        let settings = game_manager::GameSettings {
+            depth: self.config.depth,
+            max_depth: self.config.max_depth,
+            anti_shuffle: self.config.anti_shuffle,
+            whatif_enabled: self.config.whatif_enabled,
+            whatif_permits: Arc::clone(&self.whatif_permits),
+            whatif_pool: self.whatif_pool.clone(),
+            whatif_budget: SessionBudget::new(self.config.whatif_session_budget),
+            whatif_max_memory: (self.config.whatif_max_memory > 0).then_some(self.config.whatif_max_memory),
+            bot_username: self.config.bot_username.clone(),
+            game_timeout: self.config.game_timeout,
+            min_harvest_moves: self.config.min_harvest_moves,
+            move_retries: self.config.move_retries,
+            opening_variety: self.config.opening_variety.then(|| OpeningVariety {
+                plies: self.config.opening_variety_plies,
+                ..OpeningVariety::default()
+            }),
+            opening_seed: game_opening_seed(self.config.opening_seed, &game_id),
+            game_store: self.game_store.clone(),
+            draw_policy: self.config.draw.clone(),
+            chat_enabled: self.config.chat_enabled,
+            rematch_offers: self.rematch_offers.clone(),
+        };
+        let harvester = self.harvester.clone();
+        self.games
+            .spawn_game(client, game_id, settings, harvester)
+            .await;
+    }
+
+    /// Pick up games that were already in progress when the bot started,
+    /// so a restart resumes them instead of abandoning them.
+    ///
+    /// Games Lichess reports as ongoing are resumed, and saved games it no
+    /// longer lists are forgotten. If Lichess can't be asked, every saved
+    /// game is resumed instead; one that has ended simply finishes at once.
+    async fn resume_ongoing_games(&self) {
+        let saved = match &self.game_store {
+            Some(store) => store.load_all().await.unwrap_or_else(|e| {
+                warn!("Failed to load saved games: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        match self.client.get_ongoing_games(MAX_ONGOING_GAMES).await {
+            Ok(games) => {
+                for game in &saved {
+                    if !games.iter().any(|ongoing| ongoing.game_id == game.game_id) {
+                        info!(game_id = %game.game_id, "Forgetting saved game that has ended");
+                        self.forget_game(&game.game_id).await;
+                    }
+                }
+                for game in games {
+                    info!(game_id = %game.game_id, "Resuming in-progress game");
+                    self.spawn_game(game.game_id).await;
+                }
+            }
+            Err(e) => {
+                warn!("Failed to fetch ongoing games: {:?}", e);
+                for game in saved {
+                    info!(game_id = %game.game_id, "Resuming saved game");
+                    self.spawn_game(game.game_id).await;
+                }
+            }
+        }
+    }
+
+    /// Delete `game_id`'s saved state, if any.
+    async fn forget_game(&self, game_id: &str) {
+        if let Some(store) = &self.game_store {
+            if let Err(e) = store.remove(game_id).await {
+                warn!(game_id, "Failed to delete saved game: {}", e);
+            }
+        }
+    }
+
+    /// Post `text` in the player chat of `game_id`. Chat is a courtesy,
+    /// so a failure is only logged.
+    pub async fn send_chat_message(&self, game_id: &str, text: &str) {
+        if let Err(e) = self.client.write_to_chat(game_id, "player", text).await {
+            warn!(game_id, "Failed to send chat message: {:?}", e);
+        }
+    }
+
+    /// Decline a challenge, telling Lichess why so the challenger sees
+    /// a meaningful message.
+    pub async fn decline_with_reason(&self, challenge_id: &str, reason: ChallengeDeclineReason) {
+        if let Err(e) = self
+            .client
+            .challenge_decline(challenge_id, Some(reason.as_str()))
+            .await
+        {
+            warn!(challenge_id, "Failed to decline: {:?}", e);
+        }
+    }
+}
+
+/// Seed for one game's opening randomization: `seed` (or the clock, if
+/// unset) mixed with the game id, so concurrent games differ while a fixed
+/// seed reproduces them. The id is hashed with FNV-1a, which unlike
+/// `DefaultHasher` gives the same result on every Rust release.
+fn game_opening_seed(seed: Option<u64>, game_id: &str) -> u64 {
+    let base = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    let mut hasher = FnvHasher::default();
+    hasher.write(game_id.as_bytes());
+    base ^ hasher.finish()
+}
+
+/// Next event from the stream, or `None` once it ends, errors, or a stop
+/// is requested.
+async fn next_event<S>(stream: &mut S, stop: &mut watch::Receiver<bool>) -> Option<Event>
+where
+    S: Stream<Item = LicheszterResult<Event>> + Unpin,
+{
+    if *stop.borrow() {
+        return None;
+    }
+    tokio::select! {
+        biased;
+        _ = stop.changed() => None,
+        next = stream.try_next() => next.ok().flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harvest::GameRecord;
+    use licheszter::models::board::GameID;
+    use crate::whatif::BranchTree;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::collections::HashMap;
+
+    /// Sink that only counts flushes.
+    struct FlushCounter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl HarvestSink for FlushCounter {
+        async fn record_game(&mut self, _: GameRecord) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn record_branch_tree(&mut self, _: &str, _: &BranchTree) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), BotError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_waits_for_games_then_flushes() {
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let bot = LichessBot::new(
+            BotConfig::default(),
+            Box::new(FlushCounter(Arc::clone(&flushes))),
+        );
+        bot.games
+            .spawn_task("quick".to_string(), tokio::time::sleep(Duration::from_millis(20)))
+            .await;
+
+        let stop = bot.stop_handle();
+        let (stats, _) = tokio::join!(
+            // No events ever arrive; only the stop signal ends the loop.
+            bot.run_stream(tokio_stream::pending::<LicheszterResult<Event>>()),
+            async move { stop.stop() },
+        );
+
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            stats,
+            ShutdownStats {
+                games_finished: 1,
+                games_aborted: 0,
+                flushed: true,
+            }
+        );
+        assert_eq!(bot.games.active_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_aborts_games_past_timeout() {
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let bot = LichessBot::new(
+            BotConfig::default(),
+            Box::new(FlushCounter(Arc::clone(&flushes))),
+        );
+        bot.games
+            .spawn_task("stuck".to_string(), std::future::pending::<()>())
+            .await;
+
+        let stats = bot.graceful_shutdown(Duration::from_millis(20)).await;
+        assert_eq!(stats.games_finished, 0);
+        assert_eq!(stats.games_aborted, 1);
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    }
+
+    /// Sink whose flushes stall until `gate` has permits.
+    struct StalledSink {
+        gate: Arc<Semaphore>,
+        flushes: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl HarvestSink for StalledSink {
+        async fn record_game(&mut self, _: GameRecord) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn record_branch_tree(&mut self, _: &str, _: &BranchTree) -> Result<(), BotError> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), BotError> {
+            self.gate.acquire().await.unwrap().forget();
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_sink_does_not_delay_events() {
+        let gate = Arc::new(Semaphore::new(0));
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let sink = StalledSink {
+            gate: Arc::clone(&gate),
+            flushes: Arc::clone(&flushes),
+        };
+        let mut bot = LichessBot::new(BotConfig::default(), Box::new(sink));
+        bot.games = GameSupervisor::new().with_finish_grace(Duration::ZERO);
+        let game_ids = ["game1", "game2", "game3"];
+        for game_id in game_ids {
+            bot.games
+                .spawn_task(game_id.to_string(), std::future::pending::<()>())
+                .await;
+        }
+        let finished = game_ids.map(|id| {
+            Ok(Event::GameFinish {
+                game: GameID { id: id.to_string() },
+            })
+        });
+        let events = tokio_stream::iter(finished).chain(tokio_stream::pending());
+
+        let stop = bot.stop_handle();
+        let (stats, _) = tokio::join!(bot.run_stream(events), async {
+            // Every game finish is handled while the first flush is stuck
+            let handled = tokio::time::timeout(Duration::from_secs(1), async {
+                while bot.games.active_count().await > 0 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await;
+            assert!(handled.is_ok(), "events waited on the harvest sink");
+            assert_eq!(flushes.load(Ordering::SeqCst), 0);
+            stop.stop();
+            gate.add_permits(10);
+        });
+
+        assert!(stats.flushed);
+        assert!(flushes.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[test]
+    fn test_env_string_round_trips() {
+        let env = [
+            ("RUST_BOT_TOKEN", "lip_secret"),
+            ("BOT_DEPTH", "7"),
+            ("BOT_MAX_DEPTH", "12"),
+            ("BOT_ANTI_SHUFFLE_CP", "30"),
+            ("BOT_ANTI_SHUFFLE_MIN_CP", "150"),
+            ("BOT_MAX_GAMES", "3"),
+            ("BOT_WHATIF", "true"),
+            ("BOT_WHATIF_POOL_SIZE", "5"),
+            ("BOT_WHATIF_MAX_CONCURRENT", "4"),
+            ("BOT_WHATIF_SESSION_BUDGET", "900"),
+            ("BOT_WHATIF_MAX_MEMORY", "1048576"),
+            ("BOT_GAME_TIMEOUT_SECS", "1800"),
+            ("BOT_MIN_HARVEST_MOVES", "10"),
+            ("BOT_MOVE_RETRIES", "5"),
+            ("BOT_OPENING_VARIETY", "true"),
+            ("BOT_OPENING_VARIETY_PLIES", "6"),
+            ("BOT_OPENING_SEED", "42"),
+            ("BOT_STATE_DIR", "/var/lib/stonksfish/games"),
+            ("BOT_CHALLENGE_SUMMARY_SECS", "60"),
+            ("BOT_CHALLENGE_COOLDOWN_SECS", "10"),
+            ("BOT_TOURNAMENTS", "abcd1234,swiss:efgh5678"),
+            ("BOT_METRICS_ADDR", "127.0.0.1:9100"),
+            ("BOT_HARVEST_QUEUE", "16"),
+            ("BOT_HARVEST_OVERFLOW", "drop"),
+            ("BOT_ACCEPT_BOT", "false"),
+            ("BOT_ACCEPT_HUMAN", "true"),
+            ("BOT_VARIANTS", "standard,chess960"),
+            ("BOT_SPEEDS", "blitz,rapid"),
+            ("BOT_BLOCKED_USERS", "spammer,troll"),
+            ("BOT_RATED_HOURS", "mon-fri 18-23"),
+            ("BOT_REMATCH", "always"),
+            ("BOT_ACCEPT_DRAWS", "true"),
+            ("BOT_CHAT", "true"),
+            ("BOT_DRAW_MAX_EVAL_CP", "-50"),
+            ("BOT_DRAW_MIN_PLIES", "60"),
+        ];
+        let vars = |env: HashMap<String, String>| move |key: &str| env.get(key).cloned();
+        let given: HashMap<String, String> =
+            env.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect();
+        let config = BotConfig::from_vars(vars(given.clone())).unwrap();
+        let dumped = config.to_env_string();
+
+        // Read back the way a saved .env file would be
+        let path = std::env::temp_dir().join(format!("stonksfish-{}.env", std::process::id()));
+        std::fs::write(&path, &dumped).unwrap();
+        // The iterator is the only dotenv API that parses without setting vars
+        #[allow(deprecated)]
+        let parsed: HashMap<String, String> =
+            dotenv::from_path_iter(&path).unwrap().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).ok();
+
+        let reloaded = BotConfig::from_vars(vars(parsed)).unwrap();
+        assert_eq!(reloaded, config);
+        assert_eq!(reloaded.depth, 7);
+        assert_eq!(reloaded.challenge.blocked_users, vec!["spammer", "troll"]);
+        assert_eq!(reloaded.tournaments.len(), 2);
+        assert_eq!(reloaded.harvest_overflow, OverflowPolicy::Drop);
+
+        // A challenge filter file that can't be loaded stops startup
+        let missing = std::env::temp_dir().join("stonksfish-missing.yaml");
+        let mut with_missing = given.clone();
+        with_missing.insert("BOT_CHALLENGE_CONFIG".to_string(), missing.display().to_string());
+        assert!(matches!(BotConfig::from_vars(vars(with_missing)), Err(BotError::Config(_))));
+
+        // So do a schedule or rematch policy that doesn't parse
+        for (key, value) in [("BOT_RATED_HOURS", "weekends"), ("BOT_REMATCH", "sometimes")] {
+            let mut invalid = given.clone();
+            invalid.insert(key.to_string(), value.to_string());
+            assert!(matches!(BotConfig::from_vars(vars(invalid)), Err(BotError::Config(_))), "{}", key);
+        }
+    }
+
+    fn challenge(id: &str) -> Challenge {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "url": format!("https://lichess.org/{}", id),
+            "finalColor": "white",
+            "color": "random",
+            "timeControl": { "type": "clock", "limit": 300, "increment": 2, "show": "5+2" },
+            "variant": { "key": "standard", "name": "Standard" },
+            "challenger": { "id": "opponent", "name": "Opponent", "title": null },
+            "perf": { "name": "Blitz" },
+            "rated": true,
+            "speed": "blitz",
+            "status": "created",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_declines_challenge_burst() {
+        let config = BotConfig {
+            max_concurrent_games: 2,
+            challenge_cooldown: Duration::from_secs(10),
+            ..BotConfig::default()
+        };
+        let bot = LichessBot::new(config, Box::new(FlushCounter(Arc::new(AtomicUsize::new(0)))));
+        for game_id in ["first", "second"] {
+            bot.games.spawn_task(game_id.to_string(), async {}).await;
+        }
+        assert_eq!(bot.games.wait_for_all(Duration::from_secs(1)).await, 2);
+        assert_eq!(bot.games.active_count().await, 0);
+
+        // Both slots are free, but only the first of the burst is taken
+        let mut decisions = Vec::new();
+        for id in ["burst1", "burst2", "burst3"] {
+            decisions.push(bot.challenge_decision(&challenge(id)).await);
+        }
+        assert_eq!(
+            decisions,
+            vec![None, Some(ChallengeDeclineReason::Later), Some(ChallengeDeclineReason::Later)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_always_accepts_rematch_at_game_limit() {
+        let mut config = BotConfig {
+            max_concurrent_games: 1,
+            ..BotConfig::default()
+        };
+        config.challenge.rematch_policy = RematchPolicy::Always;
+        config.challenge.blocked_users = vec!["opponent".to_string()];
+        let bot = LichessBot::new(config, Box::new(FlushCounter(Arc::new(AtomicUsize::new(0)))));
+        bot.games.spawn_task("running".to_string(), std::future::pending()).await;
+        bot.rematch_offers.record("Opponent", None, Instant::now());
+
+        assert_eq!(bot.challenge_decision(&challenge("rematch1")).await, None);
+        // Only once
+        assert_eq!(
+            bot.challenge_decision(&challenge("rematch1")).await,
+            Some(ChallengeDeclineReason::Later)
+        );
+        bot.games.abort_all().await;
+    }
+
+    #[test]
+    fn test_parse_account_username() {
+        let body = r#"{"id":"stonksbot","username":"StonksBot","title":"BOT"}"#;
+        assert_eq!(parse_account_username(body).unwrap(), "StonksBot");
+        assert!(parse_account_username(r#"{"id":"stonksbot"}"#).is_err());
+        assert!(parse_account_username("not json").is_err());
+    }
+
+    #[test]
+    fn test_game_opening_seed_is_stable() {
+        // FNV-1a of "a", so a fixed seed replays the same games on any build
+        assert_eq!(game_opening_seed(Some(0), "a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(game_opening_seed(Some(1), "a"), 0xaf63_dc4c_8601_ec8d);
+        assert_ne!(game_opening_seed(Some(0), "b"), game_opening_seed(Some(0), "a"));
     }
 }
@@ -0,0 +1,156 @@
+//! Durable per-game state, so games survive a restart.
+//!
+//! Correspondence games can last for days, far longer than a single run
+//! of the bot. While a game is played its id, our color and how many
+//! half-moves have been processed are saved as one small JSON file per
+//! game; on startup the saved games are resumed alongside whatever
+//! Lichess reports as ongoing. Files are read and written with `tokio::fs`
+//! so a slow disk never holds up the async workers.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tracing::warn;
+
+use crate::error::BotError;
+
+/// What is remembered about a game in progress.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedGame {
+    pub game_id: String,
+    /// Our color: `"White"` or `"Black"`.
+    pub bot_color: String,
+    /// Half-moves played when the state was last saved.
+    pub moves_processed: usize,
+    /// When the state was last saved (Unix seconds).
+    pub updated_at: u64,
+}
+
+impl PersistedGame {
+    /// State of `game_id` as of now.
+    pub fn new(game_id: &str, bot_color: &str, moves_processed: usize) -> Self {
+        Self {
+            game_id: game_id.to_string(),
+            bot_color: bot_color.to_string(),
+            moves_processed,
+            updated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+}
+
+/// Directory of persisted games, one `<game_id>.json` file each.
+#[derive(Debug, Clone)]
+pub struct GameStore {
+    dir: PathBuf,
+}
+
+impl GameStore {
+    /// Open (creating if needed) the store in `dir`. Only meant for
+    /// startup, as the directory is created with blocking I/O.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, BotError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Save `game`, replacing any earlier state. The file is written
+    /// under a temporary name first, so a crash never leaves a torn file.
+    pub async fn save(&self, game: &PersistedGame) -> Result<(), BotError> {
+        let path = self.path(&game.game_id)?;
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(game)
+            .map_err(|e| BotError::State(format!("Encoding {}: {}", game.game_id, e)))?;
+        fs::write(&tmp, json).await?;
+        fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    /// Saved state of `game_id`, if any.
+    pub async fn load(&self, game_id: &str) -> Result<Option<PersistedGame>, BotError> {
+        let path = self.path(game_id)?;
+        match fs::read_to_string(&path).await {
+            Ok(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| BotError::State(format!("Reading {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every saved game, in no particular order. Unreadable files are
+    /// skipped rather than failing the whole load.
+    pub async fn load_all(&self) -> Result<Vec<PersistedGame>, BotError> {
+        let mut games = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let game = fs::read_to_string(&path)
+                .await
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            match game {
+                Some(game) => games.push(game),
+                None => warn!("Skipping unreadable game state {}", path.display()),
+            }
+        }
+        Ok(games)
+    }
+
+    /// Forget `game_id`, e.g. once it has ended.
+    pub async fn remove(&self, game_id: &str) -> Result<(), BotError> {
+        match fs::remove_file(self.path(game_id)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// File holding `game_id`. Lichess ids are alphanumeric; anything else
+    /// is refused so an id can't point outside the store.
+    fn path(&self, game_id: &str) -> Result<PathBuf, BotError> {
+        if game_id.is_empty() || !game_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(BotError::Config(format!("Invalid game id {:?}", game_id)));
+        }
+        Ok(self.dir.join(format!("{}.json", game_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("stonksfish_games_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let store = GameStore::new(&dir).unwrap();
+
+        let game = PersistedGame::new("abcd1234", "Black", 17);
+        store.save(&game).await.unwrap();
+        assert_eq!(store.load("abcd1234").await.unwrap(), Some(game.clone()));
+
+        let later = PersistedGame::new("abcd1234", "Black", 19);
+        store.save(&later).await.unwrap();
+        store.save(&PersistedGame::new("efgh5678", "White", 0)).await.unwrap();
+        let mut all = store.load_all().await.unwrap();
+        all.sort_by(|a, b| a.game_id.cmp(&b.game_id));
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0], later);
+
+        store.remove("abcd1234").await.unwrap();
+        store.remove("abcd1234").await.unwrap();
+        assert_eq!(store.load("abcd1234").await.unwrap(), None);
+        assert!(store.load("../escape").await.is_err());
+
+        // A corrupt file is reported as bad state, not as a harvest error
+        std::fs::write(dir.join("efgh5678.json"), "{").unwrap();
+        assert!(matches!(store.load("efgh5678").await, Err(BotError::State(_))));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,241 @@
+//! Arena and Swiss tournament participation.
+//!
+//! Tournament pairings need no special handling once the bot has joined:
+//! Lichess starts each game with an ordinary `gameStart` event. What this
+//! module adds is entering the configured tournaments at startup and
+//! withdrawing from them on shutdown, so a stopped bot isn't paired into
+//! games nobody will play.
+//!
+//! See https://lichess.org/api#tag/Arena-tournaments and
+//! https://lichess.org/api#tag/Swiss-tournaments
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::error::BotError;
+
+/// Lichess API root the tournament endpoints live under.
+const LICHESS_API: &str = "https://lichess.org/api";
+
+/// Kind of tournament, which decides the endpoints used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TournamentKind {
+    Arena,
+    Swiss,
+}
+
+/// A tournament to take part in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tournament {
+    pub kind: TournamentKind,
+    pub id: String,
+}
+
+impl Tournament {
+    /// Parse `swiss:<id>`, `arena:<id>` or a bare `<id>` (an arena).
+    /// Lichess ids are alphanumeric; anything else is rejected.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        let (kind, id) = match spec.split_once(':') {
+            Some((kind, id)) if kind.eq_ignore_ascii_case("swiss") => (TournamentKind::Swiss, id),
+            Some((kind, id)) if kind.eq_ignore_ascii_case("arena") => (TournamentKind::Arena, id),
+            Some(_) => return None,
+            None => (TournamentKind::Arena, spec),
+        };
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            kind,
+            id: id.to_string(),
+        })
+    }
+
+    /// Endpoint for `action` (`join` or `withdraw`) on this tournament.
+    fn url(&self, base: &str, action: &str) -> String {
+        let path = match self.kind {
+            TournamentKind::Arena => "tournament",
+            TournamentKind::Swiss => "swiss",
+        };
+        format!("{}/{}/{}/{}", base, path, self.id, action)
+    }
+}
+
+impl fmt::Display for Tournament {
+    /// The form [`Tournament::parse`] reads back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TournamentKind::Arena => write!(f, "arena:{}", self.id),
+            TournamentKind::Swiss => write!(f, "swiss:{}", self.id),
+        }
+    }
+}
+
+/// The configured tournaments and which of them the bot is currently in.
+///
+/// Once [`Tournaments::leave_all`] has been called, joining is refused
+/// for good, so a bot that was told to stop never re-enters.
+#[derive(Debug)]
+pub struct Tournaments {
+    token: String,
+    base_url: String,
+    configured: Vec<Tournament>,
+    joined: Mutex<HashSet<Tournament>>,
+    leaving: AtomicBool,
+}
+
+impl Tournaments {
+    pub fn new(token: &str, configured: Vec<Tournament>) -> Self {
+        Self {
+            token: token.to_string(),
+            base_url: LICHESS_API.to_string(),
+            configured,
+            joined: Mutex::new(HashSet::new()),
+            leaving: AtomicBool::new(false),
+        }
+    }
+
+    /// Send requests to `base_url` instead of the Lichess API.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Number of tournaments the bot is currently in.
+    pub fn joined_count(&self) -> usize {
+        self.joined.lock().unwrap().len()
+    }
+
+    /// Join every configured tournament not joined yet. Failures are
+    /// logged and skipped. Returns how many were joined by this call.
+    pub async fn join_all(&self) -> usize {
+        let mut newly_joined = 0;
+        for tournament in &self.configured {
+            if self.leaving.load(Ordering::SeqCst) {
+                break;
+            }
+            if self.joined.lock().unwrap().contains(tournament) {
+                continue;
+            }
+            match self.post(&tournament.url(&self.base_url, "join")).await {
+                Ok(()) => {
+                    info!(tournament = %tournament, "Joined tournament");
+                    self.joined.lock().unwrap().insert(tournament.clone());
+                    newly_joined += 1;
+                }
+                Err(e) => warn!(tournament = %tournament, "Failed to join tournament: {}", e),
+            }
+        }
+        // A leave_all racing with the loop above must not leave us paired
+        if self.leaving.load(Ordering::SeqCst) {
+            self.withdraw_joined().await;
+        }
+        newly_joined
+    }
+
+    /// Withdraw from every joined tournament and refuse to join again.
+    pub async fn leave_all(&self) {
+        self.leaving.store(true, Ordering::SeqCst);
+        self.withdraw_joined().await;
+    }
+
+    async fn withdraw_joined(&self) {
+        let joined: Vec<Tournament> = self.joined.lock().unwrap().drain().collect();
+        for tournament in joined {
+            match self.post(&tournament.url(&self.base_url, "withdraw")).await {
+                Ok(()) => info!(tournament = %tournament, "Withdrew from tournament"),
+                Err(e) => warn!(tournament = %tournament, "Failed to withdraw from tournament: {}", e),
+            }
+        }
+    }
+
+    async fn post(&self, url: &str) -> Result<(), BotError> {
+        reqwest::Client::new()
+            .post(url)
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| BotError::Request(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_tournament() {
+        let arena = Tournament::parse("abcd1234").unwrap();
+        assert_eq!(arena.kind, TournamentKind::Arena);
+        assert_eq!(Tournament::parse(" arena:abcd1234 "), Some(arena.clone()));
+        assert_eq!(Tournament::parse(&arena.to_string()), Some(arena));
+
+        let swiss = Tournament::parse("Swiss:XyZ98765").unwrap();
+        assert_eq!(swiss.kind, TournamentKind::Swiss);
+        assert_eq!(swiss.id, "XyZ98765");
+        assert_eq!(swiss.to_string(), "swiss:XyZ98765");
+
+        assert_eq!(Tournament::parse(""), None);
+        assert_eq!(Tournament::parse("team:abc"), None);
+        assert_eq!(Tournament::parse("arena:../x"), None);
+    }
+
+    /// Fake Lichess answering every request with 200, recording the
+    /// request lines.
+    async fn fake_lichess() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 2048];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]);
+                let line = request.lines().next().unwrap_or_default().to_string();
+                seen.lock().unwrap().push(line);
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+                socket.write_all(response.as_bytes()).await.ok();
+            }
+        });
+        (base, requests)
+    }
+
+    #[tokio::test]
+    async fn test_join_then_leave_without_rejoining() {
+        let (base, requests) = fake_lichess().await;
+        let configured = vec![
+            Tournament::parse("arena1").unwrap(),
+            Tournament::parse("swiss:swiss1").unwrap(),
+        ];
+        let tournaments = Tournaments::new("token", configured).with_base_url(&base);
+
+        assert_eq!(tournaments.join_all().await, 2);
+        assert_eq!(tournaments.join_all().await, 0);
+        assert_eq!(tournaments.joined_count(), 2);
+
+        tournaments.leave_all().await;
+        assert_eq!(tournaments.joined_count(), 0);
+        assert_eq!(tournaments.join_all().await, 0);
+
+        let mut requests = requests.lock().unwrap().clone();
+        requests[2..].sort();
+        assert_eq!(
+            requests,
+            vec![
+                "POST /api/tournament/arena1/join HTTP/1.1",
+                "POST /api/swiss/swiss1/join HTTP/1.1",
+                "POST /api/swiss/swiss1/withdraw HTTP/1.1",
+                "POST /api/tournament/arena1/withdraw HTTP/1.1",
+            ]
+        );
+    }
+}
@@ -4,18 +4,23 @@ use licheszter::{
     client::Licheszter,
     models::board::{BoardState, Challenger, Event},
 };
-use log::{debug, info, warn};
+use tracing::{debug, info, warn};
 use std::str::FromStr;
 use stonksfish::engine::player::{Bot, Player};
+use stonksfish::engine::search::MAX_DEPTH;
 use tokio_stream::StreamExt;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    env_logger::init();
+    stonksfish::util::logging::init();
 
     const DEPTH: u8 = 5;
-    let bot_player = Bot { depth: DEPTH };
+    let bot_player = Bot {
+        depth: DEPTH,
+        max_depth: MAX_DEPTH,
+        ..Bot::default()
+    };
 
     let client =
         Licheszter::new(std::env::var("RUST_BOT_TOKEN").expect("RUST_BOT_TOKEN must be set."));
@@ -10,22 +10,36 @@
 //!     ↕ stdin/stdout
 //! uci::run_uci_loop()
 //!     ↕ function calls
-//! engine::search::find_move()
+//! engine::search::search_with_tt()
 //! engine::evaluation::evaluate_board()
 //! ```
 
-use chess::{Board, ChessMove, Color, MoveGen, Square};
+pub mod moves;
+
+use chess::{Board, ChessMove, MoveGen, Piece, Square};
 use std::io::{self, BufRead, Write};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::engine::search::find_move;
+use crate::engine::search::{
+    clamp_depth, search_until_stopped, search_with_tt, terminal_score, SearchResult, MAX_DEPTH,
+};
+use crate::engine::transposition::{Bound, TranspositionTable};
 use crate::engine::evaluation::simple::evaluate_board;
+use crate::util::lru::LruCache;
+use crate::util::position::{board_from_moves, to_san, MoveError};
 
 /// Engine identity constants.
 const ENGINE_NAME: &str = "Stonksfish";
 const ENGINE_AUTHOR: &str = "Claus Martinsen + Ada Chess AI";
 const DEFAULT_DEPTH: u8 = 5;
-const MAX_DEPTH: u8 = 20;
+
+/// Longest line the `ttpv` command follows through the transposition
+/// table.
+const MAX_TT_PV_LENGTH: usize = 64;
 
 /// Run the UCI protocol loop on stdin/stdout.
 ///
@@ -38,9 +52,7 @@ pub fn run_uci_loop() {
     let mut stdout = stdout.lock();
     let mut reader = stdin.lock();
 
-    let mut board = Board::default();
-    let mut depth = DEFAULT_DEPTH;
-    let mut debug_mode = false;
+    let mut engine = UciEngine::default();
     let mut line = String::new();
 
     loop {
@@ -48,38 +60,134 @@ pub fn run_uci_loop() {
         if reader.read_line(&mut line).is_err() {
             break;
         }
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+        if !engine.handle_command(&line, &mut stdout) {
+            break;
+        }
+    }
+    engine.cancel_ponder();
+}
+
+/// A `go ponder` search running on its own thread.
+///
+/// Nothing is printed while pondering: the GUI either confirms the
+/// predicted move with `ponderhit`, or sends `stop` on a pondermiss and
+/// follows up with a new `position` and `go`.
+struct PonderSearch {
+    board: Board,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<(Option<SearchResult>, TranspositionTable)>,
+}
+
+impl PonderSearch {
+    /// Start searching `board` to `depth`, taking over `tt` until the
+    /// search finishes.
+    fn start(board: Board, depth: u8, mut tt: TranspositionTable) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let result = search_until_stopped(&board, depth, &mut tt, &stop);
+                (result, tt)
+            })
+        };
+        PonderSearch { board, stop, handle }
+    }
+
+    /// Wait for the search to finish, first telling it to stop if
+    /// `interrupt`. Returns its result and the transposition table.
+    fn finish(self, interrupt: bool) -> (Option<SearchResult>, TranspositionTable) {
+        if interrupt {
+            self.stop.store(true, Ordering::Relaxed);
         }
+        self.handle
+            .join()
+            .unwrap_or_else(|_| (None, TranspositionTable::default()))
+    }
+}
 
+/// State of a UCI session: the current position, options and any
+/// running ponder search.
+pub struct UciEngine {
+    board: Board,
+    depth: u8,
+    max_depth: u8,
+    debug_mode: bool,
+    tt: TranspositionTable,
+    ponder: Option<PonderSearch>,
+}
+
+impl Default for UciEngine {
+    fn default() -> Self {
+        UciEngine {
+            board: Board::default(),
+            depth: DEFAULT_DEPTH,
+            max_depth: MAX_DEPTH,
+            debug_mode: false,
+            tt: TranspositionTable::default(),
+            ponder: None,
+        }
+    }
+}
+
+impl UciEngine {
+    /// Whether a `go ponder` search is in progress.
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.is_some()
+    }
+
+    /// Abort a ponder search, if any, without reporting a move.
+    pub fn cancel_ponder(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            self.tt = ponder.finish(true).1;
+        }
+    }
+
+    /// End a ponder search, interrupting it if `interrupt` or otherwise
+    /// letting it complete, and report its move (`0000` if none was found).
+    fn finish_ponder<W: Write>(&mut self, interrupt: bool, out: &mut W) {
+        if let Some(ponder) = self.ponder.take() {
+            let board = ponder.board;
+            let (result, tt) = ponder.finish(interrupt);
+            self.tt = tt;
+            write_search_result(out, &board, result);
+        }
+    }
+
+    /// Process one line of input, writing any response to `out`. Returns
+    /// `false` once the session should end (`quit`).
+    pub fn handle_command<W: Write>(&mut self, line: &str, out: &mut W) -> bool {
+        let trimmed = line.trim();
         let parts: Vec<&str> = trimmed.split_whitespace().collect();
         if parts.is_empty() {
-            continue;
+            return true;
         }
 
         match parts[0] {
             "uci" => {
-                writeln!(stdout, "id name {}", ENGINE_NAME).ok();
-                writeln!(stdout, "id author {}", ENGINE_AUTHOR).ok();
-                writeln!(stdout, "option name Depth type spin default {} min 1 max {}", DEFAULT_DEPTH, MAX_DEPTH).ok();
-                writeln!(stdout, "option name CrewAI type check default false").ok();
-                writeln!(stdout, "uciok").ok();
-                stdout.flush().ok();
+                writeln!(out, "id name {}", ENGINE_NAME).ok();
+                writeln!(out, "id author {}", ENGINE_AUTHOR).ok();
+                writeln!(out, "option name Depth type spin default {} min 1 max {}", DEFAULT_DEPTH, MAX_DEPTH).ok();
+                writeln!(out, "option name MaxDepth type spin default {} min 1 max {}", MAX_DEPTH, MAX_DEPTH).ok();
+                writeln!(out, "option name Ponder type check default false").ok();
+                writeln!(out, "option name CrewAI type check default false").ok();
+                writeln!(out, "uciok").ok();
+                out.flush().ok();
             }
 
             "isready" => {
-                writeln!(stdout, "readyok").ok();
-                stdout.flush().ok();
+                writeln!(out, "readyok").ok();
+                out.flush().ok();
             }
 
             "ucinewgame" => {
-                board = Board::default();
+                self.cancel_ponder();
+                self.board = Board::default();
+                self.tt.clear();
             }
 
             "debug" => {
                 if parts.len() > 1 {
-                    debug_mode = parts[1] == "on";
+                    self.debug_mode = parts[1] == "on";
                 }
             }
 
@@ -89,12 +197,20 @@ pub fn run_uci_loop() {
                     match option.name.to_lowercase().as_str() {
                         "depth" => {
                             if let Ok(d) = option.value.parse::<u8>() {
-                                depth = d.clamp(1, MAX_DEPTH);
+                                self.depth = clamp_depth(d, self.max_depth);
                             }
                         }
+                        "maxdepth" => {
+                            if let Ok(d) = option.value.parse::<u8>() {
+                                self.max_depth = clamp_depth(d, MAX_DEPTH);
+                                self.depth = clamp_depth(self.depth, self.max_depth);
+                            }
+                        }
+                        // Pondering is driven by `go ponder`; nothing to configure
+                        "ponder" => {}
                         _ => {
-                            if debug_mode {
-                                writeln!(stdout, "info string unknown option: {}", option.name).ok();
+                            if self.debug_mode {
+                                writeln!(out, "info string unknown option: {}", option.name).ok();
                             }
                         }
                     }
@@ -102,61 +218,129 @@ pub fn run_uci_loop() {
             }
 
             "position" => {
-                board = parse_position(&parts);
-                if debug_mode {
-                    writeln!(stdout, "info string position set: {}", board).ok();
-                    stdout.flush().ok();
+                self.cancel_ponder();
+                self.board = parse_position(&parts);
+                if self.debug_mode {
+                    writeln!(out, "info string position set: {}", self.board).ok();
+                    out.flush().ok();
                 }
             }
 
             "go" => {
-                let go_depth = parse_go_depth(&parts).unwrap_or(depth);
+                self.cancel_ponder();
+                let go_depth = resolve_go_depth(&parts, self.depth, self.max_depth);
 
-                // Run the search
-                let best_move = find_move(&board, go_depth);
-                let eval = evaluate_board(&board);
+                if parts.contains(&"ponder") {
+                    let tt = std::mem::replace(&mut self.tt, TranspositionTable::new(1));
+                    self.ponder = Some(PonderSearch::start(self.board, go_depth, tt));
+                    return true;
+                }
 
-                // Send info about the search
-                writeln!(stdout, "info depth {} score cp {}", go_depth, eval).ok();
+                // Run the search
+                let result = search_with_tt(&self.board, go_depth, &mut self.tt);
+                write_search_result(out, &self.board, Some(result));
+            }
 
-                // Send the best move
-                let move_str = format_move(best_move);
-                writeln!(stdout, "bestmove {}", move_str).ok();
-                stdout.flush().ok();
+            "ponderhit" => {
+                // The predicted move was played: the ponder search becomes
+                // the real one
+                self.finish_ponder(false, out);
             }
 
             "stop" => {
-                // We don't have async search yet, so stop is a no-op
+                // Only a ponder search runs in the background; `go` without
+                // `ponder` has already answered
+                self.finish_ponder(true, out);
             }
 
             "quit" => {
-                break;
+                self.cancel_ponder();
+                return false;
             }
 
             "eval" => {
                 // Non-standard: evaluate current position
-                let eval = evaluate_board(&board);
-                let piece_count = count_pieces(&board);
-                writeln!(stdout, "info string eval={} pieces={} side={:?}", eval, piece_count, board.side_to_move()).ok();
-                stdout.flush().ok();
+                let eval = evaluate_board(&self.board);
+                let piece_count = count_pieces(&self.board);
+                writeln!(out, "info string eval={} pieces={} side={:?}", eval, piece_count, self.board.side_to_move()).ok();
+                out.flush().ok();
+            }
+
+            "ttpv" => {
+                // Non-standard: the line stored in the transposition table,
+                // without searching
+                let line = self.tt.principal_variation(&self.board, MAX_TT_PV_LENGTH);
+                let mut board = self.board;
+                for (ply, (chess_move, entry)) in line.iter().enumerate() {
+                    let bound = match entry.bound {
+                        Bound::Exact => "exact",
+                        Bound::Lower => "lower",
+                        Bound::Upper => "upper",
+                    };
+                    writeln!(
+                        out,
+                        "info string ttpv ply {} move {} san {} depth {} score cp {} bound {}",
+                        ply + 1,
+                        format_move(*chess_move),
+                        to_san(&board, *chess_move),
+                        entry.depth,
+                        entry.score,
+                        bound
+                    )
+                    .ok();
+                    board = board.make_move_new(*chess_move);
+                }
+                let mut pv = String::from("info string ttpv pv");
+                for (chess_move, _) in &line {
+                    pv.push(' ');
+                    pv.push_str(&format_move(*chess_move));
+                }
+                writeln!(out, "{}", pv).ok();
+                out.flush().ok();
             }
 
             "perft" => {
                 // Non-standard: run perft for move generation testing
                 let perft_depth = parts.get(1).and_then(|s| s.parse::<u8>().ok()).unwrap_or(1);
-                let count = perft(&board, perft_depth);
-                writeln!(stdout, "info string perft({})={}", perft_depth, count).ok();
-                stdout.flush().ok();
+                let count = perft(&self.board, perft_depth);
+                writeln!(out, "info string perft({})={}", perft_depth, count).ok();
+                out.flush().ok();
             }
 
             _ => {
-                if debug_mode {
-                    writeln!(stdout, "info string unknown command: {}", trimmed).ok();
-                    stdout.flush().ok();
+                if self.debug_mode {
+                    writeln!(out, "info string unknown command: {}", trimmed).ok();
+                    out.flush().ok();
                 }
             }
         }
+        true
+    }
+}
+
+/// Report a finished search on `board`: an `info` line and `bestmove`, or
+/// `bestmove 0000` if the search found no move.
+fn write_search_result<W: Write>(out: &mut W, board: &Board, result: Option<SearchResult>) {
+    match result {
+        Some(result) => {
+            let eval = evaluate_board(board);
+
+            // Send info about the search
+            writeln!(
+                out,
+                "info depth {} seldepth {} nodes {} hashfull {} score cp {}",
+                result.depth, result.seldepth, result.nodes, result.hashfull, eval
+            )
+            .ok();
+
+            // Send the best move
+            writeln!(out, "bestmove {}", format_move(result.best_move)).ok();
+        }
+        None => {
+            writeln!(out, "bestmove 0000").ok();
+        }
     }
+    out.flush().ok();
 }
 
 /// Parse a UCI `position` command.
@@ -164,69 +348,35 @@ pub fn run_uci_loop() {
 /// Supports:
 /// - `position startpos [moves e2e4 e7e5 ...]`
 /// - `position fen <fen_string> [moves e2e4 e7e5 ...]`
+///
+/// Moves may be given in UCI or SAN notation (e.g. `moves e4 e5 Nf3`).
+///
+/// An invalid FEN falls back to the starting position, and moves are applied
+/// up to the first illegal one.
 fn parse_position(parts: &[&str]) -> Board {
     if parts.len() < 2 {
         return Board::default();
     }
 
-    let (mut board, moves_start) = if parts[1] == "startpos" {
-        let idx = parts.iter().position(|&p| p == "moves").unwrap_or(parts.len());
-        (Board::default(), idx + 1)
+    let moves_idx = parts.iter().position(|&p| p == "moves").unwrap_or(parts.len());
+    let moves = parts.get(moves_idx + 1..).unwrap_or(&[]);
+
+    let fen_str = if parts[1] == "startpos" {
+        None
     } else if parts[1] == "fen" {
         // Collect FEN components (up to 6 parts after "fen")
-        let moves_idx = parts.iter().position(|&p| p == "moves").unwrap_or(parts.len());
-        let fen_parts: Vec<&str> = parts[2..moves_idx].to_vec();
-        let fen_str = fen_parts.join(" ");
-        let board = Board::from_str(&fen_str).unwrap_or_default();
-        (board, moves_idx + 1)
+        Some(parts[2..moves_idx].join(" "))
     } else {
         return Board::default();
     };
 
-    // Apply moves
-    if moves_start < parts.len() {
-        for move_str in &parts[moves_start..] {
-            if let Some(chess_move) = parse_uci_move(&board, move_str) {
-                let mut new_board = Board::default();
-                board.make_move(chess_move, &mut new_board);
-                board = new_board;
-            }
+    let start_fen = fen_str.filter(|fen| Board::from_str(fen).is_ok());
+    match board_from_moves(start_fen.as_deref(), moves) {
+        Ok(board) => board,
+        Err(MoveError::IllegalMove { index, .. }) => {
+            board_from_moves(start_fen.as_deref(), &moves[..index]).unwrap_or_default()
         }
-    }
-
-    board
-}
-
-/// Parse a UCI move string (e.g., "e2e4", "e7e8q") into a ChessMove.
-fn parse_uci_move(board: &Board, move_str: &str) -> Option<ChessMove> {
-    let move_str = move_str.trim();
-    if move_str.len() < 4 {
-        return None;
-    }
-
-    let from = Square::from_str(&move_str[0..2]).ok()?;
-    let to = Square::from_str(&move_str[2..4]).ok()?;
-
-    // Check for promotion piece
-    let promotion = if move_str.len() > 4 {
-        match move_str.as_bytes()[4] {
-            b'q' | b'Q' => Some(chess::Piece::Queen),
-            b'r' | b'R' => Some(chess::Piece::Rook),
-            b'b' | b'B' => Some(chess::Piece::Bishop),
-            b'n' | b'N' => Some(chess::Piece::Knight),
-            _ => None,
-        }
-    } else {
-        None
-    };
-
-    let chess_move = ChessMove::new(from, to, promotion);
-
-    // Verify the move is legal
-    if board.legal(chess_move) {
-        Some(chess_move)
-    } else {
-        None
+        Err(MoveError::InvalidFen(_)) => Board::default(),
     }
 }
 
@@ -245,6 +395,12 @@ pub fn format_move(m: ChessMove) -> String {
     format!("{}{}{}", from, to, promo)
 }
 
+/// Search depth for a `go` command: the requested depth, or `default`,
+/// clamped to `max_depth`.
+fn resolve_go_depth(parts: &[&str], default: u8, max_depth: u8) -> u8 {
+    clamp_depth(parse_go_depth(parts).unwrap_or(default), max_depth)
+}
+
 /// Parse depth from `go` command arguments.
 ///
 /// Supports: `go depth 8`, `go movetime 5000` (returns None for time-based).
@@ -287,9 +443,14 @@ fn perft(board: &Board, depth: u8) -> u64 {
         return 1;
     }
 
-    let mut count = 0u64;
     let movegen = MoveGen::new_legal(board);
-    let mut new_board = Board::default();
+    if depth == 1 {
+        // Bulk count: the leaves don't need to be played out
+        return movegen.len() as u64;
+    }
+
+    let mut count = 0u64;
+    let mut new_board = *board;
 
     for chess_move in movegen {
         board.make_move(chess_move, &mut new_board);
@@ -299,6 +460,51 @@ fn perft(board: &Board, depth: u8) -> u64 {
     count
 }
 
+/// Perft split by root move ("divide"), in move generation order, for
+/// finding which subtree a move generation bug hides in.
+pub fn perft_divide(board: &Board, depth: u8) -> Vec<(ChessMove, u64)> {
+    MoveGen::new_legal(board)
+        .map(|m| (m, perft(&board.make_move_new(m), depth.saturating_sub(1))))
+        .collect()
+}
+
+/// Positions searched by [`bench`]: the opening, a quiet middlegame and
+/// a pawn endgame.
+const BENCH_FENS: [&str; 3] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1 w - - 0 7",
+    "8/5pk1/6p1/8/5P2/6P1/6K1/8 w - - 0 1",
+];
+
+/// Nodes searched by [`bench`] and how long it took.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    /// Nodes per second.
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+/// Search a fixed set of positions to `depth` with fresh tables, to
+/// compare engine speed between builds.
+pub fn bench(depth: u8) -> BenchResult {
+    let start = Instant::now();
+    let nodes = BENCH_FENS
+        .iter()
+        .filter_map(|fen| Board::from_str(fen).ok())
+        .map(|board| search_with_tt(&board, depth, &mut TranspositionTable::default()).nodes)
+        .sum();
+    BenchResult {
+        nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
 /// Classify the game phase based on piece count.
 pub fn classify_phase(board: &Board) -> &'static str {
     let pieces = count_pieces(board);
@@ -311,48 +517,178 @@ pub fn classify_phase(board: &Board) -> &'static str {
     }
 }
 
+/// Side of the board a king castled to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastleSide {
+    Kingside,
+    Queenside,
+}
+
+/// What a single move changed, beyond its UCI string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionDiff {
+    /// Source square, destination square and the piece that moved (a
+    /// pawn for promotions, the king for castling).
+    pub piece_moved: (Square, Square, Piece),
+    /// Piece taken, including a pawn taken en passant.
+    pub captured: Option<Piece>,
+    /// Whether the side to move after the move is in check, directly or
+    /// by discovery.
+    pub gave_check: bool,
+    pub en_passant: bool,
+    pub castled: Option<CastleSide>,
+    /// Piece a pawn promoted to.
+    pub promoted: Option<Piece>,
+}
+
+/// Describe the move leading from `before` to `after`, or `None` if no
+/// legal move in `before` gives `after`.
+pub fn diff_positions(before: &Board, after: &Board) -> Option<PositionDiff> {
+    let chess_move = MoveGen::new_legal(before).find(|&m| before.make_move_new(m) == *after)?;
+    let (from, to) = (chess_move.get_source(), chess_move.get_dest());
+    let piece = before.piece_on(from)?;
+    let en_passant =
+        piece == Piece::Pawn && from.get_file() != to.get_file() && before.piece_on(to).is_none();
+    let file_distance = to.get_file().to_index() as i32 - from.get_file().to_index() as i32;
+    let castled = match (piece, file_distance) {
+        (Piece::King, 2) => Some(CastleSide::Kingside),
+        (Piece::King, -2) => Some(CastleSide::Queenside),
+        _ => None,
+    };
+
+    Some(PositionDiff {
+        piece_moved: (from, to, piece),
+        captured: if en_passant { Some(Piece::Pawn) } else { before.piece_on(to) },
+        gave_check: after.checkers().popcnt() > 0,
+        en_passant,
+        castled,
+        promoted: chess_move.get_promotion(),
+    })
+}
+
 /// Get evaluation and all legal moves with their evaluations.
 ///
 /// This is the main interface for crewai-rust agents to use Stonksfish
-/// as a tool. Returns structured data about the position.
+/// as a tool. Returns structured data about the position. Moves are
+/// scored statically; see [`analyze_position_deep`] for searched scores.
 pub fn analyze_position(board: &Board, depth: u8) -> PositionAnalysis {
+    analyze_position_top_k(board, depth, None)
+}
+
+/// Like [`analyze_position`], but only the best `top_k` moves (all of them
+/// if `None`) are returned in `legal_moves`.
+///
+/// Every move is ranked by a cheap static evaluation first; only the top
+/// candidates are then turned into full `MoveEvaluation`s. Ties keep move
+/// generation order, so the result is a prefix of the uncapped analysis.
+/// `depth` is reserved for per-move search and currently unused.
+pub fn analyze_position_top_k(board: &Board, _depth: u8, top_k: Option<usize>) -> PositionAnalysis {
     let eval = evaluate_board(board);
-    let phase = classify_phase(board);
-    let piece_count = count_pieces(board);
 
-    let mut legal_moves = Vec::new();
     let movegen = MoveGen::new_legal(board);
-    let mut new_board = Board::default();
-
-    for chess_move in movegen {
+    let mut ranked: Vec<(i32, usize, ChessMove)> = Vec::with_capacity(movegen.len());
+    let mut new_board = *board;
+    for (index, chess_move) in movegen.enumerate() {
         board.make_move(chess_move, &mut new_board);
-        let move_eval = -evaluate_board(&new_board);
-        legal_moves.push(MoveEvaluation {
-            uci: format_move(chess_move),
-            eval_cp: move_eval,
-            is_capture: board.piece_on(chess_move.get_dest()).is_some(),
-            is_check: new_board.checkers().popcnt() > 0,
-        });
+        ranked.push((-evaluate_board(&new_board), index, chess_move));
+    }
+    let no_moves = ranked.is_empty();
+
+    // Best moves first, ties in generation order
+    let k = top_k.unwrap_or(ranked.len()).min(ranked.len());
+    if k < ranked.len() {
+        if k > 0 {
+            ranked.select_nth_unstable_by_key(k - 1, rank_key);
+        }
+        ranked.truncate(k);
     }
+    ranked.sort_unstable_by_key(rank_key);
+
+    build_analysis(board, eval, ranked, no_moves)
+}
+
+/// Like [`analyze_position`], but every score comes from a real search:
+/// the position is searched to `depth` for `eval_cp`, and each legal
+/// move to `depth - 1` (at least 1) for its minimax score.
+///
+/// Much slower than the static ranking, but accurate enough for
+/// post-game annotation, where a move that loses material a few plies
+/// later must not look best.
+pub fn analyze_position_deep(board: &Board, depth: u8) -> PositionAnalysis {
+    let depth = depth.max(1);
+    let mut tt = TranspositionTable::default();
+    let movegen = MoveGen::new_legal(board);
+    let no_moves = movegen.len() == 0;
+    let eval = match no_moves {
+        true => evaluate_board(board),
+        false => search_with_tt(board, depth, &mut tt).score,
+    };
+
+    let mut ranked: Vec<(i32, usize, ChessMove)> = movegen
+        .enumerate()
+        .map(|(index, chess_move)| {
+            let after = board.make_move_new(chess_move);
+            let score = match terminal_score(&after, 1) {
+                Some(score) => -score,
+                None => -search_with_tt(&after, (depth - 1).max(1), &mut tt).score,
+            };
+            (score, index, chess_move)
+        })
+        .collect();
+    ranked.sort_unstable_by_key(rank_key);
 
-    // Sort by evaluation (best moves first)
-    legal_moves.sort_by(|a, b| b.eval_cp.cmp(&a.eval_cp));
+    build_analysis(board, eval, ranked, no_moves)
+}
+
+/// Sort key putting the best moves first, ties in generation order.
+fn rank_key(&(eval, index, _): &(i32, usize, ChessMove)) -> (std::cmp::Reverse<i32>, usize) {
+    (std::cmp::Reverse(eval), index)
+}
 
-    PositionAnalysis {
+/// Assemble the analysis of `board` from its evaluation and the moves to
+/// report with their scores, already sorted best-first.
+fn build_analysis(
+    board: &Board,
+    eval: i32,
+    ranked: Vec<(i32, usize, ChessMove)>,
+    no_moves: bool,
+) -> PositionAnalysis {
+    let phase = classify_phase(board);
+    let piece_count = count_pieces(board);
+    let mut new_board = *board;
+    let legal_moves = ranked
+        .into_iter()
+        .map(|(move_eval, _, chess_move)| {
+            board.make_move(chess_move, &mut new_board);
+            MoveEvaluation {
+                uci: format_move(chess_move),
+                eval_cp: move_eval,
+                is_capture: board.piece_on(chess_move.get_dest()).is_some(),
+                is_check: new_board.checkers().popcnt() > 0,
+                delta_from_best: 0,
+            }
+        })
+        .collect();
+
+    let in_check = board.checkers().popcnt() > 0;
+
+    let mut analysis = PositionAnalysis {
         fen: format!("{}", board),
         eval_cp: eval,
         phase: phase.to_string(),
         piece_count,
         side_to_move: format!("{:?}", board.side_to_move()),
         legal_moves,
-        is_check: board.checkers().popcnt() > 0,
-        is_checkmate: MoveGen::new_legal(board).len() == 0 && board.checkers().popcnt() > 0,
-        is_stalemate: MoveGen::new_legal(board).len() == 0 && board.checkers().popcnt() == 0,
-    }
+        is_check: in_check,
+        is_checkmate: no_moves && in_check,
+        is_stalemate: no_moves && !in_check,
+    };
+    analysis.annotate_deltas();
+    analysis
 }
 
 /// Result of analyzing a chess position.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PositionAnalysis {
     /// FEN string of the position.
     pub fen: String,
@@ -374,8 +710,134 @@ pub struct PositionAnalysis {
     pub is_stalemate: bool,
 }
 
+impl PositionAnalysis {
+    /// UCI string of the best move found, if any legal move exists.
+    pub fn best_move_uci(&self) -> Option<&str> {
+        self.legal_moves.first().map(|m| m.uci.as_str())
+    }
+
+    /// The best `n` moves (fewer if there aren't that many).
+    pub fn top_n_moves(&self, n: usize) -> &[MoveEvaluation] {
+        &self.legal_moves[..n.min(self.legal_moves.len())]
+    }
+
+    /// Set each move's `delta_from_best` relative to the first (best)
+    /// move. [`analyze_position`] already does this; call it again after
+    /// editing `legal_moves`.
+    pub fn annotate_deltas(&mut self) {
+        if let Some(best) = self.legal_moves.first().map(|m| m.eval_cp) {
+            for m in &mut self.legal_moves {
+                m.delta_from_best = m.eval_cp - best;
+            }
+        }
+    }
+
+    /// Expected score for the side to move, in `[0, 1]`.
+    pub fn win_prob(&self) -> f64 {
+        win_probability(self.eval_cp)
+    }
+
+    /// Serialize the analysis to JSON for crewai-rust agents.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("PositionAnalysis is always serializable")
+    }
+
+    /// Deserialize an analysis previously produced by [`Self::to_json_value`].
+    pub fn from_json(v: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(v.clone())
+    }
+}
+
+/// Convert a centipawn evaluation into an expected score in `[0, 1]`.
+///
+/// Uses the logistic curve Lichess fits to its game database, see
+/// https://lichess.org/page/accuracy
+pub fn win_probability(eval_cp: i32) -> f64 {
+    1.0 / (1.0 + (-0.00368208 * eval_cp as f64).exp())
+}
+
+/// Default number of positions kept by an [`AnalysisCache`].
+pub const DEFAULT_ANALYSIS_CACHE_CAPACITY: usize = 1000;
+
+/// Thread-safe LRU cache of [`PositionAnalysis`] results keyed by FEN and
+/// depth, shared between what-if generation, game management and UCI so a
+/// position is only analysed once.
+#[derive(Debug)]
+pub struct AnalysisCache {
+    entries: Mutex<LruCache<(String, u8), PositionAnalysis>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for AnalysisCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ANALYSIS_CACHE_CAPACITY)
+    }
+}
+
+impl AnalysisCache {
+    /// Create a cache holding at most `capacity` analyses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of lookups answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups that had to run [`analyze_position`].
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of analyses currently cached.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<(String, u8), PositionAnalysis>> {
+        // A panic while holding the lock can't leave the LRU half-updated in
+        // a way that matters for a cache, so recover from poisoning.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// [`analyze_position`] for `fen`, served from `cache` when the same FEN
+/// and depth have been analysed before.
+///
+/// The lock is not held while analysing, so two threads racing on the same
+/// new position may both run the engine; the second result simply
+/// overwrites the first.
+pub fn cached_analyze_position(
+    fen: &str,
+    depth: u8,
+    cache: &AnalysisCache,
+) -> Result<PositionAnalysis, MoveError> {
+    let key = (fen.to_string(), depth);
+    if let Some(analysis) = cache.lock().get(&key) {
+        cache.hits.fetch_add(1, Ordering::Relaxed);
+        return Ok(analysis.clone());
+    }
+
+    let board = Board::from_str(fen).map_err(|_| MoveError::InvalidFen(fen.to_string()))?;
+    cache.misses.fetch_add(1, Ordering::Relaxed);
+    let analysis = analyze_position(&board, depth);
+    cache.lock().put(key, analysis.clone());
+    Ok(analysis)
+}
+
 /// Evaluation of a single move.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MoveEvaluation {
     /// UCI format move string (e.g., "e2e4").
     pub uci: String,
@@ -385,11 +847,17 @@ pub struct MoveEvaluation {
     pub is_capture: bool,
     /// Whether this move gives check.
     pub is_check: bool,
+    /// Centipawns this move scores below the best move: 0 for the best,
+    /// negative for worse ones.
+    #[serde(default)]
+    pub delta_from_best: i32,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::search::{search, MATE_SCORE};
+    use chess::Color;
 
     #[test]
     fn test_parse_position_startpos() {
@@ -405,6 +873,20 @@ mod tests {
         assert_ne!(board, Board::default());
     }
 
+    #[test]
+    fn test_parse_position_stops_at_illegal_move() {
+        let parts = vec!["position", "startpos", "moves", "e2e4", "e2e4", "e7e5"];
+        let expected = parse_position(&["position", "startpos", "moves", "e2e4"]);
+        assert_eq!(parse_position(&parts), expected);
+    }
+
+    #[test]
+    fn test_parse_position_san_moves() {
+        let san = parse_position(&["position", "startpos", "moves", "e4", "e5", "Nf3"]);
+        let uci = parse_position(&["position", "startpos", "moves", "e2e4", "e7e5", "g1f3"]);
+        assert_eq!(san, uci);
+    }
+
     #[test]
     fn test_parse_position_fen() {
         let parts = vec!["position", "fen", "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR", "b", "KQkq", "e3", "0", "1"];
@@ -445,12 +927,130 @@ mod tests {
         assert!(!analysis.is_stalemate);
     }
 
+    #[test]
+    fn test_move_deltas_from_best() {
+        let mut analysis = analyze_position(&Board::default(), 1);
+        assert_eq!(analysis.legal_moves[0].delta_from_best, 0);
+        assert!(analysis.legal_moves[19].delta_from_best < 0);
+        assert!(analysis
+            .legal_moves
+            .windows(2)
+            .all(|w| w[0].delta_from_best >= w[1].delta_from_best));
+
+        let top = analysis.top_n_moves(3);
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].uci, analysis.best_move_uci().unwrap());
+        assert_eq!(analysis.top_n_moves(50).len(), 20);
+
+        // Dropping the best move re-bases the deltas
+        analysis.legal_moves.remove(0);
+        analysis.annotate_deltas();
+        assert_eq!(analysis.legal_moves[0].delta_from_best, 0);
+    }
+
+    #[test]
+    fn test_analyze_position_top_k() {
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3")
+                .unwrap();
+        let full = analyze_position(&board, 1);
+        let top = analyze_position_top_k(&board, 1, Some(3));
+        assert_eq!(top.legal_moves.len(), 3);
+        for (a, b) in top.legal_moves.iter().zip(&full.legal_moves) {
+            assert_eq!(a.uci, b.uci);
+            assert_eq!(a.eval_cp, b.eval_cp);
+        }
+        assert!(top.legal_moves[0].eval_cp >= top.legal_moves[2].eval_cp);
+
+        // A cap larger than the move count returns everything; zero
+        // returns nothing but still classifies the position.
+        assert_eq!(analyze_position_top_k(&board, 1, Some(100)).legal_moves.len(), full.legal_moves.len());
+        let none = analyze_position_top_k(&board, 1, Some(0));
+        assert!(none.legal_moves.is_empty());
+        assert!(!none.is_checkmate && !none.is_stalemate);
+    }
+
+    #[test]
+    fn test_analyze_position_deep_finds_tactics() {
+        // Nf7+ forks king and queen; statically it's just a knight move
+        let board = Board::from_str("3q3k/6pp/8/4N3/8/8/6PP/6K1 w - - 0 1").unwrap();
+        let fork = "e5f7";
+        assert_ne!(analyze_position(&board, 4).best_move_uci(), Some(fork));
+        let deep = analyze_position_deep(&board, 4);
+        assert_eq!(deep.best_move_uci(), Some(fork));
+        assert!(deep.eval_cp > 0);
+        assert_eq!(deep.legal_moves.len(), analyze_position(&board, 4).legal_moves.len());
+
+        // Back-rank mate scores as mate in one
+        let mate = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let deep = analyze_position_deep(&mate, 2);
+        assert_eq!(deep.best_move_uci(), Some("a1a8"));
+        assert_eq!(deep.legal_moves[0].eval_cp, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn test_position_analysis_json_round_trip() {
+        let analysis = analyze_position(&Board::default(), 1);
+        let json = analysis.to_json_value();
+        assert_eq!(json["legal_moves"].as_array().unwrap().len(), 20);
+
+        let restored = PositionAnalysis::from_json(&json).unwrap();
+        assert_eq!(restored.fen, analysis.fen);
+        assert_eq!(restored.eval_cp, analysis.eval_cp);
+        assert_eq!(restored.legal_moves.len(), 20);
+        for (a, b) in restored.legal_moves.iter().zip(&analysis.legal_moves) {
+            assert_eq!(a.uci, b.uci);
+            assert_eq!(a.eval_cp, b.eval_cp);
+        }
+
+        assert!(PositionAnalysis::from_json(&serde_json::json!({ "fen": 1 })).is_err());
+    }
+
+    #[test]
+    fn test_cached_analyze_position_runs_engine_once() {
+        let cache = AnalysisCache::default();
+        let fen = Board::default().to_string();
+
+        let first = cached_analyze_position(&fen, 1, &cache).unwrap();
+        let second = cached_analyze_position(&fen, 1, &cache).unwrap();
+        assert_eq!(cache.misses(), 1);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(first.fen, second.fen);
+        assert_eq!(first.legal_moves.len(), second.legal_moves.len());
+
+        // A different depth is a separate entry.
+        cached_analyze_position(&fen, 2, &cache).unwrap();
+        assert_eq!(cache.misses(), 2);
+
+        assert!(cached_analyze_position("not a fen", 1, &cache).is_err());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_win_probability() {
+        assert!((win_probability(0) - 0.5).abs() < 1e-9);
+        assert!(win_probability(300) > 0.7);
+        assert!((win_probability(-300) + win_probability(300) - 1.0).abs() < 1e-9);
+    }
+
     #[test]
     fn test_classify_phase() {
         let board = Board::default();
         assert_eq!(classify_phase(&board), "opening");
     }
 
+    #[test]
+    fn test_go_depth_clamped_to_max_depth() {
+        let parts = vec!["go", "depth", "40"];
+        assert_eq!(resolve_go_depth(&parts, DEFAULT_DEPTH, MAX_DEPTH), MAX_DEPTH);
+        assert_eq!(resolve_go_depth(&parts, DEFAULT_DEPTH, 8), 8);
+
+        let parts = vec!["go", "movetime", "5000"];
+        assert_eq!(resolve_go_depth(&parts, 12, 8), 8);
+        assert_eq!(resolve_go_depth(&parts, 3, 8), 3);
+    }
+
     #[test]
     fn test_parse_go_depth() {
         let parts = vec!["go", "depth", "8"];
@@ -465,6 +1065,90 @@ mod tests {
         let board = Board::default();
         assert_eq!(perft(&board, 1), 20);
         assert_eq!(perft(&board, 2), 400);
+        assert_eq!(perft(&board, 3), 8902);
+    }
+
+    /// Feed `commands` to `engine`, returning everything it printed.
+    fn run_commands(engine: &mut UciEngine, commands: &[&str]) -> String {
+        let mut out = Vec::new();
+        for command in commands {
+            assert!(engine.handle_command(command, &mut out));
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn best_move(output: &str) -> Option<&str> {
+        output.lines().find_map(|line| line.strip_prefix("bestmove "))
+    }
+
+    #[test]
+    fn test_ttpv_is_legal_line() {
+        let mut engine = UciEngine::default();
+        let output = run_commands(&mut engine, &["ttpv"]);
+        assert_eq!(output, "info string ttpv pv\n");
+
+        let output = run_commands(
+            &mut engine,
+            &["position startpos moves e2e4 e7e5", "go depth 4", "ttpv"],
+        );
+        let pv = output
+            .lines()
+            .find_map(|line| line.strip_prefix("info string ttpv pv"))
+            .unwrap();
+        let moves: Vec<&str> = pv.split_whitespace().collect();
+        assert!(!moves.is_empty());
+        assert_eq!(Some(moves[0]), best_move(&output));
+        assert!(board_from_moves(Some(&engine.board.to_string()), &moves).is_ok());
+        assert_eq!(output.matches("info string ttpv ply").count(), moves.len());
+    }
+
+    #[test]
+    fn test_pondermiss_restarts_search() {
+        let mut engine = UciEngine::default();
+        // Ponder on the predicted reply 1...e5, deep enough to still be
+        // running when the GUI stops it
+        let output = run_commands(
+            &mut engine,
+            &["position startpos moves e2e4 e7e5", "go ponder depth 20"],
+        );
+        assert_eq!(output, "");
+        assert!(engine.is_pondering());
+
+        // The opponent played 1...d5 instead
+        let output = run_commands(&mut engine, &["stop"]);
+        assert!(!engine.is_pondering());
+        let pondered = best_move(&output).unwrap();
+        let board = parse_position(&["position", "startpos", "moves", "e2e4", "e7e5"]);
+        assert!(pondered == "0000" || board_from_moves(Some(&board.to_string()), &[pondered]).is_ok());
+
+        let output = run_commands(
+            &mut engine,
+            &["position startpos moves e2e4 d7d5", "go depth 2"],
+        );
+        let reply = best_move(&output).unwrap();
+        assert!(board_from_moves(None, &["e2e4", "d7d5", reply]).is_ok());
+    }
+
+    #[test]
+    fn test_ponderhit_reports_pondered_move() {
+        let mut engine = UciEngine::default();
+        let output = run_commands(&mut engine, &["position startpos moves e2e4", "go ponder depth 2"]);
+        assert_eq!(output, "");
+
+        let output = run_commands(&mut engine, &["ponderhit"]);
+        let board = parse_position(&["position", "startpos", "moves", "e2e4"]);
+        assert_eq!(best_move(&output), Some(format_move(search(&board, 2).best_move).as_str()));
+
+        // `stop` outside of pondering has nothing to report
+        assert_eq!(run_commands(&mut engine, &["stop"]), "");
+    }
+
+    #[test]
+    fn test_stop_without_result_reports_null_move() {
+        let mut engine = UciEngine::default();
+        run_commands(&mut engine, &["position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", "go ponder"]);
+        let output = run_commands(&mut engine, &["stop"]);
+        assert_eq!(best_move(&output), Some("0000"));
     }
 
     #[test]
@@ -477,4 +1161,60 @@ mod tests {
         assert_eq!(option.name, "CrewAI");
         assert_eq!(option.value, "true");
     }
+
+    /// `before` from `fen` and the diff of playing `uci` in it.
+    fn diff_after(fen: &str, uci: &str) -> PositionDiff {
+        let before = Board::from_str(fen).unwrap();
+        let after = before.make_move_new(ChessMove::from_str(uci).unwrap());
+        diff_positions(&before, &after).unwrap()
+    }
+
+    #[test]
+    fn test_diff_positions_special_moves() {
+        let plain = diff_after("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1f3");
+        assert_eq!(plain.piece_moved, (Square::G1, Square::F3, Piece::Knight));
+        assert_eq!((plain.captured, plain.gave_check, plain.castled), (None, false, None));
+
+        let ep = diff_after("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3", "d4e3");
+        assert!(ep.en_passant);
+        assert_eq!(ep.captured, Some(Piece::Pawn));
+
+        let castles = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R";
+        let kingside = diff_after(&format!("{} w KQkq - 0 1", castles), "e1g1");
+        assert_eq!(kingside.castled, Some(CastleSide::Kingside));
+        assert_eq!(kingside.piece_moved.2, Piece::King);
+        let queenside = diff_after(&format!("{} b KQkq - 0 1", castles), "e8c8");
+        assert_eq!(queenside.castled, Some(CastleSide::Queenside));
+        assert_eq!(queenside.captured, None);
+
+        // Black king on a1: queen and rook check it along the a-file
+        for (suffix, piece, check) in [
+            ("q", Piece::Queen, true),
+            ("r", Piece::Rook, true),
+            ("b", Piece::Bishop, false),
+            ("n", Piece::Knight, false),
+        ] {
+            let promotion = diff_after("8/P7/8/8/8/8/8/k6K w - - 0 1", &format!("a7a8{}", suffix));
+            assert_eq!(promotion.promoted, Some(piece));
+            assert_eq!(promotion.piece_moved.2, Piece::Pawn);
+            assert_eq!(promotion.gave_check, check, "{:?}", piece);
+        }
+
+        // The knight steps off the e-file, uncovering the rook
+        let discovered = diff_after("4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1", "e4c5");
+        assert!(discovered.gave_check);
+        assert_eq!(discovered.captured, None);
+
+        let unrelated = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(diff_positions(&Board::default(), &unrelated), None);
+    }
+
+    #[test]
+    fn test_perft_divide_sums_to_perft() {
+        let board = Board::default();
+        let divide = perft_divide(&board, 3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), perft(&board, 3));
+        assert!(BENCH_FENS.iter().all(|fen| Board::from_str(fen).is_ok()));
+    }
 }
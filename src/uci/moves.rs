@@ -0,0 +1,61 @@
+//! Move notation for PGN import.
+//!
+//! PGN movetext is written in SAN, while the engine and Lichess speak UCI.
+//! The parsing itself lives in [`crate::util::position`] next to the UCI
+//! parser, so both resolve moves against the same legal move list.
+
+use chess::{Board, ChessMove};
+
+use crate::util::position::parse_san;
+pub use crate::util::position::{parse_uci_move, san_line_to_uci, to_san};
+
+/// Parse a SAN move (e.g. "Nf3", "Nbd7", "exd5", "O-O", "e8=Q+") on
+/// `board`, disambiguating it against all legal moves. Returns `None` if
+/// the move is malformed, illegal or ambiguous.
+pub fn parse_san_move(board: &Board, san: &str) -> Option<ChessMove> {
+    parse_san(board, san)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn uci(board: &Board, uci: &str) -> Option<ChessMove> {
+        parse_uci_move(board, uci)
+    }
+
+    #[test]
+    fn test_parse_san_move() {
+        // Knights on b1 and f3 can both reach d2
+        let board = Board::from_str("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san_move(&board, "Nd2"), None);
+        assert_eq!(parse_san_move(&board, "Nbd2"), uci(&board, "b1d2"));
+        assert_eq!(parse_san_move(&board, "Nfd2#"), uci(&board, "f3d2"));
+
+        // Rooks on a1 and a5 can both reach a3
+        let board = Board::from_str("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san_move(&board, "R1a3"), uci(&board, "a1a3"));
+        assert_eq!(parse_san_move(&board, "R5a3+"), uci(&board, "a5a3"));
+
+        let board = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(parse_san_move(&board, "O-O"), uci(&board, "e8g8"));
+        assert_eq!(parse_san_move(&board, "O-O-O"), uci(&board, "e8c8"));
+
+        let board = Board::from_str("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for (san, expected) in [
+            ("a8=Q", "a7a8q"),
+            ("a8=R", "a7a8r"),
+            ("a8=B", "a7a8b"),
+            ("a8=N", "a7a8n"),
+        ] {
+            assert_eq!(
+                parse_san_move(&board, san),
+                uci(&board, expected),
+                "{}",
+                san
+            );
+        }
+        assert_eq!(parse_san_move(&board, "axb8=Q+"), uci(&board, "a7b8q"));
+    }
+}
@@ -0,0 +1,48 @@
+//! Log output with per-game context.
+//!
+//! Events are printed by `tracing-subscriber`'s formatter, prefixed with
+//! the spans they happened in, e.g.
+//!
+//! ```text
+//! 2024-05-01T12:00:00.000000Z  INFO game{game_id=abc123 color=White}: stonksfish::lichess::game_manager: Game ended: mate
+//! ```
+//!
+//! Records from dependencies still using `log` macros are forwarded by
+//! `tracing-log`. Filtering follows `RUST_LOG` (`info`,
+//! `stonksfish::whatif=debug`, ...), defaulting to `error` like
+//! `env_logger`.
+
+use tracing_log::LogTracer;
+use tracing_subscriber::EnvFilter;
+
+/// Filter used when `RUST_LOG` is unset or doesn't parse.
+const DEFAULT_FILTER: &str = "error";
+
+/// The filter for the `RUST_LOG`-style `spec`, or [`DEFAULT_FILTER`].
+fn env_filter(spec: Option<&str>) -> EnvFilter {
+    spec.and_then(|spec| EnvFilter::try_new(spec).ok())
+        .unwrap_or_else(|| EnvFilter::new(DEFAULT_FILTER))
+}
+
+/// Install a formatting subscriber filtered by `RUST_LOG` as the global
+/// `tracing` subscriber, and route `log` records to it. Replaces
+/// `env_logger::init`.
+pub fn init() {
+    LogTracer::init().ok();
+    let filter = env_filter(std::env::var("RUST_LOG").ok().as_deref());
+    tracing_subscriber::fmt().with_env_filter(filter).try_init().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::level_filters::LevelFilter;
+
+    #[test]
+    fn test_filter_directives() {
+        let filter = env_filter(Some("info,stonksfish::whatif=debug"));
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+        assert_eq!(env_filter(None).max_level_hint(), Some(LevelFilter::ERROR));
+        assert_eq!(env_filter(Some("=[")).max_level_hint(), Some(LevelFilter::ERROR));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A fixed-capacity map that evicts its least recently used entry once
+/// full.
+///
+/// Every insert or successful lookup stamps the entry with a fresh tick;
+/// `order` maps ticks back to keys so the oldest entry is found in
+/// O(log n).
+///
+#[derive(Debug, Clone)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// Create an empty cache holding at most `capacity` entries (at least 1).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Maximum number of entries.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of entries currently stored.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up `key`, marking it as most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let (value, stamp) = self.entries.get_mut(key)?;
+        self.order.remove(stamp);
+        self.order.insert(tick, key.clone());
+        *stamp = tick;
+        Some(value)
+    }
+
+    /// Insert `value` under `key`, evicting the least recently used entry
+    /// if the cache is full. Returns the previous value for `key`, if any.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        let tick = self.next_tick();
+        if let Some((old, stamp)) = self.entries.remove(&key) {
+            self.order.remove(&stamp);
+            self.order.insert(tick, key.clone());
+            self.entries.insert(key, (value, tick));
+            return Some(old);
+        }
+
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (value, tick));
+        None
+    }
+
+    /// Remove every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        // Touch "a" so "b" becomes the eviction candidate.
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.put("c", 3);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn test_put_replaces_existing() {
+        let mut cache = LruCache::new(2);
+        assert_eq!(cache.put("a", 1), None);
+        assert_eq!(cache.put("a", 10), Some(1));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"a"), Some(&10));
+    }
+}
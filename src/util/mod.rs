@@ -1,2 +1,6 @@
 pub mod io;
+pub mod logging;
+pub mod lru;
+pub mod pool;
+pub mod position;
 pub mod print;
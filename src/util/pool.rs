@@ -0,0 +1,100 @@
+//! A fixed set of worker threads for CPU-heavy background work.
+//!
+//! Kept apart from tokio's blocking pool, which also serves file I/O, DNS
+//! lookups and live move searches, so background work queued here can
+//! never hold those up.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Cloneable handle to a pool of worker threads. Jobs run in the order
+/// they are queued, at most one per thread at a time. The threads exit
+/// once every handle is dropped and the queue is empty.
+#[derive(Debug, Clone)]
+pub struct ThreadPool {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl ThreadPool {
+    /// Start `size` worker threads (at least one) named `name-N`.
+    pub fn new(name: &str, size: usize) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for index in 0..size.max(1) {
+            let queue = Arc::clone(&queue);
+            thread::Builder::new()
+                .name(format!("{}-{}", name, index))
+                .spawn(move || loop {
+                    // The lock is released before the job runs
+                    let job = queue.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to start worker thread");
+        }
+        Self { jobs }
+    }
+
+    /// Queue `job`, returning a receiver for its result. The receiver
+    /// errors if the job panicked.
+    pub fn spawn<F, T>(&self, job: F) -> oneshot::Receiver<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // A panicking job must not take its worker down with it
+            if let Ok(value) = catch_unwind(AssertUnwindSafe(job)) {
+                result.send(value).ok();
+            }
+        });
+        // The workers live as long as any sender, so this can't fail
+        self.jobs.send(job).ok();
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_pool_caps_concurrent_jobs() {
+        let pool = ThreadPool::new("test-pool", 2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let receivers: Vec<_> = (0..6)
+            .map(|i| {
+                let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+                pool.spawn(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i * 10
+                })
+            })
+            .collect();
+
+        let failed = pool.spawn(|| panic!("job failed"));
+        let mut results = Vec::new();
+        for receiver in receivers {
+            results.push(receiver.await.unwrap());
+        }
+        assert_eq!(results, [0, 10, 20, 30, 40, 50]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(failed.await.is_err());
+        // Both workers survived the panic
+        assert_eq!(pool.spawn(|| 1).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,489 @@
+use chess::{Board, ChessMove, File, MoveGen, Piece, Rank, Square};
+use std::fmt;
+use std::str::FromStr;
+
+/// Error returned when a position can't be built from a FEN and move list.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// The starting FEN could not be parsed.
+    InvalidFen(String),
+    /// The move at `index` (0-based) is malformed or illegal in the
+    /// position reached by the preceding moves.
+    IllegalMove { index: usize, uci: String },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::InvalidFen(fen) => write!(f, "invalid FEN '{}'", fen),
+            MoveError::IllegalMove { index, uci } => {
+                write!(f, "illegal move '{}' at index {}", uci, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Build a board from an optional starting FEN (the standard starting
+/// position if `None`) and a list of moves in UCI or SAN format, validating
+/// each move along the way.
+///
+pub fn board_from_moves(start_fen: Option<&str>, moves: &[&str]) -> Result<Board, MoveError> {
+    let mut board = match start_fen {
+        Some(fen) => Board::from_str(fen).map_err(|_| MoveError::InvalidFen(fen.to_string()))?,
+        None => Board::default(),
+    };
+
+    for (index, move_str) in moves.iter().enumerate() {
+        let chess_move = parse_move(&board, move_str).ok_or_else(|| MoveError::IllegalMove {
+            index,
+            uci: move_str.to_string(),
+        })?;
+        board = board.make_move_new(chess_move);
+    }
+
+    Ok(board)
+}
+
+/// Parse a move given in either UCI (e.g. "g1f3") or SAN (e.g. "Nf3")
+/// notation, returning `None` if it is malformed or illegal on the board.
+///
+pub fn parse_move(board: &Board, move_str: &str) -> Option<ChessMove> {
+    parse_uci_move(board, move_str).or_else(|| parse_san(board, move_str))
+}
+
+/// Parse a move in Standard Algebraic Notation (e.g. "Nf3", "exd5",
+/// "O-O", "e8=Q+", "Nbd7") into a ChessMove by resolving it against the
+/// legal moves on the board. Returns `None` if the move is malformed,
+/// illegal or ambiguous.
+///
+/// See https://www.chessprogramming.org/Algebraic_Chess_Notation#Standard_Algebraic_Notation_.28SAN.29
+///
+pub fn parse_san(board: &Board, san: &str) -> Option<ChessMove> {
+    let san = san.trim().trim_end_matches(['+', '#', '!', '?']);
+    if !san.is_ascii() {
+        return None;
+    }
+
+    // Castling is written from the king's point of view
+    let castle_file = match san {
+        "O-O" | "0-0" => Some(File::G),
+        "O-O-O" | "0-0-0" => Some(File::C),
+        _ => None,
+    };
+    if let Some(file) = castle_file {
+        let rank = board.side_to_move().to_my_backrank();
+        let castle = ChessMove::new(
+            Square::make_square(rank, File::E),
+            Square::make_square(rank, file),
+            None,
+        );
+        let is_king = board.piece_on(castle.get_source()) == Some(Piece::King);
+        return match is_king && board.legal(castle) {
+            true => Some(castle),
+            false => None,
+        };
+    }
+
+    // Split off the promotion piece ("e8=Q" or "e8Q")
+    let (body, promotion) = match san.as_bytes().last()? {
+        b'Q' | b'R' | b'B' | b'N' if san.len() > 2 => {
+            let piece = san_piece(san.as_bytes()[san.len() - 1] as char)?;
+            (san[..san.len() - 1].trim_end_matches('='), Some(piece))
+        }
+        _ => (san, None),
+    };
+    if body.len() < 2 {
+        return None;
+    }
+
+    let dest = Square::from_str(&body[body.len() - 2..]).ok()?;
+    let (piece, qualifiers) = match body.chars().next()? {
+        c @ ('K' | 'Q' | 'R' | 'B' | 'N') => (san_piece(c)?, &body[1..body.len() - 2]),
+        _ => (Piece::Pawn, &body[..body.len() - 2]),
+    };
+
+    // Whatever remains is an optional capture marker and disambiguation
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in qualifiers.chars().filter(|&c| c != 'x') {
+        match c {
+            'a'..='h' => from_file = Some(File::from_index(c as usize - 'a' as usize)),
+            '1'..='8' => from_rank = Some(Rank::from_index(c as usize - '1' as usize)),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = MoveGen::new_legal(board).filter(|m| {
+        let source = m.get_source();
+        m.get_dest() == dest
+            && m.get_promotion() == promotion
+            && board.piece_on(source) == Some(piece)
+            && from_file.is_none_or(|f| source.get_file() == f)
+            && from_rank.is_none_or(|r| source.get_rank() == r)
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(chess_move), None) => Some(chess_move),
+        _ => None,
+    }
+}
+
+/// Convert a line of SAN moves (e.g. a PGN mainline) played from
+/// `start_fen` (the standard starting position if `None`) into UCI
+/// strings, resolving each move against the position it is played in.
+///
+pub fn san_line_to_uci(start_fen: Option<&str>, sans: &[&str]) -> Result<Vec<String>, MoveError> {
+    let mut board = match start_fen {
+        Some(fen) => Board::from_str(fen).map_err(|_| MoveError::InvalidFen(fen.to_string()))?,
+        None => Board::default(),
+    };
+
+    let mut ucis = Vec::with_capacity(sans.len());
+    for (index, san) in sans.iter().enumerate() {
+        let chess_move = parse_san(&board, san).ok_or_else(|| MoveError::IllegalMove {
+            index,
+            uci: san.to_string(),
+        })?;
+        ucis.push(chess_move.to_string());
+        board = board.make_move_new(chess_move);
+    }
+    Ok(ucis)
+}
+
+/// The mainline moves of a PGN game, in SAN as written.
+///
+/// Tag pairs, comments (`{...}` and `;` to end of line), variations,
+/// move numbers, NAGs and the game result are skipped.
+///
+pub fn pgn_mainline(pgn: &str) -> Vec<String> {
+    let mut movetext = String::with_capacity(pgn.len());
+    let mut chars = pgn.chars();
+    let mut variation_depth = 0usize;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '[' if variation_depth == 0 => {
+                chars.by_ref().find(|&c| c == ']');
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth = variation_depth.saturating_sub(1),
+            _ if variation_depth == 0 => movetext.push(c),
+            _ => {}
+        }
+        if matches!(c, '{' | ';' | '[' | ')') {
+            movetext.push(' ');
+        }
+    }
+
+    movetext
+        .split_whitespace()
+        .map(|token| match token.split_once('.') {
+            // "12.", "12...", "12.e4"
+            Some((number, san)) if number.chars().all(|c| c.is_ascii_digit()) => {
+                san.trim_start_matches('.')
+            }
+            _ => token,
+        })
+        .filter(|token| {
+            !token.is_empty()
+                && !token.starts_with('$')
+                && !matches!(*token, "1-0" | "0-1" | "1/2-1/2" | "*")
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Value of the PGN tag `name` (e.g. `FEN` in `[FEN "..."]`), if present.
+///
+pub fn pgn_tag<'a>(pgn: &'a str, name: &str) -> Option<&'a str> {
+    pgn.lines().find_map(|line| {
+        let tag = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+        let (tag_name, value) = tag.split_once(char::is_whitespace)?;
+        match tag_name == name {
+            true => value.trim().strip_prefix('"')?.strip_suffix('"'),
+            false => None,
+        }
+    })
+}
+
+/// Format a legal move in Standard Algebraic Notation, the inverse of
+/// [`parse_san`]: piece letter, the minimal disambiguation, capture
+/// marker, promotion and check or mate suffix.
+///
+pub fn to_san(board: &Board, chess_move: ChessMove) -> String {
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
+    let piece = board.piece_on(source).unwrap_or(Piece::Pawn);
+    let is_capture = board.piece_on(dest).is_some()
+        || (piece == Piece::Pawn && source.get_file() != dest.get_file());
+
+    let mut san = String::new();
+    let is_castle = piece == Piece::King
+        && (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs() == 2;
+    if is_castle {
+        san.push_str(match dest.get_file() {
+            File::G => "O-O",
+            _ => "O-O-O",
+        });
+    } else if piece == Piece::Pawn {
+        if is_capture {
+            san.push(file_char(source.get_file()));
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = chess_move.get_promotion() {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+    } else {
+        san.push(piece_char(piece));
+        let rivals: Vec<Square> = MoveGen::new_legal(board)
+            .filter(|m| {
+                m.get_dest() == dest
+                    && m.get_source() != source
+                    && board.piece_on(m.get_source()) == Some(piece)
+            })
+            .map(|m| m.get_source())
+            .collect();
+        if !rivals.is_empty() {
+            let shares_file = rivals.iter().any(|sq| sq.get_file() == source.get_file());
+            let shares_rank = rivals.iter().any(|sq| sq.get_rank() == source.get_rank());
+            if !shares_file {
+                san.push(file_char(source.get_file()));
+            } else if !shares_rank {
+                san.push((b'1' + source.get_rank().to_index() as u8) as char);
+            } else {
+                san.push_str(&source.to_string());
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+    }
+
+    let after = board.make_move_new(chess_move);
+    if after.checkers().popcnt() > 0 {
+        san.push(match MoveGen::new_legal(&after).len() {
+            0 => '#',
+            _ => '+',
+        });
+    }
+    san
+}
+
+#[inline]
+fn file_char(file: File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+/// SAN letter of a non-pawn piece.
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::King => 'K',
+        Piece::Queen => 'Q',
+        Piece::Rook => 'R',
+        Piece::Bishop => 'B',
+        _ => 'N',
+    }
+}
+
+/// Map a SAN piece letter to its piece.
+fn san_piece(c: char) -> Option<Piece> {
+    match c {
+        'K' => Some(Piece::King),
+        'Q' => Some(Piece::Queen),
+        'R' => Some(Piece::Rook),
+        'B' => Some(Piece::Bishop),
+        'N' => Some(Piece::Knight),
+        _ => None,
+    }
+}
+
+/// Parse a UCI move string (e.g., "e2e4", "e7e8q") into a ChessMove,
+/// returning `None` if it is malformed or illegal on the given board.
+///
+pub fn parse_uci_move(board: &Board, move_str: &str) -> Option<ChessMove> {
+    let move_str = move_str.trim();
+    if move_str.len() < 4 || !move_str.is_ascii() {
+        return None;
+    }
+
+    let from = Square::from_str(&move_str[0..2]).ok()?;
+    let to = Square::from_str(&move_str[2..4]).ok()?;
+
+    // Check for promotion piece
+    let promotion = if move_str.len() > 4 {
+        match move_str.as_bytes()[4] {
+            b'q' | b'Q' => Some(Piece::Queen),
+            b'r' | b'R' => Some(Piece::Rook),
+            b'b' | b'B' => Some(Piece::Bishop),
+            b'n' | b'N' => Some(Piece::Knight),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let chess_move = ChessMove::new(from, to, promotion);
+
+    // Verify the move is legal
+    if board.legal(chess_move) {
+        Some(chess_move)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_board_from_moves_valid_sequence() {
+        let board = board_from_moves(None, &["e2e4", "e7e5", "g1f3"]).unwrap();
+        let expected =
+            Board::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+                .unwrap();
+        assert_eq!(board, expected);
+    }
+
+    #[test]
+    fn test_board_from_moves_illegal_move() {
+        let err = board_from_moves(None, &["e2e4", "e2e4", "g1f3"]).unwrap_err();
+        match err {
+            MoveError::IllegalMove { index, uci } => {
+                assert_eq!(index, 1);
+                assert_eq!(uci, "e2e4");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_board_from_moves_san() {
+        let san = board_from_moves(None, &["e4", "e5", "Nf3"]).unwrap();
+        let uci = board_from_moves(None, &["e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(san, uci);
+    }
+
+    fn uci(s: &str) -> ChessMove {
+        ChessMove::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_to_san_round_trips() {
+        let cases = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1f3", "Nf3"),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", "e4d5", "exd5"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "O-O-O"),
+            ("8/P6k/8/8/8/8/8/K7 w - - 0 1", "a7a8q", "a8=Q"),
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8", "Ra8#"),
+            ("4k3/8/8/8/8/8/K7/R6R w - - 0 1", "a1d1", "Rad1"),
+            ("4k3/8/8/N7/8/8/8/N3K3 w - - 0 1", "a1b3", "N1b3"),
+            ("3k4/8/8/8/8/8/4Q3/4K3 w - - 0 1", "e2e7", "Qe7+"),
+        ];
+        for (fen, uci, san) in cases {
+            let board = Board::from_str(fen).unwrap();
+            let chess_move = parse_uci_move(&board, uci).unwrap();
+            assert_eq!(to_san(&board, chess_move), san, "{} in {}", uci, fen);
+            assert_eq!(parse_san(&board, san), Some(chess_move));
+        }
+    }
+
+    #[test]
+    fn test_parse_san_pieces_and_captures() {
+        let board = board_from_moves(None, &["e2e4", "d7d5"]).unwrap();
+        assert_eq!(parse_san(&board, "exd5"), Some(uci("e4d5")));
+        assert_eq!(parse_san(&board, "Nf3"), Some(uci("g1f3")));
+        assert_eq!(parse_san(&board, "Bb5+"), Some(uci("f1b5")));
+        assert_eq!(parse_san(&board, "Ke2"), Some(uci("e1e2")));
+        assert_eq!(parse_san(&board, "Nf6"), None);
+        assert_eq!(parse_san(&board, "e5!?"), Some(uci("e4e5")));
+    }
+
+    #[test]
+    fn test_parse_san_disambiguation() {
+        // Knights on b1 and f3 can both reach d2
+        let board = Board::from_str("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "Nd2"), None);
+        assert_eq!(parse_san(&board, "Nbd2"), Some(uci("b1d2")));
+        assert_eq!(parse_san(&board, "Nfd2"), Some(uci("f3d2")));
+        assert_eq!(parse_san(&board, "N3d2"), Some(uci("f3d2")));
+        assert_eq!(parse_san(&board, "Nf3d2"), Some(uci("f3d2")));
+
+        // Rooks on a1 and a5 can both reach a3
+        let board = Board::from_str("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "Ra3"), None);
+        assert_eq!(parse_san(&board, "R1a3"), Some(uci("a1a3")));
+        assert_eq!(parse_san(&board, "R5a3"), Some(uci("a5a3")));
+    }
+
+    #[test]
+    fn test_parse_san_castling() {
+        let board = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "O-O"), Some(uci("e1g1")));
+        assert_eq!(parse_san(&board, "O-O-O"), Some(uci("e1c1")));
+        assert_eq!(parse_san(&board, "0-0+"), Some(uci("e1g1")));
+
+        let black = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(parse_san(&black, "O-O-O"), Some(uci("e8c8")));
+
+        let no_rights = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        assert_eq!(parse_san(&no_rights, "O-O"), None);
+    }
+
+    #[test]
+    fn test_parse_san_promotion() {
+        let board = Board::from_str("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "a8=Q+"), Some(uci("a7a8q")));
+        assert_eq!(parse_san(&board, "a8N"), Some(uci("a7a8n")));
+        assert_eq!(parse_san(&board, "axb8=R"), Some(uci("a7b8r")));
+        assert_eq!(parse_san(&board, "a8=B"), Some(uci("a7a8b")));
+        assert_eq!(parse_san(&board, "a8"), None);
+    }
+
+    #[test]
+    fn test_pgn_mainline() {
+        let pgn = "[Event \"Casual\"]\n[FEN \"8/8/8/8/8/8/8/K6k w - - 0 1\"]\n\n\
+                   1. e4 {best by test} e5 2.Nf3 Nc6 (2... d6 3. d4) 3. Bb5 $1 a6 ; Morphy\n\
+                   4. O-O 1-0";
+        assert_eq!(pgn_mainline(pgn), vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "O-O"]);
+        assert_eq!(pgn_tag(pgn, "FEN"), Some("8/8/8/8/8/8/8/K6k w - - 0 1"));
+        assert_eq!(pgn_tag(pgn, "Site"), None);
+        assert!(pgn_mainline("*").is_empty());
+    }
+
+    #[test]
+    fn test_san_line_to_uci() {
+        let line = ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"];
+        assert_eq!(
+            san_line_to_uci(None, &line).unwrap(),
+            vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5c6", "d7c6", "e1g1"]
+        );
+
+        let err = san_line_to_uci(None, &["e4", "e4"]).unwrap_err();
+        assert_eq!(
+            err,
+            MoveError::IllegalMove {
+                index: 1,
+                uci: "e4".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_board_from_moves_invalid_fen() {
+        let err = board_from_moves(Some("not a fen"), &[]).unwrap_err();
+        assert_eq!(err, MoveError::InvalidFen("not a fen".to_string()));
+    }
+}
@@ -27,12 +27,17 @@
 //! moves.
 
 use chess::{Board, ChessMove, Color, MoveGen, EMPTY};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use crate::engine::evaluation::simple::evaluate_board;
-use crate::engine::search::find_move;
+use crate::engine::evaluation::simple::{evaluate_board, PAWN_VALUE};
+use crate::engine::search::{search_with_node_limit, MATE_SCORE};
+use crate::engine::transposition::TranspositionTable;
 use crate::uci::{analyze_position, classify_phase, count_pieces, format_move};
+use crate::util::position::{board_from_moves, pgn_mainline, pgn_tag, to_san};
 
 /// Maximum look-ahead depth (32 half-moves = 16 full moves).
 pub const MAX_BRANCH_DEPTH: u8 = 32;
@@ -40,6 +45,10 @@ pub const MAX_BRANCH_DEPTH: u8 = 32;
 /// Default branching width at each level.
 pub const DEFAULT_WIDTH: usize = 3;
 
+/// `move_uci` of a pass in [`BranchConfig::threat_mode`], the UCI null
+/// move.
+pub const PASS_UCI: &str = "0000";
+
 /// Configuration for what-if branching.
 #[derive(Debug, Clone)]
 pub struct BranchConfig {
@@ -47,14 +56,30 @@ pub struct BranchConfig {
     pub max_depth: u8,
     /// Number of candidate moves to explore at each depth. Default: 3.
     pub width: usize,
-    /// Minimum search depth for move ordering. Default: 3.
+    /// Minimum search depth for move ordering. Default: 3. At 1 or less,
+    /// moves are ordered by static evaluation.
     pub ordering_depth: u8,
+    /// Maximum search nodes spent ordering moves across the whole tree,
+    /// separate from `node_budget`. Once spent, the remaining nodes order
+    /// their moves by static evaluation. Default: 50_000.
+    pub ordering_node_budget: usize,
     /// Whether to use selective deepening (reduce depth for lower-ranked moves).
     pub selective_deepening: bool,
     /// Maximum total nodes to generate (budget). Default: 10_000.
     pub node_budget: usize,
-    /// Minimum evaluation change to keep exploring a branch (centipawns).
+    /// Minimum evaluation change to keep exploring a branch (centipawns,
+    /// so presets are given in multiples of [`PAWN_VALUE`]).
     pub prune_threshold: i32,
+    /// Look for the opponent's threats: on the plies where the root side
+    /// is to move, it passes (a [`PASS_UCI`] node) unless in check, so the
+    /// opponent's best moves are ranked from the unchanged position and
+    /// the tree shows what it would do given a free move. Default: false
+    /// (both sides move as usual).
+    pub threat_mode: bool,
+    /// Ceiling on the tree's estimated memory use in bytes, see
+    /// [`BranchNode::estimated_bytes`]. Expansion stops once it is
+    /// reached, independent of `node_budget`. Default: None (no limit).
+    pub max_memory_bytes: Option<usize>,
 }
 
 impl Default for BranchConfig {
@@ -63,9 +88,12 @@ impl Default for BranchConfig {
             max_depth: MAX_BRANCH_DEPTH,
             width: DEFAULT_WIDTH,
             ordering_depth: 3,
+            ordering_node_budget: 50_000,
             selective_deepening: true,
             node_budget: 10_000,
-            prune_threshold: 500, // Prune if position swings > 5 pawns
+            prune_threshold: 5 * PAWN_VALUE, // Prune if position swings > 5 pawns
+            threat_mode: false,
+            max_memory_bytes: None,
         }
     }
 }
@@ -77,9 +105,12 @@ impl BranchConfig {
             max_depth: 8,
             width: 2,
             ordering_depth: 2,
+            ordering_node_budget: 10_000,
             selective_deepening: true,
             node_budget: 500,
-            prune_threshold: 300,
+            prune_threshold: 3 * PAWN_VALUE,
+            threat_mode: false,
+            max_memory_bytes: None,
         }
     }
 
@@ -89,9 +120,99 @@ impl BranchConfig {
             max_depth: MAX_BRANCH_DEPTH,
             width: 3,
             ordering_depth: 4,
+            ordering_node_budget: 200_000,
             selective_deepening: true,
             node_budget: 50_000,
-            prune_threshold: 800,
+            prune_threshold: 8 * PAWN_VALUE,
+            threat_mode: false,
+            max_memory_bytes: None,
+        }
+    }
+
+    /// Create a config for middlegame analysis: deeper than `quick()` so
+    /// tactics play out, but a fraction of the `deep()` budget.
+    pub fn medium() -> Self {
+        Self {
+            max_depth: 16,
+            width: 3,
+            ordering_depth: 3,
+            ordering_node_budget: 50_000,
+            selective_deepening: true,
+            node_budget: 5_000,
+            prune_threshold: 5 * PAWN_VALUE,
+            threat_mode: false,
+            max_memory_bytes: None,
+        }
+    }
+
+    /// Pick a preset for a phase as named by `classify_phase`: `quick()`
+    /// for the well-known opening, `medium()` for the middlegame and
+    /// `deep()` for the endgame, where precise calculation matters most.
+    /// Unknown phases get the default config.
+    pub fn preset_from_phase(phase: &str) -> Self {
+        match phase {
+            "opening" => Self::quick(),
+            "middlegame" => Self::medium(),
+            "endgame" => Self::deep(),
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Node budget shared by every tree generated during a what-if session
+/// (e.g. one game), so the total analysis cost stays bounded.
+///
+/// Clones share the same pool. Trees reserve nodes up front and refund
+/// whatever they didn't use.
+#[derive(Debug, Clone)]
+pub struct SessionBudget {
+    /// Nodes left in the pool, or `None` for an unlimited session.
+    remaining: Option<Arc<AtomicUsize>>,
+}
+
+impl SessionBudget {
+    /// Create a session with `total` nodes to spend. Zero means unlimited.
+    pub fn new(total: usize) -> Self {
+        Self {
+            remaining: (total > 0).then(|| Arc::new(AtomicUsize::new(total))),
+        }
+    }
+
+    /// Create a session without a cumulative limit.
+    pub fn unlimited() -> Self {
+        Self { remaining: None }
+    }
+
+    /// Nodes left in the pool, or `None` if unlimited.
+    pub fn remaining(&self) -> Option<usize> {
+        self.remaining.as_ref().map(|r| r.load(Ordering::SeqCst))
+    }
+
+    /// Whether the pool has been spent.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == Some(0)
+    }
+
+    /// Take up to `wanted` nodes from the pool, returning how many were
+    /// granted (zero once the session is exhausted).
+    pub fn reserve(&self, wanted: usize) -> usize {
+        match &self.remaining {
+            None => wanted,
+            Some(remaining) => {
+                let previous = remaining
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                        Some(left - left.min(wanted))
+                    })
+                    .unwrap_or(0);
+                previous.min(wanted)
+            }
+        }
+    }
+
+    /// Return nodes that were reserved but not used.
+    pub fn refund(&self, unused: usize) {
+        if let Some(remaining) = &self.remaining {
+            remaining.fetch_add(unused, Ordering::SeqCst);
         }
     }
 }
@@ -107,7 +228,9 @@ pub struct BranchNode {
     pub move_uci: Option<String>,
     /// Depth from root (0 = current position).
     pub depth: u8,
-    /// Static evaluation in centipawns (from side to move).
+    /// Static evaluation in centipawns, from the side that moved into
+    /// this position (the side to move at the root). See
+    /// [`BranchNode::root_eval_cp`] for the root side's view.
     pub eval_cp: i32,
     /// Game phase at this node.
     pub phase: String,
@@ -125,6 +248,55 @@ pub struct BranchNode {
     pub fork_id: String,
 }
 
+impl BranchNode {
+    /// Moves from the root of `tree` to this node, found by following
+    /// `parent_id` links. Stops early at a parent missing from `tree`, so
+    /// a node with broken links gets a shorter path.
+    pub fn path_from_root<'a>(&'a self, tree: &'a BranchTree) -> Vec<&'a str> {
+        let mut path = Vec::new();
+        let mut node = self;
+        while let Some(uci) = node.move_uci.as_deref() {
+            path.push(uci);
+            // A parent cycle can't be longer than the tree
+            match node.parent_id.as_deref().and_then(|id| tree.node(id)) {
+                Some(parent) if path.len() <= tree.nodes.len() => node = parent,
+                _ => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// Rough memory held by this node in a tree: the struct itself plus
+    /// its strings. The branch_id is counted three times, since the
+    /// parent's `children` and the tree's index keep copies of it.
+    pub fn estimated_bytes(&self) -> usize {
+        let optional = |s: &Option<String>| s.as_ref().map_or(0, String::len);
+        std::mem::size_of::<Self>()
+            + self.fen.len()
+            + 3 * self.branch_id.len()
+            + self.phase.len()
+            + self.fork_id.len()
+            + optional(&self.move_uci)
+            + optional(&self.terminal_reason)
+            + optional(&self.parent_id)
+    }
+
+    /// `eval_cp` from the point of view of the side to move at the root.
+    /// Below the root, the opponent moved into the nodes at even depths.
+    pub fn root_eval_cp(&self) -> i32 {
+        match self.depth > 0 && self.depth.is_multiple_of(2) {
+            true => -self.eval_cp,
+            false => self.eval_cp,
+        }
+    }
+
+    /// Whether `depth` matches the number of moves from the root.
+    pub fn depth_consistent(&self, tree: &BranchTree) -> bool {
+        self.path_from_root(tree).len() == self.depth as usize
+    }
+}
+
 /// Result of what-if branching from a position.
 #[derive(Debug, Clone)]
 pub struct BranchTree {
@@ -140,6 +312,237 @@ pub struct BranchTree {
     pub max_depth_reached: u8,
     /// Principal variation (best line).
     pub principal_variation: Vec<String>,
+    /// Search nodes spent ordering moves.
+    pub ordering_nodes: u64,
+    /// Nodes whose moves were ordered by static evaluation because the
+    /// ordering node budget was spent.
+    pub static_orderings: usize,
+    /// Estimated memory held by `nodes`, the sum of their
+    /// [`BranchNode::estimated_bytes`].
+    pub memory_bytes: usize,
+    /// Whether expansion stopped early because `memory_bytes` reached
+    /// [`BranchConfig::max_memory_bytes`].
+    pub truncated: bool,
+    /// branch_id → index into `nodes`, maintained by [`BranchTree::push_node`].
+    index: HashMap<String, usize>,
+}
+
+impl BranchTree {
+    /// Append a node, keeping the branch_id index in sync. Returns the new
+    /// node's index.
+    pub fn push_node(&mut self, node: BranchNode) -> usize {
+        let idx = self.nodes.len();
+        self.memory_bytes += node.estimated_bytes();
+        self.index.insert(node.branch_id.clone(), idx);
+        self.nodes.push(node);
+        idx
+    }
+
+    /// Index of the node with the given branch_id.
+    ///
+    /// O(1) through the index; falls back to a scan if `nodes` was edited
+    /// directly and the index no longer matches.
+    pub fn node_index(&self, branch_id: &str) -> Option<usize> {
+        match self.index.get(branch_id) {
+            Some(&idx) if self.nodes.get(idx).is_some_and(|n| n.branch_id == branch_id) => {
+                Some(idx)
+            }
+            _ => self.nodes.iter().position(|n| n.branch_id == branch_id),
+        }
+    }
+
+    /// Node with the given branch_id.
+    pub fn node(&self, branch_id: &str) -> Option<&BranchNode> {
+        self.node_index(branch_id).map(|idx| &self.nodes[idx])
+    }
+
+    /// Nodes `depth` plies from the root, in generation order.
+    pub fn nodes_at_depth(&self, depth: u8) -> Vec<&BranchNode> {
+        self.nodes.iter().filter(|n| n.depth == depth).collect()
+    }
+
+    /// Nodes without children: terminal positions and the ends of
+    /// unexpanded lines.
+    pub fn leaf_nodes(&self) -> Vec<&BranchNode> {
+        self.nodes.iter().filter(|n| n.children.is_empty()).collect()
+    }
+
+    /// Nodes with at least one child.
+    pub fn internal_nodes(&self) -> Vec<&BranchNode> {
+        self.nodes.iter().filter(|n| !n.children.is_empty()).collect()
+    }
+
+    /// Rebuild the branch_id index after editing `nodes` directly.
+    pub fn rebuild_index(&mut self) {
+        self.index = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, n)| (n.branch_id.clone(), idx))
+            .collect();
+    }
+
+    /// Evaluation at each node along the principal variation, starting
+    /// with the root. Has one more element than `principal_variation`.
+    pub fn best_line_evals(&self) -> Vec<i32> {
+        self.best_line_indices()
+            .into_iter()
+            .map(|idx| self.nodes[idx].eval_cp)
+            .collect()
+    }
+
+    /// FEN at each node along the principal variation, starting with the
+    /// root. Has one more element than `principal_variation`.
+    pub fn best_line_fens(&self) -> Vec<String> {
+        self.best_line_indices()
+            .into_iter()
+            .map(|idx| self.nodes[idx].fen.clone())
+            .collect()
+    }
+
+    /// Node indices along the principal variation, root first.
+    fn best_line_indices(&self) -> Vec<usize> {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut indices = vec![0];
+        let mut current_idx = 0;
+        for m in &self.principal_variation {
+            let node = &self.nodes[current_idx];
+            let next = node.children.iter().find_map(|child_id| {
+                self.node_index(child_id)
+                    .filter(|&idx| self.nodes[idx].move_uci.as_ref() == Some(m))
+            });
+            match next {
+                Some(idx) => {
+                    indices.push(idx);
+                    current_idx = idx;
+                }
+                None => break,
+            }
+        }
+        indices
+    }
+
+    /// A copy of the tree without the nodes scoring below `threshold`
+    /// for the side to move at the root (see [`BranchNode::root_eval_cp`])
+    /// and their descendants, i.e. the lines where that side stays at
+    /// least at `threshold` throughout. The root is always kept. The
+    /// principal variation is recomputed from what is left.
+    pub fn prune_below_eval(&self, threshold: i32) -> BranchTree {
+        let mut tree = self.clone();
+        if tree.nodes.is_empty() {
+            return tree;
+        }
+        let mut keep = vec![false; tree.nodes.len()];
+        keep[0] = true;
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            for child_id in &tree.nodes[idx].children {
+                let Some(child) = tree.node_index(child_id) else {
+                    continue;
+                };
+                if tree.nodes[child].root_eval_cp() >= threshold {
+                    keep[child] = true;
+                    stack.push(child);
+                }
+            }
+        }
+        retain_nodes(&mut tree, &keep);
+        tree.principal_variation = extract_pv(&tree);
+        tree
+    }
+
+    /// Check that branch_ids are unique and parent/child links agree,
+    /// returning every inconsistency found.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let mut seen = HashSet::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            if !seen.insert(node.branch_id.as_str()) {
+                errors.push(ValidationError::DuplicateBranchId(node.branch_id.clone()));
+            }
+
+            for child_id in &node.children {
+                match self.node(child_id) {
+                    None => errors.push(ValidationError::MissingChild {
+                        parent: node.branch_id.clone(),
+                        child: child_id.clone(),
+                    }),
+                    Some(child) if child.depth != node.depth + 1 => {
+                        errors.push(ValidationError::DepthMismatch {
+                            parent: node.branch_id.clone(),
+                            child: child_id.clone(),
+                            parent_depth: node.depth,
+                            child_depth: child.depth,
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if let Some(parent_id) = &node.parent_id {
+                let listed = self
+                    .node(parent_id)
+                    .is_some_and(|parent| parent.children.contains(&node.branch_id));
+                if !listed {
+                    errors.push(ValidationError::NotListedByParent {
+                        node: node.branch_id.clone(),
+                        parent: parent_id.clone(),
+                    });
+                }
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// Structural problem found by [`BranchTree::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// Two or more nodes share this branch_id.
+    DuplicateBranchId(String),
+    /// `parent` lists a child that isn't in the tree.
+    MissingChild { parent: String, child: String },
+    /// `node` names `parent` as its parent, but `parent` doesn't list it
+    /// as a child (or doesn't exist).
+    NotListedByParent { node: String, parent: String },
+    /// `child` isn't exactly one level below `parent`.
+    DepthMismatch {
+        parent: String,
+        child: String,
+        parent_depth: u8,
+        child_depth: u8,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::DuplicateBranchId(id) => write!(f, "duplicate branch_id '{}'", id),
+            ValidationError::MissingChild { parent, child } => {
+                write!(f, "'{}' lists missing child '{}'", parent, child)
+            }
+            ValidationError::NotListedByParent { node, parent } => {
+                write!(f, "'{}' is not a child of its parent '{}'", node, parent)
+            }
+            ValidationError::DepthMismatch {
+                parent,
+                child,
+                parent_depth,
+                child_depth,
+            } => write!(
+                f,
+                "child '{}' at depth {} under '{}' at depth {}",
+                child, child_depth, parent, parent_depth
+            ),
+        }
+    }
 }
 
 /// Generate a what-if branching tree from the given position.
@@ -150,6 +553,7 @@ pub struct BranchTree {
 pub fn generate_branch_tree(fen: &str, config: &BranchConfig) -> Option<BranchTree> {
     let root_board = Board::from_str(fen).ok()?;
     let root_eval = evaluate_board(&root_board);
+    let root_terminal = terminal_reason(&root_board);
 
     let mut tree = BranchTree {
         root_fen: fen.to_string(),
@@ -158,6 +562,11 @@ pub fn generate_branch_tree(fen: &str, config: &BranchConfig) -> Option<BranchTr
         total_nodes: 0,
         max_depth_reached: 0,
         principal_variation: Vec::new(),
+        ordering_nodes: 0,
+        static_orderings: 0,
+        memory_bytes: 0,
+        truncated: false,
+        index: HashMap::new(),
     };
 
     let root_node = BranchNode {
@@ -168,33 +577,119 @@ pub fn generate_branch_tree(fen: &str, config: &BranchConfig) -> Option<BranchTr
         eval_cp: root_eval,
         phase: classify_phase(&root_board).to_string(),
         piece_count: count_pieces(&root_board),
-        is_terminal: MoveGen::new_legal(&root_board).len() == 0,
-        terminal_reason: terminal_reason(&root_board),
+        is_terminal: root_terminal.is_some(),
+        terminal_reason: root_terminal,
         parent_id: None,
         children: Vec::new(),
         fork_id: format!("fork-root"),
     };
 
-    tree.nodes.push(root_node);
+    tree.push_node(root_node);
     tree.total_nodes = 1;
 
     // Recursive branching
-    expand_node(&mut tree, 0, &root_board, config, &mut 1);
+    let mut ordering = MoveOrdering::new();
+    let mut candidates = Vec::new();
+    expand_node(&mut tree, 0, &root_board, config, &mut 1, &mut ordering, &mut candidates);
+    tree.ordering_nodes = ordering.nodes;
+    tree.static_orderings = ordering.static_fallbacks;
 
     // Extract principal variation
     tree.principal_variation = extract_pv(&tree);
     tree.max_depth_reached = tree.nodes.iter().map(|n| n.depth).max().unwrap_or(0);
 
+    debug_assert_eq!(tree.validate(), Ok(()), "inconsistent branch tree");
     Some(tree)
 }
 
+/// Generate a what-if tree from the position after the first `at_move`
+/// half-moves of a PGN game, e.g. to explore the alternatives to the
+/// move actually played there.
+///
+/// The game starts from its `FEN` tag if it has one. Returns `None` if
+/// the PGN has fewer than `at_move` mainline moves or one of them is
+/// illegal.
+pub fn generate_branch_tree_from_pgn(
+    pgn: &str,
+    config: &BranchConfig,
+    at_move: u32,
+) -> Option<BranchTree> {
+    let mainline = pgn_mainline(pgn);
+    let played: Vec<&str> = mainline.get(..at_move as usize)?.iter().map(String::as_str).collect();
+    let board = board_from_moves(pgn_tag(pgn, "FEN"), &played).ok()?;
+    generate_branch_tree(&board.to_string(), config)
+}
+
+/// Remove dominated branches: every subtree whose root scores more than
+/// `keep_within_cp` below its best sibling, unless it lies on the
+/// principal variation. Parent/child links, `total_nodes` and
+/// `max_depth_reached` are updated to match. Returns the number of nodes
+/// removed.
+///
+/// Siblings' evaluations share the perspective of the side that moved
+/// into them, so they compare directly.
+pub fn prune_tree(tree: &mut BranchTree, keep_within_cp: i32) -> usize {
+    if tree.nodes.is_empty() {
+        return 0;
+    }
+    let pv: HashSet<usize> = tree.best_line_indices().into_iter().collect();
+
+    let mut keep = vec![false; tree.nodes.len()];
+    keep[0] = true;
+    let mut stack = vec![0];
+    while let Some(idx) = stack.pop() {
+        let children: Vec<usize> = tree.nodes[idx]
+            .children
+            .iter()
+            .filter_map(|id| tree.node_index(id))
+            .collect();
+        let Some(best) = children.iter().map(|&child| tree.nodes[child].eval_cp).max() else {
+            continue;
+        };
+        for child in children {
+            if pv.contains(&child) || best - tree.nodes[child].eval_cp <= keep_within_cp {
+                keep[child] = true;
+                stack.push(child);
+            }
+        }
+    }
+
+    let before = tree.nodes.len();
+    retain_nodes(tree, &keep);
+    before - tree.nodes.len()
+}
+
+/// Keep the nodes whose entry in `keep` is set, dropping links to the
+/// others and updating the tree's totals. A kept node's parent must be
+/// kept too.
+fn retain_nodes(tree: &mut BranchTree, keep: &[bool]) {
+    let mut kept = keep.iter();
+    tree.nodes.retain(|_| *kept.next().unwrap());
+    let kept_ids: HashSet<String> = tree.nodes.iter().map(|n| n.branch_id.clone()).collect();
+    for node in &mut tree.nodes {
+        node.children.retain(|id| kept_ids.contains(id));
+    }
+    tree.rebuild_index();
+    tree.total_nodes = tree.nodes.len();
+    tree.memory_bytes = tree.nodes.iter().map(BranchNode::estimated_bytes).sum();
+    tree.max_depth_reached = tree.nodes.iter().map(|n| n.depth).max().unwrap_or(0);
+
+    debug_assert_eq!(tree.validate(), Ok(()), "inconsistent branch tree after pruning");
+}
+
 /// Expand a node by generating child branches.
+///
+/// `ordering` ranks the candidate moves and `candidates` is scratch space
+/// for the ranking, both shared by the whole recursion so each node
+/// doesn't allocate its own.
 fn expand_node(
     tree: &mut BranchTree,
     node_idx: usize,
     board: &Board,
     config: &BranchConfig,
     node_counter: &mut usize,
+    ordering: &mut MoveOrdering,
+    candidates: &mut Vec<(ChessMove, i32)>,
 ) {
     let current_depth = tree.nodes[node_idx].depth;
 
@@ -202,32 +697,44 @@ fn expand_node(
     if current_depth >= config.max_depth {
         return;
     }
-    if tree.total_nodes >= config.node_budget {
+    if tree.total_nodes >= config.node_budget || memory_exhausted(tree, config) {
         return;
     }
     if tree.nodes[node_idx].is_terminal {
         return;
     }
 
-    // Generate and rank candidate moves
-    let candidates = rank_moves(board, config);
-    let width = candidates.len().min(config.width);
-
     let parent_id = tree.nodes[node_idx].branch_id.clone();
     let parent_eval = tree.nodes[node_idx].eval_cp;
 
-    let mut child_indices = Vec::new();
+    // In threat mode the root side (to move at even depths) passes, so
+    // the opponent moves twice in a row
+    if config.threat_mode && current_depth.is_multiple_of(2) {
+        if let Some(passed) = board.null_move() {
+            let child = branch_node(&parent_id, current_depth + 1, PASS_UCI.to_string(), &passed, *node_counter);
+            tree.nodes[node_idx].children = vec![child.branch_id.clone()];
+            let child_idx = tree.push_node(child);
+            tree.total_nodes += 1;
+            *node_counter += 1;
+            expand_node(tree, child_idx, &passed, config, node_counter, ordering, candidates);
+            return;
+        }
+    }
+
+    // Generate and rank candidate moves
+    ordering.rank_moves(board, config, candidates);
+    let width = candidates.len().min(config.width);
+
+    let mut child_indices = Vec::with_capacity(width);
+    let mut new_board = *board;
 
     for (rank, (chess_move, move_eval)) in candidates.iter().take(width).enumerate() {
-        if tree.total_nodes >= config.node_budget {
+        if tree.total_nodes >= config.node_budget || memory_exhausted(tree, config) {
             break;
         }
 
-        let mut new_board = Board::default();
         board.make_move(*chess_move, &mut new_board);
 
-        let move_str = format_move(*chess_move);
-        let branch_id = format!("{}-{}", parent_id, move_str);
         let child_eval = -evaluate_board(&new_board);
 
         // Pruning: skip if evaluation swings too much (likely losing)
@@ -237,23 +744,9 @@ fn expand_node(
             }
         }
 
-        let child_node = BranchNode {
-            branch_id: branch_id.clone(),
-            fen: format!("{}", new_board),
-            move_uci: Some(move_str),
-            depth: current_depth + 1,
-            eval_cp: child_eval,
-            phase: classify_phase(&new_board).to_string(),
-            piece_count: count_pieces(&new_board),
-            is_terminal: MoveGen::new_legal(&new_board).len() == 0,
-            terminal_reason: terminal_reason(&new_board),
-            parent_id: Some(parent_id.clone()),
-            children: Vec::new(),
-            fork_id: format!("fork-{}", *node_counter),
-        };
-
-        tree.nodes.push(child_node);
-        let child_idx = tree.nodes.len() - 1;
+        let move_str = format_move(*chess_move);
+        let child_node = branch_node(&parent_id, current_depth + 1, move_str, &new_board, *node_counter);
+        let child_idx = tree.push_node(child_node);
         child_indices.push((child_idx, new_board));
         tree.total_nodes += 1;
         *node_counter += 1;
@@ -274,25 +767,122 @@ fn expand_node(
             child_config.max_depth = child_config.max_depth.saturating_sub(rank as u8 * 2);
             child_config.width = (child_config.width).max(1);
         }
-        expand_node(tree, child_idx, &child_board, &child_config, node_counter);
+        expand_node(tree, child_idx, &child_board, &child_config, node_counter, ordering, candidates);
     }
 }
 
-/// Rank candidate moves by evaluation (using shallow search).
-fn rank_moves(board: &Board, config: &BranchConfig) -> Vec<(ChessMove, i32)> {
-    let mut moves: Vec<(ChessMove, i32)> = Vec::new();
-    let movegen = MoveGen::new_legal(board);
-    let mut new_board = Board::default();
+/// The node for `board`, reached from `parent_id` by `move_str`, as the
+/// `counter`th node created.
+fn branch_node(parent_id: &str, depth: u8, move_str: String, board: &Board, counter: usize) -> BranchNode {
+    let terminal = terminal_reason(board);
+    BranchNode {
+        branch_id: format!("{}-{}", parent_id, move_str),
+        fen: format!("{}", board),
+        move_uci: Some(move_str),
+        depth,
+        eval_cp: -evaluate_board(board),
+        phase: classify_phase(board).to_string(),
+        piece_count: count_pieces(board),
+        is_terminal: terminal.is_some(),
+        terminal_reason: terminal,
+        parent_id: Some(parent_id.to_string()),
+        children: Vec::new(),
+        fork_id: format!("fork-{}", counter),
+    }
+}
 
-    for chess_move in movegen {
-        board.make_move(chess_move, &mut new_board);
-        let eval = -evaluate_board(&new_board);
-        moves.push((chess_move, eval));
+/// Whether `tree` has reached its memory ceiling, marking it truncated
+/// if so.
+fn memory_exhausted(tree: &mut BranchTree, config: &BranchConfig) -> bool {
+    if config.max_memory_bytes.is_some_and(|max| tree.memory_bytes >= max) {
+        tree.truncated = true;
     }
+    tree.truncated
+}
 
-    // Sort by evaluation (best moves first)
-    moves.sort_by(|a, b| b.1.cmp(&a.1));
-    moves
+/// Transposition table slots for ordering searches: they are shallow, so
+/// a small table suffices and is cheap to share across the tree.
+const ORDERING_TT_ENTRIES: usize = 1 << 12;
+
+/// Ranks candidate moves for a whole tree, tracking the search nodes
+/// spent against [`BranchConfig::ordering_node_budget`].
+struct MoveOrdering {
+    tt: TranspositionTable,
+    /// Search nodes spent so far.
+    nodes: u64,
+    /// Rankings that fell back to static evaluation.
+    static_fallbacks: usize,
+}
+
+impl MoveOrdering {
+    fn new() -> Self {
+        Self {
+            tt: TranspositionTable::new(ORDERING_TT_ENTRIES),
+            nodes: 0,
+            static_fallbacks: 0,
+        }
+    }
+
+    /// Rank candidate moves by evaluation into `moves`, best first,
+    /// replacing its contents so the caller can reuse the allocation.
+    ///
+    /// Moves are scored with an `ordering_depth` ply search while the
+    /// ordering budget lasts, and by static evaluation once it is spent
+    /// (or when `ordering_depth` is 1 or less), so tree generation always
+    /// makes progress.
+    fn rank_moves(&mut self, board: &Board, config: &BranchConfig, moves: &mut Vec<(ChessMove, i32)>) {
+        moves.clear();
+        let movegen = MoveGen::new_legal(board);
+        moves.reserve(movegen.len());
+        let mut new_board = *board;
+
+        for chess_move in movegen {
+            board.make_move(chess_move, &mut new_board);
+            let eval = -evaluate_board(&new_board);
+            moves.push((chess_move, eval));
+        }
+        if config.ordering_depth > 1 && !self.search_scores(board, config, moves) {
+            self.static_fallbacks += 1;
+        }
+
+        // Sort by evaluation (best moves first)
+        moves.sort_by(|a, b| b.1.cmp(&a.1));
+    }
+
+    /// Replace the static scores in `moves` with search scores. Leaves
+    /// them untouched and returns `false` if the budget runs out first.
+    fn search_scores(&mut self, board: &Board, config: &BranchConfig, moves: &mut [(ChessMove, i32)]) -> bool {
+        let budget = config.ordering_node_budget as u64;
+        let mut scores = Vec::with_capacity(moves.len());
+        for &(chess_move, _) in moves.iter() {
+            if self.nodes >= budget {
+                return false;
+            }
+            let after = board.make_move_new(chess_move);
+            let score = match terminal_reason(&after).as_deref() {
+                Some("checkmate") => MATE_SCORE,
+                Some(_) => 0,
+                None => {
+                    let depth = config.ordering_depth - 1;
+                    match search_with_node_limit(&after, depth, &mut self.tt, budget - self.nodes) {
+                        Some(result) => {
+                            self.nodes += result.nodes;
+                            -result.score
+                        }
+                        None => {
+                            self.nodes = budget;
+                            return false;
+                        }
+                    }
+                }
+            };
+            scores.push(score);
+        }
+        for ((_, eval), score) in moves.iter_mut().zip(scores) {
+            *eval = score;
+        }
+        true
+    }
 }
 
 /// Determine if a position is terminal and why.
@@ -322,7 +912,7 @@ fn extract_pv(tree: &BranchTree) -> Vec<String> {
 
         // Find the best child (highest absolute evaluation)
         let best_child_id = &node.children[0]; // First child is the best (sorted)
-        if let Some(child_idx) = tree.nodes.iter().position(|n| &n.branch_id == best_child_id) {
+        if let Some(child_idx) = tree.node_index(best_child_id) {
             if let Some(ref m) = tree.nodes[child_idx].move_uci {
                 pv.push(m.clone());
             }
@@ -369,6 +959,7 @@ pub fn tree_summary(tree: &BranchTree) -> TreeSummary {
         stalemates: stalemate_count,
         eval_range: (min_eval, max_eval),
         principal_variation: tree.principal_variation.clone(),
+        best_line_evals: tree.best_line_evals(),
         branching_factor: if tree.total_nodes > 1 {
             (tree.total_nodes as f64 - 1.0) / tree.nodes.iter().filter(|n| !n.children.is_empty()).count().max(1) as f64
         } else {
@@ -388,6 +979,8 @@ pub struct TreeSummary {
     pub stalemates: u32,
     pub eval_range: (i32, i32),
     pub principal_variation: Vec<String>,
+    /// Evaluation at the root and after each principal variation move.
+    pub best_line_evals: Vec<i32>,
     pub branching_factor: f64,
 }
 
@@ -400,6 +993,7 @@ impl fmt::Display for TreeSummary {
         writeln!(f, "  Eval range: [{}, {}] cp", self.eval_range.0, self.eval_range.1)?;
         writeln!(f, "  Avg branching factor: {:.1}", self.branching_factor)?;
         writeln!(f, "  Principal variation: {}", self.principal_variation.join(" "))?;
+        writeln!(f, "  Best line evals: {:?}", self.best_line_evals)?;
         write!(f, "  Depth distribution: ")?;
         for (d, count) in self.depth_distribution.iter().enumerate() {
             if *count > 0 {
@@ -418,11 +1012,15 @@ pub fn tree_to_json(tree: &BranchTree) -> serde_json::Value {
         "total_nodes": tree.total_nodes,
         "max_depth_reached": tree.max_depth_reached,
         "principal_variation": tree.principal_variation,
+        "memory_bytes": tree.memory_bytes,
+        "truncated": tree.truncated,
         "config": {
             "max_depth": tree.config.max_depth,
             "width": tree.config.width,
             "node_budget": tree.config.node_budget,
             "selective_deepening": tree.config.selective_deepening,
+            "threat_mode": tree.config.threat_mode,
+            "max_memory_bytes": tree.config.max_memory_bytes,
         },
         "nodes": tree.nodes.iter().map(|n| {
             serde_json::json!({
@@ -443,6 +1041,101 @@ pub fn tree_to_json(tree: &BranchTree) -> serde_json::Value {
     })
 }
 
+/// Render a BranchTree as a Graphviz `digraph`, one node per position
+/// labelled with the move leading to it and its evaluation. Terminal
+/// positions are drawn as boxes.
+pub fn tree_to_dot(tree: &BranchTree) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = String::from("digraph whatif {\n");
+    for node in &tree.nodes {
+        let shape = if node.is_terminal { "box" } else { "ellipse" };
+        let label = format!("{} ({:+})", node.move_uci.as_deref().unwrap_or("root"), node.eval_cp);
+        out.push_str(&format!(
+            "  {} [label={}, shape={}];\n",
+            quote(&node.branch_id),
+            quote(&label),
+            shape
+        ));
+        for child in &node.children {
+            out.push_str(&format!("  {} -> {};\n", quote(&node.branch_id), quote(child)));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Render a BranchTree as a PGN game from its root position: each node's
+/// first child continues the line and the others become variations.
+pub fn tree_to_pgn(tree: &BranchTree) -> String {
+    let mut movetext = String::new();
+    if let (Some(root), Ok(board)) = (tree.nodes.first(), Board::from_str(&tree.root_fen)) {
+        let fullmove = tree
+            .root_fen
+            .split_whitespace()
+            .nth(5)
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        pgn_variations(tree, root, &board, fullmove, false, &mut movetext);
+    }
+    format!(
+        "[Event \"What-if\"]\n[SetUp \"1\"]\n[FEN \"{}\"]\n\n{} *\n",
+        tree.root_fen, movetext
+    )
+}
+
+/// Append the moves below `node`, whose position is `board` at full move
+/// `fullmove`. `numbered` forces a move number on the first move, as
+/// needed after a variation.
+fn pgn_variations(
+    tree: &BranchTree,
+    node: &BranchNode,
+    board: &Board,
+    fullmove: u32,
+    numbered: bool,
+    out: &mut String,
+) {
+    // A pass is `None`, written as the `--` null move
+    let children: Vec<(&BranchNode, Option<ChessMove>, Board)> = node
+        .children
+        .iter()
+        .filter_map(|id| tree.node(id))
+        .filter_map(|child| match child.move_uci.as_deref()? {
+            PASS_UCI => Some((child, None, board.null_move()?)),
+            uci => {
+                let chess_move = ChessMove::from_str(uci).ok()?;
+                board
+                    .legal(chess_move)
+                    .then(|| (child, Some(chess_move), board.make_move_new(chess_move)))
+            }
+        })
+        .collect();
+    let Some(((main, main_move, main_after), alternatives)) = children.split_first() else {
+        return;
+    };
+    let next_fullmove = fullmove + (board.side_to_move() == Color::Black) as u32;
+
+    push_pgn_move(board, *main_move, fullmove, numbered, out);
+    for (child, chess_move, after) in alternatives {
+        out.push_str(" (");
+        push_pgn_move(board, *chess_move, fullmove, true, out);
+        pgn_variations(tree, child, after, next_fullmove, false, out);
+        out.push(')');
+    }
+    pgn_variations(tree, main, main_after, next_fullmove, !alternatives.is_empty(), out);
+}
+
+fn push_pgn_move(board: &Board, chess_move: Option<ChessMove>, fullmove: u32, numbered: bool, out: &mut String) {
+    if !out.is_empty() && !out.ends_with('(') {
+        out.push(' ');
+    }
+    let san = chess_move.map_or_else(|| "--".to_string(), |chess_move| to_san(board, chess_move));
+    match (board.side_to_move(), numbered) {
+        (Color::White, _) => out.push_str(&format!("{}. {}", fullmove, san)),
+        (Color::Black, true) => out.push_str(&format!("{}... {}", fullmove, san)),
+        (Color::Black, false) => out.push_str(&san),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -457,6 +1150,35 @@ mod tests {
         assert_eq!(config.node_budget, 10_000);
     }
 
+    #[test]
+    fn test_preset_from_phase() {
+        assert_eq!(BranchConfig::preset_from_phase("opening").max_depth, 8);
+        assert_eq!(BranchConfig::preset_from_phase("middlegame").max_depth, 16);
+        assert_eq!(BranchConfig::preset_from_phase("endgame").max_depth, MAX_BRANCH_DEPTH);
+        assert_eq!(BranchConfig::preset_from_phase("unknown").max_depth, MAX_BRANCH_DEPTH);
+    }
+
+    #[test]
+    fn test_path_from_root_reaches_leaf() {
+        let config = BranchConfig {
+            max_depth: 4,
+            ..BranchConfig::quick()
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        let leaf = tree.nodes.iter().find(|n| n.depth == 4).expect("a depth 4 leaf");
+
+        let path = leaf.path_from_root(&tree);
+        assert_eq!(path.len(), 4);
+        let board = crate::util::position::board_from_moves(Some(STARTPOS), &path).unwrap();
+        assert_eq!(board, Board::from_str(&leaf.fen).unwrap());
+        assert!(tree.nodes.iter().all(|n| n.depth_consistent(&tree)));
+        assert!(tree.nodes[0].path_from_root(&tree).is_empty());
+
+        let mut orphan = leaf.clone();
+        orphan.parent_id = Some("missing".to_string());
+        assert!(!orphan.depth_consistent(&tree));
+    }
+
     #[test]
     fn test_generate_branch_tree_startpos() {
         let config = BranchConfig::quick();
@@ -469,15 +1191,255 @@ mod tests {
         assert!(tree.nodes[0].children.len() <= config.width);
     }
 
+    #[test]
+    fn test_generate_branch_tree_from_pgn() {
+        let pgn = "[Event \"Ruy Lopez\"]\n\n\
+                   1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 \
+                   6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 *";
+        let config = BranchConfig::quick();
+        let tree = generate_branch_tree_from_pgn(pgn, &config, 8).unwrap();
+
+        let expected = "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQK2R w KQkq -";
+        let root_fen: Vec<&str> = tree.root_fen.split_whitespace().take(4).collect();
+        assert_eq!(root_fen.join(" "), expected);
+        assert!(tree.total_nodes > 1);
+
+        assert!(generate_branch_tree_from_pgn(pgn, &config, 0).is_some());
+        assert!(generate_branch_tree_from_pgn(pgn, &config, 21).is_none());
+        assert!(generate_branch_tree_from_pgn("1. e4 e4", &config, 2).is_none());
+    }
+
+    #[test]
+    fn test_prune_tree_keeps_pv_and_drops_dominated_siblings() {
+        let mut tree = generate_branch_tree(STARTPOS, &BranchConfig::quick()).unwrap();
+        let pv = tree.principal_variation.clone();
+        let pv_fens = tree.best_line_fens();
+
+        // Make the root's second child a clear blunder
+        let dominated_id = tree.nodes[0].children[1].clone();
+        let dominated = tree.node_index(&dominated_id).unwrap();
+        tree.nodes[dominated].eval_cp -= 10 * PAWN_VALUE;
+        let dominated_subtree = tree
+            .nodes
+            .iter()
+            .filter(|n| n.branch_id.starts_with(&dominated_id))
+            .count();
+        assert!(dominated_subtree > 1);
+
+        let before = tree.total_nodes;
+        let removed = prune_tree(&mut tree, 5 * PAWN_VALUE);
+        assert!(removed >= dominated_subtree);
+        assert_eq!(tree.total_nodes, before - removed);
+        assert_eq!(tree.nodes.len(), tree.total_nodes);
+        assert!(tree.node(&dominated_id).is_none());
+        assert!(!tree.nodes[0].children.contains(&dominated_id));
+        assert_eq!(tree.principal_variation, pv);
+        assert_eq!(tree.best_line_fens(), pv_fens);
+        assert_eq!(tree.validate(), Ok(()));
+
+        // Nothing but the PV survives a negative margin
+        prune_tree(&mut tree, i32::MIN / 2);
+        assert_eq!(tree.total_nodes, pv.len() + 1);
+    }
+
+    #[test]
+    fn test_prune_below_eval() {
+        let tree = generate_branch_tree(STARTPOS, &BranchConfig::quick()).unwrap();
+        assert!(tree.nodes.iter().any(|n| n.eval_cp < 0));
+
+        let pruned = tree.prune_below_eval(0);
+        assert!(pruned.total_nodes < tree.total_nodes);
+        assert_eq!(pruned.nodes.len(), pruned.total_nodes);
+        assert!(pruned.nodes.iter().all(|n| n.root_eval_cp() >= 0));
+        assert_eq!(pruned.validate(), Ok(()));
+        let has_parent = |n: &BranchNode| n.parent_id.as_deref().is_some_and(|id| pruned.node(id).is_some());
+        assert!(pruned.nodes.iter().skip(1).all(has_parent));
+        assert_eq!(pruned.best_line_fens().len(), pruned.principal_variation.len() + 1);
+        assert!(pruned.max_depth_reached <= tree.max_depth_reached);
+    }
+
+    #[test]
+    fn test_prune_below_eval_uses_root_perspective() {
+        let config = BranchConfig {
+            max_depth: 2,
+            width: 3,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 100,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+
+        // A queen up: the opponent's replies score badly for the opponent
+        // but well for the root side, so every line is kept
+        let ahead = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let tree = generate_branch_tree(ahead, &config).unwrap();
+        assert_eq!(tree.max_depth_reached, 2);
+        assert!(tree.nodes_at_depth(2).iter().all(|n| n.eval_cp < 0));
+        assert_eq!(tree.prune_below_eval(0).total_nodes, tree.total_nodes);
+
+        // A queen down: nothing below the root holds
+        let behind = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1";
+        let tree = generate_branch_tree(behind, &config).unwrap();
+        assert!(tree.nodes_at_depth(2).iter().all(|n| n.eval_cp > 0));
+        assert_eq!(tree.prune_below_eval(0).total_nodes, 1);
+    }
+
+    #[test]
+    fn test_nodes_by_depth_and_kind() {
+        let config = BranchConfig {
+            max_depth: 3,
+            width: 3,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 1_000,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+
+        let roots = tree.nodes_at_depth(0);
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].branch_id, tree.nodes[0].branch_id);
+        assert_eq!(tree.nodes_at_depth(1).len(), 3);
+        assert_eq!(tree.nodes_at_depth(2).len(), 9);
+        assert_eq!(tree.nodes_at_depth(4).len(), 0);
+
+        let leaves = tree.leaf_nodes();
+        assert_eq!(leaves.len(), 27);
+        assert!(leaves.iter().all(|n| n.depth == 3));
+        assert_eq!(tree.internal_nodes().len(), 1 + 3 + 9);
+    }
+
+    #[test]
+    fn test_threat_mode_follows_opponent_threat() {
+        // Black to move, with White threatening Qxf7#
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3";
+        let config = BranchConfig {
+            max_depth: 2,
+            width: 2,
+            ordering_depth: 3,
+            ordering_node_budget: 1_000_000,
+            selective_deepening: false,
+            node_budget: 50,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let defended = generate_branch_tree(fen, &config).unwrap();
+        assert_ne!(defended.principal_variation[1], "h5f7");
+
+        // Black passes, and White's best move is the mate
+        let threats = generate_branch_tree(fen, &BranchConfig { threat_mode: true, ..config }).unwrap();
+        assert_eq!(threats.principal_variation, ["0000", "h5f7"]);
+        assert_eq!(threats.nodes[0].children.len(), 1);
+        let mate = threats.nodes.iter().find(|n| n.depth == 2).unwrap();
+        assert_eq!(mate.terminal_reason.as_deref(), Some("checkmate"));
+        assert_eq!(threats.validate(), Ok(()));
+        let pgn = tree_to_pgn(&threats);
+        assert!(pgn.contains("-- 4. Qxf7#"), "{}", pgn);
+
+        // In check there is no passing
+        let check = "rnbqkbnr/ppppp1pp/8/5p1Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2";
+        let tree = generate_branch_tree(check, &BranchConfig { threat_mode: true, ..config }).unwrap();
+        assert_eq!(tree.principal_variation[0], "g7g6");
+    }
+
+    #[test]
+    fn test_ordering_falls_back_to_static_eval() {
+        // "Kiwipete": 48 legal moves to order at the root
+        let wide = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let config = BranchConfig {
+            max_depth: 3,
+            ordering_node_budget: 1_000,
+            ..BranchConfig::quick()
+        };
+        let tree = generate_branch_tree(wide, &config).unwrap();
+        assert!(tree.static_orderings > 0);
+        assert_eq!(tree.ordering_nodes, 1_000);
+        assert!(tree.total_nodes > 1);
+        assert_eq!(tree.max_depth_reached, 3);
+        assert_eq!(tree.validate(), Ok(()));
+
+        // A small tree within budget is ordered by search throughout
+        let config = BranchConfig {
+            max_depth: 2,
+            width: 2,
+            ..BranchConfig::quick()
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        assert_eq!(tree.static_orderings, 0);
+        assert!(tree.ordering_nodes > 0);
+    }
+
+    #[test]
+    fn test_validate_detects_corruption() {
+        let config = BranchConfig {
+            max_depth: 3,
+            width: 2,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 100,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        assert_eq!(tree.validate(), Ok(()));
+        let child = tree.nodes[0].children[0].clone();
+        let child_idx = tree.node_index(&child).unwrap();
+
+        // Duplicate branch_id
+        let mut dup = tree.clone();
+        let mut copy = dup.nodes[child_idx].clone();
+        copy.children.clear();
+        dup.push_node(copy);
+        assert!(dup.validate().unwrap_err().contains(&ValidationError::DuplicateBranchId(child.clone())));
+
+        // Child pointing to a node that doesn't exist
+        let mut missing = tree.clone();
+        missing.nodes[0].children.push("nowhere".to_string());
+        assert!(missing.validate().unwrap_err().contains(&ValidationError::MissingChild {
+            parent: "root".to_string(),
+            child: "nowhere".to_string(),
+        }));
+
+        // Parent no longer lists its child
+        let mut orphan = tree.clone();
+        orphan.nodes[0].children.retain(|id| *id != child);
+        assert!(orphan.validate().unwrap_err().contains(&ValidationError::NotListedByParent {
+            node: child.clone(),
+            parent: "root".to_string(),
+        }));
+
+        // Child depth out of step with its parent
+        let mut deep = tree.clone();
+        deep.nodes[child_idx].depth = 5;
+        assert!(deep.validate().unwrap_err().contains(&ValidationError::DepthMismatch {
+            parent: "root".to_string(),
+            child: child.clone(),
+            parent_depth: 0,
+            child_depth: 5,
+        }));
+    }
+
     #[test]
     fn test_branch_tree_depth() {
         let config = BranchConfig {
             max_depth: 4,
             width: 2,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: false,
             node_budget: 100,
             prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
         let tree = generate_branch_tree(STARTPOS, &config).unwrap();
         assert!(tree.max_depth_reached <= 4);
@@ -489,14 +1451,46 @@ mod tests {
             max_depth: 32,
             width: 3,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: false,
             node_budget: 50,
             prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
         let tree = generate_branch_tree(STARTPOS, &config).unwrap();
         assert!(tree.total_nodes <= 50, "Should respect node budget, got {}", tree.total_nodes);
     }
 
+    #[test]
+    fn test_memory_cap_truncates_before_node_budget() {
+        let config = BranchConfig {
+            max_depth: 32,
+            width: 3,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 1_000,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let full = generate_branch_tree(STARTPOS, &config).unwrap();
+        assert!(!full.truncated);
+        assert_eq!(full.total_nodes, 1_000);
+
+        let cap = full.memory_bytes / 10;
+        let capped = BranchConfig {
+            max_memory_bytes: Some(cap),
+            ..config
+        };
+        let tree = generate_branch_tree(STARTPOS, &capped).unwrap();
+        assert!(tree.truncated);
+        assert!(tree.total_nodes < 200, "got {} nodes", tree.total_nodes);
+        let largest = tree.nodes.iter().map(BranchNode::estimated_bytes).max().unwrap();
+        assert!(tree.memory_bytes >= cap && tree.memory_bytes < cap + largest);
+    }
+
     #[test]
     fn test_principal_variation() {
         let config = BranchConfig::quick();
@@ -504,6 +1498,39 @@ mod tests {
         assert!(!tree.principal_variation.is_empty(), "PV should not be empty");
     }
 
+    #[test]
+    fn test_best_line_evals_and_fens() {
+        let config = BranchConfig::quick();
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        let evals = tree.best_line_evals();
+        let fens = tree.best_line_fens();
+
+        assert_eq!(evals.len(), tree.principal_variation.len() + 1);
+        assert_eq!(fens.len(), tree.principal_variation.len() + 1);
+        assert_eq!(evals[0], tree.nodes[0].eval_cp);
+        assert_eq!(fens[0], STARTPOS);
+        assert_eq!(tree_summary(&tree).best_line_evals, evals);
+    }
+
+    #[test]
+    fn test_session_budget_reserve_and_refund() {
+        let budget = SessionBudget::new(1_000);
+        let shared = budget.clone();
+
+        assert_eq!(budget.reserve(600), 600);
+        assert_eq!(shared.reserve(600), 400);
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.reserve(10), 0);
+
+        shared.refund(150);
+        assert_eq!(budget.remaining(), Some(150));
+
+        let unlimited = SessionBudget::unlimited();
+        assert_eq!(unlimited.reserve(500), 500);
+        assert!(!unlimited.is_exhausted());
+        assert_eq!(SessionBudget::new(0).remaining(), None);
+    }
+
     #[test]
     fn test_tree_summary() {
         let config = BranchConfig::quick();
@@ -521,9 +1548,12 @@ mod tests {
             max_depth: 2,
             width: 2,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: false,
             node_budget: 10,
             prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
         let tree = generate_branch_tree(STARTPOS, &config).unwrap();
         let json = tree_to_json(&tree);
@@ -531,6 +1561,36 @@ mod tests {
         assert!(json["nodes"].is_array());
     }
 
+    #[test]
+    fn test_tree_to_dot_and_pgn() {
+        let config = BranchConfig {
+            max_depth: 2,
+            width: 2,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 10,
+            prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+
+        let dot = tree_to_dot(&tree);
+        assert!(dot.starts_with("digraph whatif {"));
+        assert_eq!(dot.matches("label=").count(), tree.nodes.len());
+        assert_eq!(dot.matches(" -> ").count(), tree.nodes.len() - 1);
+
+        let pgn = tree_to_pgn(&tree);
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", STARTPOS)));
+        let movetext = pgn.lines().last().unwrap();
+        assert!(movetext.starts_with("1. "), "{}", movetext);
+        // Two replies to each of two first moves: a variation at each ply
+        assert_eq!(movetext.matches(" (1. ").count(), 1, "{}", movetext);
+        assert_eq!(movetext.matches("(1... ").count(), 2, "{}", movetext);
+        assert!(movetext.ends_with(" *"));
+    }
+
     #[test]
     fn test_terminal_detection() {
         // Scholar's mate position (checkmate)
@@ -549,9 +1609,12 @@ mod tests {
             max_depth: 2,
             width: 2,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: false,
             node_budget: 20,
             prune_threshold: 10_000,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
         let tree = generate_branch_tree(STARTPOS, &config).unwrap();
 
@@ -566,7 +1629,7 @@ mod tests {
         // Parent-child links should be consistent
         for node in &tree.nodes {
             for child_id in &node.children {
-                let child = tree.nodes.iter().find(|n| &n.branch_id == child_id);
+                let child = tree.node(child_id);
                 assert!(child.is_some(), "Child {} not found in tree", child_id);
                 assert_eq!(child.unwrap().parent_id.as_ref().unwrap(), &node.branch_id);
             }
@@ -579,17 +1642,23 @@ mod tests {
             max_depth: 6,
             width: 3,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: true,
             node_budget: 200,
             prune_threshold: 500,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
         let config_flat = BranchConfig {
             max_depth: 6,
             width: 3,
             ordering_depth: 1,
+            ordering_node_budget: 0,
             selective_deepening: false,
             node_budget: 200,
             prune_threshold: 500,
+            threat_mode: false,
+            max_memory_bytes: None,
         };
 
         let tree_selective = generate_branch_tree(STARTPOS, &config_selective).unwrap();
@@ -601,4 +1670,75 @@ mod tests {
             || tree_selective.total_nodes <= tree_flat.total_nodes,
             "Selective deepening should either reach deeper PV or use fewer nodes");
     }
+
+    #[test]
+    fn test_node_index_large_tree() {
+        let config = BranchConfig {
+            max_depth: 8,
+            width: 4,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 5_000,
+            prune_threshold: 500,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let mut tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        assert!(tree.nodes.len() > 1_000);
+
+        for (idx, node) in tree.nodes.iter().enumerate() {
+            assert_eq!(tree.node_index(&node.branch_id), Some(idx));
+        }
+        assert_eq!(tree.node_index("no-such-branch"), None);
+
+        // Editing nodes directly leaves the index stale; lookups still
+        // resolve and rebuild_index brings it back in sync.
+        let last = tree.nodes.pop().unwrap();
+        tree.nodes.insert(1, last.clone());
+        assert_eq!(tree.node_index(&last.branch_id), Some(1));
+        tree.rebuild_index();
+        assert_eq!(tree.index.get(&last.branch_id), Some(&1));
+    }
+
+    /// Timing comparison of the indexed lookup against the linear scan it
+    /// replaced. Run with
+    /// `cargo test bench_node_index -- --ignored --nocapture`
+    /// to see the times.
+    #[test]
+    #[ignore]
+    fn bench_node_index() {
+        let config = BranchConfig {
+            max_depth: 8,
+            width: 4,
+            ordering_depth: 1,
+            ordering_node_budget: 0,
+            selective_deepening: false,
+            node_budget: 5_000,
+            prune_threshold: 500,
+            threat_mode: false,
+            max_memory_bytes: None,
+        };
+        let tree = generate_branch_tree(STARTPOS, &config).unwrap();
+        let ids: Vec<&str> = tree.nodes.iter().map(|n| n.branch_id.as_str()).collect();
+
+        let start = std::time::Instant::now();
+        let indexed: usize = ids.iter().filter_map(|id| tree.node_index(id)).sum();
+        let indexed_time = start.elapsed();
+        let start = std::time::Instant::now();
+        let scanned: usize = ids
+            .iter()
+            .filter_map(|id| tree.nodes.iter().position(|n| n.branch_id == *id))
+            .sum();
+        let scan_time = start.elapsed();
+        println!(
+            "{} lookups: index {:?}, scan {:?} ({:.0}x)",
+            ids.len(),
+            indexed_time,
+            scan_time,
+            scan_time.as_secs_f64() / indexed_time.as_secs_f64()
+        );
+        assert_eq!(indexed, scanned);
+        assert!(indexed_time < scan_time);
+    }
 }
@@ -0,0 +1,73 @@
+//! Runs each `stonksfish-cli` subcommand and checks its output parses.
+
+use std::process::{Command, Output};
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn cli(args: &[&str]) -> String {
+    let Output { status, stdout, stderr } = Command::new(env!("CARGO_BIN_EXE_stonksfish-cli"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(status.success(), "{:?}: {}", args, String::from_utf8_lossy(&stderr));
+    String::from_utf8(stdout).unwrap()
+}
+
+#[test]
+fn test_analyze_prints_json() {
+    let output = cli(&["analyze", START_FEN, "--depth", "2", "--multipv", "3"]);
+    let analysis: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(analysis["fen"], START_FEN);
+    assert_eq!(analysis["legal_moves"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_whatif_prints_tree() {
+    let json: serde_json::Value = serde_json::from_str(&cli(&["whatif", "startpos"])).unwrap();
+    assert_eq!(json["root_fen"], START_FEN);
+    assert!(cli(&["whatif", START_FEN, "--dot"]).starts_with("digraph whatif {"));
+    let pgn = cli(&["whatif", START_FEN, "--config", "quick", "--pgn"]);
+    assert!(pgn.contains(&format!("[FEN \"{}\"]", START_FEN)));
+}
+
+#[test]
+fn test_perft_prints_divide() {
+    let output = cli(&["perft", "startpos", "2"]);
+    assert_eq!(output.lines().filter(|line| line.ends_with(": 20")).count(), 20);
+    assert!(output.ends_with("Nodes searched: 400\n"));
+}
+
+#[test]
+fn test_bench_prints_nps() {
+    let output = cli(&["bench", "--depth=2"]);
+    let nps = output
+        .lines()
+        .find_map(|line| line.strip_prefix("NPS: "))
+        .unwrap();
+    assert!(nps.parse::<u64>().is_ok());
+}
+
+#[test]
+fn test_bad_arguments_fail() {
+    let status = Command::new(env!("CARGO_BIN_EXE_stonksfish-cli"))
+        .args(["perft", "startpos"])
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(status.code(), Some(2));
+    let status = Command::new(env!("CARGO_BIN_EXE_stonksfish-cli"))
+        .args(["whatif", "startpos", "--dot", "--pgn"])
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn test_help_lists_subcommands() {
+    let help = cli(&["--help"]);
+    for command in ["analyze", "whatif", "perft", "bench"] {
+        assert!(help.contains(command), "{}", help);
+    }
+    assert!(cli(&["whatif", "--help"]).contains("--config <CONFIG>"));
+}